@@ -0,0 +1,30 @@
+////////////////////////////////////////////////////////////////////////////////
+// Terminal bell, rung when an action fails (see `config::BellEvent`)
+////////////////////////////////////////////////////////////////////////////////
+
+use config::BellStyle;
+
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::thread;
+use std::time::Duration;
+
+pub fn ring(style: BellStyle) -> io::Result<()> {
+    // Like the clipboard, write to /dev/tty directly so that this doesn't
+    // interfere with termbox's output buffer
+    let mut tty = OpenOptions::new().write(true).open("/dev/tty")?;
+    match style {
+        BellStyle::Audible =>
+            tty.write_all(b"\x07")?,
+        BellStyle::Visual => {
+            // Reverse video on, then off. Same as vim's 'visualbell' in most
+            // terminals.
+            tty.write_all(b"\x1b[?5h")?;
+            tty.flush()?;
+            thread::sleep(Duration::from_millis(100));
+            tty.write_all(b"\x1b[?5l")?;
+        }
+    }
+    tty.flush()
+}
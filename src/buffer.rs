@@ -0,0 +1,181 @@
+////////////////////////////////////////////////////////////////////////////////
+// Editable contents of a file
+//
+// A gap buffer: bytes are kept in a single vector with a gap at the last edit
+// position, so insertions and deletions near each other (the usual case when
+// editing) don't shift the rest of the file.
+//
+// Edits are recorded for undo, see `undo`.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::io;
+
+use undo::{Edit, History};
+
+/// Gap is grown by this many bytes when it's full.
+const GAP_SIZE: usize = 64 * 1024;
+
+pub struct Buffer {
+    /// Bytes before the gap, the gap, and bytes after the gap.
+    data: Vec<u8>,
+    gap_start: usize,
+    gap_end: usize,
+
+    /// Edited since loaded or saved?
+    modified: bool,
+
+    history: History,
+}
+
+impl Buffer {
+    pub fn new(data: Vec<u8>) -> Buffer {
+        let len = data.len();
+        Buffer {
+            data,
+            gap_start: len,
+            gap_end: len,
+            modified: false,
+            history: History::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len() - (self.gap_end - self.gap_start)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, idx: usize) -> Option<u8> {
+        if idx < self.gap_start {
+            Some(self.data[idx])
+        } else {
+            self.data.get(idx + self.gap_end - self.gap_start).cloned()
+        }
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    /// Call after writing the buffer to its file.
+    pub fn set_saved(&mut self) {
+        self.modified = false;
+    }
+
+    /// Contents as a single slice. Moves the gap to the end, so this is cheap
+    /// when called repeatedly without edits in between.
+    pub fn as_slice(&mut self) -> &[u8] {
+        let len = self.len();
+        self.move_gap(len);
+        &self.data[..len]
+    }
+
+    pub fn insert(&mut self, offset: usize, byte: u8) {
+        self.replace(offset, offset, &[byte]);
+    }
+
+    /// Append bytes read from the file. Doesn't mark the buffer as modified.
+    pub fn append_loaded(&mut self, bytes: &[u8]) {
+        let len = self.len();
+        self.move_gap(len);
+        self.reserve_gap(bytes.len());
+        self.data[self.gap_start..self.gap_start + bytes.len()].copy_from_slice(bytes);
+        self.gap_start += bytes.len();
+    }
+
+    /// Replace bytes in `start..end` with `bytes`.
+    pub fn replace(&mut self, start: usize, end: usize, bytes: &[u8]) {
+        assert!(start <= end && end <= self.len());
+        let removed = (start..end).map(|idx| self.get(idx).unwrap()).collect();
+        self.history.push(Edit {
+            offset: start,
+            removed,
+            inserted: bytes.to_vec(),
+        });
+        self.splice(start, end, bytes);
+    }
+
+    pub fn set(&mut self, offset: usize, byte: u8) {
+        assert!(offset < self.len());
+        let idx = if offset < self.gap_start {
+            offset
+        } else {
+            offset + self.gap_end - self.gap_start
+        };
+        self.history.push_set(offset, self.data[idx], byte);
+        self.data[idx] = byte;
+        self.modified = true;
+    }
+
+    /// Remove bytes in `start..end`.
+    pub fn remove(&mut self, start: usize, end: usize) {
+        self.replace(start, end, &[]);
+    }
+
+    /// Undo the last edit. Returns the offset of the edit, or `None` if
+    /// there's nothing to undo.
+    pub fn undo(&mut self) -> io::Result<Option<usize>> {
+        let edit = match self.history.pop_undo()? {
+            Some(edit) =>
+                edit,
+            None =>
+                return Ok(None),
+        };
+        let offset = edit.offset;
+        self.splice(offset, offset + edit.inserted.len(), &edit.removed);
+        self.history.undone(edit);
+        Ok(Some(offset))
+    }
+
+    /// Redo the last undone edit. Returns the offset of the edit.
+    pub fn redo(&mut self) -> Option<usize> {
+        let edit = self.history.pop_redo()?;
+        let offset = edit.offset;
+        self.splice(offset, offset + edit.removed.len(), &edit.inserted);
+        self.history.redone(edit);
+        Some(offset)
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Replace bytes in `start..end` without recording it.
+    fn splice(&mut self, start: usize, end: usize, bytes: &[u8]) {
+        self.move_gap(start);
+        self.gap_end += end - start;
+        self.reserve_gap(bytes.len());
+        self.data[self.gap_start..self.gap_start + bytes.len()].copy_from_slice(bytes);
+        self.gap_start += bytes.len();
+        self.modified = true;
+    }
+
+    /// Make sure the gap has room for `n` bytes.
+    fn reserve_gap(&mut self, n: usize) {
+        let gap = self.gap_end - self.gap_start;
+        if gap < n {
+            let grow = n - gap + GAP_SIZE;
+            let gap_end = self.gap_end;
+            self.data.splice(gap_end..gap_end, vec![0; grow]);
+            self.gap_end += grow;
+        }
+    }
+
+    fn move_gap(&mut self, offset: usize) {
+        if offset < self.gap_start {
+            // Move bytes in offset..gap_start to the end of the gap
+            let n = self.gap_start - offset;
+            self.data.copy_within(offset..self.gap_start, self.gap_end - n);
+            self.gap_start -= n;
+            self.gap_end -= n;
+        } else if offset > self.gap_start {
+            // Move bytes after the gap to the start of the gap
+            let n = offset - self.gap_start;
+            self.data.copy_within(self.gap_end..self.gap_end + n, self.gap_start);
+            self.gap_start += n;
+            self.gap_end += n;
+        }
+    }
+}
@@ -0,0 +1,44 @@
+////////////////////////////////////////////////////////////////////////////////
+// System clipboard access
+//
+// We use the OSC 52 escape sequence: the terminal emulator puts the text to
+// the clipboard. Works in most terminals, inside tmux (with `set-clipboard
+// on`), and over ssh, without depending on X11 or Wayland.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+
+pub fn copy(text: &str) -> io::Result<()> {
+    // termbox writes to /dev/tty too, so this works even when stdout is
+    // redirected
+    let mut tty = OpenOptions::new().write(true).open("/dev/tty")?;
+    write!(tty, "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+    tty.flush()
+}
+
+static BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64(bytes: &[u8]) -> String {
+    let mut ret = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b0 = chunk[0] as usize;
+        let b1 = *chunk.get(1).unwrap_or(&0) as usize;
+        let b2 = *chunk.get(2).unwrap_or(&0) as usize;
+
+        ret.push(BASE64_CHARS[b0 >> 2] as char);
+        ret.push(BASE64_CHARS[((b0 & 0b11) << 4) | (b1 >> 4)] as char);
+        if chunk.len() > 1 {
+            ret.push(BASE64_CHARS[((b1 & 0b1111) << 2) | (b2 >> 6)] as char);
+        } else {
+            ret.push('=');
+        }
+        if chunk.len() > 2 {
+            ret.push(BASE64_CHARS[b2 & 0b11_1111] as char);
+        } else {
+            ret.push('=');
+        }
+    }
+    ret
+}
@@ -1,31 +1,409 @@
+use std::cell::Cell;
+
 use termbox_simple::*;
 
+#[derive(Clone, Copy)]
 pub struct Style {
     pub fg: u16,
     pub bg: u16,
 }
 
-pub const DEFAULT: Style = Style {
+const DEFAULT: Style = Style {
     fg: TB_DEFAULT,
     bg: TB_DEFAULT,
 };
 
-pub const CURSOR_NO_FOCUS: Style = Style {
-    fg: TB_WHITE,
-    bg: TB_YELLOW,
-};
+/// Styles of the UI elements. Foregrounds may be adjusted on startup and
+/// after `:theme`, see `check_contrast()`.
+#[derive(Clone, Copy)]
+pub struct Palette {
+    /// Text, borders of overlays, and everything else without a style.
+    pub default: Style,
+    pub cursor_no_focus: Style,
+    pub cursor_focus: Style,
+    pub status_bar: Style,
+    pub highlight: Style,
+    pub selection: Style,
+    pub same_byte: Style,
+    /// Bytes of memory images that are not in the file, e.g. `.bss`.
+    pub virtual_byte: Style,
+    /// Fields of the applied template (`:template`), alternating so that
+    /// adjacent fields can be told apart.
+    pub template_field: Style,
+    pub template_field_alt: Style,
+    /// Bytes that differ from the reference file (`--compare`).
+    pub diff: Style,
+    /// Parts of ELF files, see `gui::hex::elf_regions`. Adjacent parts get
+    /// the next style.
+    pub elf_regions: [Style; 6],
+}
 
-pub const CURSOR_FOCUS: Style = Style {
-    fg: TB_WHITE,
-    bg: TB_GREEN,
+const PALETTE: Palette = Palette {
+    default: DEFAULT,
+    cursor_no_focus: Style {
+        fg: TB_WHITE,
+        bg: TB_YELLOW,
+    },
+    cursor_focus: Style {
+        fg: TB_WHITE,
+        bg: TB_GREEN,
+    },
+    status_bar: Style {
+        fg: TB_WHITE,
+        bg: TB_GREEN,
+    },
+    highlight: Style {
+        fg: TB_BLACK,
+        bg: TB_BLUE,
+    },
+    selection: Style {
+        fg: TB_BLACK,
+        bg: TB_MAGENTA,
+    },
+    same_byte: Style {
+        fg: TB_BLACK,
+        bg: TB_CYAN,
+    },
+    virtual_byte: Style {
+        fg: GRAY_256,
+        bg: TB_DEFAULT,
+    },
+    template_field: Style {
+        fg: TB_BLACK,
+        bg: LIGHT_CYAN_256,
+    },
+    template_field_alt: Style {
+        fg: TB_BLACK,
+        bg: PEACH_256,
+    },
+    diff: Style {
+        fg: TB_RED,
+        bg: TB_DEFAULT,
+    },
+    elf_regions: ELF_REGIONS,
 };
 
-pub const STATUS_BAR: Style = Style {
-    fg: TB_WHITE,
-    bg: TB_GREEN,
+/// For terminals with light backgrounds: lighter backgrounds with black text.
+const LIGHT_PALETTE: Palette = Palette {
+    default: DEFAULT,
+    cursor_no_focus: Style {
+        fg: TB_BLACK,
+        bg: LIGHT_YELLOW_256,
+    },
+    cursor_focus: Style {
+        fg: TB_BLACK,
+        bg: LIGHT_GREEN_256,
+    },
+    status_bar: Style {
+        fg: TB_BLACK,
+        bg: LIGHT_GREEN_256,
+    },
+    highlight: Style {
+        fg: TB_BLACK,
+        bg: LIGHT_BLUE_256,
+    },
+    selection: Style {
+        fg: TB_BLACK,
+        bg: PINK_256,
+    },
+    same_byte: Style {
+        fg: TB_BLACK,
+        bg: PALE_CYAN_256,
+    },
+    virtual_byte: Style {
+        fg: LIGHT_GRAY_256,
+        bg: TB_DEFAULT,
+    },
+    template_field: Style {
+        fg: TB_BLACK,
+        bg: LIGHT_CYAN_256,
+    },
+    template_field_alt: Style {
+        fg: TB_BLACK,
+        bg: PEACH_256,
+    },
+    diff: Style {
+        fg: DARK_RED_256,
+        bg: TB_DEFAULT,
+    },
+    elf_regions: ELF_REGIONS,
 };
 
-pub const HIGHLIGHT: Style = Style {
-    fg: TB_BLACK,
-    bg: TB_BLUE,
-};
+/// Light backgrounds with black text, readable with both palettes.
+const ELF_REGIONS: [Style; 6] = [
+    Style {
+        fg: TB_BLACK,
+        bg: LIGHT_CYAN_256,
+    },
+    Style {
+        fg: TB_BLACK,
+        bg: PEACH_256,
+    },
+    Style {
+        fg: TB_BLACK,
+        bg: HONEYDEW_256,
+    },
+    Style {
+        fg: TB_BLACK,
+        bg: LAVENDER_256,
+    },
+    Style {
+        fg: TB_BLACK,
+        bg: CORNSILK_256,
+    },
+    Style {
+        fg: TB_BLACK,
+        bg: MISTY_ROSE_256,
+    },
+];
+
+/// Names of the palettes, for `:theme`.
+pub const THEMES: &[&str] = &["dark", "light"];
+
+/// Dark gray in the 256-color palette.
+const GRAY_256: u16 = 242;
+
+/// Light backgrounds of template fields, in the 256-color palette.
+const LIGHT_CYAN_256: u16 = 152;
+const PEACH_256: u16 = 223;
+
+/// More light backgrounds, for ELF regions.
+const HONEYDEW_256: u16 = 194;
+const LAVENDER_256: u16 = 189;
+const CORNSILK_256: u16 = 230;
+const MISTY_ROSE_256: u16 = 224;
+
+/// Colors of the light palette, in the 256-color palette.
+const LIGHT_GRAY_256: u16 = 248;
+const LIGHT_YELLOW_256: u16 = 229;
+const LIGHT_GREEN_256: u16 = 157;
+const LIGHT_BLUE_256: u16 = 153;
+const PINK_256: u16 = 218;
+const PALE_CYAN_256: u16 = 195;
+const DARK_RED_256: u16 = 124;
+
+thread_local! {
+    static CURRENT_PALETTE: Cell<Palette> = const { Cell::new(PALETTE) };
+}
+
+pub fn palette() -> Palette {
+    CURRENT_PALETTE.with(|palette| palette.get())
+}
+
+/// Switch to the palette. Returns false when there's no palette with the
+/// name.
+pub fn set_theme(name: &str) -> bool {
+    let palette = match name {
+        "dark" =>
+            PALETTE,
+        "light" =>
+            LIGHT_PALETTE,
+        _ =>
+            return false,
+    };
+    CURRENT_PALETTE.with(|current| current.set(palette));
+    true
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Byte colors
+
+/// Styles of byte values, read from `<config dir>/colors` (see
+/// `config::load_byte_colors`). Drawn below the other highlights.
+#[derive(Clone)]
+pub struct ByteColors {
+    /// Indexed by byte value. Empty when no values have styles.
+    styles: Vec<Option<Style>>,
+}
+
+impl ByteColors {
+    pub fn new() -> ByteColors {
+        ByteColors { styles: Vec::new() }
+    }
+
+    /// Style the values from `start` to `end` (inclusive). Later calls win.
+    pub fn set(&mut self, start: u8, end: u8, style: Style) {
+        if self.styles.is_empty() {
+            self.styles = vec![None; 256];
+        }
+        for byte in start..=end {
+            self.styles[byte as usize] = Some(style);
+        }
+    }
+
+    pub fn get(&self, byte: u8) -> Option<Style> {
+        self.styles.get(byte as usize).and_then(|style| *style)
+    }
+
+    /// Number of values with styles.
+    pub fn len(&self) -> usize {
+        self.styles.iter().filter(|style| style.is_some()).count()
+    }
+}
+
+/// A color name like "red", "dim" (gray), "default", or a number in the
+/// 256-color palette.
+pub fn parse_color(s: &str) -> Option<u16> {
+    let color = match s {
+        "default" =>
+            TB_DEFAULT,
+        "black" =>
+            TB_BLACK,
+        "red" =>
+            TB_RED,
+        "green" =>
+            TB_GREEN,
+        "yellow" =>
+            TB_YELLOW,
+        "blue" =>
+            TB_BLUE,
+        "magenta" =>
+            TB_MAGENTA,
+        "cyan" =>
+            TB_CYAN,
+        "white" =>
+            TB_WHITE,
+        "dim" | "gray" =>
+            GRAY_256,
+        _ =>
+            match s.parse::<u8>() {
+                Ok(n) =>
+                    u16::from(n),
+                Err(_) =>
+                    return None,
+            },
+    };
+    Some(color)
+}
+
+/// `<fg>` or `<fg>/<bg>`, e.g. "red" or "black/yellow".
+pub fn parse_style(s: &str) -> Option<Style> {
+    let mut colors = s.splitn(2, '/');
+    let fg = parse_color(colors.next()?)?;
+    let bg = match colors.next() {
+        Some(bg) =>
+            parse_color(bg)?,
+        None =>
+            TB_DEFAULT,
+    };
+    Some(Style { fg, bg })
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Contrast checking
+
+/// Styles with lower contrast ratios than this are considered unreadable.
+/// (WCAG asks for 4.5 for text, but we only want to catch e.g. red on magenta)
+const MIN_CONTRAST: f64 = 2.0;
+
+/// Black and white in the 256-color palette. 0 can't be used for black as it's
+/// `TB_DEFAULT`.
+const BLACK_256: u16 = 16;
+const WHITE_256: u16 = 231;
+
+/// Colors 0-15 of xterm's default palette.
+static BASE_COLORS: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// Approximate RGB of a color in 256-color mode (the mode rhex uses), assuming
+/// xterm's default palette. `None` for `TB_DEFAULT`, which is whatever the
+/// terminal's default is.
+pub fn rgb(color: u16) -> Option<(u8, u8, u8)> {
+    let idx = (color & 0xff) as usize;
+    if idx == TB_DEFAULT as usize {
+        None
+    } else if idx < 16 {
+        Some(BASE_COLORS[idx])
+    } else if idx < 232 {
+        // 6x6x6 color cube
+        let idx = idx - 16;
+        let level = |n: usize| if n == 0 { 0 } else { (55 + 40 * n) as u8 };
+        Some((level(idx / 36), level(idx / 6 % 6), level(idx % 6)))
+    } else {
+        // Grayscale ramp
+        let v = (8 + 10 * (idx - 232)) as u8;
+        Some((v, v, v))
+    }
+}
+
+/// Relative luminance, as defined in WCAG.
+pub fn luminance((r, g, b): (u8, u8, u8)) -> f64 {
+    let linear = |c: u8| {
+        let c = f64::from(c) / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b)
+}
+
+/// Between 1 (same luminance) and 21 (black and white).
+pub fn contrast_ratio(c1: (u8, u8, u8), c2: (u8, u8, u8)) -> f64 {
+    let l1 = luminance(c1);
+    let l2 = luminance(c2);
+    if l1 > l2 {
+        (l1 + 0.05) / (l2 + 0.05)
+    } else {
+        (l2 + 0.05) / (l1 + 0.05)
+    }
+}
+
+/// Check contrast of the palette's styles. When `fix` is set, foregrounds of
+/// low-contrast styles are replaced with black or white, whichever is more
+/// readable on the background. Returns names and contrast ratios of the
+/// low-contrast styles.
+pub fn check_contrast(fix: bool) -> Vec<(&'static str, f64)> {
+    let mut palette = palette();
+    let mut ret = Vec::new();
+    {
+        let mut styles: [(&'static str, &mut Style); 9] = [
+            ("text", &mut palette.default),
+            ("cursor", &mut palette.cursor_focus),
+            ("inactive cursor", &mut palette.cursor_no_focus),
+            ("status bar", &mut palette.status_bar),
+            ("highlight", &mut palette.highlight),
+            ("selection", &mut palette.selection),
+            ("same byte", &mut palette.same_byte),
+            ("template field", &mut palette.template_field),
+            ("template field", &mut palette.template_field_alt),
+        ];
+        for &mut (name, ref mut style) in styles.iter_mut() {
+            let (fg, bg) = match (rgb(style.fg), rgb(style.bg)) {
+                (Some(fg), Some(bg)) =>
+                    (fg, bg),
+                _ =>
+                    continue,
+            };
+            let ratio = contrast_ratio(fg, bg);
+            if ratio >= MIN_CONTRAST {
+                continue;
+            }
+            ret.push((name, ratio));
+            if fix {
+                let on_black = contrast_ratio(rgb(BLACK_256).unwrap(), bg);
+                let on_white = contrast_ratio(rgb(WHITE_256).unwrap(), bg);
+                style.fg = if on_black > on_white { BLACK_256 } else { WHITE_256 };
+            }
+        }
+    }
+    CURRENT_PALETTE.with(|current| current.set(palette));
+    ret
+}
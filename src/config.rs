@@ -0,0 +1,570 @@
+////////////////////////////////////////////////////////////////////////////////
+// Settings given on the command line, command aliases read from
+// `~/.config/rhex/aliases`, byte colors from `~/.config/rhex/colors`, and
+// templates in `~/.config/rhex/templates`
+////////////////////////////////////////////////////////////////////////////////
+
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use colors::{self, ByteColors, Style};
+use utils::parse_number;
+
+/// How offsets are shown in the offset column.
+#[derive(Clone, Copy, PartialEq)]
+pub enum OffsetFormat {
+    Hex,
+    Dec,
+    Oct,
+}
+
+impl OffsetFormat {
+    pub fn parse(s: &str) -> Option<OffsetFormat> {
+        match s {
+            "hex" =>
+                Some(OffsetFormat::Hex),
+            "dec" =>
+                Some(OffsetFormat::Dec),
+            "oct" =>
+                Some(OffsetFormat::Oct),
+            _ =>
+                None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OffsetFormat::Hex =>
+                "hex",
+            OffsetFormat::Dec =>
+                "dec",
+            OffsetFormat::Oct =>
+                "oct",
+        }
+    }
+
+    pub fn next(self) -> OffsetFormat {
+        match self {
+            OffsetFormat::Hex =>
+                OffsetFormat::Dec,
+            OffsetFormat::Dec =>
+                OffsetFormat::Oct,
+            OffsetFormat::Oct =>
+                OffsetFormat::Hex,
+        }
+    }
+}
+
+/// How bytes that are not printable ASCII are shown in the ASCII view.
+#[derive(Clone, Copy, PartialEq)]
+pub enum NonPrintable {
+    /// '.'
+    Dot,
+
+    /// Unicode control pictures for control characters (e.g. '␀', '␊'), '.'
+    /// for the rest.
+    Pictures,
+
+    /// Glyphs of code page 437, like DOS hex editors.
+    Cp437,
+}
+
+impl NonPrintable {
+    pub fn parse(s: &str) -> Option<NonPrintable> {
+        match s {
+            "dot" =>
+                Some(NonPrintable::Dot),
+            "pictures" =>
+                Some(NonPrintable::Pictures),
+            "cp437" =>
+                Some(NonPrintable::Cp437),
+            _ =>
+                None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            NonPrintable::Dot =>
+                "dot",
+            NonPrintable::Pictures =>
+                "pictures",
+            NonPrintable::Cp437 =>
+                "cp437",
+        }
+    }
+
+    pub fn next(self) -> NonPrintable {
+        match self {
+            NonPrintable::Dot =>
+                NonPrintable::Pictures,
+            NonPrintable::Pictures =>
+                NonPrintable::Cp437,
+            NonPrintable::Cp437 =>
+                NonPrintable::Dot,
+        }
+    }
+}
+
+/// How bytes are decoded in the ASCII view.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Ascii,
+    Latin1,
+
+    /// EBCDIC code page 37.
+    Ebcdic,
+    ShiftJis,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Encoding {
+    pub fn parse(s: &str) -> Option<Encoding> {
+        match s {
+            "ascii" =>
+                Some(Encoding::Ascii),
+            "latin1" =>
+                Some(Encoding::Latin1),
+            "ebcdic" =>
+                Some(Encoding::Ebcdic),
+            "sjis" =>
+                Some(Encoding::ShiftJis),
+            "utf8" =>
+                Some(Encoding::Utf8),
+            "utf16le" =>
+                Some(Encoding::Utf16Le),
+            "utf16be" =>
+                Some(Encoding::Utf16Be),
+            _ =>
+                None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Ascii =>
+                "ascii",
+            Encoding::Latin1 =>
+                "latin1",
+            Encoding::Ebcdic =>
+                "ebcdic",
+            Encoding::ShiftJis =>
+                "sjis",
+            Encoding::Utf8 =>
+                "utf8",
+            Encoding::Utf16Le =>
+                "utf16le",
+            Encoding::Utf16Be =>
+                "utf16be",
+        }
+    }
+}
+
+/// Column sums shown in the footer.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ColumnSum {
+    Xor,
+
+    /// Sum modulo 256.
+    Sum,
+}
+
+impl ColumnSum {
+    pub fn parse(s: &str) -> Option<ColumnSum> {
+        match s {
+            "xor" =>
+                Some(ColumnSum::Xor),
+            "sum" =>
+                Some(ColumnSum::Sum),
+            _ =>
+                None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ColumnSum::Xor =>
+                "xor",
+            ColumnSum::Sum =>
+                "sum",
+        }
+    }
+}
+
+/// Failed actions that can ring the bell.
+#[derive(Clone, Copy, PartialEq)]
+pub enum BellEvent {
+    /// Search or n/N didn't find the pattern.
+    NotFound,
+
+    /// Moving past the beginning or end of the buffer.
+    Edge,
+
+    /// Goto to an invalid offset or address.
+    InvalidGoto,
+}
+
+impl BellEvent {
+    /// Parse a comma-separated list of events, e.g. `search,goto`. `all` is
+    /// all events, `none` is no events.
+    pub fn parse(s: &str) -> Option<Vec<BellEvent>> {
+        let mut ret = Vec::new();
+        for word in s.split(',') {
+            match word {
+                "search" =>
+                    ret.push(BellEvent::NotFound),
+                "edge" =>
+                    ret.push(BellEvent::Edge),
+                "goto" =>
+                    ret.push(BellEvent::InvalidGoto),
+                "all" =>
+                    ret.extend_from_slice(&[
+                        BellEvent::NotFound,
+                        BellEvent::Edge,
+                        BellEvent::InvalidGoto,
+                    ]),
+                "none" =>
+                    {}
+                _ =>
+                    return None,
+            }
+        }
+        Some(ret)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum BellStyle {
+    /// Terminal bell (BEL).
+    Audible,
+
+    /// Briefly reverse the screen colors.
+    Visual,
+}
+
+impl BellStyle {
+    pub fn parse(s: &str) -> Option<BellStyle> {
+        match s {
+            "audible" =>
+                Some(BellStyle::Audible),
+            "visual" =>
+                Some(BellStyle::Visual),
+            _ =>
+                None,
+        }
+    }
+}
+
+/// What to do with low-contrast styles (e.g. text on the cursor that is hard
+/// to read), checked on startup.
+#[derive(Clone, Copy, PartialEq)]
+pub enum ContrastCheck {
+    /// Use black or white text instead.
+    Fix,
+
+    /// Keep the colors, show a warning in the info line.
+    Warn,
+
+    Off,
+}
+
+impl ContrastCheck {
+    pub fn parse(s: &str) -> Option<ContrastCheck> {
+        match s {
+            "fix" =>
+                Some(ContrastCheck::Fix),
+            "warn" =>
+                Some(ContrastCheck::Warn),
+            "off" =>
+                Some(ContrastCheck::Off),
+            _ =>
+                None,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Config {
+    pub offset_format: OffsetFormat,
+
+    /// Address the file is loaded at. When set, the offset column shows
+    /// addresses relative to this base.
+    pub base: Option<usize>,
+
+    /// Max. redraws per second. Events that arrive within a frame are
+    /// handled together and drawn once. 0 means no limit.
+    pub fps: u32,
+
+    /// Bytes are grouped in the hex view in groups of this many bytes (1, 2,
+    /// 4 or 8).
+    pub group_size: i32,
+
+    /// Show this many bytes per line, instead of filling the width.
+    pub cols: Option<i32>,
+
+    /// At most this many search matches are highlighted. n/N search for the
+    /// rest on demand.
+    pub max_matches: usize,
+
+    /// Marks are backed up every this many seconds (when changed) and on
+    /// quit, keeping a few timestamped versions. 0 disables backups.
+    pub autosave_secs: u64,
+
+    /// Ring the bell when these actions fail. The info line shows a message
+    /// either way.
+    pub bell_events: Vec<BellEvent>,
+    pub bell_style: BellStyle,
+
+    pub contrast: ContrastCheck,
+
+    /// Format of the info line, see `gui::hex::status`. The default shows the
+    /// path, the offset, and whatever is active (selection, origin, ...).
+    pub status_format: Option<String>,
+
+    /// Show only the hex view, without the offset column, the ASCII view, and
+    /// the info line. `Z` toggles this.
+    pub zen: bool,
+
+    /// Show sector numbers in the offset column, and move a sector at a time
+    /// with PageUp/PageDown.
+    pub sectors: bool,
+
+    /// Size of a sector, for the sector mode and `sector:N` in goto.
+    pub sector_size: usize,
+
+    /// Reload the files when they change, like `tail -f`. `F` toggles this.
+    pub follow: bool,
+
+    /// Show lines that are the same as the line before them as a single `*`
+    /// row, like `hexdump`.
+    pub squeeze: bool,
+
+    /// Move the cursor to the first match after it when a search is
+    /// accepted, instead of waiting for n.
+    pub search_jump: bool,
+
+    /// Center the view on the match after n/N.
+    pub search_center: bool,
+
+    /// `C` cycles through the styles.
+    pub non_printable: NonPrintable,
+
+    /// Decoding of the ASCII view. `:set encoding=..` changes it for the
+    /// current buffer.
+    pub encoding: Encoding,
+
+    /// `--encoding auto`: use the guessed encoding of text files, instead of
+    /// suggesting it.
+    pub auto_encoding: bool,
+
+    /// Show a row below the hex view with the XOR or sum of the visible bytes
+    /// of each column, and the CRC-32 of the visible bytes.
+    pub footer: Option<ColumnSum>,
+
+    /// Show bytes as bits in the hex view. `b` toggles this, space flips the
+    /// bit under the cursor.
+    pub bits: bool,
+
+    /// Color the parts of ELF files (header, program headers, sections, ...)
+    /// in the hex view.
+    pub elf_regions: bool,
+
+    /// Names of command aliases and the commands they run, see `:alias`.
+    pub aliases: Vec<(String, String)>,
+
+    /// Styles of byte values, e.g. to make zeros dim.
+    pub byte_colors: ByteColors,
+
+    /// Edits are disabled and the buffer is not saved to its file: `-R`, the
+    /// file is not writable, or the buffer is not a whole file (e.g. process
+    /// memory). Cleared after `:saveas`, unless set by `-R` or
+    /// `:set readonly=on`.
+    pub readonly: bool,
+
+    /// Endianness of values entered with `c` and of the integer table, until
+    /// it's changed there (`--big-endian`).
+    pub big_endian: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            offset_format: OffsetFormat::Hex,
+            base: None,
+            fps: 60,
+            group_size: 1,
+            cols: None,
+            max_matches: 100_000,
+            autosave_secs: 300,
+            bell_events: Vec::new(),
+            bell_style: BellStyle::Audible,
+            contrast: ContrastCheck::Fix,
+            status_format: None,
+            zen: false,
+            sectors: false,
+            sector_size: 512,
+            follow: false,
+            squeeze: false,
+            search_jump: false,
+            search_center: false,
+            non_printable: NonPrintable::Dot,
+            encoding: Encoding::Ascii,
+            auto_encoding: false,
+            footer: None,
+            bits: false,
+            elf_regions: true,
+            aliases: Vec::new(),
+            byte_colors: ByteColors::new(),
+            readonly: false,
+            big_endian: false,
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/rhex`, or `~/.config/rhex` when that's not set.
+fn config_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(ref dir) if !dir.is_empty() =>
+            Some(Path::new(dir).join("rhex")),
+        _ =>
+            env::var_os("HOME").map(|home| Path::new(&home).join(".config/rhex")),
+    }
+}
+
+fn aliases_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("aliases"))
+}
+
+fn colors_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("colors"))
+}
+
+/// `<config dir>/templates/<name>`, when it exists. Templates there are
+/// offered for files of the type, e.g. `templates/png` for PNG images.
+pub fn template_path(name: &str) -> Option<PathBuf> {
+    let path = config_dir()?.join("templates").join(name);
+    if path.is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
+
+/// Read the aliases file. Lines are `<name> <commands>`, as in `:alias`.
+/// Empty lines and lines starting with '#' are skipped. No file means no
+/// aliases.
+pub fn load_aliases() -> Result<Vec<(String, String)>, String> {
+    let path = match aliases_path() {
+        Some(path) =>
+            path,
+        None =>
+            return Ok(Vec::new()),
+    };
+    let mut contents = String::new();
+    match File::open(&path) {
+        Ok(mut file) =>
+            if let Err(err) = file.read_to_string(&mut contents) {
+                return Err(format!("Can't read {}: {}", path.display(), err));
+            },
+        Err(_) =>
+            return Ok(Vec::new()),
+    }
+
+    let mut aliases = Vec::new();
+    for (line_idx, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_alias(line) {
+            Some(alias) =>
+                aliases.push(alias),
+            None =>
+                return Err(format!(
+                    "{} line {}: expected <name> <commands>",
+                    path.display(),
+                    line_idx + 1
+                )),
+        }
+    }
+    Ok(aliases)
+}
+
+/// `<name> <commands>`. Commands are separated with ';'.
+pub fn parse_alias(s: &str) -> Option<(String, String)> {
+    let s = s.trim();
+    let name_end = s.find(char::is_whitespace)?;
+    let name = &s[..name_end];
+    let commands = s[name_end..].trim();
+    if name.contains(';') || commands.is_empty() {
+        return None;
+    }
+    Some((name.to_owned(), commands.to_owned()))
+}
+
+/// Read the byte colors file. Lines are `<byte>[-<byte>] <fg>[/<bg>]`, e.g.
+/// `0x00 dim`, `0x90 red`, `0x7f-0xff yellow`, see `colors::parse_style`.
+/// Later lines win. Empty lines and lines starting with '#' are skipped. No
+/// file means no colors.
+pub fn load_byte_colors() -> Result<ByteColors, String> {
+    let mut byte_colors = ByteColors::new();
+    let path = match colors_path() {
+        Some(path) =>
+            path,
+        None =>
+            return Ok(byte_colors),
+    };
+    let mut contents = String::new();
+    match File::open(&path) {
+        Ok(mut file) =>
+            if let Err(err) = file.read_to_string(&mut contents) {
+                return Err(format!("Can't read {}: {}", path.display(), err));
+            },
+        Err(_) =>
+            return Ok(byte_colors),
+    }
+
+    for (line_idx, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_byte_color(line) {
+            Some((start, end, style)) =>
+                byte_colors.set(start, end, style),
+            None =>
+                return Err(format!(
+                    "{} line {}: expected <byte>[-<byte>] <fg>[/<bg>]",
+                    path.display(),
+                    line_idx + 1
+                )),
+        }
+    }
+    Ok(byte_colors)
+}
+
+/// `<byte>[-<byte>] <fg>[/<bg>]`.
+fn parse_byte_color(s: &str) -> Option<(u8, u8, Style)> {
+    let mut words = s.split_whitespace();
+    let bytes = words.next()?;
+    let style = colors::parse_style(words.next()?)?;
+    if words.next().is_some() {
+        return None;
+    }
+    let (start, end) = match bytes.find('-') {
+        Some(idx) =>
+            (parse_number(&bytes[..idx])?, parse_number(&bytes[idx + 1..])?),
+        None => {
+            let byte = parse_number(bytes)?;
+            (byte, byte)
+        }
+    };
+    if start > end || end > 0xff {
+        return None;
+    }
+    Some((start as u8, end as u8, style))
+}
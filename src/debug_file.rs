@@ -0,0 +1,102 @@
+////////////////////////////////////////////////////////////////////////////////
+// Finding the separate debug info file of a stripped ELF file, in the places
+// gdb looks: by build-id in `/usr/lib/debug/.build-id`, or by the name in
+// `.gnu_debuglink` next to the file, in its `.debug` directory, or under
+// `/usr/lib/debug`. Files found by name must match the CRC in the link.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use hash::{crc32, to_hex};
+use load;
+use parser::elf::Elf;
+
+const DEBUG_DIR: &str = "/usr/lib/debug";
+
+pub struct DebugFile {
+    pub path: String,
+    pub contents: Vec<u8>,
+    /// How the file was found: "build-id" or "debuglink".
+    pub found_by: &'static str,
+}
+
+pub fn find(path: &str, elf: &Elf, data: &[u8]) -> Result<DebugFile, String> {
+    let build_id = elf.build_id(data);
+    let debuglink = elf.debuglink(data);
+    if build_id.is_none() && debuglink.is_none() {
+        return Err("No build-id or .gnu_debuglink, the file has no separate debug info"
+            .to_owned());
+    }
+
+    if let Some(ref build_id) = build_id {
+        if build_id.len() >= 2 {
+            let candidate = format!(
+                "{}/.build-id/{}/{}.debug",
+                DEBUG_DIR,
+                to_hex(&build_id[..1]),
+                to_hex(&build_id[1..])
+            );
+            if let Some(contents) = read(Path::new(&candidate)) {
+                return Ok(DebugFile {
+                    path: candidate,
+                    contents,
+                    found_by: "build-id",
+                });
+            }
+        }
+    }
+
+    let mut crc_mismatch = None;
+    if let Some(ref link) = debuglink {
+        // Relative to the directory of the file, after following symlinks
+        let dir = fs::canonicalize(path)
+            .ok()
+            .and_then(|path| path.parent().map(|dir| dir.to_owned()))
+            .unwrap_or_else(|| PathBuf::from("."));
+        let relative_dir = dir.strip_prefix("/").unwrap_or(&dir).to_owned();
+        let candidates = [
+            dir.join(&link.name),
+            dir.join(".debug").join(&link.name),
+            Path::new(DEBUG_DIR).join(relative_dir).join(&link.name),
+        ];
+        for candidate in &candidates {
+            // Don't pick the file itself when it's named like the link
+            if Path::new(path) == candidate.as_path() {
+                continue;
+            }
+            if let Some(contents) = read(candidate) {
+                if crc32(&contents) == link.crc {
+                    return Ok(DebugFile {
+                        path: candidate.to_string_lossy().into_owned(),
+                        contents,
+                        found_by: "debuglink",
+                    });
+                }
+                crc_mismatch = Some(candidate.to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    let mut msg = String::from("Debug file not found");
+    if let Some(ref build_id) = build_id {
+        msg.push_str(&format!(" (build-id {}", to_hex(build_id)));
+        if let Some(ref link) = debuglink {
+            msg.push_str(&format!(", debuglink {}", link.name));
+        }
+        msg.push(')');
+    } else if let Some(ref link) = debuglink {
+        msg.push_str(&format!(" (debuglink {})", link.name));
+    }
+    if let Some(candidate) = crc_mismatch {
+        msg.push_str(&format!(", {} has a different CRC", candidate));
+    }
+    Err(msg)
+}
+
+fn read(path: &Path) -> Option<Vec<u8>> {
+    if !path.is_file() {
+        return None;
+    }
+    load::load(path, 0, None).ok().map(|loaded| loaded.contents)
+}
@@ -0,0 +1,234 @@
+////////////////////////////////////////////////////////////////////////////////
+// File type detection from magic numbers at the start of the file (or at a
+// fixed offset, for tar). Only the first few hundred bytes are looked at, so
+// this is cheap enough to run after every edit.
+//
+// `scan` (`:scan`) looks for files embedded anywhere in the buffer instead,
+// e.g. compressed parts of firmware images.
+////////////////////////////////////////////////////////////////////////////////
+
+use parser::macho::is_macho;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FileKind {
+    Elf,
+    Pe,
+    MachO,
+    Zip,
+    Gzip,
+    Bzip2,
+    Xz,
+    Zstd,
+    SevenZip,
+    Tar,
+    Png,
+    Jpeg,
+    Gif,
+    Pdf,
+    Sqlite,
+    Wasm,
+}
+
+/// Magic numbers at the start of the file.
+const MAGIC: [(&[u8], FileKind); 15] = [
+    (b"\x7fELF", FileKind::Elf),
+    (b"MZ", FileKind::Pe),
+    (b"PK\x03\x04", FileKind::Zip),
+    // Empty archive
+    (b"PK\x05\x06", FileKind::Zip),
+    (b"\x1f\x8b", FileKind::Gzip),
+    (b"BZh", FileKind::Bzip2),
+    (b"\xfd7zXZ\x00", FileKind::Xz),
+    (b"\x28\xb5\x2f\xfd", FileKind::Zstd),
+    (b"7z\xbc\xaf\x27\x1c", FileKind::SevenZip),
+    (b"\x89PNG\r\n\x1a\n", FileKind::Png),
+    (b"\xff\xd8\xff", FileKind::Jpeg),
+    (b"GIF8", FileKind::Gif),
+    (b"%PDF-", FileKind::Pdf),
+    (b"SQLite format 3\x00", FileKind::Sqlite),
+    (b"\x00asm", FileKind::Wasm),
+];
+
+/// "ustar" in the header of the first entry.
+const TAR_MAGIC_OFFSET: usize = 257;
+
+impl FileKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            FileKind::Elf =>
+                "ELF",
+            FileKind::Pe =>
+                "PE",
+            FileKind::MachO =>
+                "Mach-O",
+            FileKind::Zip =>
+                "ZIP",
+            FileKind::Gzip =>
+                "gzip",
+            FileKind::Bzip2 =>
+                "bzip2",
+            FileKind::Xz =>
+                "xz",
+            FileKind::Zstd =>
+                "Zstandard",
+            FileKind::SevenZip =>
+                "7z",
+            FileKind::Tar =>
+                "tar",
+            FileKind::Png =>
+                "PNG",
+            FileKind::Jpeg =>
+                "JPEG",
+            FileKind::Gif =>
+                "GIF",
+            FileKind::Pdf =>
+                "PDF",
+            FileKind::Sqlite =>
+                "SQLite",
+            FileKind::Wasm =>
+                "WebAssembly",
+        }
+    }
+
+    /// Name of the template offered for files of this type, see
+    /// `config::template_path`.
+    pub fn template_name(self) -> &'static str {
+        match self {
+            FileKind::Elf =>
+                "elf",
+            FileKind::Pe =>
+                "pe",
+            FileKind::MachO =>
+                "macho",
+            FileKind::Zip =>
+                "zip",
+            FileKind::Gzip =>
+                "gzip",
+            FileKind::Bzip2 =>
+                "bzip2",
+            FileKind::Xz =>
+                "xz",
+            FileKind::Zstd =>
+                "zstd",
+            FileKind::SevenZip =>
+                "7z",
+            FileKind::Tar =>
+                "tar",
+            FileKind::Png =>
+                "png",
+            FileKind::Jpeg =>
+                "jpeg",
+            FileKind::Gif =>
+                "gif",
+            FileKind::Pdf =>
+                "pdf",
+            FileKind::Sqlite =>
+                "sqlite",
+            FileKind::Wasm =>
+                "wasm",
+        }
+    }
+
+    /// Has a structure view (`S`), instead of only templates.
+    pub fn has_parser(self) -> bool {
+        matches!(self, FileKind::Elf | FileKind::Pe | FileKind::MachO)
+    }
+}
+
+pub fn detect(data: &[u8]) -> Option<FileKind> {
+    // Mach-O has several magic numbers, for each word size and endianness
+    if is_macho(data) {
+        return Some(FileKind::MachO);
+    }
+    for &(magic, kind) in &MAGIC {
+        if data.starts_with(magic) {
+            return Some(kind);
+        }
+    }
+    if data.len() >= TAR_MAGIC_OFFSET + 5
+        && &data[TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + 5] == b"ustar"
+    {
+        return Some(FileKind::Tar);
+    }
+    None
+}
+
+/// A file found inside the buffer by `scan`.
+pub struct Embedded {
+    pub offset: usize,
+    pub kind: &'static str,
+    /// Details read from the header, e.g. the file name of a ZIP entry.
+    pub info: String,
+}
+
+/// Search the whole buffer for signatures of files that are often embedded
+/// in firmware images. Stops after `max` hits, the flag tells if it stopped.
+/// zlib has a two-byte signature, so random data has some false positives.
+pub fn scan(data: &[u8], max: usize) -> (Vec<Embedded>, bool) {
+    let mut ret = Vec::new();
+    for offset in 0..data.len() {
+        if let Some((kind, info)) = signature_at(&data[offset..]) {
+            if ret.len() == max {
+                return (ret, true);
+            }
+            ret.push(Embedded { offset, kind, info });
+        }
+    }
+    (ret, false)
+}
+
+fn signature_at(data: &[u8]) -> Option<(&'static str, String)> {
+    let byte = |idx: usize| data.get(idx).cloned().unwrap_or(0);
+    let u16_le = |idx: usize| u16::from(byte(idx)) | (u16::from(byte(idx + 1)) << 8);
+    match byte(0) {
+        // Only deflate (8), reserved flags not set
+        0x1f if data.starts_with(b"\x1f\x8b\x08") && byte(3) & 0xe0 == 0 =>
+            Some(("gzip", String::new())),
+        // Deflate with a 32K window, the header checksum holds for these, and
+        // the first block is not of the invalid type 3
+        0x78 if [0x01, 0x5e, 0x9c, 0xda].contains(&byte(1))
+            && data.len() > 2
+            && (byte(2) >> 1) & 3 != 3 =>
+            Some((
+                "zlib",
+                match byte(1) {
+                    0x01 =>
+                        "no compression",
+                    0x5e =>
+                        "fast",
+                    0x9c =>
+                        "default",
+                    _ =>
+                        "best",
+                }.to_owned(),
+            )),
+        0x89 if data.starts_with(b"\x89PNG\r\n\x1a\n") =>
+            Some(("PNG", String::new())),
+        b'P' if data.starts_with(b"PK\x03\x04") => {
+            let name_len = u16_le(26) as usize;
+            let name = data.get(30..30 + name_len).unwrap_or(&[]);
+            let name: String = String::from_utf8_lossy(name).chars().take(60).collect();
+            Some(("ZIP entry", name))
+        }
+        0x7f if data.starts_with(b"\x7fELF") && (byte(4) == 1 || byte(4) == 2)
+            && (byte(5) == 1 || byte(5) == 2) =>
+            Some((
+                "ELF",
+                format!(
+                    "{}-bit {} endian",
+                    if byte(4) == 1 { 32 } else { 64 },
+                    if byte(5) == 1 { "little" } else { "big" }
+                ),
+            )),
+        b'h' if data.starts_with(b"hsqs") && data.len() >= 32 =>
+            Some(("squashfs", format!("v{}.{}, little endian", u16_le(28), u16_le(30)))),
+        b's' if data.starts_with(b"sqsh") && data.len() >= 32 => {
+            let u16_be = |idx: usize| (u16::from(byte(idx)) << 8) | u16::from(byte(idx + 1));
+            Some(("squashfs", format!("v{}.{}, big endian", u16_be(28), u16_be(30))))
+        }
+        0xfd if data.starts_with(b"\xfd7zXZ\x00") =>
+            Some(("xz", String::new())),
+        _ =>
+            None,
+    }
+}
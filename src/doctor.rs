@@ -0,0 +1,383 @@
+////////////////////////////////////////////////////////////////////////////////
+// `rhex --doctor`: check the terminal and the configuration, and print a
+// report, e.g. to attach to bug reports about drawing or keys.
+//
+// The terminal is asked directly (through /dev/tty, in raw mode): DECRQM for
+// the mouse modes, OSC 4 for the 256-color palette, and the cursor position
+// after printing characters for their widths. Terminals that don't answer a
+// query are reported as unknown after a short timeout.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::env;
+use std::ffi::CStr;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+
+use libc;
+
+use colors::ByteColors;
+use config::Config;
+use gui::hex::glyphs;
+use gui::hex::{is_builtin, split_commands, unknown_status_fields};
+
+/// How long to wait for the terminal to answer a query.
+const QUERY_TIMEOUT_MS: i32 = 300;
+
+/// Characters rhex draws that terminals disagree on, and where they're used.
+const WIDTH_SAMPLES: [(char, &str); 4] = [
+    ('│', "borders"),
+    ('·', "bit mode and UTF-8 continuation bytes"),
+    ('␀', "--nonprint pictures"),
+    ('あ', "wide characters with --encoding utf8 or sjis"),
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Level {
+    Ok,
+    Warn,
+    Error,
+}
+
+struct Report {
+    lines: Vec<(Level, String)>,
+}
+
+impl Report {
+    fn add(&mut self, level: Level, msg: String) {
+        self.lines.push((level, msg));
+    }
+
+    fn print(&self, title: &str) {
+        println!("{}", title);
+        for &(level, ref msg) in &self.lines {
+            let level = match level {
+                Level::Ok =>
+                    "ok   ",
+                Level::Warn =>
+                    "warn ",
+                Level::Error =>
+                    "error",
+            };
+            println!("  {} {}", level, msg);
+        }
+    }
+}
+
+/// Run the checks and print the report. `aliases`, `byte_colors`: results of
+/// reading the aliases and colors files. Returns false if something is
+/// broken.
+pub fn run(
+    aliases: &Result<Vec<(String, String)>, String>,
+    byte_colors: &Result<ByteColors, String>,
+    config: &Config,
+) -> bool {
+    // The terminal is probed before printing anything, so that the answers
+    // are not mixed with the report
+    let terminal = check_terminal();
+    let config_report = check_config(aliases, byte_colors, config);
+
+    terminal.print("Terminal");
+    println!();
+    config_report.print("Configuration");
+
+    let failed = terminal
+        .lines
+        .iter()
+        .chain(config_report.lines.iter())
+        .any(|&(level, _)| level == Level::Error);
+    !failed
+}
+
+fn check_terminal() -> Report {
+    let mut report = Report { lines: Vec::new() };
+
+    match env::var("TERM") {
+        Ok(ref term) if term != "dumb" =>
+            report.add(Level::Ok, format!("TERM={}", term)),
+        Ok(term) =>
+            report.add(Level::Error, format!("TERM={}, rhex needs cursor movement", term)),
+        Err(_) =>
+            report.add(Level::Error, "TERM is not set".to_owned()),
+    }
+
+    unsafe {
+        libc::setlocale(libc::LC_CTYPE, b"\0".as_ptr() as *const libc::c_char);
+    }
+    let codeset = unsafe { CStr::from_ptr(libc::nl_langinfo(libc::CODESET)) }
+        .to_string_lossy()
+        .into_owned();
+    if codeset == "UTF-8" {
+        report.add(Level::Ok, "Locale is UTF-8".to_owned());
+    } else {
+        report.add(
+            Level::Warn,
+            format!(
+                "Locale character set is {}, not UTF-8: widths of non-ASCII characters are \
+                 unknown, set LANG or LC_ALL (e.g. en_US.UTF-8)",
+                codeset
+            ),
+        );
+    }
+
+    let mut tty = match RawTty::open() {
+        Ok(tty) =>
+            tty,
+        Err(err) => {
+            report.add(Level::Error, format!("Can't use /dev/tty: {}", err));
+            return report;
+        }
+    };
+
+    if tty.erase == 0x08 {
+        report.add(
+            Level::Warn,
+            "Backspace sends Ctrl-h (stty erase ^H), it's read as Ctrl-h, not as Backspace"
+                .to_owned(),
+        );
+    }
+    if env::var_os("TMUX").is_some() {
+        report.add(
+            Level::Warn,
+            "In tmux: with a long escape-time Esc is slow, and Esc followed by a key is \
+             read as Alt-<key> (set -s escape-time 10)"
+                .to_owned(),
+        );
+    }
+
+    // 256 colors: the palette is used for the theme and the highlights
+    match tty.query(b"\x1b]4;255;?\x07", &[0x07, b'\\']) {
+        Some(_) =>
+            report.add(Level::Ok, "256 colors (the terminal reported color 255)".to_owned()),
+        None =>
+            if env::var("TERM").is_ok_and(|term| term.contains("256color"))
+                || env::var_os("COLORTERM").is_some()
+            {
+                report.add(
+                    Level::Ok,
+                    "256 colors (from TERM/COLORTERM, the terminal didn't report its palette)"
+                        .to_owned(),
+                );
+            } else {
+                report.add(
+                    Level::Warn,
+                    "The terminal may not have 256 colors, highlights and themes may look \
+                     wrong (try TERM=xterm-256color)"
+                        .to_owned(),
+                );
+            },
+    }
+
+    // Mouse: button events (1002) in the SGR format (1006), see `gui::mouse`
+    for &(mode, name) in &[(1002, "mouse button events"), (1006, "SGR mouse reports")] {
+        let request = format!("\x1b[?{}$p", mode);
+        match tty.query(request.as_bytes(), b"y").and_then(|answer| decrpm_value(&answer)) {
+            Some(1) | Some(2) | Some(3) | Some(4) =>
+                report.add(Level::Ok, format!("Mode {} ({}) is supported", mode, name)),
+            Some(_) =>
+                report.add(
+                    Level::Warn,
+                    format!("Mode {} ({}) is not supported, clicks won't work", mode, name),
+                ),
+            None =>
+                report.add(
+                    Level::Warn,
+                    format!(
+                        "Mode {} ({}) is unknown, the terminal didn't answer DECRQM",
+                        mode,
+                        name
+                    ),
+                ),
+        }
+    }
+
+    // Widths: print each character at the start of the line and ask where the
+    // cursor is
+    let mut widths = Vec::new();
+    for &(ch, used_for) in &WIDTH_SAMPLES {
+        let request = format!("\r{}\x1b[6n", ch);
+        let col = tty.query(request.as_bytes(), b"R").and_then(|answer| cursor_column(&answer));
+        widths.push((ch, used_for, col));
+    }
+    tty.write(b"\r\x1b[K");
+    for (ch, used_for, col) in widths {
+        let expected = glyphs::width(ch);
+        match col {
+            Some(col) if col - 1 == expected =>
+                report.add(Level::Ok, format!("'{}' takes {} column(s)", ch, expected)),
+            Some(col) =>
+                report.add(
+                    Level::Warn,
+                    format!(
+                        "'{}' takes {} column(s) in the terminal but {} in the locale: \
+                         {} are misaligned",
+                        ch,
+                        col - 1,
+                        expected,
+                        used_for
+                    ),
+                ),
+            None =>
+                report.add(
+                    Level::Warn,
+                    format!("Width of '{}' is unknown, the terminal didn't report it", ch),
+                ),
+        }
+    }
+
+    report
+}
+
+fn check_config(
+    aliases: &Result<Vec<(String, String)>, String>,
+    byte_colors: &Result<ByteColors, String>,
+    config: &Config,
+) -> Report {
+    let mut report = Report { lines: Vec::new() };
+
+    let aliases: &[(String, String)] = match *aliases {
+        Ok(ref aliases) => {
+            report.add(Level::Ok, format!("{} aliases", aliases.len()));
+            aliases
+        }
+        Err(ref err) => {
+            report.add(Level::Error, err.clone());
+            &[]
+        }
+    };
+
+    for (idx, (name, commands)) in aliases.iter().enumerate() {
+        if is_builtin(name) {
+            report.add(
+                Level::Warn,
+                format!("Alias {} has the name of a command, the command runs instead", name),
+            );
+        } else if aliases[..idx].iter().any(|(other, _)| other == name) {
+            report.add(
+                Level::Warn,
+                format!("Alias {} is defined more than once, the first one is used", name),
+            );
+        }
+        for cmd in split_commands(commands) {
+            let cmd = cmd.trim();
+            let cmd_name = cmd.split_whitespace().next().unwrap_or("");
+            let known = cmd_name.is_empty()
+                || cmd.starts_with("s/")
+                || cmd.starts_with("%s/")
+                || is_builtin(cmd_name)
+                || aliases.iter().any(|(alias, _)| alias == cmd_name);
+            if !known {
+                report.add(
+                    Level::Error,
+                    format!("Alias {} runs {}, which is not a command", name, cmd_name),
+                );
+            }
+        }
+    }
+
+    match *byte_colors {
+        Ok(ref byte_colors) =>
+            report.add(Level::Ok, format!("{} byte values with colors", byte_colors.len())),
+        Err(ref err) =>
+            report.add(Level::Error, err.clone()),
+    }
+
+    if let Some(ref format) = config.status_format {
+        for field in unknown_status_fields(format) {
+            report.add(
+                Level::Warn,
+                format!("--status: %{} is not a field, it's shown as is", field),
+            );
+        }
+    }
+
+    report
+}
+
+/// `Ps` of a DECRPM answer `ESC [ ? <mode> ; <Ps> $ y`.
+fn decrpm_value(answer: &[u8]) -> Option<u32> {
+    let answer = String::from_utf8_lossy(answer);
+    let start = answer.rfind(';')? + 1;
+    let end = answer.rfind('$')?;
+    answer.get(start..end)?.parse().ok()
+}
+
+/// Column (from 1) of a cursor position report `ESC [ <row> ; <col> R`.
+fn cursor_column(answer: &[u8]) -> Option<usize> {
+    let answer = String::from_utf8_lossy(answer);
+    let start = answer.rfind(';')? + 1;
+    let end = answer.rfind('R')?;
+    answer.get(start..end)?.parse().ok()
+}
+
+/// /dev/tty in raw mode, restored on drop.
+struct RawTty {
+    file: File,
+    saved: libc::termios,
+
+    /// Character the terminal sends for Backspace, as set with `stty erase`.
+    erase: u8,
+}
+
+impl RawTty {
+    fn open() -> io::Result<RawTty> {
+        let file = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        let fd = file.as_raw_fd();
+        let mut saved: libc::termios = unsafe { mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut saved) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = saved;
+        unsafe { libc::cfmakeraw(&mut raw) };
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(RawTty {
+            file,
+            saved,
+            erase: saved.c_cc[libc::VERASE],
+        })
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let _ = self.file.write_all(bytes);
+        let _ = self.file.flush();
+    }
+
+    /// Send the request, and read the answer up to one of the `end` bytes.
+    /// `None` if the terminal doesn't answer in time.
+    fn query(&mut self, request: &[u8], end: &[u8]) -> Option<Vec<u8>> {
+        self.write(request);
+        let mut answer = Vec::new();
+        loop {
+            let mut pollfd = libc::pollfd {
+                fd: self.file.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            if unsafe { libc::poll(&mut pollfd, 1, QUERY_TIMEOUT_MS) } <= 0 {
+                return None;
+            }
+            let mut byte = [0];
+            match self.file.read(&mut byte) {
+                Ok(1) =>
+                    {}
+                _ =>
+                    return None,
+            }
+            answer.push(byte[0]);
+            if end.contains(&byte[0]) {
+                return Some(answer);
+            }
+        }
+    }
+}
+
+impl Drop for RawTty {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(self.file.as_raw_fd(), libc::TCSANOW, &self.saved);
+        }
+    }
+}
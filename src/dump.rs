@@ -0,0 +1,189 @@
+////////////////////////////////////////////////////////////////////////////////
+// `rhex --dump [--skip <n>] [--length <n>] <file>`: print the file as lines of
+// offsets, hex, and ASCII, as in the hex view, e.g. for `rhex --dump x | less`.
+// With `--plain` only hex digits are printed, like `xxd -p`. `:dump` writes
+// the same text for a part of the buffer.
+//
+// The file is read a chunk at a time, so dumping a part of a large image
+// doesn't read the rest of it. Output is flushed after every chunk, so pagers
+// show the first lines right away, and stops quietly when the pager exits.
+// Offsets and non-printable bytes are dimmed when stdout is a terminal.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cmp;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+use config::Config;
+use gui::hex::lines::{format_offset, prefix};
+use load::file_size;
+
+use libc;
+
+/// Bytes read (and lines printed) between flushes.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Bytes per line when `--cols` is not given.
+const DEFAULT_COLS: usize = 16;
+
+/// Bytes per line of plain dumps, as in `xxd -p`.
+const PLAIN_COLS: usize = 30;
+
+/// ANSI escapes for the dimmed parts of the output.
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Offsets, hex, and ASCII.
+    Lines,
+
+    /// Only hex digits (`--plain`).
+    Plain,
+}
+
+pub fn run(
+    path: &str,
+    config: &Config,
+    skip: usize,
+    length: Option<usize>,
+    format: Format,
+) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    let file_len = file_size(&mut file)? as usize;
+    let start = cmp::min(skip, file_len);
+    let end = match length {
+        Some(length) =>
+            cmp::min(start.saturating_add(length), file_len),
+        None =>
+            file_len,
+    };
+    file.seek(SeekFrom::Start(start as u64))?;
+
+    let stdout = io::stdout();
+    let out = BufWriter::with_capacity(CHUNK_SIZE, stdout.lock());
+    let reader = BufReader::with_capacity(CHUNK_SIZE, file).take((end - start) as u64);
+    let color = unsafe { libc::isatty(libc::STDOUT_FILENO) } == 1;
+
+    match dump(reader, out, config, start, end, format, color) {
+        // Pager exited before the end
+        Err(ref err) if err.kind() == io::ErrorKind::BrokenPipe =>
+            Ok(()),
+        ret =>
+            ret,
+    }
+}
+
+/// Dump the bytes of the reader, which are at `start..end` of the file.
+pub fn dump<R: Read, W: Write>(
+    mut reader: R,
+    mut out: W,
+    config: &Config,
+    start: usize,
+    end: usize,
+    format: Format,
+    color: bool,
+) -> io::Result<()> {
+    if format == Format::Plain {
+        return dump_plain(reader, out);
+    }
+
+    let cols = config.cols.map_or(DEFAULT_COLS, |cols| cols as usize);
+    let group_size = config.group_size as usize;
+    let base = config.base.unwrap_or(0);
+    let format = config.offset_format;
+    let last = base + end.saturating_sub(1);
+    let digits = format_offset(last, format, 0).len() - prefix(format).len();
+    let (dim, reset) = if color { (DIM, RESET) } else { ("", "") };
+
+    let mut row = vec![0; cols];
+    let mut line = String::new();
+    let mut offset = start;
+    let mut unflushed = 0;
+    loop {
+        let len = read_row(&mut reader, &mut row)?;
+        if len == 0 {
+            break;
+        }
+
+        line.clear();
+        line.push_str(dim);
+        line.push_str(&format_offset(base + offset, format, digits));
+        line.push_str(reset);
+        line.push('│');
+        for (col, byte) in row.iter().enumerate() {
+            if col != 0 && col % group_size == 0 {
+                line.push(' ');
+            }
+            if col < len {
+                line.push_str(&format!("{:02x}", byte));
+            } else {
+                line.push_str("  ");
+            }
+        }
+        line.push('│');
+        for &byte in &row[..len] {
+            if (32..=126).contains(&byte) {
+                line.push(byte as char);
+            } else {
+                line.push_str(dim);
+                line.push('.');
+                line.push_str(reset);
+            }
+        }
+        line.push('\n');
+        out.write_all(line.as_bytes())?;
+
+        offset += len;
+        unflushed += len;
+        if unflushed >= CHUNK_SIZE {
+            out.flush()?;
+            unflushed = 0;
+        }
+    }
+    out.flush()
+}
+
+fn dump_plain<R: Read, W: Write>(mut reader: R, mut out: W) -> io::Result<()> {
+    let mut row = [0; PLAIN_COLS];
+    let mut line = String::new();
+    let mut unflushed = 0;
+    loop {
+        let len = read_row(&mut reader, &mut row)?;
+        if len == 0 {
+            break;
+        }
+        line.clear();
+        for byte in &row[..len] {
+            line.push_str(&format!("{:02x}", byte));
+        }
+        line.push('\n');
+        out.write_all(line.as_bytes())?;
+
+        unflushed += len;
+        if unflushed >= CHUNK_SIZE {
+            out.flush()?;
+            unflushed = 0;
+        }
+    }
+    out.flush()
+}
+
+/// Fill the row from the reader. Returns fewer bytes than the row length only
+/// at the end of the input.
+fn read_row<R: Read>(reader: &mut R, row: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < row.len() {
+        match reader.read(&mut row[len..]) {
+            Ok(0) =>
+                break,
+            Ok(n) =>
+                len += n,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted =>
+                {}
+            Err(err) =>
+                return Err(err),
+        }
+    }
+    Ok(len)
+}
@@ -0,0 +1,269 @@
+////////////////////////////////////////////////////////////////////////////////
+// `rhex --find <pattern> [--recursive] <path>...`: search files for a byte
+// pattern (hex bytes or "text", as in `:search`) and print `path:offset` for
+// every match.
+//
+// Files are searched in parallel, a chunk at a time, so large images are not
+// read into memory. Results are printed in the order of the files, as they
+// are found.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+
+use libc;
+
+use config::Config;
+use gui::hex::lines::format_offset;
+use gui::hex::search::Pattern;
+use detect::{detect, FileKind};
+
+/// Bytes read at a time.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Only search files of this type, detected from the magic number.
+#[derive(Clone, Copy, PartialEq)]
+pub enum FileType {
+    Elf,
+    Pe,
+    MachO,
+}
+
+impl FileType {
+    pub fn parse(s: &str) -> Option<FileType> {
+        match s {
+            "elf" =>
+                Some(FileType::Elf),
+            "pe" =>
+                Some(FileType::Pe),
+            "macho" =>
+                Some(FileType::MachO),
+            _ =>
+                None,
+        }
+    }
+
+    fn matches(self, header: &[u8]) -> bool {
+        let kind = match self {
+            FileType::Elf =>
+                FileKind::Elf,
+            FileType::Pe =>
+                FileKind::Pe,
+            FileType::MachO =>
+                FileKind::MachO,
+        };
+        detect(header) == Some(kind)
+    }
+}
+
+pub struct FindOptions {
+    pub pattern: Vec<u8>,
+
+    /// Search files in directories, and their subdirectories.
+    pub recursive: bool,
+
+    /// Skip files smaller or larger than these.
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+
+    pub file_type: Option<FileType>,
+}
+
+/// Print matches to stdout. Returns the first match, or `None` when nothing
+/// is found.
+pub fn run(paths: &[PathBuf], opts: &FindOptions, config: &Config) -> Option<(String, usize)> {
+    let mut files = Vec::new();
+    for path in paths {
+        collect_files(path, opts, false, &mut files);
+    }
+    let files = Arc::new(files);
+
+    let pattern = Arc::new(Pattern::new(opts.pattern.clone(), false));
+    let next_file = Arc::new(AtomicUsize::new(0));
+    let (sender, receiver) = mpsc::channel();
+
+    for _ in 0..cmp::min(n_cpus(), files.len()) {
+        let files = files.clone();
+        let pattern = pattern.clone();
+        let next_file = next_file.clone();
+        let sender = sender.clone();
+        let file_type = opts.file_type;
+        let max_matches = config.max_matches;
+        thread::spawn(move || loop {
+            let idx = next_file.fetch_add(1, Ordering::SeqCst);
+            if idx >= files.len() {
+                break;
+            }
+            let matches = search_file(&files[idx], &pattern, file_type, max_matches);
+            if sender.send((idx, matches)).is_err() {
+                break;
+            }
+        });
+    }
+    drop(sender);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut first = None;
+
+    // Results that came before the results of an earlier file
+    let mut pending = BTreeMap::new();
+    let mut next_print = 0;
+    for (idx, matches) in receiver {
+        pending.insert(idx, matches);
+        while let Some(matches) = pending.remove(&next_print) {
+            let path = files[next_print].to_string_lossy();
+            match matches {
+                Ok(matches) =>
+                    for offset in matches {
+                        if first.is_none() {
+                            first = Some((path.clone().into_owned(), offset));
+                        }
+                        let _ = writeln!(
+                            out,
+                            "{}:{}",
+                            path,
+                            format_offset(offset, config.offset_format, 0)
+                        );
+                    },
+                Err(err) =>
+                    eprintln!("rhex: {}: {}", path, err),
+            }
+            next_print += 1;
+        }
+    }
+
+    first
+}
+
+fn n_cpus() -> usize {
+    let n = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_ONLN) };
+    if n < 1 {
+        1
+    } else {
+        n as usize
+    }
+}
+
+/// Add the file, or the files in the directory when searching recursively.
+/// Symlinks to directories in directories are not followed, to avoid loops.
+fn collect_files(path: &Path, opts: &FindOptions, in_dir: bool, files: &mut Vec<PathBuf>) {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) =>
+            metadata,
+        Err(err) => {
+            eprintln!("rhex: {}: {}", path.display(), err);
+            return;
+        }
+    };
+
+    if metadata.is_dir() {
+        if !opts.recursive {
+            eprintln!("rhex: {}: is a directory (use --recursive)", path.display());
+            return;
+        }
+        let is_symlink = fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink());
+        if in_dir && is_symlink {
+            return;
+        }
+        let mut entries: Vec<PathBuf> = match fs::read_dir(path) {
+            Ok(dir) =>
+                dir.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+            Err(err) => {
+                eprintln!("rhex: {}: {}", path.display(), err);
+                return;
+            }
+        };
+        entries.sort();
+        for entry in entries {
+            collect_files(&entry, opts, true, files);
+        }
+    } else if metadata.is_file() {
+        let size = metadata.len();
+        if opts.min_size.is_none_or(|min| size >= min)
+            && opts.max_size.is_none_or(|max| size <= max)
+        {
+            files.push(path.to_owned());
+        }
+    }
+}
+
+/// Offsets of the matches in the file, at most `max` of them. Files of other
+/// types than `file_type` have no matches.
+fn search_file(
+    path: &Path,
+    pattern: &Pattern,
+    file_type: Option<FileType>,
+    max: usize,
+) -> io::Result<Vec<usize>> {
+    let mut file = File::open(path)?;
+    let mut matches = Vec::new();
+
+    // The part of the file we search in: the last chunk read, after the last
+    // `pattern.len() - 1` bytes of the chunk before it, as a match can start
+    // there.
+    let mut window: Vec<u8> = Vec::with_capacity(CHUNK_SIZE + pattern.len());
+    // File offset of the window
+    let mut window_offset = 0;
+    // Where to continue searching in the window
+    let mut from = 0;
+    let mut chunk = vec![0; CHUNK_SIZE];
+
+    loop {
+        let n = read_chunk(&mut file, &mut chunk)?;
+        if n == 0 {
+            break;
+        }
+        if window_offset == 0 && window.is_empty() {
+            if let Some(file_type) = file_type {
+                if !file_type.matches(&chunk[..n]) {
+                    return Ok(matches);
+                }
+            }
+        }
+        window.extend_from_slice(&chunk[..n]);
+
+        while let Some(idx) = pattern.find_next(&window, from) {
+            if matches.len() == max {
+                return Ok(matches);
+            }
+            matches.push(window_offset + idx);
+            // Matches don't overlap, as in the search in the hex view
+            from = idx + pattern.len();
+        }
+
+        let keep = cmp::min(window.len(), pattern.len() - 1);
+        let drop = window.len() - keep;
+        window.drain(..drop);
+        window_offset += drop;
+        from = from.saturating_sub(drop);
+    }
+
+    Ok(matches)
+}
+
+/// Fill the chunk from the file. Returns fewer bytes than the chunk length
+/// only at the end of the file.
+fn read_chunk(file: &mut File, chunk: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < chunk.len() {
+        match file.read(&mut chunk[len..]) {
+            Ok(0) =>
+                break,
+            Ok(n) =>
+                len += n,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted =>
+                {}
+            Err(err) =>
+                return Err(err),
+        }
+    }
+    Ok(len)
+}
@@ -0,0 +1,198 @@
+////////////////////////////////////////////////////////////////////////////////
+// Generated byte patterns, for filling buffers (`:gen`)
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cmp;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Letters of cyclic patterns, as in pwntools' `cyclic`.
+pub const CYCLIC_ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+
+/// Length of unique subsequences in cyclic patterns, unless given.
+pub const CYCLIC_N: usize = 4;
+
+/// `cyclic_find` searches this many bytes of the pattern. The whole pattern
+/// is 26^n bytes, much longer than any buffer overflowed in practice when
+/// n > 4.
+const CYCLIC_FIND_MAX: usize = 16 * 1024 * 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Generator {
+    /// De Bruijn sequence: every `n` bytes long subsequence is unique, so the
+    /// offset of bytes seen e.g. in a crashed program's register can be found.
+    Cyclic { n: usize },
+
+    /// `start`, `start + 1`, ... as `width` bytes little endian integers.
+    Counter { start: u64, width: usize },
+
+    /// Pseudo-random bytes, the same for the same seed.
+    Random { seed: u64 },
+}
+
+impl Generator {
+    /// Parse a generator name and `<param>=<value>` arguments. Missing
+    /// parameters get defaults, the seed of random bytes is the current time.
+    pub fn parse(kind: &str, params: &[&str]) -> Result<Generator, String> {
+        let mut gen = match kind {
+            "cyclic" =>
+                Generator::Cyclic { n: CYCLIC_N },
+            "counter" =>
+                Generator::Counter { start: 0, width: 1 },
+            "random" =>
+                Generator::Random { seed: time_seed() },
+            _ =>
+                return Err(format!("Unknown pattern: {} (cyclic, counter, random)", kind)),
+        };
+
+        for param in params {
+            let (name, value) = match param.find('=') {
+                Some(idx) =>
+                    (&param[..idx], &param[idx + 1..]),
+                None =>
+                    return Err(format!("Expected <param>=<value>: {}", param)),
+            };
+            let value = parse_u64(value).ok_or_else(|| format!("Invalid value: {}", param))?;
+            gen = match (gen, name) {
+                (Generator::Cyclic { .. }, "n") if (1..=8).contains(&value) =>
+                    Generator::Cyclic { n: value as usize },
+                (Generator::Counter { width, .. }, "start") =>
+                    Generator::Counter { start: value, width },
+                (Generator::Counter { start, .. }, "width")
+                    if value == 1 || value == 2 || value == 4 || value == 8 =>
+                    Generator::Counter { start, width: value as usize },
+                (Generator::Random { .. }, "seed") =>
+                    Generator::Random { seed: value },
+                _ =>
+                    return Err(format!("Invalid parameter for {}: {}", kind, param)),
+            };
+        }
+
+        Ok(gen)
+    }
+
+    pub fn generate(&self, len: usize) -> Vec<u8> {
+        match *self {
+            Generator::Cyclic { n } =>
+                cyclic(n, len),
+            Generator::Counter { start, width } => {
+                let mut ret = Vec::with_capacity(len + width);
+                let mut value = start;
+                while ret.len() < len {
+                    for i in 0..width {
+                        ret.push((value >> (i * 8)) as u8);
+                    }
+                    value = value.wrapping_add(1);
+                }
+                ret.truncate(len);
+                ret
+            }
+            Generator::Random { seed } => {
+                // xorshift64*, state can't be 0
+                let mut state = if seed == 0 { 0x9e37_79b9_7f4a_7c15 } else { seed };
+                let mut ret = Vec::with_capacity(len + 8);
+                while ret.len() < len {
+                    state ^= state >> 12;
+                    state ^= state << 25;
+                    state ^= state >> 27;
+                    let value = state.wrapping_mul(0x2545_f491_4f6c_dd1d);
+                    for i in 0..8 {
+                        ret.push((value >> (i * 8)) as u8);
+                    }
+                }
+                ret.truncate(len);
+                ret
+            }
+        }
+    }
+}
+
+/// Parameters, in the format `parse` takes, so a fill can be repeated.
+impl fmt::Display for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Generator::Cyclic { n } =>
+                write!(f, "cyclic n={}", n),
+            Generator::Counter { start, width } =>
+                write!(f, "counter start={} width={}", start, width),
+            Generator::Random { seed } =>
+                write!(f, "random seed={}", seed),
+        }
+    }
+}
+
+/// First `len` bytes of the De Bruijn sequence of `CYCLIC_ALPHABET` with
+/// subsequences of length `n`. The sequence is repeated when it's shorter
+/// than `len`.
+pub fn cyclic(n: usize, len: usize) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(len);
+    if len == 0 {
+        return ret;
+    }
+    let mut a = vec![0; n + 1];
+    de_bruijn(1, 1, n, &mut a, &mut ret, len);
+    // Only short enough for n = 1 and 2 (26 and 676 bytes)
+    let period = ret.len();
+    while ret.len() < len {
+        let byte = ret[ret.len() - period];
+        ret.push(byte);
+    }
+    ret
+}
+
+/// Offset of the bytes in the cyclic pattern with subsequences of length
+/// `n`, as pwntools' `cyclic_find`. Only the first `n` bytes are looked up,
+/// as they're unique in the pattern.
+pub fn cyclic_find(bytes: &[u8], n: usize) -> Option<usize> {
+    if bytes.len() < n || !bytes[..n].iter().all(|b| CYCLIC_ALPHABET.contains(b)) {
+        return None;
+    }
+    // n is at most 8, this doesn't overflow
+    let len = (CYCLIC_ALPHABET.len() as u64).pow(n as u32) + n as u64 - 1;
+    let len = cmp::min(len, CYCLIC_FIND_MAX as u64) as usize;
+    cyclic(n, len).windows(n).position(|w| w == &bytes[..n])
+}
+
+/// The recursive algorithm from "Combinatorial Generation" (Ruskey), which
+/// concatenates Lyndon words in lexicographic order. Stops when `out` has
+/// `len` bytes, returns whether it did.
+fn de_bruijn(t: usize, p: usize, n: usize, a: &mut [usize], out: &mut Vec<u8>, len: usize) -> bool {
+    if t > n {
+        if n.is_multiple_of(p) {
+            for &idx in &a[1..p + 1] {
+                out.push(CYCLIC_ALPHABET[idx]);
+                if out.len() == len {
+                    return true;
+                }
+            }
+        }
+        return false;
+    }
+
+    a[t] = a[t - p];
+    if de_bruijn(t + 1, p, n, a, out, len) {
+        return true;
+    }
+    for j in a[t - p] + 1..CYCLIC_ALPHABET.len() {
+        a[t] = j;
+        if de_bruijn(t + 1, t, n, a, out, len) {
+            return true;
+        }
+    }
+    false
+}
+
+fn parse_u64(s: &str) -> Option<u64> {
+    if s.starts_with("0x") || s.starts_with("0X") {
+        u64::from_str_radix(&s[2..], 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+fn time_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() << 32 ^ u64::from(d.subsec_nanos()))
+        .unwrap_or(0)
+}
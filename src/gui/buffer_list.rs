@@ -0,0 +1,109 @@
+use std::cmp;
+
+use colors;
+use utils::*;
+
+use term_input::{Arrow, Key};
+use termbox_simple::*;
+
+/// Return value of the overlay. Returned by `keypressed()` method.
+pub enum BufferListRet {
+    /// User selected a buffer.
+    Select(usize),
+
+    /// User cancelled.
+    Abort,
+
+    /// Overlay still has focus.
+    Continue,
+}
+
+/// Lists open buffers, user picks one with j/k and Enter.
+pub struct BufferListOverlay {
+    pos_x: i32,
+    pos_y: i32,
+    width: i32,
+    height: i32,
+
+    paths: Vec<String>,
+    selected: usize,
+}
+
+impl BufferListOverlay {
+    pub fn new(
+        width: i32,
+        height: i32,
+        pos_x: i32,
+        pos_y: i32,
+        paths: Vec<String>,
+        selected: usize,
+    ) -> BufferListOverlay {
+        let width_ = cmp::min(width, 50);
+        let height_ = cmp::min(height, paths.len() as i32 + 2);
+
+        let pos_x = pos_x + (width - width_) / 2;
+        let pos_y = pos_y + (height - height_) / 2;
+
+        BufferListOverlay {
+            pos_x,
+            pos_y,
+            width: width_,
+            height: height_,
+            paths,
+            selected,
+        }
+    }
+
+    pub fn draw(&self, tb: &mut Termbox) {
+        draw_box(tb, self.pos_x, self.pos_y, self.width, self.height);
+
+        // Keep the selected buffer visible when there are more buffers than
+        // rows
+        let rows = (self.height - 2) as usize;
+        let first = if self.selected >= rows {
+            self.selected + 1 - rows
+        } else {
+            0
+        };
+
+        for (row, path) in self.paths.iter().enumerate().skip(first).take(rows) {
+            let style = if row == self.selected {
+                colors::palette().cursor_focus
+            } else {
+                colors::palette().default
+            };
+            let line = format!("{}: {}", row + 1, path);
+            let line: String = line.chars().take((self.width - 2) as usize).collect();
+            print(
+                tb,
+                self.pos_x + 1,
+                self.pos_y + 1 + (row - first) as i32,
+                style,
+                &line,
+            );
+        }
+    }
+
+    pub fn keypressed(&mut self, key: Key) -> BufferListRet {
+        match key {
+            Key::Arrow(Arrow::Up) | Key::Char('k') => {
+                if self.selected > 0 {
+                    self.selected -= 1;
+                }
+                BufferListRet::Continue
+            }
+            Key::Arrow(Arrow::Down) | Key::Char('j') => {
+                if self.selected + 1 < self.paths.len() {
+                    self.selected += 1;
+                }
+                BufferListRet::Continue
+            }
+            Key::Char('\r') =>
+                BufferListRet::Select(self.selected),
+            Key::Esc | Key::Char('q') =>
+                BufferListRet::Abort,
+            _ =>
+                BufferListRet::Continue,
+        }
+    }
+}
@@ -0,0 +1,221 @@
+////////////////////////////////////////////////////////////////////////////////
+// The event loop of the UI. `EventLoop::wait` blocks until something happens
+// and returns it as messages: terminal input and mouse events from stdin,
+// expired timers, and wake-ups from worker threads. Worker threads can't be
+// polled directly, they write to a pipe (see `Waker`) when they have
+// something for the UI.
+//
+// SIGTERM and SIGHUP (e.g. a closed ssh session) are reported as
+// `Msg::Terminate`, so that the main loop can save its state and restore the
+// terminal before exiting.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use gui::mouse::{self, InputEvent};
+
+use libc;
+use nix::fcntl::{O_CLOEXEC, O_NONBLOCK};
+use nix::poll::{poll, PollFd, POLLIN};
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+use nix::unistd::pipe2;
+use term_input::{Event, Input};
+
+/// Timers of the main loop. A timer fires once, at its deadline, or
+/// periodically.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Timer {
+    /// Next frame, when there's something to draw.
+    Frame,
+    /// Checking followed files, see `hex::follow`.
+    Follow,
+    /// Redrawing progress of background jobs.
+    Jobs,
+}
+
+const N_TIMERS: usize = 3;
+
+pub enum Msg {
+    Input(InputEvent),
+    Timer(Timer),
+    /// A worker thread called `Waker::wake`.
+    Wake,
+    /// Got SIGTERM or SIGHUP.
+    Terminate,
+}
+
+/// Set by the signal handler.
+static TERMINATE: AtomicBool = AtomicBool::new(false);
+
+/// Write end of the `Waker` pipe, for the signal handler to interrupt `poll`
+/// even when the signal arrives right before it.
+static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn terminate_handler(_: libc::c_int) {
+    TERMINATE.store(true, Ordering::SeqCst);
+    let fd = SIGNAL_PIPE.load(Ordering::SeqCst);
+    if fd != -1 {
+        // Only async-signal-safe functions here
+        unsafe {
+            libc::write(fd, [0u8].as_ptr() as *const libc::c_void, 1);
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct TimerState {
+    deadline: Option<Instant>,
+    /// Periodic timers are set again when they fire.
+    interval: Option<Duration>,
+}
+
+/// Wakes up the event loop from another thread.
+#[derive(Clone)]
+pub struct Waker {
+    pipe: Arc<File>,
+}
+
+impl Waker {
+    pub fn wake(&self) {
+        // The pipe is non-blocking. When it's full the loop will wake up
+        // anyway.
+        let _ = (&*self.pipe).write(&[0]);
+    }
+}
+
+pub struct EventLoop {
+    input: Input,
+    evs: Vec<Event>,
+    timers: [TimerState; N_TIMERS],
+    /// Read end of the `Waker` pipe.
+    wake_pipe: File,
+    waker: Waker,
+}
+
+impl EventLoop {
+    pub fn new() -> EventLoop {
+        let (read_fd, write_fd) = pipe2(O_NONBLOCK | O_CLOEXEC).unwrap();
+        let (wake_pipe, write_pipe) =
+            unsafe { (File::from_raw_fd(read_fd), File::from_raw_fd(write_fd)) };
+
+        SIGNAL_PIPE.store(write_fd, Ordering::SeqCst);
+        let action = SigAction::new(
+            SigHandler::Handler(terminate_handler),
+            SaFlags::empty(),
+            SigSet::empty(),
+        );
+        for &signal in &[Signal::SIGTERM, Signal::SIGHUP] {
+            unsafe {
+                let _ = sigaction(signal, &action);
+            }
+        }
+
+        EventLoop {
+            input: Input::new(),
+            evs: Vec::with_capacity(10),
+            timers: [TimerState {
+                deadline: None,
+                interval: None,
+            }; N_TIMERS],
+            wake_pipe,
+            waker: Waker {
+                pipe: Arc::new(write_pipe),
+            },
+        }
+    }
+
+    pub fn waker(&self) -> Waker {
+        self.waker.clone()
+    }
+
+    /// Fire the timer once at the deadline. `None` cancels it.
+    pub fn set_deadline(&mut self, timer: Timer, deadline: Option<Instant>) {
+        self.timers[timer as usize] = TimerState {
+            deadline,
+            interval: None,
+        };
+    }
+
+    /// Fire the timer every `interval`. `None` stops it. Setting the interval
+    /// of a running timer doesn't restart it.
+    pub fn set_interval(&mut self, timer: Timer, interval: Option<Duration>) {
+        let state = &mut self.timers[timer as usize];
+        if state.interval == interval && (interval.is_none() || state.deadline.is_some()) {
+            return;
+        }
+        *state = TimerState {
+            deadline: interval.map(|interval| Instant::now() + interval),
+            interval,
+        };
+    }
+
+    /// Wait for input, a timer, or a wake-up. Events that arrive together
+    /// (e.g. key repeats while drawing) are returned together, with the
+    /// time they were read.
+    pub fn wait(&mut self) -> (Vec<Msg>, Instant) {
+        let timeout = self.timers
+            .iter()
+            .filter_map(|timer| timer.deadline)
+            .min()
+            .map_or(-1, ms_until);
+
+        let mut fds = [
+            PollFd::new(libc::STDIN_FILENO, POLLIN),
+            PollFd::new(self.wake_pipe.as_raw_fd(), POLLIN),
+        ];
+        let _ = poll(&mut fds, timeout);
+
+        let mut msgs = Vec::new();
+
+        // Also reports resizes, which interrupt `poll`
+        self.input.read_input_events(&mut self.evs);
+        let now = Instant::now();
+        msgs.extend(mouse::decode(&mut self.evs).into_iter().map(Msg::Input));
+
+        if fds[1].revents().is_some_and(|events| events.contains(POLLIN)) {
+            let mut buf = [0; 64];
+            while let Ok(n) = (&self.wake_pipe).read(&mut buf) {
+                if n == 0 {
+                    break;
+                }
+            }
+            msgs.push(Msg::Wake);
+        }
+
+        if TERMINATE.swap(false, Ordering::SeqCst) {
+            msgs.push(Msg::Terminate);
+        }
+
+        for (idx, state) in self.timers.iter_mut().enumerate() {
+            match state.deadline {
+                Some(deadline) if deadline <= now => {
+                    state.deadline = state.interval.map(|interval| now + interval);
+                    msgs.push(Msg::Timer(TIMERS[idx]));
+                }
+                _ =>
+                    {}
+            }
+        }
+
+        (msgs, now)
+    }
+}
+
+/// Indexed by `Timer as usize`.
+const TIMERS: [Timer; N_TIMERS] = [Timer::Frame, Timer::Follow, Timer::Jobs];
+
+/// Milliseconds until the deadline, rounded up, as a `poll` timeout.
+fn ms_until(deadline: Instant) -> i32 {
+    let now = Instant::now();
+    if deadline > now {
+        let wait = deadline - now;
+        (wait.as_secs() * 1000 + u64::from(wait.subsec_millis()) + 1) as i32
+    } else {
+        0
+    }
+}
@@ -1,110 +1,159 @@
-use std::cmp;
-
+use buffer::Buffer;
 use colors;
+use config::{Encoding, NonPrintable};
+use gui::hex::encoding::{self, Cell};
+use gui::hex::glyphs;
+use gui::hex::highlights::ByteHighlights;
+use gui::hex::squeeze::Row;
+use utils::{in_ranges, range_index};
 
 use termbox_simple::*;
 
-pub struct AsciiView<'view> {
+pub struct AsciiView {
     pos_x: i32,
     pos_y: i32,
     width: i32,
     height: i32,
 
-    data: &'view [u8],
-
-    cursor_x: i32,
-    cursor_y: i32,
-    scroll: i32,
+    cursor_x: usize,
+    cursor_y: usize,
 
     has_focus: bool,
+
+    encoding: Encoding,
+    non_printable: NonPrintable,
 }
 
-impl<'view> AsciiView<'view> {
+impl AsciiView {
     pub fn new(
         width: i32,
         height: i32,
         pos_x: i32,
         pos_y: i32,
-        data: &'view [u8],
-    ) -> AsciiView<'view> {
+        encoding: Encoding,
+        non_printable: NonPrintable,
+    ) -> AsciiView {
         AsciiView {
             width: width,
             height: height,
             pos_x: pos_x,
             pos_y: pos_y,
-            data: data,
             cursor_x: 0,
             cursor_y: 0,
-            scroll: 0,
             has_focus: false,
+            encoding,
+            non_printable,
         }
     }
 
-    pub fn set_scroll(&mut self, scroll: i32) {
-        self.scroll = scroll;
-    }
-
-    pub fn draw(&self, tb: &mut Termbox, hl: &[usize], hl_len: usize) {
-        let rows = self.height;
-        let cols = self.width;
-
-        let mut hl_idx = 0;
-
-        'outer: for row in self.scroll..self.scroll + rows {
+    /// `rows`: rows shown in the hex grid.
+    pub fn draw(
+        &self,
+        tb: &mut Termbox,
+        rows: &[Row],
+        data: &Buffer,
+        highlights: &ByteHighlights,
+    ) {
+        let ByteHighlights {
+            hl,
+            same_byte,
+            selection,
+            virtual_ranges,
+            template_ranges,
+            reference,
+            byte_colors,
+            ..
+        } = *highlights;
+        let cols = self.width as usize;
+
+        for (row_idx, row) in rows.iter().enumerate().take(self.height as usize) {
+            let line = match *row {
+                Row::Line(line) =>
+                    line,
+                Row::Squeezed =>
+                    continue,
+            };
             for col in 0..cols {
-                let byte_idx = (row * cols + col) as usize;
-                if let Some(&byte) = self.data.get(byte_idx) {
-                    let ch = if byte >= 32 && byte <= 126 {
-                        byte
-                    } else {
-                        b'.'
+                let byte_idx = line * cols + col;
+                if let Some(byte) = data.get(byte_idx) {
+                    let cell =
+                        encoding::decode(data, byte_idx, byte, self.encoding, self.non_printable);
+                    let (ch, continuation) = match cell {
+                        // No room for the second cell of a wide character
+                        Cell::Char(ch) if col + 1 == cols && glyphs::width(ch) > 1 =>
+                            ('.', false),
+                        Cell::Char(ch) =>
+                            (ch, false),
+                        Cell::Continuation =>
+                            (encoding::CONTINUATION, true),
                     };
 
-                    while hl_idx < hl.len() && hl[hl_idx] + hl_len < byte_idx {
-                        hl_idx += 1;
-                    }
-
-                    let style = if self.cursor_x == col && self.cursor_y == row {
+                    let selected =
+                        selection.is_some_and(|(start, end)| byte_idx >= start && byte_idx < end);
+                    let style = if self.cursor_x == col && self.cursor_y == line {
                         if self.has_focus {
-                            colors::CURSOR_FOCUS
+                            colors::palette().cursor_focus
                         } else {
-                            colors::CURSOR_NO_FOCUS
+                            colors::palette().cursor_no_focus
                         }
-                    } else if let Some(&hl_offset) = hl.get(hl_idx) {
-                        if byte_idx >= hl_offset && byte_idx < hl_offset + hl_len {
-                            colors::HIGHLIGHT
+                    } else if selected {
+                        colors::palette().selection
+                    } else if hl.range_end(byte_idx).is_some() {
+                        colors::palette().highlight
+                    } else if reference.is_some_and(|r| r.get(byte_idx) != Some(&byte)) {
+                        colors::palette().diff
+                    } else if in_ranges(virtual_ranges, byte_idx) {
+                        colors::palette().virtual_byte
+                    } else if let Some(idx) = range_index(template_ranges, byte_idx) {
+                        if idx % 2 == 0 {
+                            colors::palette().template_field
                         } else {
-                            colors::DEFAULT
+                            colors::palette().template_field_alt
                         }
+                    } else if continuation {
+                        colors::palette().virtual_byte
+                    } else if same_byte == Some(byte) {
+                        colors::palette().same_byte
+                    } else if let Some(style) = byte_colors.get(byte) {
+                        style
                     } else {
-                        colors::DEFAULT
+                        colors::palette().default
                     };
 
                     tb.change_cell(
-                        self.pos_x + col,
-                        self.pos_y + row - self.scroll,
-                        ch as char,
+                        self.pos_x + col as i32,
+                        self.pos_y + row_idx as i32,
+                        ch,
                         style.fg,
                         style.bg,
                     );
                 } else {
-                    break 'outer;
+                    break;
                 }
             }
         }
     }
 
-    pub fn move_cursor_offset(&mut self, byte_idx: i32) {
-        let cursor_y = byte_idx / self.width;
-        let cursor_x = byte_idx % self.width;
-
-        if cursor_y > self.scroll + self.height - 3 {
-            self.scroll = cursor_y - (self.height - 3);
-        } else if cursor_y < self.scroll + 2 {
-            self.scroll = cmp::max(cursor_y - 2, 0);
+    /// Byte at the given screen position, or `None` if it's not in the view.
+    /// Can be past the end of the buffer. `rows`: rows shown in the hex grid.
+    pub fn offset_at(&self, x: i32, y: i32, rows: &[Row]) -> Option<usize> {
+        let col = x - self.pos_x;
+        let row = y - self.pos_y;
+        if col < 0 || col >= self.width || row < 0 || row >= self.height {
+            return None;
+        }
+        match rows.get(row as usize) {
+            Some(&Row::Line(line)) =>
+                Some(line * self.width as usize + col as usize),
+            _ =>
+                None,
         }
+    }
 
-        self.cursor_y = cursor_y;
-        self.cursor_x = cursor_x;
+    /// Scrolling follows the hex grid, see `draw`.
+    pub fn move_cursor_offset(&mut self, byte_idx: usize) {
+        let width = self.width as usize;
+        self.cursor_y = byte_idx / width;
+        self.cursor_x = byte_idx % width;
     }
 }
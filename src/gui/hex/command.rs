@@ -0,0 +1,269 @@
+////////////////////////////////////////////////////////////////////////////////
+// vim-style command line (`:`), drawn in place of the info line
+////////////////////////////////////////////////////////////////////////////////
+
+use colors;
+use utils::*;
+
+use term_input::{Arrow, Key};
+use termbox_simple::*;
+
+/// Commands, for completion. `HexGui::run_command()` runs them.
+const COMMANDS: &[&str] = &[
+    "alias", "cyclic", "debug", "disas", "dump", "dump!", "elf", "export-patch", "export-patch!",
+    "gen", "got", "goto", "hash", "lock", "nohl", "off2va", "quit", "quit!", "read-dump",
+    "records", "report", "reverse", "saveas", "saveas!", "scan", "search", "set", "symbols",
+    "template", "theme", "unlock", "va2off", "verify", "wq", "write", "write!",
+];
+
+/// Other names of commands, not offered as completions.
+const OTHER_COMMANDS: &[&str] = &["nohlsearch", "q", "q!", "w", "w!"];
+
+/// Completions of command arguments.
+const ARGUMENTS: &[(&str, &[&str])] = &[
+    ("debug", &["open"]),
+    ("disas", &["aarch64", "arm", "x86", "x86_64"]),
+    ("dump", &["plain"]),
+    ("gen", &["counter", "cyclic", "random"]),
+    ("hash", &["md5", "sha256"]),
+    ("set", &["cols=", "group=", "max-matches=", "offsets=", "sector=", "squeeze=", "search-jump=", "search-center=", "nonprint=", "encoding=", "footer=", "bits=", "elf-regions=", "readonly="]),
+    ("theme", colors::THEMES),
+];
+
+/// Is this the name of a command (not an alias)? `s/`, `%s/` are not names.
+pub fn is_builtin(name: &str) -> bool {
+    COMMANDS.contains(&name) || OTHER_COMMANDS.contains(&name)
+}
+
+/// Split commands separated with ';'. ';'s in double quotes (e.g. in search
+/// patterns) don't separate commands.
+pub fn split_commands(input: &str) -> Vec<&str> {
+    let mut ret = Vec::new();
+    let mut in_quotes = false;
+    let mut start = 0;
+    for (idx, c) in input.char_indices() {
+        match c {
+            '"' =>
+                in_quotes = !in_quotes,
+            ';' if !in_quotes => {
+                ret.push(&input[start..idx]);
+                start = idx + 1;
+            }
+            _ =>
+                {}
+        }
+    }
+    ret.push(&input[start..]);
+    ret
+}
+
+pub enum CommandRet {
+    /// User submitted the command.
+    Run(String),
+
+    /// User cancelled.
+    Abort,
+
+    /// Command line still has focus.
+    Continue,
+}
+
+pub struct CommandLine {
+    pos_x: i32,
+    pos_y: i32,
+    width: i32,
+    input: String,
+
+    /// Earlier commands, oldest first. Up and Down go through the ones that
+    /// start with the input typed before.
+    history: Vec<String>,
+    history_idx: Option<usize>,
+    typed: String,
+
+    /// Completions of the word at the end of the input, and the one shown.
+    /// Tab shows the next one.
+    completions: Vec<&'static str>,
+    completion_idx: Option<usize>,
+}
+
+impl CommandLine {
+    pub fn new(width: i32, pos_x: i32, pos_y: i32, history: Vec<String>) -> CommandLine {
+        CommandLine {
+            pos_x,
+            pos_y,
+            width,
+            input: String::new(),
+            history,
+            history_idx: None,
+            typed: String::new(),
+            completions: Vec::new(),
+            completion_idx: None,
+        }
+    }
+
+    pub fn draw(&self, tb: &mut Termbox) {
+        let style = colors::palette().default;
+        for x in self.pos_x..=self.pos_x + self.width {
+            tb.change_cell(x, self.pos_y, ' ', style.fg, style.bg);
+        }
+
+        print(tb, self.pos_x, self.pos_y, colors::palette().default, ":");
+        print(tb, self.pos_x + 1, self.pos_y, colors::palette().default, &self.input);
+        let cursor_x = self.pos_x + 1 + self.input.chars().count() as i32;
+        tb.change_cell(
+            cursor_x,
+            self.pos_y,
+            ' ',
+            colors::palette().cursor_focus.fg,
+            colors::palette().cursor_focus.bg,
+        );
+
+        // Completions after the input, the current one highlighted
+        if self.completions.len() > 1 {
+            let mut x = cursor_x + 3;
+            for (idx, completion) in self.completions.iter().enumerate() {
+                if x + completion.len() as i32 > self.pos_x + self.width {
+                    break;
+                }
+                let style = if self.completion_idx == Some(idx) {
+                    colors::palette().cursor_no_focus
+                } else {
+                    colors::palette().default
+                };
+                print(tb, x, self.pos_y, style, completion);
+                x += completion.len() as i32 + 2;
+            }
+        }
+    }
+
+    pub fn keypressed(&mut self, key: Key) -> CommandRet {
+        if key != Key::Tab {
+            self.completions.clear();
+            self.completion_idx = None;
+        }
+        match key {
+            Key::Esc =>
+                CommandRet::Abort,
+            Key::Char('\r') =>
+                CommandRet::Run(self.input.clone()),
+            Key::Backspace => {
+                // Like vim, backspace on empty input leaves the command line
+                if self.input.pop().is_none() {
+                    CommandRet::Abort
+                } else {
+                    self.history_idx = None;
+                    CommandRet::Continue
+                }
+            }
+            Key::Arrow(Arrow::Up) => {
+                self.history_prev();
+                CommandRet::Continue
+            }
+            Key::Arrow(Arrow::Down) => {
+                self.history_next();
+                CommandRet::Continue
+            }
+            Key::Tab => {
+                self.complete();
+                CommandRet::Continue
+            }
+            Key::Char(ch) => {
+                self.input.push(ch);
+                self.history_idx = None;
+                CommandRet::Continue
+            }
+            _ =>
+                CommandRet::Continue,
+        }
+    }
+
+    /// Insert pasted text. Line breaks are dropped, so pasting doesn't run the
+    /// command.
+    pub fn paste(&mut self, text: &str) {
+        self.input.extend(text.chars().filter(|&c| c != '\r' && c != '\n'));
+        self.history_idx = None;
+        self.completions.clear();
+        self.completion_idx = None;
+    }
+
+    fn history_prev(&mut self) {
+        let end = match self.history_idx {
+            None => {
+                self.typed = self.input.clone();
+                self.history.len()
+            }
+            Some(idx) =>
+                idx,
+        };
+        let typed = &self.typed;
+        if let Some(idx) = self.history[..end].iter().rposition(|cmd| cmd.starts_with(typed)) {
+            self.history_idx = Some(idx);
+            self.input = self.history[idx].clone();
+        }
+    }
+
+    fn history_next(&mut self) {
+        let start = match self.history_idx {
+            None =>
+                return,
+            Some(idx) =>
+                idx + 1,
+        };
+        let typed = &self.typed;
+        match self.history[start..].iter().position(|cmd| cmd.starts_with(typed)) {
+            Some(idx) => {
+                self.history_idx = Some(start + idx);
+                self.input = self.history[start + idx].clone();
+            }
+            None => {
+                // Back to what was typed
+                self.history_idx = None;
+                self.input = self.typed.clone();
+            }
+        }
+    }
+
+    /// Complete the command name, or the argument at the end of the input.
+    fn complete(&mut self) {
+        // Start of the completed word
+        let word_start = match self.input.rfind(' ') {
+            Some(idx) =>
+                idx + 1,
+            None =>
+                0,
+        };
+        match self.completion_idx {
+            None => {
+                let word = &self.input[word_start..];
+                let candidates = if word_start == 0 {
+                    COMMANDS
+                } else {
+                    let cmd = self.input.split_whitespace().next().unwrap_or("");
+                    ARGUMENTS
+                        .iter()
+                        .find(|&&(name, _)| name == cmd)
+                        .map_or(&[][..], |&(_, args)| args)
+                };
+                self.completions =
+                    candidates.iter().cloned().filter(|c| c.starts_with(word)).collect();
+                if self.completions.is_empty() {
+                    return;
+                }
+                self.completion_idx = Some(0);
+            }
+            Some(idx) =>
+                self.completion_idx = Some((idx + 1) % self.completions.len()),
+        }
+        let completion = self.completions[self.completion_idx.unwrap()];
+        self.input.truncate(word_start);
+        self.input.push_str(completion);
+        // A single completion is done, continue with the arguments
+        if self.completions.len() == 1 {
+            if !completion.ends_with('=') {
+                self.input.push(' ');
+            }
+            self.completions.clear();
+            self.completion_idx = None;
+        }
+    }
+}
@@ -0,0 +1,61 @@
+////////////////////////////////////////////////////////////////////////////////
+// Comparing the buffer with a reference file (`--compare`). Differing bytes
+// are colored in the hex and ASCII views, `]d`/`[d` jump between runs of
+// differing bytes.
+////////////////////////////////////////////////////////////////////////////////
+
+/// Does the byte at `idx` differ from the reference? Bytes after the end of
+/// the reference differ.
+pub fn differs(data: &[u8], reference: &[u8], idx: usize) -> bool {
+    data.get(idx) != reference.get(idx)
+}
+
+/// Start of the first run of differing bytes after the one at the offset.
+/// `progress` is called with the number of bytes checked every `step` bytes,
+/// we give up (and return `None`) when it returns false.
+pub fn next_diff<P: FnMut(usize) -> bool>(
+    data: &[u8],
+    reference: &[u8],
+    offset: usize,
+    step: usize,
+    mut progress: P,
+) -> Option<Option<usize>> {
+    let len = data.len();
+    let mut idx = offset;
+    // Skip the run we're in
+    let mut in_run = true;
+    while idx < len {
+        if (idx - offset).is_multiple_of(step) && !progress(idx - offset) {
+            return None;
+        }
+        let differ = differs(data, reference, idx);
+        if differ && !in_run {
+            return Some(Some(idx));
+        }
+        in_run &= differ;
+        idx += 1;
+    }
+    Some(None)
+}
+
+/// Start of the last run of differing bytes that starts before the offset.
+/// See `next_diff` for `step` and `progress`.
+pub fn prev_diff<P: FnMut(usize) -> bool>(
+    data: &[u8],
+    reference: &[u8],
+    offset: usize,
+    step: usize,
+    mut progress: P,
+) -> Option<Option<usize>> {
+    let mut idx = offset;
+    while idx > 0 {
+        if (offset - idx).is_multiple_of(step) && !progress(offset - idx) {
+            return None;
+        }
+        idx -= 1;
+        if differs(data, reference, idx) && (idx == 0 || !differs(data, reference, idx - 1)) {
+            return Some(Some(idx));
+        }
+    }
+    Some(None)
+}
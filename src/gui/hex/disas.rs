@@ -0,0 +1,356 @@
+////////////////////////////////////////////////////////////////////////////////
+// Disassembly of the bytes around the cursor, shown in the panel (`d`)
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cell::Cell;
+use std::cmp;
+use std::collections::BTreeMap;
+
+use buffer::Buffer;
+use gui::hex::got;
+use utils::parse_number;
+use parser::elf::{Elf, EM_386, EM_AARCH64, EM_ARM, ET_REL, SHN_UNDEF, STT_FILE, STT_SECTION};
+use parser::macho::{is_macho, MachO};
+use parser::pe::Pe;
+
+use capstone::{self, Capstone, InsnGroupType, Mode, NO_EXTRA_MODE};
+
+/// Instructions are never longer than this, in any of the architectures.
+const MAX_INSN_LEN: usize = 15;
+
+/// Bytes shown before the instruction text. Longer instructions are cut.
+const MAX_SHOWN_BYTES: usize = 8;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Arch {
+    X86_64,
+    X86,
+    Arm,
+    AArch64,
+}
+
+impl Arch {
+    pub fn name(self) -> &'static str {
+        match self {
+            Arch::X86_64 =>
+                "x86_64",
+            Arch::X86 =>
+                "x86",
+            Arch::Arm =>
+                "arm",
+            Arch::AArch64 =>
+                "aarch64",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Arch> {
+        match name {
+            "x86_64" | "x86-64" | "amd64" =>
+                Some(Arch::X86_64),
+            "x86" | "i386" =>
+                Some(Arch::X86),
+            "arm" =>
+                Some(Arch::Arm),
+            "aarch64" | "arm64" =>
+                Some(Arch::AArch64),
+            _ =>
+                None,
+        }
+    }
+
+    /// Architecture of an ELF, Mach-O, or PE file, x86_64 for other files.
+    pub fn guess(data: &[u8]) -> Arch {
+        if let Ok(elf) = Elf::parse(data) {
+            return match elf.header.e_machine {
+                EM_386 =>
+                    Arch::X86,
+                EM_ARM =>
+                    Arch::Arm,
+                EM_AARCH64 =>
+                    Arch::AArch64,
+                _ =>
+                    Arch::X86_64,
+            };
+        }
+        if is_macho(data) {
+            if let Ok(macho) = MachO::parse(data) {
+                if macho.header.cpu_type == 0x0100_000c {
+                    return Arch::AArch64;
+                }
+            }
+        } else if data.starts_with(b"MZ") {
+            if let Ok(pe) = Pe::parse(data) {
+                match pe.coff_header.machine {
+                    0x14c =>
+                        return Arch::X86,
+                    0x1c0 =>
+                        return Arch::Arm,
+                    0xaa64 =>
+                        return Arch::AArch64,
+                    _ =>
+                        {}
+                }
+            }
+        }
+        Arch::X86_64
+    }
+
+    /// Instructions start at multiples of this.
+    fn alignment(self) -> usize {
+        match self {
+            Arch::X86_64 | Arch::X86 =>
+                1,
+            Arch::Arm | Arch::AArch64 =>
+                4,
+        }
+    }
+
+    fn capstone(self) -> capstone::CsResult<Capstone> {
+        let (arch, mode) = match self {
+            Arch::X86_64 =>
+                (capstone::Arch::X86, Mode::Mode64),
+            Arch::X86 =>
+                (capstone::Arch::X86, Mode::Mode32),
+            Arch::Arm =>
+                (capstone::Arch::ARM, Mode::Arm),
+            Arch::AArch64 =>
+                (capstone::Arch::ARM64, Mode::Arm),
+        };
+        let mut cs = Capstone::new_raw(arch, mode, NO_EXTRA_MODE, None)?;
+        // For the groups, to find jumps and calls
+        cs.set_detail(true)?;
+        Ok(cs)
+    }
+}
+
+/// The file parsed as ELF, for translating between file offsets and virtual
+/// addresses, and naming targets of jumps and calls.
+pub struct DisasElf {
+    pub elf: Elf,
+    /// Names and sizes of the symbols, by address.
+    symbols: BTreeMap<u64, (String, u64)>,
+    /// Functions called by the PLT stubs, by the address of the stub.
+    plt: BTreeMap<u64, String>,
+}
+
+impl DisasElf {
+    pub fn new(elf: Elf, data: &[u8]) -> DisasElf {
+        let mut symbols = BTreeMap::new();
+        // Values of symbols in object files are offsets in sections
+        if elf.header.e_type != ET_REL {
+            for sym in elf.symbols(data) {
+                if sym.name.is_empty() || sym.shndx == SHN_UNDEF || sym.value == 0
+                    || sym.ty == STT_SECTION || sym.ty == STT_FILE
+                {
+                    continue;
+                }
+                // `.symtab` has the local functions too, prefer its names
+                if sym.dynamic && symbols.contains_key(&sym.value) {
+                    continue;
+                }
+                symbols.insert(sym.value, (sym.name, sym.size));
+            }
+        }
+        let plt = got::plt_functions(&elf, data);
+        DisasElf { elf, symbols, plt }
+    }
+
+    /// Name of the address like objdump shows it: `<main>`, `<main+0x1c>`,
+    /// or `<puts@plt>`.
+    fn name(&self, addr: u64) -> Option<String> {
+        if let Some(name) = self.plt.get(&addr) {
+            return Some(format!("<{}@plt>", name));
+        }
+        let (&start, &(ref name, size)) = self.symbols.range(..=addr).next_back()?;
+        if addr == start {
+            Some(format!("<{}>", name))
+        } else if addr - start < size {
+            Some(format!("<{}+0x{:x}>", name, addr - start))
+        } else {
+            None
+        }
+    }
+}
+
+pub struct Instruction {
+    pub offset: usize,
+    pub bytes: Vec<u8>,
+    pub mnemonic: String,
+    pub operands: String,
+    /// Address of the jump or call, when it's an immediate operand.
+    pub target: Option<u64>,
+    /// Symbol of the target, e.g. `<puts@plt>`.
+    pub target_name: Option<String>,
+}
+
+impl Instruction {
+    fn covers(&self, offset: usize) -> bool {
+        offset >= self.offset && offset - self.offset < self.bytes.len()
+    }
+
+    fn end(&self) -> usize {
+        self.offset + self.bytes.len()
+    }
+
+    fn line(&self) -> String {
+        let bytes: Vec<String> = self.bytes
+            .iter()
+            .take(MAX_SHOWN_BYTES)
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        let mut line = format!(
+            "0x{:08x}  {:<w$}  {:<8} {}",
+            self.offset,
+            bytes.join(" "),
+            self.mnemonic,
+            self.operands,
+            w = MAX_SHOWN_BYTES * 3 - 1
+        );
+        if let Some(ref name) = self.target_name {
+            line.push(' ');
+            line.push_str(name);
+        }
+        line
+    }
+}
+
+/// Instructions in `code`, which starts at `start` in the buffer, while `more`
+/// returns true for the instructions so far. Bytes that don't decode are shown
+/// as `.byte`. Instructions of ELF files are decoded at their virtual
+/// addresses, so that targets of jumps and calls are virtual addresses too.
+fn disassemble<F>(
+    cs: &Capstone,
+    arch: Arch,
+    elf: Option<&DisasElf>,
+    code: &[u8],
+    start: usize,
+    more: F,
+) -> Vec<Instruction>
+where
+    F: Fn(&[Instruction]) -> bool,
+{
+    let mut ret = Vec::new();
+    let mut idx = 0;
+    while idx < code.len() && more(&ret) {
+        let offset = start + idx;
+        let addr = elf.and_then(|elf| elf.elf.offset_addr(offset)).unwrap_or(offset as u64);
+        let end = cmp::min(idx + MAX_INSN_LEN, code.len());
+        let insn = match cs.disasm_count(&code[idx..end], addr, 1) {
+            Ok(ref insns) if !insns.is_empty() => {
+                let insn = insns.iter().next().unwrap();
+                let operands = insn.op_str().unwrap_or("");
+                let branch = cs.insn_detail(&insn).ok().is_some_and(|detail| {
+                    detail.groups().any(|group| {
+                        group.0 == InsnGroupType::CS_GRP_JUMP as u8
+                            || group.0 == InsnGroupType::CS_GRP_CALL as u8
+                    })
+                });
+                let target = if branch { immediate_target(operands) } else { None };
+                Instruction {
+                    offset,
+                    bytes: insn.bytes().to_vec(),
+                    mnemonic: insn.mnemonic().unwrap_or("").to_owned(),
+                    operands: operands.to_owned(),
+                    target,
+                    target_name: match (elf, target) {
+                        (Some(elf), Some(target)) =>
+                            elf.name(target),
+                        _ =>
+                            None,
+                    },
+                }
+            }
+            _ => {
+                let bytes = &code[idx..cmp::min(idx + arch.alignment(), code.len())];
+                let operands: Vec<String> =
+                    bytes.iter().map(|byte| format!("0x{:02x}", byte)).collect();
+                Instruction {
+                    offset,
+                    bytes: bytes.to_vec(),
+                    mnemonic: ".byte".to_owned(),
+                    operands: operands.join(", "),
+                    target: None,
+                    target_name: None,
+                }
+            }
+        };
+        idx += insn.bytes.len();
+        ret.push(insn);
+    }
+    ret
+}
+
+/// Target of a jump or call with an immediate operand, e.g. `0x401000` in
+/// `call 0x401000` or `#0x1c0` in `cbz x0, #0x1c0`. Targets are the last
+/// operand.
+fn immediate_target(operands: &str) -> Option<u64> {
+    let last = operands.rsplit(", ").next()?;
+    let last = last.strip_prefix('#').unwrap_or(last);
+    parse_number(last).map(|target| target as u64)
+}
+
+/// Shown instructions, and the index of the instruction at the cursor.
+/// `start` is where the shown instructions start. It's kept while the cursor
+/// is in the shown instructions or a bit after them, so that moving the cursor
+/// scrolls the disassembly instead of decoding from the cursor again.
+pub fn instructions(
+    data: &Buffer,
+    cursor: usize,
+    rows: usize,
+    arch: Arch,
+    elf: Option<&DisasElf>,
+    start: &Cell<usize>,
+) -> capstone::CsResult<(Vec<Instruction>, Option<usize>)> {
+    let cs = arch.capstone()?;
+
+    let mut first = start.get();
+    if first > cursor || cursor - first > rows * MAX_INSN_LEN {
+        first = cursor - cursor % arch.alignment();
+    }
+
+    // Instructions up to the cursor, then enough to fill the pane
+    let end = cmp::min(cursor + (rows + 1) * MAX_INSN_LEN, data.len());
+    let code: Vec<u8> = (first..end).filter_map(|offset| data.get(offset)).collect();
+    let mut insns = disassemble(&cs, arch, elf, &code, first, |insns| match insns.last() {
+        None =>
+            true,
+        Some(last) =>
+            last.end() <= cursor || insns.len() < rows,
+    });
+
+    let current = insns.iter().position(|insn| insn.covers(cursor));
+    let skip = match current {
+        Some(idx) if idx >= rows =>
+            idx + 1 - rows,
+        _ =>
+            0,
+    };
+    if let Some(insn) = insns.get(skip) {
+        start.set(insn.offset);
+    }
+
+    insns.drain(..skip);
+    insns.truncate(rows);
+    Ok((insns, current.map(|idx| idx - skip)))
+}
+
+/// Lines of the disassembly pane, and the row of the instruction at the
+/// cursor.
+pub fn lines(
+    data: &Buffer,
+    cursor: usize,
+    rows: usize,
+    arch: Arch,
+    elf: Option<&DisasElf>,
+    start: &Cell<usize>,
+) -> (Vec<String>, Option<usize>) {
+    if data.is_empty() {
+        return (vec!["Empty buffer".to_owned()], None);
+    }
+    match instructions(data, cursor, rows, arch, elf, start) {
+        Ok((insns, current)) =>
+            (insns.iter().map(|insn| insn.line()).collect(), current),
+        Err(err) =>
+            (vec![format!("Can't disassemble {}: {}", arch.name(), err)], None),
+    }
+}
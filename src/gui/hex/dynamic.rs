@@ -0,0 +1,134 @@
+use std::cmp;
+
+use colors;
+use parser::elf::{Dyn, DynValue};
+use utils::*;
+
+use term_input::{Arrow, Key};
+use termbox_simple::*;
+
+pub enum DynamicRet {
+    /// Jump to the byte offset.
+    Jump(usize),
+
+    /// User cancelled.
+    Abort,
+
+    /// Overlay still has focus.
+    Continue,
+}
+
+/// Shows the `.dynamic` section of an ELF file, like `readelf -d`: needed
+/// libraries, rpath, soname, init/fini functions etc. Enter jumps to the
+/// selected address, when it's in the file.
+pub struct DynamicOverlay {
+    pos_x: i32,
+    pos_y: i32,
+    width: i32,
+    height: i32,
+
+    /// Entries and file offsets of their addresses.
+    entries: Vec<(Dyn, Option<usize>)>,
+
+    selected: usize,
+}
+
+impl DynamicOverlay {
+    pub fn new(
+        width: i32,
+        height: i32,
+        pos_x: i32,
+        pos_y: i32,
+        entries: Vec<(Dyn, Option<usize>)>,
+    ) -> DynamicOverlay {
+        let width_ = cmp::min(width, 100);
+        let height_ = cmp::min(height, 30);
+
+        let pos_x = pos_x + (width - width_) / 2;
+        let pos_y = pos_y + (height - height_) / 2;
+
+        DynamicOverlay {
+            pos_x,
+            pos_y,
+            width: width_,
+            height: height_,
+            entries,
+            selected: 0,
+        }
+    }
+
+    pub fn draw(&self, tb: &mut Termbox) {
+        draw_box(tb, self.pos_x, self.pos_y, self.width, self.height);
+
+        let title = format!(" Dynamic section ({} entries) ", self.entries.len());
+        print(tb, self.pos_x + 2, self.pos_y, colors::palette().default, &title);
+
+        let rows = (self.height - 4) as usize;
+        let first = if self.selected >= rows {
+            self.selected + 1 - rows
+        } else {
+            0
+        };
+
+        for (row, &(ref entry, offset)) in self.entries.iter().enumerate().skip(first).take(rows) {
+            let style = if row == self.selected {
+                colors::palette().cursor_focus
+            } else {
+                colors::palette().default
+            };
+            let tag = match entry.tag_str() {
+                Some(name) =>
+                    name.to_owned(),
+                None =>
+                    format!("0x{:x}", entry.tag),
+            };
+            let value = match entry.value {
+                DynValue::Str(ref name) =>
+                    name.clone(),
+                DynValue::Addr(addr) =>
+                    format!("0x{:x}{}", addr, if offset.is_some() { "  ->" } else { "" }),
+                DynValue::Num(n) =>
+                    format!("{}", n),
+            };
+            let line = format!("{:<16} {}", tag, value);
+            let line: String = line.chars().take((self.width - 2) as usize).collect();
+            print(tb, self.pos_x + 1, self.pos_y + 1 + (row - first) as i32, style, &line);
+        }
+
+        print(
+            tb,
+            self.pos_x + 1,
+            self.pos_y + self.height - 2,
+            colors::palette().default,
+            "Enter: jump to address",
+        );
+    }
+
+    pub fn keypressed(&mut self, key: Key) -> DynamicRet {
+        match key {
+            Key::Arrow(Arrow::Up) | Key::Char('k') => {
+                if self.selected > 0 {
+                    self.selected -= 1;
+                }
+                DynamicRet::Continue
+            }
+            Key::Arrow(Arrow::Down) | Key::Char('j') => {
+                if self.selected + 1 < self.entries.len() {
+                    self.selected += 1;
+                }
+                DynamicRet::Continue
+            }
+            Key::Char('\r') =>
+                match self.entries.get(self.selected).and_then(|&(_, offset)| offset) {
+                    Some(offset) =>
+                        DynamicRet::Jump(offset),
+                    None =>
+                        DynamicRet::Continue,
+                },
+            Key::Esc | Key::Char('q') =>
+                DynamicRet::Abort,
+            _ =>
+                DynamicRet::Continue,
+        }
+    }
+}
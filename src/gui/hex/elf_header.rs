@@ -0,0 +1,204 @@
+////////////////////////////////////////////////////////////////////////////////
+// ELF header editor (`:elf`). Fields are edited by value, the overlay encodes
+// them in the byte order of the file.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cmp;
+
+use colors;
+use parser::elf::HeaderField;
+use utils::*;
+
+use term_input::{Arrow, Key};
+use termbox_simple::*;
+
+pub enum ElfHeaderRet {
+    /// Write the bytes at the offset, the new value of a field.
+    Write { offset: usize, bytes: Vec<u8> },
+
+    /// Jump to the byte offset of the field.
+    Jump(usize),
+
+    /// User cancelled.
+    Abort,
+
+    /// Overlay still has focus.
+    Continue,
+}
+
+pub struct ElfHeaderOverlay {
+    pos_x: i32,
+    pos_y: i32,
+    width: i32,
+    height: i32,
+
+    fields: Vec<HeaderField>,
+    selected: usize,
+
+    /// New value of the selected field, while editing.
+    input: Option<String>,
+
+    /// Why the last value was rejected, shown until the next key.
+    error: Option<String>,
+}
+
+impl ElfHeaderOverlay {
+    pub fn new(
+        width: i32,
+        height: i32,
+        pos_x: i32,
+        pos_y: i32,
+        fields: Vec<HeaderField>,
+    ) -> ElfHeaderOverlay {
+        let width_ = cmp::min(width, 70);
+        let height_ = cmp::min(height, fields.len() as i32 + 5);
+
+        let pos_x = pos_x + (width - width_) / 2;
+        let pos_y = pos_y + (height - height_) / 2;
+
+        ElfHeaderOverlay {
+            pos_x,
+            pos_y,
+            width: width_,
+            height: height_,
+            fields,
+            selected: 0,
+            input: None,
+            error: None,
+        }
+    }
+
+    /// Show the values after a write.
+    pub fn set_fields(&mut self, fields: Vec<HeaderField>) {
+        self.selected = cmp::min(self.selected, fields.len().saturating_sub(1));
+        self.fields = fields;
+    }
+
+    pub fn draw(&self, tb: &mut Termbox) {
+        draw_box(tb, self.pos_x, self.pos_y, self.width, self.height);
+
+        print(tb, self.pos_x + 2, self.pos_y, colors::palette().default, " ELF header ");
+
+        let rows = (self.height - 4) as usize;
+        let first = if self.selected >= rows {
+            self.selected + 1 - rows
+        } else {
+            0
+        };
+
+        for (idx, field) in self.fields.iter().enumerate().skip(first).take(rows) {
+            let style = if idx == self.selected {
+                colors::palette().cursor_focus
+            } else {
+                colors::palette().default
+            };
+            let value = match self.input {
+                Some(ref input) if idx == self.selected =>
+                    format!("> {}", input),
+                _ =>
+                    match field.value {
+                        Some(value) =>
+                            format!("0x{:0w$x}", value, w = field.size * 2),
+                        None =>
+                            "(truncated)".to_owned(),
+                    },
+            };
+            let line = format!("  {:<16} 0x{:04x}  {}", field.name, field.offset, value);
+            let line: String = line.chars().take((self.width - 2) as usize).collect();
+            print(tb, self.pos_x + 1, self.pos_y + 1 + (idx - first) as i32, style, &line);
+        }
+
+        let help = match self.error {
+            Some(ref error) =>
+                error.as_str(),
+            None if self.input.is_some() =>
+                "Enter: write, Esc: cancel",
+            None =>
+                "Enter, e: edit, g: jump to field, Esc: close",
+        };
+        print(tb, self.pos_x + 1, self.pos_y + self.height - 2, colors::palette().default, help);
+    }
+
+    pub fn keypressed(&mut self, key: Key) -> ElfHeaderRet {
+        self.error = None;
+        if self.input.is_some() {
+            return self.keypressed_input(key);
+        }
+        match key {
+            Key::Arrow(Arrow::Up) | Key::Char('k') => {
+                if self.selected > 0 {
+                    self.selected -= 1;
+                }
+                ElfHeaderRet::Continue
+            }
+            Key::Arrow(Arrow::Down) | Key::Char('j') => {
+                if self.selected + 1 < self.fields.len() {
+                    self.selected += 1;
+                }
+                ElfHeaderRet::Continue
+            }
+            Key::Char('\r') | Key::Char('e') => {
+                match self.fields.get(self.selected) {
+                    Some(&HeaderField { value: Some(_), .. }) =>
+                        self.input = Some(String::new()),
+                    Some(_) =>
+                        self.error = Some("Field is out of the file".to_owned()),
+                    None =>
+                        {}
+                }
+                ElfHeaderRet::Continue
+            }
+            Key::Char('g') =>
+                match self.fields.get(self.selected) {
+                    Some(field) =>
+                        ElfHeaderRet::Jump(field.offset),
+                    None =>
+                        ElfHeaderRet::Continue,
+                },
+            Key::Esc | Key::Char('q') =>
+                ElfHeaderRet::Abort,
+            _ =>
+                ElfHeaderRet::Continue,
+        }
+    }
+
+    fn keypressed_input(&mut self, key: Key) -> ElfHeaderRet {
+        match key {
+            Key::Esc =>
+                self.input = None,
+            Key::Backspace => {
+                if let Some(ref mut input) = self.input {
+                    input.pop();
+                }
+            }
+            Key::Char('\r') => {
+                let input = self.input.take().unwrap_or_default();
+                let field = &self.fields[self.selected];
+                match parse_number(&input) {
+                    Some(value) =>
+                        match field.encode(value as u64) {
+                            Ok(bytes) =>
+                                return ElfHeaderRet::Write {
+                                    offset: field.offset,
+                                    bytes,
+                                },
+                            Err(err) =>
+                                self.error = Some(err),
+                        },
+                    None =>
+                        self.error = Some(format!("Invalid number: {}", input)),
+                }
+                // Let the user fix the value
+                self.input = Some(input);
+            }
+            Key::Char(ch) if ch.is_ascii_hexdigit() || ch == 'x' => {
+                if let Some(ref mut input) = self.input {
+                    input.push(ch);
+                }
+            }
+            _ =>
+                {}
+        }
+        ElfHeaderRet::Continue
+    }
+}
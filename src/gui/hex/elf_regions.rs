@@ -0,0 +1,60 @@
+////////////////////////////////////////////////////////////////////////////////
+// Parts of ELF files colored in the hex view (`:set elf-regions=on|off`): the
+// ELF header, the program headers, the section header table, and the
+// sections. The info line shows the part under the cursor.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cmp;
+
+use parser::elf::{Elf, SectionType};
+
+pub struct ElfRegion {
+    pub start: usize,
+    /// Exclusive.
+    pub end: usize,
+    pub name: String,
+}
+
+/// Parts of the file, sorted and non-overlapping. When parts overlap (e.g. a
+/// section that contains the program headers) the one that starts first is
+/// kept. `len`: size of the file, parts are cut at the end of the file.
+pub fn regions(elf: &Elf, len: usize) -> Vec<ElfRegion> {
+    let mut regions = Vec::new();
+    let mut add = |start: u64, size: u64, name: String| {
+        let start = cmp::min(start, len as u64) as usize;
+        let end = cmp::min((start as u64).saturating_add(size), len as u64) as usize;
+        if end > start {
+            regions.push(ElfRegion { start, end, name });
+        }
+    };
+
+    add(0, if elf.is_64 { 64 } else { 52 }, "ELF header".to_owned());
+
+    let ph_size = if elf.is_64 { 56 } else { 32 };
+    for idx in 0..elf.program_headers.len() as u64 {
+        let start = elf.header.e_phoff.saturating_add(idx * ph_size);
+        add(start, ph_size, format!("program header {}", idx));
+    }
+
+    let sh_size = if elf.is_64 { 64 } else { 40 };
+    add(
+        elf.header.e_shoff,
+        u64::from(elf.header.e_shnum) * sh_size,
+        "section header table".to_owned(),
+    );
+
+    for sh in &elf.section_headers {
+        if sh.sh_type != SectionType::Nobits && sh.sh_type != SectionType::Null {
+            add(sh.sh_offset, sh.sh_size, format!("section {}", sh.name));
+        }
+    }
+
+    regions.sort_by_key(|region| region.start);
+    let mut ret: Vec<ElfRegion> = Vec::with_capacity(regions.len());
+    for region in regions {
+        if ret.last().is_none_or(|last| region.start >= last.end) {
+            ret.push(region);
+        }
+    }
+    ret
+}
@@ -0,0 +1,66 @@
+////////////////////////////////////////////////////////////////////////////////
+// Structure view of ELF files (`S`): the file header, program headers, and
+// section headers, like `readelf -h -l -S`.
+////////////////////////////////////////////////////////////////////////////////
+
+use parser::elf::{self, Elf, SectionType};
+
+use gui::hex::structure::{field, StructureRow};
+
+/// Rows of the structure view. Enter jumps to the selected header field or
+/// table entry.
+pub fn rows(elf: &Elf, data: &[u8]) -> Vec<StructureRow> {
+    let mut rows = Vec::new();
+
+    rows.push(StructureRow::Heading("File header".to_owned()));
+    for header_field in elf::header_fields(data).unwrap_or_default() {
+        let value = match header_field.value {
+            Some(value) =>
+                format!("0x{:x}", value),
+            None =>
+                "-".to_owned(),
+        };
+        rows.push(field(header_field.name, value, Some(header_field.offset)));
+    }
+
+    let ph_size = if elf.is_64 { 56 } else { 32 };
+    rows.push(StructureRow::Heading(format!(
+        "Program headers ({})",
+        elf.program_headers.len()
+    )));
+    for (idx, ph) in elf.program_headers.iter().enumerate() {
+        let value = format!(
+            "offset 0x{:x}, address 0x{:x}, file size 0x{:x}, memory size 0x{:x}",
+            ph.p_offset,
+            ph.p_vaddr,
+            ph.p_filesz,
+            ph.p_memsz
+        );
+        let offset = (elf.header.e_phoff as usize).saturating_add(idx * ph_size);
+        rows.push(field(&format!("[{}] {}", idx, ph.p_type.name()), value, Some(offset)));
+    }
+
+    let sh_size = if elf.is_64 { 64 } else { 40 };
+    rows.push(StructureRow::Heading(format!(
+        "Section headers ({})",
+        elf.section_headers.len()
+    )));
+    for (idx, sh) in elf.section_headers.iter().enumerate() {
+        let mut value = format!(
+            "{}, offset 0x{:x}, size 0x{:x}",
+            sh.sh_type.name(),
+            sh.sh_offset,
+            sh.sh_size
+        );
+        if sh.sh_addr != 0 {
+            value.push_str(&format!(", address 0x{:x}", sh.sh_addr));
+        }
+        if sh.sh_type == SectionType::Nobits {
+            value.push_str(", not in the file");
+        }
+        let offset = (elf.header.e_shoff as usize).saturating_add(idx * sh_size);
+        rows.push(field(&format!("[{}] {}", idx, sh.name), value, Some(offset)));
+    }
+
+    rows
+}
@@ -0,0 +1,383 @@
+////////////////////////////////////////////////////////////////////////////////
+// Decoding of bytes in the ASCII view (`--encoding`, `:set encoding=..`), for
+// files that are not ASCII text: UTF-8, UTF-16, Latin-1, EBCDIC (code page 37,
+// mainframe dumps), and Shift-JIS.
+//
+// A character of UTF-8 or UTF-16 is shown in the cell of its first byte (wide
+// characters take the next cell too), the cells of the other bytes are
+// shaded. Shift-JIS double-byte characters (kanji, full-width kana, ...) need
+// a table we don't have, their bytes are shown as `DOUBLE_BYTE`. The
+// single-byte half-width katakana are decoded.
+//
+// `guess` guesses the encoding of text files from the byte counts of the
+// beginning of the file (`--encoding auto` selects it, otherwise it's
+// suggested in the info line).
+////////////////////////////////////////////////////////////////////////////////
+
+use std::char;
+use std::cmp;
+use std::str;
+
+use buffer::Buffer;
+use config::{Encoding, NonPrintable};
+use gui::hex::entropy::byte_counts;
+use gui::hex::glyphs::{self, char_glyph, glyph};
+
+/// Shown for both bytes of a Shift-JIS double-byte character.
+const DOUBLE_BYTE: char = '▒';
+
+/// Shift-JIS lead bytes are found by looking back at most this many bytes.
+const MAX_LOOK_BACK: usize = 4096;
+
+/// Encoding is guessed from this many bytes at the beginning of the file.
+const GUESS_SAMPLE: usize = 1024 * 1024;
+
+/// Files with less text than this (in the guessed encoding) are binary.
+const MIN_TEXT_RATIO: f32 = 0.75;
+
+/// Shown for bytes after the first byte of a UTF-8 or UTF-16 character.
+pub const CONTINUATION: char = '·';
+
+/// A guessed encoding.
+pub struct Guess {
+    pub name: &'static str,
+    pub encoding: Encoding,
+}
+
+/// What the ASCII view shows in the cell of a byte.
+pub enum Cell {
+    /// Character starting at the byte. Wide characters are only returned for
+    /// characters of two or more bytes.
+    Char(char),
+
+    /// A byte after the first byte of a character, shown shaded.
+    Continuation,
+}
+
+
+/// Unicode code points of EBCDIC code page 37.
+const CP037: [u16; 256] = [
+    0x0000, 0x0001, 0x0002, 0x0003, 0x009C, 0x0009, 0x0086, 0x007F,
+    0x0097, 0x008D, 0x008E, 0x000B, 0x000C, 0x000D, 0x000E, 0x000F,
+    0x0010, 0x0011, 0x0012, 0x0013, 0x009D, 0x0085, 0x0008, 0x0087,
+    0x0018, 0x0019, 0x0092, 0x008F, 0x001C, 0x001D, 0x001E, 0x001F,
+    0x0080, 0x0081, 0x0082, 0x0083, 0x0084, 0x000A, 0x0017, 0x001B,
+    0x0088, 0x0089, 0x008A, 0x008B, 0x008C, 0x0005, 0x0006, 0x0007,
+    0x0090, 0x0091, 0x0016, 0x0093, 0x0094, 0x0095, 0x0096, 0x0004,
+    0x0098, 0x0099, 0x009A, 0x009B, 0x0014, 0x0015, 0x009E, 0x001A,
+    0x0020, 0x00A0, 0x00E2, 0x00E4, 0x00E0, 0x00E1, 0x00E3, 0x00E5,
+    0x00E7, 0x00F1, 0x00A2, 0x002E, 0x003C, 0x0028, 0x002B, 0x007C,
+    0x0026, 0x00E9, 0x00EA, 0x00EB, 0x00E8, 0x00ED, 0x00EE, 0x00EF,
+    0x00EC, 0x00DF, 0x0021, 0x0024, 0x002A, 0x0029, 0x003B, 0x00AC,
+    0x002D, 0x002F, 0x00C2, 0x00C4, 0x00C0, 0x00C1, 0x00C3, 0x00C5,
+    0x00C7, 0x00D1, 0x00A6, 0x002C, 0x0025, 0x005F, 0x003E, 0x003F,
+    0x00F8, 0x00C9, 0x00CA, 0x00CB, 0x00C8, 0x00CD, 0x00CE, 0x00CF,
+    0x00CC, 0x0060, 0x003A, 0x0023, 0x0040, 0x0027, 0x003D, 0x0022,
+    0x00D8, 0x0061, 0x0062, 0x0063, 0x0064, 0x0065, 0x0066, 0x0067,
+    0x0068, 0x0069, 0x00AB, 0x00BB, 0x00F0, 0x00FD, 0x00FE, 0x00B1,
+    0x00B0, 0x006A, 0x006B, 0x006C, 0x006D, 0x006E, 0x006F, 0x0070,
+    0x0071, 0x0072, 0x00AA, 0x00BA, 0x00E6, 0x00B8, 0x00C6, 0x00A4,
+    0x00B5, 0x007E, 0x0073, 0x0074, 0x0075, 0x0076, 0x0077, 0x0078,
+    0x0079, 0x007A, 0x00A1, 0x00BF, 0x00D0, 0x00DD, 0x00DE, 0x00AE,
+    0x005E, 0x00A3, 0x00A5, 0x00B7, 0x00A9, 0x00A7, 0x00B6, 0x00BC,
+    0x00BD, 0x00BE, 0x005B, 0x005D, 0x00AF, 0x00A8, 0x00B4, 0x00D7,
+    0x007B, 0x0041, 0x0042, 0x0043, 0x0044, 0x0045, 0x0046, 0x0047,
+    0x0048, 0x0049, 0x00AD, 0x00F4, 0x00F6, 0x00F2, 0x00F3, 0x00F5,
+    0x007D, 0x004A, 0x004B, 0x004C, 0x004D, 0x004E, 0x004F, 0x0050,
+    0x0051, 0x0052, 0x00B9, 0x00FB, 0x00FC, 0x00F9, 0x00FA, 0x00FF,
+    0x005C, 0x00F7, 0x0053, 0x0054, 0x0055, 0x0056, 0x0057, 0x0058,
+    0x0059, 0x005A, 0x00B2, 0x00D4, 0x00D6, 0x00D2, 0x00D3, 0x00D5,
+    0x0030, 0x0031, 0x0032, 0x0033, 0x0034, 0x0035, 0x0036, 0x0037,
+    0x0038, 0x0039, 0x00B3, 0x00DB, 0x00DC, 0x00D9, 0x00DA, 0x009F,
+];
+
+/// What's shown for the byte at `idx` (`byte`). Multi-byte encodings need
+/// the bytes around it.
+pub fn decode(
+    data: &Buffer,
+    idx: usize,
+    byte: u8,
+    encoding: Encoding,
+    non_printable: NonPrintable,
+) -> Cell {
+    match encoding {
+        Encoding::Ascii =>
+            Cell::Char(glyph(byte, non_printable)),
+        Encoding::Latin1 =>
+            Cell::Char(char_glyph(char::from(byte), non_printable)),
+        Encoding::Ebcdic =>
+            Cell::Char(match char::from_u32(u32::from(CP037[byte as usize])) {
+                Some(ch) =>
+                    char_glyph(ch, non_printable),
+                None =>
+                    '.',
+            }),
+        Encoding::ShiftJis =>
+            Cell::Char(decode_sjis(data, idx, byte, non_printable)),
+        Encoding::Utf8 =>
+            decode_utf8(data, idx, byte, non_printable),
+        Encoding::Utf16Le =>
+            decode_utf16(data, idx, false, non_printable),
+        Encoding::Utf16Be =>
+            decode_utf16(data, idx, true, non_printable),
+    }
+}
+
+/// Character shown for a decoded character. Wide characters are shown when
+/// the character has another byte for the second cell.
+fn char_cell(ch: char, len: usize, non_printable: NonPrintable) -> Cell {
+    let ch = char_glyph(ch, non_printable);
+    if len < 2 && glyphs::width(ch) > 1 {
+        Cell::Char('.')
+    } else {
+        Cell::Char(ch)
+    }
+}
+
+fn decode_utf8(data: &Buffer, idx: usize, byte: u8, non_printable: NonPrintable) -> Cell {
+    // Find the first byte of the character
+    for back in 0..cmp::min(idx + 1, 4) {
+        let start = idx - back;
+        let first = match data.get(start) {
+            Some(first) =>
+                first,
+            None =>
+                break,
+        };
+        if first & 0b1100_0000 == 0b1000_0000 {
+            continue;
+        }
+        let len = utf8_len(first);
+        if len <= back {
+            break;
+        }
+        let bytes: Vec<u8> = (start..start + len).filter_map(|idx| data.get(idx)).collect();
+        return match str::from_utf8(&bytes).ok().and_then(|s| s.chars().next()) {
+            Some(ch) if back == 0 =>
+                char_cell(ch, len, non_printable),
+            Some(_) =>
+                Cell::Continuation,
+            None =>
+                break,
+        };
+    }
+    Cell::Char(glyph(byte, non_printable))
+}
+
+/// Length of a UTF-8 character from its first byte, 0 if it's not a first
+/// byte.
+fn utf8_len(first: u8) -> usize {
+    if first < 0x80 {
+        1
+    } else if first & 0b1110_0000 == 0b1100_0000 {
+        2
+    } else if first & 0b1111_0000 == 0b1110_0000 {
+        3
+    } else if first & 0b1111_1000 == 0b1111_0000 {
+        4
+    } else {
+        0
+    }
+}
+
+/// Code units start at even offsets.
+fn decode_utf16(data: &Buffer, idx: usize, big_endian: bool, non_printable: NonPrintable) -> Cell {
+    let unit_at = |offset: usize| -> Option<u16> {
+        let b0 = u16::from(data.get(offset)?);
+        let b1 = u16::from(data.get(offset + 1)?);
+        Some(if big_endian { b0 << 8 | b1 } else { b1 << 8 | b0 })
+    };
+    let is_high = |unit: u16| (0xD800..0xDC00).contains(&unit);
+    let is_low = |unit: u16| (0xDC00..0xE000).contains(&unit);
+
+    let start = idx & !1;
+    let unit = match unit_at(start) {
+        Some(unit) =>
+            unit,
+        None =>
+            return Cell::Char('.'),
+    };
+    if is_low(unit) {
+        // Second half of a surrogate pair
+        return match start.checked_sub(2).and_then(&unit_at) {
+            Some(high) if is_high(high) =>
+                Cell::Continuation,
+            _ =>
+                Cell::Char('.'),
+        };
+    }
+    if idx != start {
+        return Cell::Continuation;
+    }
+    if is_high(unit) {
+        return match unit_at(start + 2) {
+            Some(low) if is_low(low) => {
+                let code = 0x10000 + ((u32::from(unit) - 0xD800) << 10) + (u32::from(low) - 0xDC00);
+                char::from_u32(code).map_or(Cell::Char('.'), |ch| char_cell(ch, 4, non_printable))
+            }
+            _ =>
+                Cell::Char('.'),
+        };
+    }
+    char::from_u32(u32::from(unit)).map_or(Cell::Char('.'), |ch| char_cell(ch, 2, non_printable))
+}
+
+fn decode_sjis(data: &Buffer, idx: usize, byte: u8, non_printable: NonPrintable) -> char {
+    if is_sjis_trail(data, idx, byte) {
+        return DOUBLE_BYTE;
+    }
+    if byte < 0x80 {
+        glyph(byte, non_printable)
+    } else if (0xA1..=0xDF).contains(&byte) {
+        // Half-width katakana, U+FF61 HALFWIDTH IDEOGRAPHIC FULL STOP, ...
+        char::from_u32(0xFF61 + u32::from(byte - 0xA1)).unwrap_or('.')
+    } else if is_sjis_lead(byte) && data.get(idx + 1).is_some_and(is_sjis_trail_byte) {
+        DOUBLE_BYTE
+    } else {
+        '.'
+    }
+}
+
+fn is_sjis_lead(byte: u8) -> bool {
+    matches!(byte, 0x81..=0x9F | 0xE0..=0xFC)
+}
+
+fn is_sjis_trail_byte(byte: u8) -> bool {
+    matches!(byte, 0x40..=0x7E | 0x80..=0xFC)
+}
+
+/// Is the byte the second byte of a double-byte character? Lead bytes can
+/// also be trail bytes, so we count the lead bytes before it: the first one
+/// starts a character, after a byte that can't be a lead byte.
+fn is_sjis_trail(data: &Buffer, idx: usize, byte: u8) -> bool {
+    if !is_sjis_trail_byte(byte) {
+        return false;
+    }
+    let mut leads = 0;
+    while leads < idx && leads < MAX_LOOK_BACK {
+        match data.get(idx - leads - 1) {
+            Some(prev) if is_sjis_lead(prev) =>
+                leads += 1,
+            _ =>
+                break,
+        }
+    }
+    leads % 2 == 1
+}
+
+/// Guess the encoding of a text file. `None` for binary files and ASCII
+/// text.
+pub fn guess(data: &[u8]) -> Option<Guess> {
+    let sample = &data[..cmp::min(data.len(), GUESS_SAMPLE)];
+    if sample.is_empty() {
+        return None;
+    }
+
+    // Byte order marks
+    if sample.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        return Some(guessed("UTF-8", Encoding::Utf8));
+    }
+    if sample.starts_with(&[0xFF, 0xFE]) {
+        return Some(guessed("UTF-16LE", Encoding::Utf16Le));
+    }
+    if sample.starts_with(&[0xFE, 0xFF]) {
+        return Some(guessed("UTF-16BE", Encoding::Utf16Be));
+    }
+
+    // ASCII text in UTF-16 has zeros in every other byte
+    if let Some(guess) = guess_utf16(sample) {
+        return Some(guess);
+    }
+
+    let counts = byte_counts(sample);
+    let (mut high, mut ascii_text, mut ebcdic_text) = (0, 0, 0);
+    for (byte, &count) in counts.iter().enumerate() {
+        let byte = byte as u8;
+        if byte >= 0x80 {
+            high += count;
+        }
+        if is_text_byte(byte) {
+            ascii_text += count;
+        }
+        if is_ebcdic_text(byte) {
+            ebcdic_text += count;
+        }
+    }
+    let total = sample.len() as f32;
+    let (high, ascii_text, ebcdic_text) = (
+        high as f32 / total,
+        ascii_text as f32 / total,
+        ebcdic_text as f32 / total,
+    );
+
+    let truncated = sample.len() < data.len();
+    if ascii_text >= MIN_TEXT_RATIO {
+        if high == 0.0 {
+            // ASCII
+            None
+        } else if is_utf8(sample, truncated) {
+            Some(guessed("UTF-8", Encoding::Utf8))
+        } else {
+            Some(guessed("Latin-1", Encoding::Latin1))
+        }
+    } else if ebcdic_text >= MIN_TEXT_RATIO {
+        Some(guessed("EBCDIC", Encoding::Ebcdic))
+    } else if ascii_text + high >= MIN_TEXT_RATIO && is_utf8(sample, truncated) {
+        // Mostly non-Latin text
+        Some(guessed("UTF-8", Encoding::Utf8))
+    } else {
+        None
+    }
+}
+
+fn guessed(name: &'static str, encoding: Encoding) -> Guess {
+    Guess { name, encoding }
+}
+
+/// Printable ASCII and whitespace.
+fn is_text_byte(byte: u8) -> bool {
+    matches!(byte, 0x20..=0x7E | b'\t' | b'\n' | b'\r')
+}
+
+/// Letters, digits, space, and common punctuation in EBCDIC.
+fn is_ebcdic_text(byte: u8) -> bool {
+    match char::from_u32(u32::from(CP037[byte as usize])) {
+        Some(ch) =>
+            ch.is_ascii_alphanumeric() || " .,:;-'\"()/".contains(ch) || ch == '\n',
+        None =>
+            false,
+    }
+}
+
+fn guess_utf16(sample: &[u8]) -> Option<Guess> {
+    let pairs = sample.len() / 2;
+    if pairs < 8 {
+        return None;
+    }
+    let zeros_at = |parity: usize| {
+        let zeros = sample[..pairs * 2]
+            .iter()
+            .enumerate()
+            .filter(|&(idx, &byte)| idx % 2 == parity && byte == 0)
+            .count();
+        zeros as f32 / pairs as f32
+    };
+    let (even, odd) = (zeros_at(0), zeros_at(1));
+    if odd > 0.5 && even < 0.05 {
+        Some(guessed("UTF-16LE", Encoding::Utf16Le))
+    } else if even > 0.5 && odd < 0.05 {
+        Some(guessed("UTF-16BE", Encoding::Utf16Be))
+    } else {
+        None
+    }
+}
+
+/// `truncated`: the sample may end in the middle of a character.
+fn is_utf8(sample: &[u8], truncated: bool) -> bool {
+    match str::from_utf8(sample) {
+        Ok(_) =>
+            true,
+        Err(err) =>
+            truncated && err.error_len().is_none(),
+    }
+}
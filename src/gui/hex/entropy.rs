@@ -0,0 +1,117 @@
+////////////////////////////////////////////////////////////////////////////////
+// Entropy minimap: a column on the right showing entropy of the whole file,
+// for spotting compressed or encrypted regions
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cmp;
+
+use termbox_simple::*;
+
+/// Entropy is calculated for blocks of this many bytes.
+const BLOCK_SIZE: usize = 256;
+
+/// Columns the minimap takes, including the vertical line on its left.
+pub const MINIMAP_WIDTH: i32 = 3;
+
+/// Shannon entropy of the bytes, in bits per byte (0.0 - 8.0).
+pub fn entropy(counts: &[usize; 256], total: usize) -> f32 {
+    if total == 0 {
+        return 0.0;
+    }
+
+    let mut ret = 0.0;
+    for &count in counts.iter() {
+        if count != 0 {
+            let p = count as f32 / total as f32;
+            ret -= p * p.log2();
+        }
+    }
+    ret
+}
+
+pub fn byte_counts(bytes: &[u8]) -> [usize; 256] {
+    let mut counts = [0; 256];
+    for &byte in bytes {
+        counts[byte as usize] += 1;
+    }
+    counts
+}
+
+pub struct Minimap {
+    pos_x: i32,
+    height: i32,
+
+    /// Entropy of each `BLOCK_SIZE` block of the file.
+    blocks: Vec<f32>,
+}
+
+/// Entropy of each `BLOCK_SIZE` block of the data.
+pub fn block_entropy(data: &[u8]) -> Vec<f32> {
+    data.chunks(BLOCK_SIZE)
+        .map(|block| entropy(&byte_counts(block), block.len()))
+        .collect()
+}
+
+impl Minimap {
+    /// Blocks are computed in the background, see `set_blocks`.
+    pub fn new(pos_x: i32, height: i32) -> Minimap {
+        Minimap {
+            pos_x,
+            height,
+            blocks: Vec::new(),
+        }
+    }
+
+    pub fn set_blocks(&mut self, blocks: Vec<f32>) {
+        self.blocks = blocks;
+    }
+
+    pub fn resize(&mut self, pos_x: i32, height: i32) {
+        self.pos_x = pos_x;
+        self.height = height;
+    }
+
+    /// `cursor`: byte offset of the cursor, marked in the minimap.
+    pub fn draw(&self, tb: &mut Termbox, cursor: usize) {
+        for y in 0..self.height {
+            tb.change_cell(self.pos_x, y, '│', TB_DEFAULT, TB_DEFAULT);
+        }
+
+        let n_blocks = self.blocks.len();
+        let rows = self.height as usize;
+        let cursor_block = cursor / BLOCK_SIZE;
+        let mut cursor_drawn = false;
+
+        for row in 0..rows {
+            let start = row * n_blocks / rows;
+            if start >= n_blocks {
+                break;
+            }
+            let end = cmp::max((row + 1) * n_blocks / rows, start + 1);
+
+            // Show the max. of the blocks so that small high-entropy regions
+            // don't disappear in large files
+            let entropy = self.blocks[start..end].iter().cloned().fold(0.0, f32::max);
+            let color = entropy_color(entropy);
+
+            let on_cursor = !cursor_drawn && cursor_block >= start && cursor_block < end;
+            cursor_drawn |= on_cursor;
+            let ch = if on_cursor { '◀' } else { ' ' };
+
+            tb.change_cell(self.pos_x + 1, row as i32, ' ', TB_DEFAULT, color);
+            tb.change_cell(self.pos_x + 2, row as i32, ch, TB_WHITE, TB_DEFAULT);
+        }
+    }
+}
+
+fn entropy_color(entropy: f32) -> u16 {
+    if entropy < 2.0 {
+        TB_BLUE
+    } else if entropy < 5.0 {
+        TB_GREEN
+    } else if entropy < 7.0 {
+        TB_YELLOW
+    } else {
+        TB_RED
+    }
+}
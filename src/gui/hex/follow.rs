@@ -0,0 +1,40 @@
+////////////////////////////////////////////////////////////////////////////////
+// Follow mode (`F`, `--follow`): the file is checked periodically (see
+// `Gui::mainloop`), bytes appended to it are appended to the buffer, other
+// changes reload it. Memory of processes (`--pid`) is re-read.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::time::SystemTime;
+
+pub enum Follow {
+    File(FileStamp),
+    Process,
+}
+
+/// What we know about the file from the last check.
+#[derive(Clone, Copy, PartialEq)]
+pub struct FileStamp {
+    pub len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl FileStamp {
+    pub fn read(path: &str) -> io::Result<FileStamp> {
+        let metadata = fs::metadata(path)?;
+        Ok(FileStamp {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+/// Contents of the file after `offset`.
+pub fn read_from(path: &str, offset: u64) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(offset))?;
+    let mut ret = Vec::new();
+    file.read_to_end(&mut ret)?;
+    Ok(ret)
+}
@@ -0,0 +1,80 @@
+////////////////////////////////////////////////////////////////////////////////
+// Footer below the hex view (`--footer xor|sum`, `:set footer=..`): XOR or sum
+// of the visible bytes of each column, and the CRC-32 of the visible bytes.
+// Computed when drawing, over the visible bytes only.
+////////////////////////////////////////////////////////////////////////////////
+
+use buffer::Buffer;
+use colors;
+use config::{ColumnSum, Config};
+use gui::hex::hex_grid::HexGrid;
+use gui::hex::squeeze::Row;
+use hash::crc32;
+use utils::hex_char;
+
+use termbox_simple::*;
+
+/// Rows taken by the footer.
+pub fn rows(config: &Config) -> i32 {
+    if config.footer.is_some() {
+        1
+    } else {
+        0
+    }
+}
+
+/// Sums the rows shown in the hex grid. `checksum_x`: where the CRC is shown,
+/// `None` when only the hex view is shown.
+pub fn draw(
+    tb: &mut Termbox,
+    y: i32,
+    width: i32,
+    kind: ColumnSum,
+    data: &Buffer,
+    hex_grid: &HexGrid,
+    checksum_x: Option<i32>,
+) {
+    let style = colors::palette().status_bar;
+    for x in 0..width {
+        tb.change_cell(x, y, ' ', style.fg, style.bg);
+    }
+
+    let bytes_per_line = hex_grid.bytes_per_line();
+    let mut sums = vec![0u8; bytes_per_line];
+    let mut visible = Vec::new();
+    for row in hex_grid.rows() {
+        if let Row::Line(line) = row {
+            for (col, sum) in sums.iter_mut().enumerate() {
+                if let Some(byte) = data.get(line * bytes_per_line + col) {
+                    *sum = match kind {
+                        ColumnSum::Xor =>
+                            *sum ^ byte,
+                        ColumnSum::Sum =>
+                            sum.wrapping_add(byte),
+                    };
+                    visible.push(byte);
+                }
+            }
+        }
+    }
+
+    let label_width = hex_grid.column_x(0) - 1;
+    print_clipped(tb, 0, y, label_width, kind.name());
+    for (col, sum) in sums.iter().enumerate() {
+        let x = hex_grid.column_x(col);
+        tb.change_cell(x, y, hex_char(sum >> 4) as char, style.fg, style.bg);
+        tb.change_cell(x + 1, y, hex_char(sum & 0b0000_1111) as char, style.fg, style.bg);
+    }
+
+    if let Some(x) = checksum_x {
+        let text = format!("crc32 {:08x}", crc32(&visible));
+        print_clipped(tb, x, y, width - x, &text);
+    }
+}
+
+fn print_clipped(tb: &mut Termbox, x: i32, y: i32, width: i32, text: &str) {
+    let style = colors::palette().status_bar;
+    for (idx, ch) in text.chars().take(width.max(0) as usize).enumerate() {
+        tb.change_cell(x + idx as i32, y, ch, style.fg, style.bg);
+    }
+}
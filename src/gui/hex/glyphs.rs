@@ -0,0 +1,100 @@
+////////////////////////////////////////////////////////////////////////////////
+// Characters shown for bytes in the ASCII view. All of them take a single
+// column (termbox skips a cell after a wide character, which would shift the
+// rest of the line), so the view stays aligned with the hex grid.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::char;
+
+use libc;
+
+use config::NonPrintable;
+
+extern "C" {
+    fn wcwidth(ch: libc::wchar_t) -> libc::c_int;
+}
+
+/// Code page 437 glyphs of 0x00-0x1F. 0x00 is blank.
+const CP437_CONTROL: [char; 32] = [
+    ' ', '☺', '☻', '♥', '♦', '♣', '♠', '•',
+    '◘', '○', '◙', '♂', '♀', '♪', '♫', '☼',
+    '►', '◄', '↕', '‼', '¶', '§', '▬', '↨',
+    '↑', '↓', '→', '←', '∟', '↔', '▲', '▼',
+];
+
+/// Code page 437 glyphs of 0x80-0xFF. 0xFF (non-breaking space) is blank.
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç',
+    'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å',
+    'É', 'æ', 'Æ', 'ô', 'ö', 'ò', 'û', 'ù',
+    'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ',
+    'á', 'í', 'ó', 'ú', 'ñ', 'Ñ', 'ª', 'º',
+    '¿', '⌐', '¬', '½', '¼', '¡', '«', '»',
+    '░', '▒', '▓', '│', '┤', '╡', '╢', '╖',
+    '╕', '╣', '║', '╗', '╝', '╜', '╛', '┐',
+    '└', '┴', '┬', '├', '─', '┼', '╞', '╟',
+    '╚', '╔', '╩', '╦', '╠', '═', '╬', '╧',
+    '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫',
+    '╪', '┘', '┌', '█', '▄', '▌', '▐', '▀',
+    'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ',
+    'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩',
+    '≡', '±', '≥', '≤', '⌠', '⌡', '÷', '≈',
+    '°', '∙', '·', '√', 'ⁿ', '²', '■', ' ',
+];
+
+fn is_printable(byte: u8) -> bool {
+    (32..=126).contains(&byte)
+}
+
+/// Columns the character takes, as termbox counts them (see `setlocale` in
+/// `main`).
+pub fn width(ch: char) -> usize {
+    let width = unsafe { wcwidth(ch as libc::wchar_t) };
+    if width < 1 {
+        1
+    } else {
+        width as usize
+    }
+}
+
+/// Character shown for a decoded character (see `encoding`). Control
+/// characters are shown like the bytes with the same value.
+pub fn char_glyph(ch: char, non_printable: NonPrintable) -> char {
+    let code = ch as u32;
+    if code < 0x80 {
+        glyph(code as u8, non_printable)
+    } else if code < 0xA0 || ch == '\u{A0}' || ch == '\u{AD}' {
+        // C1 controls, non-breaking space, soft hyphen
+        '.'
+    } else {
+        ch
+    }
+}
+
+/// Character shown for the byte.
+pub fn glyph(byte: u8, non_printable: NonPrintable) -> char {
+    if is_printable(byte) {
+        return byte as char;
+    }
+    match non_printable {
+        NonPrintable::Dot =>
+            '.',
+        NonPrintable::Pictures =>
+            if byte < 0x20 {
+                // U+2400 SYMBOL FOR NULL, ...
+                char::from_u32(0x2400 + u32::from(byte)).unwrap_or('.')
+            } else if byte == 0x7F {
+                '␡'
+            } else {
+                '.'
+            },
+        NonPrintable::Cp437 =>
+            if byte < 0x20 {
+                CP437_CONTROL[byte as usize]
+            } else if byte == 0x7F {
+                '⌂'
+            } else {
+                CP437_HIGH[byte as usize - 0x80]
+            },
+    }
+}
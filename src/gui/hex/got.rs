@@ -0,0 +1,200 @@
+use std::collections::BTreeMap;
+
+use parser::elf::{Elf, Relocation, SectionType, ET_REL};
+use parser::Reader;
+
+use gui::hex::structure::{field, StructureRow};
+
+/// Symbol and type of a relocation, like "puts (R_X86_64_JUMP_SLOT)".
+fn reloc_str(rel: &Relocation) -> String {
+    let symbol = match rel.symbol_name {
+        Some(ref name) if !name.is_empty() =>
+            name.clone(),
+        _ =>
+            "-".to_owned(),
+    };
+    match rel.type_name {
+        Some(ty) =>
+            format!("{} ({})", symbol, ty),
+        None =>
+            format!("{} (type {})", symbol, rel.ty),
+    }
+}
+
+/// Functions the PLT stubs call, by the address of the stub. For naming
+/// call targets in the disassembly, e.g. `call 0x1030 <puts@plt>`.
+pub fn plt_functions(elf: &Elf, data: &[u8]) -> BTreeMap<u64, String> {
+    let relocations = elf.relocations(data);
+    let by_addr: BTreeMap<u64, &Relocation> =
+        relocations.iter().map(|rel| (rel.offset, rel)).collect();
+    let plt_relocations: Vec<&Relocation> = relocations
+        .iter()
+        .filter(|rel| rel.section == ".rela.plt" || rel.section == ".rel.plt")
+        .collect();
+
+    let mut ret = BTreeMap::new();
+    for stub in elf.plt_stubs(data) {
+        let rel = match (stub.got_slot, stub.reloc_index) {
+            (Some(slot), _) =>
+                by_addr.get(&slot).cloned(),
+            (None, Some(index)) => {
+                let index = if elf.is_64 { index } else { index / 8 };
+                plt_relocations.get(index as usize).cloned()
+            }
+            (None, None) =>
+                None,
+        };
+        match rel.and_then(|rel| rel.symbol_name.as_ref()) {
+            Some(name) if !name.is_empty() => {
+                ret.insert(stub.addr, name.clone());
+            }
+            _ =>
+                {}
+        }
+    }
+    ret
+}
+
+/// Rows of the GOT/PLT view (`:got`): how calls are bound, PLT stubs with
+/// the functions they call, and GOT slots with their relocations. `None` when
+/// the file has no GOT or PLT.
+pub fn rows(elf: &Elf, data: &[u8]) -> Option<Vec<StructureRow>> {
+    let got_sections: Vec<_> = elf.section_headers
+        .iter()
+        .filter(|sh| {
+            (sh.name == ".got" || sh.name == ".got.plt") && sh.sh_type != SectionType::Nobits
+        })
+        .collect();
+    let has_plt = elf.section_headers.iter().any(|sh| sh.name.starts_with(".plt"));
+    if elf.header.e_type == ET_REL || (got_sections.is_empty() && !has_plt) {
+        return None;
+    }
+
+    let relocations = elf.relocations(data);
+    // Relocations of linked files are at addresses
+    let by_addr: BTreeMap<u64, &Relocation> =
+        relocations.iter().map(|rel| (rel.offset, rel)).collect();
+    // Lazy stubs push the index of their relocation in `.rela.plt` (or the
+    // offset in `.rel.plt`)
+    let plt_relocations: Vec<&Relocation> = relocations
+        .iter()
+        .filter(|rel| rel.section == ".rela.plt" || rel.section == ".rel.plt")
+        .collect();
+    let word = if elf.is_64 { 8 } else { 4 };
+    let got_plt = elf.section_headers.iter().find(|sh| sh.name == ".got.plt");
+    let resolver_slot = got_plt.map(|sh| sh.sh_addr + 2 * word);
+
+    let mut rows = Vec::new();
+
+    rows.push(StructureRow::Heading("Binding".to_owned()));
+    if elf.bind_now(data) {
+        rows.push(field("Mode", "immediate (BIND_NOW)".to_owned(), None));
+        rows.push(field(
+            "Load",
+            "The loader fills every GOT slot before the program starts".to_owned(),
+            None,
+        ));
+        rows.push(field(
+            "Calls",
+            "PLT stubs jump through their slots, the resolver is not used".to_owned(),
+            None,
+        ));
+    } else {
+        rows.push(field("Mode", "lazy".to_owned(), None));
+        rows.push(field(
+            "Calls",
+            "Code calls a PLT stub, which jumps to the address in its GOT slot".to_owned(),
+            None,
+        ));
+        rows.push(field(
+            "First call",
+            "The slot points back into .plt, to code that pushes the relocation".to_owned(),
+            None,
+        ));
+        rows.push(field(
+            "",
+            "index and jumps to the first stub, which calls the resolver (GOT[2])".to_owned(),
+            None,
+        ));
+        rows.push(field(
+            "Later calls",
+            "The resolver wrote the function's address to the slot".to_owned(),
+            None,
+        ));
+    }
+
+    let stubs = elf.plt_stubs(data);
+    rows.push(StructureRow::Heading(format!("PLT stubs ({})", stubs.len())));
+    if stubs.is_empty() && has_plt {
+        rows.push(field("", "Not decoded for this architecture".to_owned(), None));
+    }
+    for stub in &stubs {
+        let name = format!("{} 0x{:x}", stub.section, stub.addr);
+        let value = match (stub.got_slot, stub.reloc_index) {
+            (Some(slot), _) if Some(slot) == resolver_slot =>
+                "Resolver, through GOT[2]".to_owned(),
+            (Some(slot), _) =>
+                match by_addr.get(&slot) {
+                    Some(rel) =>
+                        format!("{}, slot 0x{:x}", reloc_str(rel), slot),
+                    None =>
+                        format!("Slot 0x{:x}", slot),
+                },
+            (None, Some(index)) => {
+                let index = if elf.is_64 { index } else { index / 8 };
+                match plt_relocations.get(index as usize) {
+                    Some(rel) =>
+                        format!("Lazy binding of {}, relocation {}", reloc_str(rel), index),
+                    None =>
+                        format!("Lazy binding, relocation {}", index),
+                }
+            }
+            (None, None) =>
+                "Not decoded".to_owned(),
+        };
+        rows.push(field(&name, value, Some(stub.offset)));
+    }
+
+    let r = Reader::new(data, elf.big_endian);
+    for sh in got_sections {
+        let n_slots = sh.sh_size / word;
+        rows.push(StructureRow::Heading(format!("{} ({} slots)", sh.name, n_slots)));
+        for idx in 0..n_slots {
+            let addr = sh.sh_addr + idx * word;
+            let offset = (sh.sh_offset + idx * word) as usize;
+            let value = if elf.is_64 { r.u64(offset) } else { r.u32(offset).map(u64::from) };
+            let value = match value {
+                Some(value) =>
+                    value,
+                None =>
+                    break,
+            };
+            let what = match by_addr.get(&addr) {
+                Some(rel) =>
+                    reloc_str(rel),
+                // Reserved slots, used by the lazy binding code
+                None if sh.name == ".got.plt" && idx == 0 =>
+                    "Address of .dynamic".to_owned(),
+                None if sh.name == ".got.plt" && idx == 1 =>
+                    "Link map, set by the loader".to_owned(),
+                None if sh.name == ".got.plt" && idx == 2 =>
+                    "Resolver, set by the loader".to_owned(),
+                None =>
+                    "-".to_owned(),
+            };
+            let in_plt = elf.section_headers.iter().find(|plt| {
+                plt.name.starts_with(".plt") && value >= plt.sh_addr
+                    && value - plt.sh_addr < plt.sh_size
+            });
+            let target = match in_plt {
+                Some(plt) =>
+                    format!(" = 0x{:x}, in {} until resolved", value, plt.name),
+                None =>
+                    format!(" = 0x{:x}", value),
+            };
+            rows.push(field(&format!("[{}] 0x{:x}", idx, addr), what + &target, Some(offset)));
+        }
+    }
+
+    Some(rows)
+}
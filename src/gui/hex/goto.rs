@@ -1,5 +1,6 @@
 use std::char;
 use std::cmp;
+use std::rc::Rc;
 
 use colors;
 use utils::*;
@@ -10,11 +11,26 @@ use termbox_simple::*;
 /// Return value of the overlay. Returned by `keypressed()` method.
 pub enum OverlayRet {
     /// User submitted the form.
-    Ret(i32),
+    Ret(usize),
 
     /// For vi-like "go to beginning" (gg)
     GotoBeginning,
 
+    /// For vi-like "next tab" (gt)
+    NextBuffer,
+
+    /// For vi-like "previous tab" (gT)
+    PrevBuffer,
+
+    /// Show the list of buffers (gb)
+    BufferList,
+
+    /// Input is not a number, or is an address before the base.
+    Invalid,
+
+    /// Input is not a number or a known symbol.
+    UnknownSymbol(String),
+
     /// User cancelled.
     Abort,
 
@@ -28,10 +44,61 @@ pub struct GotoOverlay {
     width: i32,
     height: i32,
     input: String,
+
+    /// When set, input is an address rather than a byte offset.
+    base: Option<usize>,
+
+    /// For `sector:N`.
+    sector_size: usize,
+
+    /// Symbols of ELF files with their file offsets, sorted by name. Empty
+    /// for other files.
+    symbols: Rc<Vec<(String, usize)>>,
+
+    /// Indices of symbols completing the input, and the one shown. Tab cycles
+    /// through them.
+    completions: Vec<usize>,
+    completion_idx: Option<usize>,
+}
+
+const SECTOR_PREFIX: &str = "sector:";
+
+/// Symbol names can start with '@', for names like "get" that would
+/// otherwise be commands like 'gg'.
+const SYMBOL_PREFIX: char = '@';
+
+/// Keys that are commands at the start of the input.
+const COMMAND_KEYS: &[char] = &['g', 't', 'T', 'b'];
+
+/// File offset of the symbol. `symbols` is sorted by name.
+pub fn find_symbol(symbols: &[(String, usize)], name: &str) -> Option<usize> {
+    let name = name.trim_start_matches(SYMBOL_PREFIX);
+    symbols
+        .binary_search_by(|(sym, _)| sym.as_str().cmp(name))
+        .ok()
+        .map(|idx| symbols[idx].1)
+}
+
+/// Parse a goto target: a byte offset, an address when `base` is set, or
+/// `sector:N` for the start of sector N. Numbers can be hex with `0x`.
+pub fn parse_target(input: &str, base: Option<usize>, sector_size: usize) -> Option<usize> {
+    if let Some(sector) = input.strip_prefix(SECTOR_PREFIX) {
+        parse_number(sector).and_then(|n| n.checked_mul(sector_size))
+    } else {
+        parse_number(input).and_then(|n| n.checked_sub(base.unwrap_or(0)))
+    }
 }
 
 impl GotoOverlay {
-    pub fn new(width: i32, height: i32, pos_x: i32, pos_y: i32) -> GotoOverlay {
+    pub fn new(
+        width: i32,
+        height: i32,
+        pos_x: i32,
+        pos_y: i32,
+        base: Option<usize>,
+        sector_size: usize,
+        symbols: Rc<Vec<(String, usize)>>,
+    ) -> GotoOverlay {
         let width_ = cmp::min(width, 50);
         let height_ = cmp::min(height, 10);
 
@@ -44,6 +111,11 @@ impl GotoOverlay {
             width: width_,
             height: height_,
             input: String::new(),
+            base,
+            sector_size,
+            symbols,
+            completions: Vec::new(),
+            completion_idx: None,
         }
     }
 
@@ -53,15 +125,24 @@ impl GotoOverlay {
             tb,
             self.pos_x + 5,
             self.pos_y + 3,
-            colors::DEFAULT,
-            "Goto byte offset:",
+            colors::palette().default,
+            match (self.base.is_some(), self.symbols.is_empty()) {
+                (true, true) =>
+                    "Goto address (or sector:N):",
+                (true, false) =>
+                    "Goto address (or sector:N, symbol):",
+                (false, true) =>
+                    "Goto byte offset (or sector:N):",
+                (false, false) =>
+                    "Goto byte offset (or sector:N, symbol):",
+            },
         );
-        print(tb, self.pos_x + 5, self.pos_y + 5, colors::DEFAULT, ">");
+        print(tb, self.pos_x + 5, self.pos_y + 5, colors::palette().default, ">");
         print(
             tb,
             self.pos_x + 7,
             self.pos_y + 5,
-            colors::DEFAULT,
+            colors::palette().default,
             &self.input,
         );
 
@@ -69,31 +150,134 @@ impl GotoOverlay {
             self.pos_x + 7 + self.input.len() as i32,
             self.pos_y + 5,
             ' ',
-            colors::CURSOR_FOCUS.fg,
-            colors::CURSOR_FOCUS.bg,
+            colors::palette().cursor_focus.fg,
+            colors::palette().cursor_focus.bg,
         );
+
+        // Completions, the shown one highlighted
+        let mut x = self.pos_x + 5;
+        for (idx, &sym_idx) in self.completions.iter().enumerate() {
+            let name = &self.symbols[sym_idx].0;
+            if x + name.len() as i32 > self.pos_x + self.width - 2 {
+                break;
+            }
+            let style = if self.completion_idx == Some(idx) {
+                colors::palette().cursor_no_focus
+            } else {
+                colors::palette().default
+            };
+            print(tb, x, self.pos_y + 7, style, name);
+            x += name.len() as i32 + 2;
+        }
+    }
+
+    /// The input without the `sector:` prefix.
+    fn number(&self) -> &str {
+        if self.input.starts_with(SECTOR_PREFIX) {
+            &self.input[SECTOR_PREFIX.len()..]
+        } else {
+            &self.input
+        }
+    }
+
+    /// Is the input a symbol name rather than a (bad) number? Symbol names
+    /// don't start with digits.
+    fn is_symbol(&self) -> bool {
+        !self.symbols.is_empty() && !self.input.is_empty()
+            && !self.input.starts_with(SECTOR_PREFIX)
+            && !self.input.starts_with(|c: char| c.is_ascii_digit())
+    }
+
+    /// Complete the symbol name, or show the next completion.
+    fn complete(&mut self) {
+        match self.completion_idx {
+            None => {
+                let prefix = self.input.trim_start_matches(SYMBOL_PREFIX);
+                self.completions = self.symbols
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (name, _))| name.starts_with(prefix))
+                    .map(|(idx, _)| idx)
+                    .collect();
+                if self.completions.is_empty() {
+                    return;
+                }
+                self.completion_idx = Some(0);
+            }
+            Some(idx) =>
+                self.completion_idx = Some((idx + 1) % self.completions.len()),
+        }
+        let name = &self.symbols[self.completions[self.completion_idx.unwrap()]].0;
+        // Keep the '@', names like "get" would be commands without it
+        let prefix = if self.input.starts_with(SYMBOL_PREFIX) || name.starts_with(COMMAND_KEYS) {
+            SYMBOL_PREFIX.to_string()
+        } else {
+            String::new()
+        };
+        self.input = prefix + name;
     }
 
     pub fn keypressed(&mut self, key: Key) -> OverlayRet {
+        if key != Key::Tab {
+            self.completions.clear();
+            self.completion_idx = None;
+        }
         match key {
             Key::Char(ch) if (ch >= '0' && ch <= '9') => {
                 self.input.push(char::from_u32(ch as u32).unwrap());
                 OverlayRet::Continue
             }
-            Key::Char('g') =>
+            // Commands like 'gg' need to come right after 'g'
+            Key::Char('g') if self.input.is_empty() =>
                 OverlayRet::GotoBeginning,
+            Key::Char('t') if self.input.is_empty() =>
+                OverlayRet::NextBuffer,
+            Key::Char('T') if self.input.is_empty() =>
+                OverlayRet::PrevBuffer,
+            Key::Char('b') if self.input.is_empty() =>
+                OverlayRet::BufferList,
+            Key::Char(ch) if SECTOR_PREFIX.starts_with(&format!("{}{}", self.input, ch)) => {
+                self.input.push(ch);
+                OverlayRet::Continue
+            }
+            // Hex input
+            Key::Char('x') if self.number() == "0" => {
+                self.input.push('x');
+                OverlayRet::Continue
+            }
+            Key::Char(ch) if self.number().starts_with("0x") && ch.is_ascii_hexdigit() => {
+                self.input.push(ch);
+                OverlayRet::Continue
+            }
+            Key::Char(ch) if !self.symbols.is_empty() && !ch.is_control() => {
+                self.input.push(ch);
+                OverlayRet::Continue
+            }
+            Key::Tab if !self.symbols.is_empty() => {
+                self.complete();
+                OverlayRet::Continue
+            }
             Key::Esc =>
                 OverlayRet::Abort,
             Key::Backspace => {
                 self.input.pop();
                 OverlayRet::Continue
             }
-            Key::Char('\r') =>
-                if self.input.is_empty() {
-                    OverlayRet::Abort
-                } else {
-                    OverlayRet::Ret(self.input.parse().unwrap())
-                },
+            Key::Char('\r') => {
+                match parse_target(&self.input, self.base, self.sector_size) {
+                    Some(offset) =>
+                        OverlayRet::Ret(offset),
+                    None if !self.is_symbol() =>
+                        OverlayRet::Invalid,
+                    None =>
+                        match find_symbol(&self.symbols, &self.input) {
+                            Some(offset) =>
+                                OverlayRet::Ret(offset),
+                            None =>
+                                OverlayRet::UnknownSymbol(self.input.clone()),
+                        },
+                }
+            }
             _ =>
                 OverlayRet::Continue,
         }
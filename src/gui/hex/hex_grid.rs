@@ -1,53 +1,120 @@
-use std::cmp;
+use std::cmp::{self, Ordering};
 use std::ptr;
+use std::time::{Duration, Instant};
 
+use buffer::Buffer;
+use gui::hex::highlights::ByteHighlights;
+use gui::hex::squeeze::Row;
 use gui::hex::HexGui;
 
-use colors;
+use colors::{self, Style};
+use config::Config;
 use utils::*;
 
 use term_input::{Arrow, Key};
 use termbox_simple::*;
 
-pub struct HexGrid<'grid> {
+/// Shown in place of the bytes when the buffer is empty.
+const EMPTY_FILE_MSG: &str = "Empty file, i to insert bytes";
+
+/// Blank rows that can be shown after the last line, so that the last bytes
+/// are not at the bottom edge.
+const EOF_PADDING: usize = 2;
+
+/// A key pressed again within this time is considered held down. Terminals
+/// don't report key releases, so a longer gap is how we know it's released.
+const REPEAT_GAP: Duration = Duration::from_millis(150);
+
+/// After this many repeats of a held j/k we move 5 lines at a time, after
+/// `REPEAT_PAGE` repeats a page at a time.
+const REPEAT_FAST: usize = 10;
+const REPEAT_PAGE: usize = 30;
+
+/// Columns a byte takes in the hex view: two hex digits, or eight bits with a
+/// separator between the nibbles.
+pub fn byte_width(bits: bool) -> i32 {
+    if bits {
+        9
+    } else {
+        2
+    }
+}
+
+/// A j/k key that is being held down.
+struct KeyRepeat {
+    key: Key,
+    last: Instant,
+    count: usize,
+}
+
+pub struct HexGrid {
     pos_x: i32,
     pos_y: i32,
     width: i32,
     height: i32,
 
-    data: &'grid [u8],
-    path: &'grid str,
+    /// Length of the buffer.
+    len: usize,
+
+    /// Bytes are shown in groups of this many bytes (1, 2, 4 or 8), with a
+    /// space between groups.
+    group_size: i32,
 
-    cursor_x: i32,
-    cursor_y: i32,
-    scroll: i32,
+    /// In sector mode PageUp/PageDown move a sector at a time.
+    sector_size: Option<usize>,
 
-    gui: *mut HexGui<'grid>,
+    /// Show bytes as bits, see `byte_width`.
+    bits: bool,
+
+    /// Digit in the line: nibbles, or bits in bit mode. The more significant
+    /// digits of a byte come first.
+    cursor_digit: usize,
+    cursor_y: usize,
+
+    /// First line of the top row.
+    scroll: usize,
+
+    /// Lines shown as a single `*` row, see `squeeze`. Empty when squeeze
+    /// mode is off.
+    squeezed: Vec<(usize, usize)>,
+
+    /// Last vertical motion key, for accelerating when it's held down.
+    repeat: Option<KeyRepeat>,
+
+    gui: *mut HexGui,
 }
 
-impl<'grid> HexGrid<'grid> {
+impl HexGrid {
     pub fn new(
         width: i32,
         height: i32,
         pos_x: i32,
         pos_y: i32,
-        data: &'grid [u8],
-        path: &'grid str,
-    ) -> HexGrid<'grid> {
+        len: usize,
+        config: &Config,
+    ) -> HexGrid {
         HexGrid {
             pos_x: pos_x,
             pos_y: pos_y,
-            height: height,
+            // Negative in tiny terminals, after the rows of the footer and
+            // the info line
+            height: cmp::max(height, 0),
             width: width,
-            data: data,
-            path: path,
+            len,
+            group_size: config.group_size,
+            sector_size: if config.sectors { Some(config.sector_size) } else { None },
+            bits: config.bits,
 
             // Cursor positions are relative to the grid.
             // (i.e. they stay the same when grid is moved)
-            cursor_x: 0,
+            cursor_digit: 0,
             cursor_y: 0,
             scroll: 0,
 
+            squeezed: Vec::new(),
+
+            repeat: None,
+
             gui: ptr::null_mut(),
         }
     }
@@ -56,92 +123,369 @@ impl<'grid> HexGrid<'grid> {
         self.width
     }
 
-    pub fn set_gui(&mut self, gui: *mut HexGui<'grid>) {
+    pub fn set_gui(&mut self, gui: *mut HexGui) {
         self.gui = gui;
     }
 
+    pub fn set_squeezed(&mut self, squeezed: Vec<(usize, usize)>) {
+        self.squeezed = squeezed;
+        self.scroll = self.row_start(self.scroll);
+    }
+
     /// How many bytes we can show in a line?
-    pub fn bytes_per_line(&self) -> i32 {
-        let bytes = self.width / 3;
+    pub fn bytes_per_line(&self) -> usize {
+        // A group takes `byte_width` columns per byte plus a space, but we
+        // don't need the space after the last group. At least one group.
+        (cmp::max((self.width + 1) / self.group_width(), 1) * self.group_size) as usize
+    }
+
+    /// Digits of a byte: two nibbles, or eight bits.
+    fn digits(&self) -> usize {
+        if self.bits {
+            8
+        } else {
+            2
+        }
+    }
+
+    /// Columns needed for a group, including the space after it.
+    fn group_width(&self) -> i32 {
+        self.group_size * byte_width(self.bits) + 1
+    }
+
+    /// Column of the byte in a line, relative to the grid.
+    fn byte_col(&self, byte_in_line: usize) -> i32 {
+        let byte_in_line = byte_in_line as i32;
+        (byte_in_line / self.group_size) * self.group_width()
+            + (byte_in_line % self.group_size) * byte_width(self.bits)
+    }
 
-        // Can we fit one more column?
-        if self.width % 3 == 2 {
-            bytes + 1
+    /// Column of the digit in its byte. Bits 4-7 are after the separator.
+    fn digit_col(&self, digit: usize) -> i32 {
+        if self.bits && digit >= 4 {
+            digit as i32 + 1
         } else {
-            bytes
+            digit as i32
         }
     }
 
-    /// Effective width of a line (e.g. ignores extra trailing space that we
-    /// can't utilize)
-    fn cols_per_line(&self) -> i32 {
-        self.bytes_per_line() * 3 - 1
+    /// Screen column of the byte in a line.
+    pub fn column_x(&self, byte_in_line: usize) -> i32 {
+        self.pos_x + self.byte_col(byte_in_line)
     }
 
     /// How many lines needed to draw the entire file?
-    fn total_lines_needed(&self) -> i32 {
-        let len = self.data.len() as i32;
+    fn total_lines_needed(&self) -> usize {
+        self.len.div_ceil(self.bytes_per_line())
+    }
+
+    /// The view can scroll until there are `EOF_PADDING` rows after the last
+    /// line.
+    fn max_scroll(&self) -> usize {
+        let rows = (self.height as usize).saturating_sub(EOF_PADDING);
+        self.rows_up(self.total_lines_needed(), rows)
+    }
+
+    /// Parts of a squeezed range that are drawn as `*` rows. The cursor's line
+    /// is shown, splitting the range it's in. Parts can be empty.
+    fn squeezed_parts(&self, range: (usize, usize)) -> [(usize, usize); 2] {
+        let (start, end) = range;
+        if self.cursor_y >= start && self.cursor_y < end {
+            [(start, self.cursor_y), (self.cursor_y + 1, end)]
+        } else {
+            [(start, end), (end, end)]
+        }
+    }
+
+    /// Lines drawn in the same `*` row as `line`, or `None` if the line is
+    /// shown.
+    fn squeezed_row(&self, line: usize) -> Option<(usize, usize)> {
+        let idx = self.squeezed
+            .binary_search_by(|&(start, end)| {
+                if end <= line {
+                    Ordering::Less
+                } else if start > line {
+                    Ordering::Greater
+                } else {
+                    Ordering::Equal
+                }
+            })
+            .ok()?;
+        self.squeezed_parts(self.squeezed[idx])
+            .iter()
+            .cloned()
+            .find(|&(start, end)| line >= start && line < end)
+    }
+
+    /// First line of the row of `line`.
+    fn row_start(&self, line: usize) -> usize {
+        self.squeezed_row(line).map_or(line, |(start, _)| start)
+    }
+
+    /// First line of the row after the row of `line`.
+    fn next_row(&self, line: usize) -> usize {
+        self.squeezed_row(line).map_or(line + 1, |(_, end)| end)
+    }
+
+    /// First line of the row `n` rows above the row starting at `line`, or 0.
+    fn rows_up(&self, line: usize, n: usize) -> usize {
+        let mut line = line;
+        for _ in 0..n {
+            if line == 0 {
+                break;
+            }
+            line = self.row_start(line - 1);
+        }
+        line
+    }
+
+    /// Rows from the row starting at `from` to the row starting at `to`.
+    fn rows_between(&self, from: usize, to: usize) -> usize {
+        if to <= from {
+            return 0;
+        }
+        let mut rows = to - from;
+        let first = self.squeezed
+            .binary_search_by(|&(_, end)| {
+                if end <= from {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                }
+            })
+            .unwrap_err();
+        for &range in &self.squeezed[first..] {
+            if range.0 >= to {
+                break;
+            }
+            for &(start, end) in &self.squeezed_parts(range) {
+                if start >= from && start < to && end > start {
+                    rows -= end - start - 1;
+                }
+            }
+        }
+        rows
+    }
+
+    /// Rows shown in the view, from the top. Rows after the end of the buffer
+    /// are not included.
+    pub fn rows(&self) -> Vec<Row> {
+        let total_lines = self.total_lines_needed();
+        let mut rows = Vec::new();
+        let mut line = self.scroll;
+        while rows.len() < self.height as usize && line < total_lines {
+            rows.push(if self.squeezed_row(line).is_some() {
+                Row::Squeezed
+            } else {
+                Row::Line(line)
+            });
+            line = self.next_row(line);
+        }
+        rows
+    }
+
+    /// Start and (exclusive) end of the bytes in the view, including the
+    /// lines of squeezed rows.
+    pub fn visible_range(&self) -> (usize, usize) {
+        let total_lines = self.total_lines_needed();
+        let mut line = self.scroll;
+        for _ in 0..self.height {
+            if line >= total_lines {
+                break;
+            }
+            line = self.next_row(line);
+        }
         let bpl = self.bytes_per_line();
-        // round up
-        (len + bpl - 1) / bpl
+        (cmp::min(self.scroll * bpl, self.len), cmp::min(line * bpl, self.len))
+    }
+
+    /// Line below `line` that the cursor can move to, skipping squeezed rows.
+    fn line_below(&self, line: usize) -> Option<usize> {
+        let below = self.squeezed_row(line + 1).map_or(line + 1, |(_, end)| end);
+        if below < self.total_lines_needed() {
+            Some(below)
+        } else {
+            None
+        }
+    }
+
+    /// Line above `line` that the cursor can move to, skipping squeezed rows.
+    fn line_above(&self, line: usize) -> Option<usize> {
+        if line == 0 {
+            return None;
+        }
+        // Squeezed lines are the same as the line before them, so that line
+        // is shown
+        Some(self.squeezed_row(line - 1).map_or(line - 1, |(start, _)| start - 1))
     }
 
     /// How many bytes do we render in last line? (this is usually different
     /// than self.width)
-    fn last_line_bytes(&self) -> i32 {
-        (self.data.len() % self.bytes_per_line() as usize) as i32
-    }
-
-    /// Unconditionally increment the Y position. Updates X position if there's
-    /// not enough columns in the next line.
-    ///
-    /// This doesn't update anything other than X and Y positions.
-    /// Only post-condition: post(self.pos_y) = self.pos_y + 1.
-    fn move_next_line(&mut self) {
-        let max_y = self.total_lines_needed() - 1;
-        debug_assert!(self.cursor_y + 1 <= max_y);
-        if self.cursor_y + 1 == max_y {
-            let last_line_bytes = self.last_line_bytes();
-            let last_line_cols = (last_line_bytes - 1) * 3 + 2;
-            if self.cursor_x >= last_line_cols {
-                self.cursor_x = last_line_cols - 1;
-            }
+    fn last_line_bytes(&self) -> usize {
+        let bpl = self.bytes_per_line();
+        let rem = self.len % bpl;
+        if rem == 0 {
+            bpl
+        } else {
+            rem
+        }
+    }
+
+    /// How many bytes are there in the cursor's line? 0 in empty buffers.
+    fn cursor_line_bytes(&self) -> usize {
+        if self.len == 0 {
+            0
+        } else if self.cursor_y + 1 == self.total_lines_needed() {
+            self.last_line_bytes()
+        } else {
+            self.bytes_per_line()
         }
-        self.cursor_y += 1;
     }
 
-    pub fn get_byte_idx(&self) -> i32 {
-        self.cursor_y * self.bytes_per_line() + self.cursor_x / 3
+    pub fn get_byte_idx(&self) -> usize {
+        self.cursor_y * self.bytes_per_line() + self.cursor_digit / self.digits()
     }
 
+    /// Digit of the byte the cursor is on, 0 for the most significant one.
+    pub fn get_digit(&self) -> usize {
+        self.cursor_digit % self.digits()
+    }
+
+    /// Column of the cursor in the grid.
     pub fn get_column(&self) -> i32 {
-        self.cursor_x
+        self.byte_col(self.cursor_digit / self.digits()) + self.digit_col(self.get_digit())
     }
 
-    pub fn get_row(&self) -> i32 {
+    pub fn get_row(&self) -> usize {
         self.cursor_y
     }
 
-    pub fn get_scroll(&self) -> i32 {
+    pub fn get_scroll(&self) -> usize {
         self.scroll
     }
 
+    /// Scroll to show `line` at the top. Ignored when the cursor wouldn't be
+    /// in the view.
+    pub fn set_scroll(&mut self, line: usize) {
+        let line = self.row_start(cmp::min(line, self.max_scroll()));
+        if line > self.cursor_y || self.rows_between(line, self.cursor_y) >= self.height as usize {
+            return;
+        }
+        self.scroll = line;
+        self.update_ascii_view();
+        self.update_lines();
+    }
+
     pub fn try_center_scroll(&mut self) {
-        if self.cursor_y - self.height / 2 >= 0 {
-            self.scroll = self.cursor_y - self.height / 2;
+        let half_height = (self.height / 2) as usize;
+        if self.rows_between(0, self.cursor_y) >= half_height {
+            let scroll = self.rows_up(self.cursor_y, half_height);
+            self.scroll = cmp::min(scroll, self.max_scroll());
+        }
+    }
+
+    /// How many lines a vertical motion key pressed at `time` moves: 1 for
+    /// single taps, more the longer the key is held.
+    fn repeat_step(&mut self, key: Key, time: Instant) -> usize {
+        let count = match self.repeat {
+            Some(ref r) if r.key == key && time.duration_since(r.last) <= REPEAT_GAP =>
+                r.count + 1,
+            _ =>
+                0,
+        };
+        self.repeat = Some(KeyRepeat {
+            key,
+            last: time,
+            count,
+        });
+
+        if count < REPEAT_FAST {
+            1
+        } else if count < REPEAT_PAGE {
+            5
+        } else {
+            cmp::max(self.height, 1) as usize
         }
     }
 
-    pub fn keypressed(&mut self, key: Key) -> bool {
+    fn move_up(&mut self) {
+        let line = match self.line_above(self.cursor_y) {
+            Some(line) =>
+                line,
+            None =>
+                return,
+        };
+        self.move_to_row(line);
+        // Keep two rows above the cursor when possible
+        while self.scroll > 0
+            && (self.cursor_y < self.scroll || self.rows_between(self.scroll, self.cursor_y) < 2)
+        {
+            self.scroll = self.row_start(self.scroll - 1);
+        }
+    }
+
+    fn move_down(&mut self) {
+        let line = match self.line_below(self.cursor_y) {
+            Some(line) =>
+                line,
+            None =>
+                return,
+        };
+        self.move_to_row(line);
+        // Keep two rows below the cursor, unless there's nothing to show there
+        let height = self.height as usize;
+        let max_scroll = self.max_scroll();
+        while self.scroll < max_scroll && self.rows_between(self.scroll, self.cursor_y) + 3 > height {
+            self.scroll = self.next_row(self.scroll);
+        }
+    }
+
+    /// Move the cursor to a line, keeping the column when possible. Doesn't
+    /// scroll.
+    fn move_to_row(&mut self, row: usize) {
+        self.cursor_y = row;
+        let line_digits = self.cursor_line_bytes() * self.digits();
+        self.cursor_digit = cmp::min(self.cursor_digit, line_digits.saturating_sub(1));
+        // The old cursor line may be squeezed now
+        self.scroll = self.row_start(self.scroll);
+    }
+
+    /// Offset of the byte `n` lines below the cursor, not counting squeezed
+    /// lines, or the last byte.
+    fn offset_below(&self, n: usize) -> usize {
+        let mut line = self.cursor_y;
+        for _ in 0..n {
+            match self.line_below(line) {
+                Some(below) =>
+                    line = below,
+                None =>
+                    return self.len.saturating_sub(1),
+            }
+        }
+        line * self.bytes_per_line() + self.cursor_digit / self.digits()
+    }
+
+    /// Offset of the byte `n` lines above the cursor, not counting squeezed
+    /// lines, or 0.
+    fn offset_above(&self, n: usize) -> usize {
+        let mut line = self.cursor_y;
+        for _ in 0..n {
+            match self.line_above(line) {
+                Some(above) =>
+                    line = above,
+                None =>
+                    return 0,
+            }
+        }
+        line * self.bytes_per_line() + self.cursor_digit / self.digits()
+    }
+
+    /// `time`: when the key was read, for accelerating held j/k.
+    pub fn keypressed(&mut self, key: Key, time: Instant) -> bool {
         match key {
             Key::Arrow(Arrow::Up) | Key::Char('k') => {
-                if self.cursor_y > self.scroll + 2 && self.cursor_y > 0 {
-                    self.cursor_y -= 1;
-                } else if self.scroll > 0 {
-                    self.scroll -= 1;
-                    self.cursor_y -= 1;
-                } else if self.cursor_y - 1 >= 0 {
-                    self.cursor_y -= 1
+                for _ in 0..self.repeat_step(key, time) {
+                    self.move_up();
                 }
 
                 self.update_ascii_view();
@@ -150,24 +494,8 @@ impl<'grid> HexGrid<'grid> {
                 true
             }
             Key::Arrow(Arrow::Down) | Key::Char('j') => {
-                // TODO: This assumes there's at least one line
-                let max_y = self.total_lines_needed() - 1;
-
-                if self.cursor_y < self.scroll + self.height - 3 && self.cursor_y < max_y {
-                    self.move_next_line();
-                } else if self.cursor_y < max_y {
-                    // We want to scroll, but is there a text to show? Otherwise we
-                    // just move cursor down.
-                    if self.scroll + self.height <= max_y {
-                        // We can scroll
-                        self.scroll += 1;
-                        // We move the cursor too, because it's not relative to the
-                        // current scroll
-                        self.cursor_y += 1;
-                    } else {
-                        // We can't scroll but there's a line that we can move to
-                        self.move_next_line();
-                    }
+                for _ in 0..self.repeat_step(key, time) {
+                    self.move_down();
                 }
 
                 self.update_ascii_view();
@@ -176,11 +504,8 @@ impl<'grid> HexGrid<'grid> {
                 true
             }
             Key::Arrow(Arrow::Left) | Key::Char('h') => {
-                if self.cursor_x > 0 {
-                    self.cursor_x -= 1;
-                    if (self.cursor_x + 1) % 3 == 0 {
-                        self.cursor_x -= 1;
-                    }
+                if self.cursor_digit > 0 {
+                    self.cursor_digit -= 1;
                 }
 
                 self.update_ascii_view();
@@ -189,29 +514,34 @@ impl<'grid> HexGrid<'grid> {
                 true
             }
             Key::Arrow(Arrow::Right) | Key::Char('l') => {
-                let next_on_blank =
-                // add 1 to move to next column
-                // add 1 to make the index 1-based
-                (self.cursor_x + 1 + 1) % 3 == 0;
-
-                let total_lines = self.total_lines_needed();
-                let last_col_in_line =
-                // FIXME: This won't work on empty files
-                if self.cursor_y + 1 == total_lines {
-                    // We're on the last line
-                    (self.last_line_bytes() - 1) * 3 + 2
-                } else {
-                    self.cols_per_line()
-                };
+                if self.cursor_digit + 1 < self.cursor_line_bytes() * self.digits() {
+                    self.cursor_digit += 1;
+                }
 
-                let potential_next_col = if next_on_blank {
-                    self.cursor_x + 2
+                self.update_ascii_view();
+                self.update_lines();
+                self.update_info_line();
+                true
+            }
+            Key::PageUp | Key::PageDown if self.sector_size.is_some() => {
+                let size = self.sector_size.unwrap();
+                let current_cursor = self.get_byte_idx();
+                let new_cursor = if key == Key::PageUp {
+                    current_cursor.saturating_sub(size)
                 } else {
-                    self.cursor_x + 1
+                    cmp::min(current_cursor.saturating_add(size), self.len.saturating_sub(1))
                 };
 
-                if potential_next_col <= last_col_in_line {
-                    self.cursor_x = potential_next_col;
+                self.move_cursor_offset(new_cursor);
+                true
+            }
+            Key::PageUp | Key::PageDown => {
+                for _ in 0..self.height {
+                    if key == Key::PageUp {
+                        self.move_up();
+                    } else {
+                        self.move_down();
+                    }
                 }
 
                 self.update_ascii_view();
@@ -219,31 +549,62 @@ impl<'grid> HexGrid<'grid> {
                 self.update_info_line();
                 true
             }
-            Key::Char('G') => {
-                self.move_cursor_offset(self.data.len() as i32 - 1);
+            Key::Home | Key::Char('0') => {
+                self.cursor_digit = 0;
+
+                self.update_ascii_view();
+                self.update_lines();
+                self.update_info_line();
                 true
             }
-            Key::Ctrl('d') => {
-                let current_cursor = self.get_byte_idx();
-                let bytes_per_line = self.bytes_per_line();
+            Key::End | Key::Char('$') => {
+                if self.len != 0 {
+                    // First digit of the last byte
+                    self.cursor_digit = (self.cursor_line_bytes() - 1) * self.digits();
+                }
 
-                let new_cursor = current_cursor + 10 * bytes_per_line;
-                let new_cursor = if new_cursor > (self.data.len() as i32) - 1 {
-                    (self.data.len() as i32) - 1
-                } else {
-                    new_cursor
-                };
+                self.update_ascii_view();
+                self.update_lines();
+                self.update_info_line();
+                true
+            }
+            Key::Char('H') | Key::Char('M') | Key::Char('L') => {
+                // Top, middle, or bottom row of the screen with bytes
+                let lines: Vec<usize> = self.rows().iter().filter_map(|row| match *row {
+                    Row::Line(line) =>
+                        Some(line),
+                    Row::Squeezed =>
+                        None,
+                }).collect();
+                if !lines.is_empty() {
+                    let idx = match key {
+                        Key::Char('H') =>
+                            0,
+                        Key::Char('M') =>
+                            (lines.len() - 1) / 2,
+                        _ =>
+                            lines.len() - 1,
+                    };
+                    self.move_to_row(lines[idx]);
+                }
 
+                self.update_ascii_view();
+                self.update_lines();
+                self.update_info_line();
+                true
+            }
+            Key::Char('G') => {
+                let last = self.len.saturating_sub(1);
+                self.move_cursor_offset(last);
+                true
+            }
+            Key::Ctrl('d') => {
+                let new_cursor = self.offset_below(10);
                 self.move_cursor_offset(new_cursor);
                 true
             }
             Key::Ctrl('u') => {
-                let current_cursor = self.get_byte_idx();
-                let bytes_per_line = self.bytes_per_line();
-
-                let new_cursor = current_cursor - 10 * bytes_per_line;
-                let new_cursor = if new_cursor < 0 { 0 } else { new_cursor };
-
+                let new_cursor = self.offset_above(10);
                 self.move_cursor_offset(new_cursor);
                 true
             }
@@ -252,134 +613,283 @@ impl<'grid> HexGrid<'grid> {
         }
     }
 
+    /// Scroll the view by `lines` (negative is up). The cursor stays where it
+    /// is, unless it goes out of the view.
+    pub fn scroll_lines(&mut self, lines: isize) {
+        let total_lines = self.total_lines_needed();
+        if total_lines == 0 {
+            return;
+        }
+
+        if lines < 0 {
+            self.scroll = self.rows_up(self.scroll, (-lines) as usize);
+        } else {
+            let max_scroll = self.max_scroll();
+            for _ in 0..lines {
+                if self.scroll >= max_scroll {
+                    break;
+                }
+                self.scroll = self.next_row(self.scroll);
+            }
+        }
+
+        // Keep the cursor in the view
+        let shown: Vec<usize> = self.rows()
+            .iter()
+            .filter_map(|row| match *row {
+                Row::Line(line) =>
+                    Some(line),
+                Row::Squeezed =>
+                    None,
+            })
+            .collect();
+        if let (Some(&first), Some(&last)) = (shown.first(), shown.last()) {
+            if self.cursor_y < first {
+                self.move_to_row(first);
+            } else if self.cursor_y > last {
+                self.move_to_row(last);
+            }
+        }
+
+        self.update_ascii_view();
+        self.update_lines();
+        self.update_info_line();
+    }
+
+    /// Byte and digit (0 for the most significant one) at the given screen
+    /// position, or `None` if there's no byte there.
+    pub fn offset_at(&self, x: i32, y: i32) -> Option<(usize, usize)> {
+        let col = x - self.pos_x;
+        let row = y - self.pos_y;
+        if col < 0 || col >= self.width || row < 0 || row >= self.height {
+            return None;
+        }
+
+        // Column in the group, the space after the group is not a byte
+        let col_in_group = col % self.group_width();
+        if col_in_group == self.group_size * byte_width(self.bits) {
+            return None;
+        }
+        let byte_in_line = (col / self.group_width() * self.group_size
+            + col_in_group / byte_width(self.bits)) as usize;
+        if byte_in_line >= self.bytes_per_line() {
+            return None;
+        }
+
+        let line = match self.rows().get(row as usize) {
+            Some(&Row::Line(line)) =>
+                line,
+            _ =>
+                return None,
+        };
+        let byte_idx = line * self.bytes_per_line() + byte_in_line;
+        if byte_idx < self.len {
+            // The nibble separator picks the bit after it
+            let col_in_byte = (col_in_group % byte_width(self.bits)) as usize;
+            let digit = if self.bits && col_in_byte > 4 {
+                col_in_byte - 1
+            } else {
+                col_in_byte
+            };
+            Some((byte_idx, digit))
+        } else {
+            None
+        }
+    }
+
+    /// Like `move_cursor_offset`, but puts the cursor on a digit of the byte
+    /// (0 for the most significant one).
+    pub fn move_cursor_digit(&mut self, byte_idx: usize, digit: usize) {
+        self.move_cursor_offset(byte_idx);
+        if byte_idx < self.len {
+            self.cursor_digit += cmp::min(digit, self.digits() - 1);
+        }
+    }
+
     pub fn update_ascii_view(&self) {
         let gui: &mut HexGui = unsafe { &mut *self.gui };
-        gui.get_ascii_view().move_cursor_offset(self.get_byte_idx());
-        gui.get_info_line().set_text(format!(
-            "{} - {}: {} (scroll: {})",
-            self.path,
-            self.get_row(),
-            self.get_column(),
-            self.get_scroll()
-        ));
+        let ascii_view = gui.get_ascii_view();
+        ascii_view.move_cursor_offset(self.get_byte_idx());
     }
 
     pub fn update_lines(&self) {
         let gui: &mut HexGui = unsafe { &mut *self.gui };
-        gui.get_lines().move_cursor_offset(self.get_byte_idx());
+        let lines = gui.get_lines();
+        lines.move_cursor_offset(self.get_byte_idx());
     }
 
     pub fn update_info_line(&self) {
         let gui: &mut HexGui = unsafe { &mut *self.gui };
-        gui.get_info_line().set_text(format!(
-            "{} - {}: {} (scroll: {})",
-            self.path,
-            self.get_row(),
-            self.get_column(),
-            self.get_scroll()
-        ));
+        gui.update_info_line();
     }
 
-    pub fn draw(&self, tb: &mut Termbox, hl: &[usize], hl_len: usize) {
-        let cols = self.bytes_per_line();
-        let rows = self.height;
+    pub fn draw(&self, tb: &mut Termbox, data: &Buffer, highlights: &ByteHighlights) {
+        let ByteHighlights {
+            hl,
+            same_byte,
+            selection,
+            virtual_ranges,
+            template_ranges,
+            elf_ranges,
+            reference,
+            byte_colors,
+        } = *highlights;
+
+        if data.is_empty() {
+            let msg: String = EMPTY_FILE_MSG.chars().take(self.width as usize).collect();
+            print(tb, self.pos_x, self.pos_y, colors::palette().default, &msg);
+            return;
+        }
 
-        let mut hl_idx = 0;
+        let in_selection = |byte_idx: usize| {
+            selection.is_some_and(|(start, end)| byte_idx >= start && byte_idx < end)
+        };
 
-        'outer: for row in self.scroll..self.scroll + rows {
+        let cols = self.bytes_per_line();
+        let rows = self.rows();
+
+        for (row_idx, row) in rows.iter().enumerate() {
+            let pos_y = self.pos_y + row_idx as i32;
+            let line = match *row {
+                Row::Line(line) =>
+                    line,
+                Row::Squeezed => {
+                    let style = colors::palette().default;
+                    tb.change_cell(self.pos_x, pos_y, '*', style.fg, style.bg);
+                    continue;
+                }
+            };
             for col in 0..cols {
-                let byte_idx = (row * cols + col) as usize;
-                if let Some(&byte) = self.data.get(byte_idx) {
-                    let char1: u8 = hex_char(byte >> 4);
-                    let char2: u8 = hex_char(byte & 0b0000_1111);
-
-                    let attr_1 = col * 3 == self.cursor_x && row == self.cursor_y;
-                    let attr_2 = col * 3 + 1 == self.cursor_x && row == self.cursor_y;
-
-                    let mut highlight = false;
-                    let style = if let Some(&hl_offset) = hl.get(hl_idx) {
-                        if byte_idx >= hl_offset && byte_idx < hl_offset + hl_len {
-                            highlight = true;
-                            colors::HIGHLIGHT
+                let byte_idx = line * cols + col;
+                if let Some(byte) = data.get(byte_idx) {
+                    let byte_col = self.byte_col(col);
+
+                    let hl_end = hl.range_end(byte_idx);
+                    let highlight = hl_end.is_some();
+                    let selected = in_selection(byte_idx);
+                    let style = if selected {
+                        colors::palette().selection
+                    } else if highlight {
+                        colors::palette().highlight
+                    } else if reference.is_some_and(|r| r.get(byte_idx) != Some(&byte)) {
+                        colors::palette().diff
+                    } else if in_ranges(virtual_ranges, byte_idx) {
+                        colors::palette().virtual_byte
+                    } else if let Some(idx) = range_index(template_ranges, byte_idx) {
+                        if idx % 2 == 0 {
+                            colors::palette().template_field
                         } else {
-                            colors::DEFAULT
+                            colors::palette().template_field_alt
                         }
+                    } else if same_byte == Some(byte) {
+                        colors::palette().same_byte
+                    } else if let Some(idx) = range_index(elf_ranges, byte_idx) {
+                        let styles = colors::palette().elf_regions;
+                        let region = styles[idx % styles.len()];
+                        // Byte colors show on the region's background
+                        match byte_colors.get(byte) {
+                            Some(style) if style.fg != colors::palette().default.fg =>
+                                Style {
+                                    fg: style.fg,
+                                    bg: region.bg,
+                                },
+                            _ =>
+                                region,
+                        }
+                    } else if let Some(style) = byte_colors.get(byte) {
+                        style
                     } else {
-                        colors::DEFAULT
+                        colors::palette().default
                     };
 
-                    while hl_idx < hl.len() && hl[hl_idx] + hl_len < byte_idx {
-                        hl_idx += 1;
-                    }
-
-                    tb.change_cell(
-                        self.pos_x + col * 3,
-                        self.pos_y + row - self.scroll,
-                        char1 as char,
-                        if attr_1 {
-                            colors::CURSOR_NO_FOCUS.fg
+                    let digits = self.digits();
+                    for digit in 0..digits {
+                        let ch = if self.bits {
+                            if byte & (0x80 >> digit) == 0 { '0' } else { '1' }
+                        } else if digit == 0 {
+                            hex_char(byte >> 4) as char
                         } else {
-                            style.fg
-                        },
-                        if attr_1 {
-                            colors::CURSOR_NO_FOCUS.bg
+                            hex_char(byte & 0b0000_1111) as char
+                        };
+                        let cursor =
+                            line == self.cursor_y && col * digits + digit == self.cursor_digit;
+                        let style = if cursor {
+                            colors::palette().cursor_no_focus
                         } else {
-                            style.bg
-                        },
-                    );
-
-                    tb.change_cell(
-                        self.pos_x + col * 3 + 1,
-                        self.pos_y + row - self.scroll,
-                        char2 as char,
-                        if attr_2 {
-                            colors::CURSOR_NO_FOCUS.fg
-                        } else {
-                            style.fg
-                        },
-                        if attr_2 {
-                            colors::CURSOR_NO_FOCUS.bg
+                            style
+                        };
+                        tb.change_cell(
+                            self.pos_x + byte_col + self.digit_col(digit),
+                            pos_y,
+                            ch,
+                            style.fg,
+                            style.bg,
+                        );
+                    }
+                    if self.bits {
+                        // Between the nibbles, dimmed unless the byte has a
+                        // background
+                        let sep = if style.bg == colors::palette().default.bg {
+                            colors::palette().virtual_byte
                         } else {
-                            style.bg
-                        },
-                    );
+                            style
+                        };
+                        tb.change_cell(self.pos_x + byte_col + 4, pos_y, '·', sep.fg, sep.bg);
+                    }
 
-                    // When highlighting a word, paint the space between bytes too
-                    let highlight = highlight && byte_idx + 1 < hl[hl_idx] + hl_len;
+                    // When highlighting a word or selecting, paint the space
+                    // between groups too
+                    let highlight = hl_end.is_some_and(|end| byte_idx + 1 < end);
+                    let selected = selected && in_selection(byte_idx + 1);
+                    let group_end = (col + 1) % self.group_size as usize == 0;
 
-                    let space_col = self.pos_x + col * 3 + 2;
-                    if highlight && space_col < self.width - 1 {
+                    if (highlight || selected) && group_end && col + 1 < cols {
+                        let style = if selected {
+                            colors::palette().selection
+                        } else {
+                            colors::palette().highlight
+                        };
                         tb.change_cell(
-                            space_col,
-                            self.pos_y + row - self.scroll,
+                            self.pos_x + byte_col + byte_width(self.bits),
+                            pos_y,
                             ' ',
-                            colors::HIGHLIGHT.fg,
-                            colors::HIGHLIGHT.bg,
+                            style.fg,
+                            style.bg,
                         );
                     }
                 } else {
-                    // Nothing to draw here, also we can break the loop
-                    break 'outer;
+                    // Nothing to draw here, the rest of the last line
+                    break;
                 }
             }
         }
+
+        // Rows after the end of the buffer
+        let style = colors::palette().virtual_byte;
+        for row_idx in rows.len()..self.height as usize {
+            let pos_y = self.pos_y + row_idx as i32;
+            tb.change_cell(self.pos_x, pos_y, '~', style.fg, style.bg);
+        }
     }
 
-    pub fn move_cursor_offset(&mut self, byte_idx: i32) {
-        let byte_idx = cmp::min((self.data.len() - 1) as i32, byte_idx);
+    pub fn move_cursor_offset(&mut self, byte_idx: usize) {
+        let byte_idx = cmp::min(self.len.saturating_sub(1), byte_idx);
 
         let bpl = self.bytes_per_line();
         self.cursor_y = byte_idx / bpl;
-        self.cursor_x = (byte_idx % bpl) * 3;
+        self.cursor_digit = (byte_idx % bpl) * self.digits();
+        self.scroll = self.row_start(self.scroll);
 
-        let min_scroll = cmp::max(0, self.cursor_y - self.height + 3);
-        let max_scroll = cmp::max(0, self.cursor_y - 3);
+        let min_scroll = self.rows_up(self.cursor_y, (self.height as usize).saturating_sub(3));
+        let max_scroll = self.rows_up(self.cursor_y, 3);
 
         if self.scroll > max_scroll {
             self.scroll = max_scroll;
         } else if self.scroll < min_scroll {
             self.scroll = min_scroll;
         }
+        // After the buffer gets smaller
+        self.scroll = cmp::min(self.scroll, self.max_scroll());
 
         self.update_ascii_view();
         self.update_lines();
@@ -0,0 +1,143 @@
+////////////////////////////////////////////////////////////////////////////////
+// Highlighted ranges of the buffer, e.g. search matches. Kept sorted so that
+// the hex and ASCII views only look at the highlights of the visible bytes.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cmp::{self, Ordering};
+
+use colors::ByteColors;
+
+/// What the hex grid and the ASCII view color, other than the cursor.
+pub struct ByteHighlights<'a> {
+    pub hl: &'a Highlights,
+    /// Highlight bytes with this value.
+    pub same_byte: Option<u8>,
+    /// Start and (exclusive) end of the selected bytes.
+    pub selection: Option<(usize, usize)>,
+    /// Ranges of bytes that are not in the file.
+    pub virtual_ranges: &'a [(usize, usize)],
+    /// Fields of the applied template.
+    pub template_ranges: &'a [(usize, usize)],
+    /// Parts of an ELF file, see `elf_regions`. Only colored in the hex grid.
+    pub elf_ranges: &'a [(usize, usize)],
+    /// Contents of the reference file, differing bytes are colored.
+    pub reference: Option<&'a [u8]>,
+    /// Styles of byte values, below the other highlights.
+    pub byte_colors: &'a ByteColors,
+}
+
+pub struct Highlights {
+    /// `(start, end)` ranges (end exclusive), sorted and without duplicates.
+    /// Ranges can overlap, e.g. matches of "aa" in "aaa".
+    ranges: Vec<(usize, usize)>,
+
+    /// Length of the longest range. Ranges with an offset start at most this
+    /// many bytes before it.
+    max_len: usize,
+}
+
+impl Highlights {
+    pub fn new() -> Highlights {
+        Highlights {
+            ranges: Vec::new(),
+            max_len: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.ranges.clear();
+        self.max_len = 0;
+    }
+
+    /// Replace the highlights with the ranges.
+    pub fn set<I: IntoIterator<Item = (usize, usize)>>(&mut self, ranges: I) {
+        self.clear();
+        self.add(ranges);
+    }
+
+    /// Add the ranges, keeping the highlights sorted. Empty ranges and ranges
+    /// that are already highlighted are ignored.
+    pub fn add<I: IntoIterator<Item = (usize, usize)>>(&mut self, ranges: I) {
+        let old_len = self.ranges.len();
+        self.ranges.extend(ranges.into_iter().filter(|&(start, end)| start < end));
+        for &(start, end) in &self.ranges[old_len..] {
+            self.max_len = cmp::max(self.max_len, end - start);
+        }
+        // Cheap when the old and new ranges are already sorted, e.g. search
+        // matches
+        self.ranges.sort();
+        self.ranges.dedup();
+    }
+
+    /// Index of the first range that starts at or after the offset.
+    fn lower_bound(&self, offset: usize) -> usize {
+        let cmp = |&(start, _): &(usize, usize)| {
+            if start < offset {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            }
+        };
+        match self.ranges.binary_search_by(cmp) {
+            Ok(idx) | Err(idx) =>
+                idx,
+        }
+    }
+
+    /// End of the highlight that has the offset and extends the furthest,
+    /// when the offset is highlighted.
+    pub fn range_end(&self, offset: usize) -> Option<usize> {
+        if self.max_len == 0 {
+            return None;
+        }
+        let first = self.lower_bound((offset + 1).saturating_sub(self.max_len));
+        self.ranges[first..]
+            .iter()
+            .take_while(|&&(start, _)| start <= offset)
+            .map(|&(_, end)| end)
+            .filter(|&end| end > offset)
+            .max()
+    }
+
+    /// Index of the highlight that starts at the offset, when there is one.
+    pub fn position(&self, offset: usize) -> Option<usize> {
+        let idx = self.lower_bound(offset);
+        match self.ranges.get(idx) {
+            Some(&(start, _)) if start == offset =>
+                Some(idx),
+            _ =>
+                None,
+        }
+    }
+
+    pub fn first_start(&self) -> Option<usize> {
+        self.ranges.first().map(|&(start, _)| start)
+    }
+
+    pub fn last_start(&self) -> Option<usize> {
+        self.ranges.last().map(|&(start, _)| start)
+    }
+
+    /// Start of the first highlight after the offset.
+    pub fn next_start(&self, offset: usize) -> Option<usize> {
+        self.ranges.get(self.lower_bound(offset + 1)).map(|&(start, _)| start)
+    }
+
+    /// Start of the last highlight before the offset.
+    pub fn prev_start(&self, offset: usize) -> Option<usize> {
+        match self.lower_bound(offset) {
+            0 =>
+                None,
+            idx =>
+                Some(self.ranges[idx - 1].0),
+        }
+    }
+}
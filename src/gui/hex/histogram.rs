@@ -0,0 +1,124 @@
+use std::cmp;
+
+use colors;
+use gui::hex::entropy::entropy;
+use utils::*;
+
+use term_input::Key;
+use termbox_simple::*;
+
+pub enum HistogramRet {
+    /// User closed the overlay.
+    Abort,
+
+    /// Overlay still has focus.
+    Continue,
+}
+
+/// Shows frequencies of byte values as a bar chart, with entropy and the most
+/// common bytes.
+pub struct HistogramOverlay {
+    pos_x: i32,
+    pos_y: i32,
+    width: i32,
+    height: i32,
+
+    counts: Box<[usize; 256]>,
+    total: usize,
+}
+
+impl HistogramOverlay {
+    /// `counts` of each byte value in the `total` bytes.
+    pub fn new(
+        width: i32,
+        height: i32,
+        pos_x: i32,
+        pos_y: i32,
+        counts: Box<[usize; 256]>,
+        total: usize,
+    ) -> HistogramOverlay {
+        let width_ = cmp::min(width, 70);
+        let height_ = cmp::min(height, 20);
+
+        let pos_x = pos_x + (width - width_) / 2;
+        let pos_y = pos_y + (height - height_) / 2;
+
+        HistogramOverlay {
+            pos_x,
+            pos_y,
+            width: width_,
+            height: height_,
+            counts,
+            total,
+        }
+    }
+
+    pub fn draw(&self, tb: &mut Termbox) {
+        draw_box(tb, self.pos_x, self.pos_y, self.width, self.height);
+
+        let title = format!(
+            " Byte histogram - entropy {:.2} bits/byte ",
+            entropy(&self.counts, self.total)
+        );
+        print(tb, self.pos_x + 2, self.pos_y, colors::palette().default, &title);
+
+        // Every column is a bucket of byte values. Use a power of two number
+        // of columns so that buckets are of the same size.
+        let mut columns = 256;
+        while columns > (self.width - 2) as usize {
+            columns /= 2;
+        }
+        let bucket_size = 256 / columns;
+        let buckets: Vec<usize> = self.counts
+            .chunks(bucket_size)
+            .map(|bucket| bucket.iter().sum())
+            .collect();
+        let max_bucket = cmp::max(buckets.iter().cloned().max().unwrap_or(0), 1);
+
+        // Two lines at the bottom for labels and most common bytes
+        let bar_height = cmp::max(self.height - 4, 1) as usize;
+        let bars_x = self.pos_x + 1 + ((self.width - 2) as usize - columns) as i32 / 2;
+        let bars_bottom = self.pos_y + self.height - 4;
+
+        for (column, &count) in buckets.iter().enumerate() {
+            // Round up so that buckets with any bytes get at least one cell
+            let bar = (count * bar_height).div_ceil(max_bucket);
+            for y in 0..bar {
+                tb.change_cell(
+                    bars_x + column as i32,
+                    bars_bottom - y as i32,
+                    '█',
+                    colors::palette().highlight.bg,
+                    colors::palette().default.bg,
+                );
+            }
+        }
+
+        let labels_y = bars_bottom + 1;
+        print(tb, bars_x, labels_y, colors::palette().default, "00");
+        print(tb, bars_x + columns as i32 / 2, labels_y, colors::palette().default, "80");
+        print(tb, bars_x + columns as i32 - 2, labels_y, colors::palette().default, "ff");
+
+        let mut common: Vec<usize> = (0..256).filter(|&b| self.counts[b] != 0).collect();
+        common.sort_by(|&b1, &b2| self.counts[b2].cmp(&self.counts[b1]));
+        let mut line = "Most common:".to_owned();
+        for &byte in common.iter().take(3) {
+            line.push_str(&format!(
+                " {:02x} ({:.1}%)",
+                byte,
+                self.counts[byte] as f32 * 100.0 / self.total as f32
+            ));
+        }
+        let line: String = line.chars().take((self.width - 2) as usize).collect();
+        print(tb, self.pos_x + 1, labels_y + 1, colors::palette().default, &line);
+    }
+
+    pub fn keypressed(&mut self, key: Key) -> HistogramRet {
+        match key {
+            Key::Esc | Key::Char('q') | Key::Char('\r') =>
+                HistogramRet::Abort,
+            _ =>
+                HistogramRet::Continue,
+        }
+    }
+}
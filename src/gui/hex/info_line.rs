@@ -24,14 +24,23 @@ impl InfoLine {
         self.text = text;
     }
 
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
     pub fn draw(&self, tb: &mut Termbox) {
-        let fg = colors::STATUS_BAR.fg;
-        let bg = colors::STATUS_BAR.bg;
+        self.draw_with(tb, &self.text);
+    }
+
+    /// Draw other text in place of ours, without changing it.
+    pub fn draw_with(&self, tb: &mut Termbox, text: &str) {
+        let fg = colors::palette().status_bar.fg;
+        let bg = colors::palette().status_bar.bg;
 
         for x in self.pos_x..=self.pos_x + self.width {
             tb.change_cell(x, self.pos_y, ' ', fg, bg);
         }
 
-        print(tb, self.pos_x, self.pos_y, colors::STATUS_BAR, &self.text);
+        print(tb, self.pos_x, self.pos_y, colors::palette().status_bar, text);
     }
 }
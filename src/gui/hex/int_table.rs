@@ -0,0 +1,197 @@
+use std::cmp;
+
+use buffer::Buffer;
+use colors;
+use config::Config;
+use utils::*;
+
+use term_input::{Arrow, Key};
+use termbox_simple::*;
+
+pub enum IntTableRet {
+    /// Jump to the byte offset.
+    Jump(usize),
+
+    /// User cancelled.
+    Abort,
+
+    /// Overlay still has focus.
+    Continue,
+}
+
+/// Shows the bytes after the cursor as a table of pointer-sized integers, for
+/// reading arrays of pointers (e.g. `.init_array`, GOT). Enter jumps to the
+/// selected value, when it points into the file.
+pub struct IntTableOverlay {
+    pos_x: i32,
+    pos_y: i32,
+    width: i32,
+    height: i32,
+
+    /// Length of the buffer.
+    len: usize,
+
+    /// Byte offset of the first entry.
+    start: usize,
+
+    /// Size of an entry, 4 or 8.
+    size: usize,
+    big_endian: bool,
+
+    /// Values are addresses relative to this base. Used when converting
+    /// values to offsets for jumping.
+    base: Option<usize>,
+
+    selected: usize,
+}
+
+impl IntTableOverlay {
+    pub fn new(
+        width: i32,
+        height: i32,
+        pos_x: i32,
+        pos_y: i32,
+        len: usize,
+        start: usize,
+        config: &Config,
+    ) -> IntTableOverlay {
+        let width_ = cmp::min(width, 60);
+        let height_ = cmp::min(height, 20);
+
+        let pos_x = pos_x + (width - width_) / 2;
+        let pos_y = pos_y + (height - height_) / 2;
+
+        IntTableOverlay {
+            pos_x,
+            pos_y,
+            width: width_,
+            height: height_,
+            len,
+            start,
+            size: 8,
+            big_endian: config.big_endian,
+            base: config.base,
+            selected: 0,
+        }
+    }
+
+    fn n_entries(&self) -> usize {
+        self.len.saturating_sub(self.start) / self.size
+    }
+
+    fn entry_offset(&self, idx: usize) -> usize {
+        self.start + idx * self.size
+    }
+
+    fn entry(&self, data: &Buffer, idx: usize) -> u64 {
+        let offset = self.entry_offset(idx);
+        let mut ret = 0u64;
+        for i in 0..self.size {
+            let i = if self.big_endian { i } else { self.size - 1 - i };
+            ret = (ret << 8) | u64::from(data.get(offset + i).unwrap());
+        }
+        ret
+    }
+
+    /// Byte offset the entry points to, if it's in the file.
+    fn entry_target(&self, data: &Buffer, idx: usize) -> Option<usize> {
+        let value = self.entry(data, idx) as usize;
+        value
+            .checked_sub(self.base.unwrap_or(0))
+            .and_then(|offset| if offset < self.len { Some(offset) } else { None })
+    }
+
+    pub fn draw(&self, tb: &mut Termbox, data: &Buffer) {
+        draw_box(tb, self.pos_x, self.pos_y, self.width, self.height);
+
+        let title = format!(
+            " u{} {} ",
+            self.size * 8,
+            if self.big_endian { "big endian" } else { "little endian" }
+        );
+        print(tb, self.pos_x + 2, self.pos_y, colors::palette().default, &title);
+
+        let n_entries = self.n_entries();
+        if n_entries == 0 {
+            print(tb, self.pos_x + 2, self.pos_y + 1, colors::palette().default, "No entries");
+            return;
+        }
+
+        let rows = (self.height - 4) as usize;
+        let first = if self.selected >= rows {
+            self.selected + 1 - rows
+        } else {
+            0
+        };
+
+        for idx in first..cmp::min(first + rows, n_entries) {
+            let style = if idx == self.selected {
+                colors::palette().cursor_focus
+            } else {
+                colors::palette().default
+            };
+            let value = self.entry(data, idx);
+            let line = format!(
+                "{:>5}  0x{:08x}  0x{:0width$x}{}",
+                idx,
+                self.entry_offset(idx),
+                value,
+                if self.entry_target(data, idx).is_some() { "  ->" } else { "" },
+                width = self.size * 2
+            );
+            let line: String = line.chars().take((self.width - 2) as usize).collect();
+            print(tb, self.pos_x + 1, self.pos_y + 1 + (idx - first) as i32, style, &line);
+        }
+
+        print(
+            tb,
+            self.pos_x + 1,
+            self.pos_y + self.height - 2,
+            colors::palette().default,
+            "Enter: jump  w: 4/8 bytes  e: endianness",
+        );
+    }
+
+    pub fn keypressed(&mut self, key: Key, data: &Buffer) -> IntTableRet {
+        match key {
+            Key::Arrow(Arrow::Up) | Key::Char('k') => {
+                if self.selected > 0 {
+                    self.selected -= 1;
+                }
+                IntTableRet::Continue
+            }
+            Key::Arrow(Arrow::Down) | Key::Char('j') => {
+                if self.selected + 1 < self.n_entries() {
+                    self.selected += 1;
+                }
+                IntTableRet::Continue
+            }
+            Key::Char('w') => {
+                // Keep the selected entry's offset
+                let offset = self.entry_offset(self.selected);
+                self.size = if self.size == 8 { 4 } else { 8 };
+                self.selected = (offset - self.start) / self.size;
+                if self.selected >= self.n_entries() {
+                    self.selected = self.n_entries().saturating_sub(1);
+                }
+                IntTableRet::Continue
+            }
+            Key::Char('e') => {
+                self.big_endian = !self.big_endian;
+                IntTableRet::Continue
+            }
+            Key::Char('\r') => {
+                if self.selected < self.n_entries() {
+                    if let Some(offset) = self.entry_target(data, self.selected) {
+                        return IntTableRet::Jump(offset);
+                    }
+                }
+                IntTableRet::Continue
+            }
+            Key::Esc | Key::Char('q') =>
+                IntTableRet::Abort,
+            _ =>
+                IntTableRet::Continue,
+        }
+    }
+}
@@ -0,0 +1,287 @@
+////////////////////////////////////////////////////////////////////////////////
+// Long operations (searches, hashes, entropy, diffs) run in a worker thread
+// on a copy of the buffer. They wake up the main loop when they finish, the
+// info line shows a progress bar, and Ctrl-c cancels. Small buffers are
+// handled without a thread, as copying would take longer than the work.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cmp;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+use gui::event_loop::Waker;
+use gui::hex::diff::{next_diff, prev_diff};
+use gui::hex::entropy::{block_entropy, byte_counts};
+use gui::hex::search::Pattern;
+use hash::HashKind;
+
+/// Buffers smaller than this are handled on the UI thread.
+pub const BACKGROUND_SIZE: usize = 16 * 1024 * 1024;
+
+/// Progress is updated and cancellation checked after every chunk of this
+/// many bytes. A multiple of the minimap block size.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Width of the progress bar, in characters.
+const BAR_WIDTH: usize = 20;
+
+pub enum Task {
+    /// Find matches, at most `max_matches`. Only in the range (end
+    /// exclusive) when given.
+    Search {
+        pattern: Pattern,
+        max_matches: usize,
+        range: Option<(usize, usize)>,
+    },
+
+    /// `:hash`, of the bytes in the range.
+    Hash { kind: HashKind, start: usize, end: usize },
+
+    /// `:verify`, with the checksum read from the checksum file.
+    Verify { kind: HashKind, expected: String },
+
+    /// Byte counts for the histogram (`#`).
+    Histogram,
+
+    /// Block entropy for the minimap (`E`).
+    Minimap,
+
+    /// Next (or previous) run of bytes that differ from the reference.
+    Diff { reference: Arc<Vec<u8>>, cursor: usize, next: bool },
+}
+
+/// Result of a task, with what's needed to show it.
+pub enum Output {
+    Search {
+        pattern: Pattern,
+        range: Option<(usize, usize)>,
+        matches: Vec<usize>,
+        capped: bool,
+    },
+    Hash { kind: HashKind, start: usize, end: usize, hash: Vec<u8> },
+    Verify { kind: HashKind, expected: String, actual: Vec<u8> },
+    Histogram(Box<[usize; 256]>, usize),
+    Minimap(Vec<f32>),
+    Diff(Option<usize>),
+}
+
+impl Task {
+    /// Shown in the progress bar. Starting a task cancels the running one
+    /// with the same name.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Task::Search { .. } =>
+                "Searching",
+            Task::Hash { .. } =>
+                "Hashing",
+            Task::Verify { .. } =>
+                "Verifying",
+            Task::Histogram =>
+                "Counting bytes",
+            Task::Minimap =>
+                "Computing entropy",
+            Task::Diff { .. } =>
+                "Comparing",
+        }
+    }
+
+    /// Bytes to go through in a buffer of `len` bytes.
+    pub fn total(&self, len: usize) -> usize {
+        match *self {
+            Task::Search { range: Some((start, end)), .. } =>
+                end - start,
+            Task::Hash { start, end, .. } =>
+                end - start,
+            Task::Diff { cursor, next, .. } =>
+                if next {
+                    len - cmp::min(cursor, len)
+                } else {
+                    cursor
+                },
+            _ =>
+                len,
+        }
+    }
+
+    /// `None` when cancelled.
+    pub fn run(self, data: &[u8], progress: &Progress) -> Option<Output> {
+        match self {
+            Task::Search { pattern, max_matches, range } => {
+                let (start, end) = range.unwrap_or((0, data.len()));
+                let (matches, capped) =
+                    search(&pattern, &data[start..end], max_matches, progress)?;
+                let matches = matches.into_iter().map(|offset| start + offset).collect();
+                Some(Output::Search { pattern, range, matches, capped })
+            }
+            Task::Hash { kind, start, end } => {
+                let hash =
+                    kind.hash_with_progress(&data[start..end], |done| progress.update(done))?;
+                Some(Output::Hash { kind, start, end, hash })
+            }
+            Task::Verify { kind, expected } => {
+                let actual = kind.hash_with_progress(data, |done| progress.update(done))?;
+                Some(Output::Verify { kind, expected, actual })
+            }
+            Task::Histogram => {
+                let mut counts = [0; 256];
+                for (idx, chunk) in data.chunks(CHUNK_SIZE).enumerate() {
+                    if !progress.update(idx * CHUNK_SIZE) {
+                        return None;
+                    }
+                    for (count, chunk_count) in counts.iter_mut().zip(byte_counts(chunk).iter()) {
+                        *count += chunk_count;
+                    }
+                }
+                Some(Output::Histogram(Box::new(counts), data.len()))
+            }
+            Task::Minimap => {
+                let mut blocks = Vec::new();
+                for (idx, chunk) in data.chunks(CHUNK_SIZE).enumerate() {
+                    if !progress.update(idx * CHUNK_SIZE) {
+                        return None;
+                    }
+                    blocks.extend(block_entropy(chunk));
+                }
+                Some(Output::Minimap(blocks))
+            }
+            Task::Diff { reference, cursor, next } =>
+                if next {
+                    next_diff(data, &reference, cursor, CHUNK_SIZE, |done| progress.update(done))
+                        .map(Output::Diff)
+                } else {
+                    prev_diff(data, &reference, cursor, CHUNK_SIZE, |done| progress.update(done))
+                        .map(Output::Diff)
+                },
+        }
+    }
+}
+
+/// `Pattern::find_all`, a chunk at a time. Chunks overlap by the pattern
+/// length, matches starting in the overlap are found in the next chunk.
+fn search(
+    pattern: &Pattern,
+    data: &[u8],
+    max: usize,
+    progress: &Progress,
+) -> Option<(Vec<usize>, bool)> {
+    let len = pattern.len();
+    let mut matches = Vec::new();
+    // Matches don't overlap, so the next one starts after the last one
+    let mut next_start = 0;
+    let mut chunk_start = 0;
+    while chunk_start < data.len() {
+        if !progress.update(chunk_start) {
+            return None;
+        }
+        let chunk_end = cmp::min(chunk_start + CHUNK_SIZE, data.len());
+        let begin = cmp::max(chunk_start, next_start);
+        if begin < chunk_end {
+            let window_end = cmp::min(chunk_end + len - 1, data.len());
+            let (found, capped) = pattern.find_all(&data[begin..window_end], max - matches.len());
+            matches.extend(found.into_iter().map(|offset| begin + offset));
+            if capped {
+                return Some((matches, true));
+            }
+            if let Some(&last) = matches.last() {
+                next_start = last + len;
+            }
+        }
+        chunk_start = chunk_end;
+    }
+    Some((matches, false))
+}
+
+/// Shared by the UI and the worker thread.
+pub struct Progress {
+    done: AtomicUsize,
+    cancelled: AtomicBool,
+}
+
+impl Progress {
+    pub fn new() -> Progress {
+        Progress {
+            done: AtomicUsize::new(0),
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns false when the job is cancelled.
+    fn update(&self, done: usize) -> bool {
+        self.done.store(done, Ordering::Relaxed);
+        !self.cancelled.load(Ordering::Relaxed)
+    }
+}
+
+pub enum JobState {
+    Running,
+    Done(Output),
+    Cancelled,
+}
+
+pub struct Job {
+    name: &'static str,
+    total: usize,
+    progress: Arc<Progress>,
+    receiver: Receiver<Option<Output>>,
+}
+
+impl Job {
+    /// Run the task on `data`, a copy of the buffer, in a new thread. The
+    /// waker is woken when it's done.
+    pub fn start(task: Task, data: Vec<u8>, waker: Option<Waker>) -> Job {
+        let name = task.name();
+        let total = task.total(data.len());
+        let progress = Arc::new(Progress::new());
+        let (sender, receiver) = channel();
+        let progress_ = progress.clone();
+        thread::spawn(move || {
+            let _ = sender.send(task.run(&data, &progress_));
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        });
+        Job {
+            name,
+            total,
+            progress,
+            receiver,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn poll(&self) -> JobState {
+        match self.receiver.try_recv() {
+            Ok(Some(output)) =>
+                JobState::Done(output),
+            Err(TryRecvError::Empty) =>
+                JobState::Running,
+            // Cancelled, or the thread panicked
+            Ok(None) | Err(TryRecvError::Disconnected) =>
+                JobState::Cancelled,
+        }
+    }
+
+    /// The thread stops at the next chunk.
+    pub fn cancel(&self) {
+        self.progress.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// For the info line, e.g. "Hashing [#####...............] 25%".
+    pub fn status(&self) -> String {
+        let done = cmp::min(self.progress.done.load(Ordering::Relaxed), self.total);
+        let percent = (done * 100).checked_div(self.total).unwrap_or(0);
+        let filled = percent * BAR_WIDTH / 100;
+        format!(
+            "{} [{}{}] {}% - Ctrl-c cancels",
+            self.name,
+            "#".repeat(filled),
+            ".".repeat(BAR_WIDTH - filled),
+            percent
+        )
+    }
+}
@@ -0,0 +1,77 @@
+/// vim-style jump list. Positions are recorded before "big" moves (goto,
+/// marks, search matches etc.), Ctrl-o walks back, Tab (Ctrl-i) walks
+/// forward. The positions Ctrl-o goes to are numbered in the gutter, 1-9
+/// go back that many jumps.
+pub struct JumpList {
+    jumps: Vec<usize>,
+
+    /// Position in `jumps`. `jumps.len()` when we're not walking the list.
+    idx: usize,
+}
+
+impl JumpList {
+    pub fn new() -> JumpList {
+        JumpList {
+            jumps: Vec::new(),
+            idx: 0,
+        }
+    }
+
+    /// Record a jump from the given offset. Drops positions we walked back
+    /// from, as in vim.
+    pub fn push(&mut self, offset: usize) {
+        self.jumps.truncate(self.idx);
+        if self.jumps.last() != Some(&offset) {
+            self.jumps.push(offset);
+        }
+        self.idx = self.jumps.len();
+    }
+
+    pub fn back(&mut self, current: usize) -> Option<usize> {
+        if self.idx == self.jumps.len() {
+            // Remember where we started walking so forward() can return here
+            if self.jumps.last() != Some(&current) {
+                self.jumps.push(current);
+            }
+            self.idx = self.jumps.len() - 1;
+        }
+        if self.idx == 0 {
+            return None;
+        }
+        self.idx -= 1;
+        Some(self.jumps[self.idx])
+    }
+
+    /// Go back `n` jumps, as `n` Ctrl-o's.
+    pub fn back_n(&mut self, current: usize, n: usize) -> Option<usize> {
+        let mut ret = None;
+        for _ in 0..n {
+            match self.back(current) {
+                Some(offset) =>
+                    ret = Some(offset),
+                None =>
+                    break,
+            }
+        }
+        ret
+    }
+
+    /// Up to `n` offsets `back` goes to, the next one first.
+    pub fn recent(&self, current: usize, n: usize) -> Vec<usize> {
+        // `back` doesn't go to the current position when it's the last jump
+        let end = if self.idx == self.jumps.len() && self.jumps.last() == Some(&current) {
+            self.idx - 1
+        } else {
+            self.idx
+        };
+        self.jumps[..end].iter().rev().take(n).cloned().collect()
+    }
+
+    pub fn forward(&mut self) -> Option<usize> {
+        if self.idx + 1 >= self.jumps.len() {
+            return None;
+        }
+        self.idx += 1;
+        Some(self.jumps[self.idx])
+    }
+}
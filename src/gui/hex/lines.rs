@@ -1,97 +1,196 @@
 use std::cmp;
 
 use colors;
+use config::OffsetFormat;
+use gui::hex::squeeze::Row;
 use utils::*;
 
 use termbox_simple::*;
 
+/// How offsets are shown in the offset column.
+#[derive(Clone, Copy)]
+pub struct OffsetStyle {
+    pub format: OffsetFormat,
+    /// Added to offsets before drawing.
+    pub base: usize,
+    /// When set, offsets are shown relative to this offset.
+    pub origin: Option<usize>,
+    /// When set, sector numbers and offsets in the sector are shown instead
+    /// of offsets.
+    pub sector_size: Option<usize>,
+}
+
 pub struct Lines {
-    bytes_per_line: i32,
-    length: i32,
+    bytes_per_line: usize,
+    length: usize,
 
     width: i32,
     height: i32,
 
     /// Byte offset (aka. address)
-    cursor: i32,
+    cursor: usize,
+
+    format: OffsetFormat,
+
+    /// Added to offsets before drawing.
+    base: usize,
 
-    scroll: i32,
+    /// When set, offsets are shown relative to this offset.
+    origin: Option<usize>,
+
+    /// When set, sector numbers and offsets in the sector are shown instead
+    /// of offsets.
+    sector_size: Option<usize>,
 }
 
 impl Lines {
-    pub fn new(bytes_per_line: i32, length: i32, width: i32, height: i32) -> Lines {
+    pub fn new(
+        bytes_per_line: usize,
+        length: usize,
+        width: i32,
+        height: i32,
+        style: OffsetStyle,
+    ) -> Lines {
         Lines {
             bytes_per_line: bytes_per_line,
             length: length,
             width: width,
             height: height,
             cursor: 0,
-            scroll: 0,
+            format: style.format,
+            base: style.base,
+            origin: style.origin,
+            sector_size: style.sector_size,
         }
     }
 
-    pub fn width(&self) -> i32 {
-        self.width
+    /// Columns needed to show the largest offset.
+    pub fn width_needed(length: usize, style: OffsetStyle) -> i32 {
+        let last_byte = length.saturating_sub(1);
+        if let Some(size) = style.sector_size {
+            return format_sector(last_byte, size, 0).len() as i32;
+        }
+        match style.origin {
+            None =>
+                format_offset(style.base + last_byte, style.format, 0).len() as i32,
+            Some(origin) => {
+                let max_distance = cmp::max(origin, last_byte.saturating_sub(origin));
+                // +1 for the sign
+                format_offset(max_distance, style.format, 0).len() as i32 + 1
+            }
+        }
     }
 
-    pub fn set_scroll(&mut self, scroll: i32) {
-        self.scroll = scroll;
+    pub fn width(&self) -> i32 {
+        self.width
     }
 
-    pub fn draw(&self, tb: &mut Termbox) {
-        let mut addr_str = String::with_capacity(self.width as usize);
-
-        let start_addr = self.scroll * self.bytes_per_line;
-
-        for line in 0..self.height {
-            let addr = start_addr + self.bytes_per_line * line;
+    /// `rows`: rows shown in the hex grid.
+    pub fn draw(&self, tb: &mut Termbox, rows: &[Row]) {
+        // No room for the column in very narrow terminals
+        if self.width <= 0 {
+            return;
+        }
+        let sign_width = if self.origin.is_some() { 1 } else { 0 };
+        let digits = (self.width as usize).saturating_sub(sign_width + prefix(self.format).len());
+        let sector_digits = self.sector_size.map_or(0, |size| {
+            (self.width as usize + 1).saturating_sub(format_sector(0, size, 0).len())
+        });
+
+        for (row_idx, row) in rows.iter().enumerate().take(self.height as usize) {
+            let row_idx = row_idx as i32;
+            let addr = match *row {
+                Row::Line(line) =>
+                    line * self.bytes_per_line,
+                Row::Squeezed => {
+                    print(tb, 0, row_idx, colors::palette().default, "*");
+                    continue;
+                }
+            };
             if addr >= self.length {
                 break;
             }
 
-            self.mk_hex_string(addr, &mut addr_str);
+            let addr_str = match (self.sector_size, self.origin) {
+                (Some(size), _) =>
+                    format_sector(addr, size, sector_digits),
+                (None, None) =>
+                    format_offset(self.base + addr, self.format, digits),
+                (None, Some(origin)) =>
+                    format_relative_offset(addr, origin, self.format, digits),
+            };
 
-            let highlight = self.cursor >= addr && self.cursor < addr + self.bytes_per_line;
-            let style = if highlight {
-                colors::CURSOR_NO_FOCUS
+            let in_line = |offset: usize| offset >= addr && offset < addr + self.bytes_per_line;
+            let style = if in_line(self.cursor) {
+                colors::palette().cursor_no_focus
+            } else if self.origin.map(&in_line).unwrap_or(false) {
+                colors::palette().highlight
             } else {
-                colors::DEFAULT
+                colors::palette().default
             };
 
-            print(tb, 0, line, style, &addr_str);
+            print(tb, 0, row_idx, style, &addr_str);
         }
     }
 
-    pub fn move_cursor_offset(&mut self, byte_offset: i32) {
+    /// Scrolling follows the hex grid, see `draw`.
+    pub fn move_cursor_offset(&mut self, byte_offset: usize) {
         self.cursor = byte_offset;
-
-        let mut line = byte_offset / self.bytes_per_line;
-        if byte_offset % self.bytes_per_line != 0 {
-            line += 1;
-        }
-
-        let min_scroll = cmp::max(0, line - self.height + 3);
-        let max_scroll = cmp::max(0, line - 3);
-
-        if self.scroll > max_scroll {
-            self.scroll = max_scroll;
-        } else if self.scroll < min_scroll {
-            self.scroll = min_scroll;
-        }
     }
+}
 
-    fn mk_hex_string(&self, addr: i32, ret: &mut String) {
-        ret.clear();
+pub fn prefix(format: OffsetFormat) -> &'static str {
+    match format {
+        OffsetFormat::Hex =>
+            "0x",
+        OffsetFormat::Dec =>
+            "",
+        OffsetFormat::Oct =>
+            "0o",
+    }
+}
 
-        // for debugging purposes:
-        // ret.push_str(format!("{}", addr).borrow());
+/// Format the offset, padded to `digits` digits.
+pub fn format_offset(addr: usize, format: OffsetFormat, digits: usize) -> String {
+    match format {
+        OffsetFormat::Hex =>
+            format!("0x{:01$x}", addr, digits),
+        OffsetFormat::Dec =>
+            format!("{:>1$}", addr, digits),
+        OffsetFormat::Oct =>
+            format!("0o{:01$o}", addr, digits),
+    }
+}
 
-        ret.push('0');
-        ret.push('x');
+/// Like `format_offset`, but shows the offset relative to `origin`, with a
+/// sign. Offsets after the origin get a '+', the origin itself gets a space in
+/// place of the sign.
+pub fn format_relative_offset(
+    offset: usize,
+    origin: usize,
+    format: OffsetFormat,
+    digits: usize,
+) -> String {
+    let (sign, distance) = if offset < origin {
+        ('-', origin - offset)
+    } else if offset > origin {
+        ('+', offset - origin)
+    } else {
+        (' ', 0)
+    };
+    format!("{}{}", sign, format_offset(distance, format, digits))
+}
 
-        for i in 0..self.width - 2 + 1 {
-            let nibble = ((addr >> (4 * (self.width - 2 - i))) & 0b0000_1111) as u8;
-            ret.push(hex_char(nibble) as char);
-        }
-    }
+/// Sector number, padded to `digits` digits, and the offset in the sector in
+/// hex, e.g. `12:1f0`.
+pub fn format_sector(offset: usize, sector_size: usize, digits: usize) -> String {
+    // Enough hex digits for the last byte of a sector
+    let offset_digits = format!("{:x}", sector_size - 1).len();
+    format!(
+        "{:>2$}:{:03$x}",
+        offset / sector_size,
+        offset % sector_size,
+        digits,
+        offset_digits
+    )
 }
@@ -0,0 +1,134 @@
+use parser::macho::{prot_str, version_str, LoadCommandKind, MachO, LC_LOAD_WEAK_DYLIB,
+                    LC_REEXPORT_DYLIB};
+
+use gui::hex::structure::{field, StructureRow};
+
+/// The offset, when it's in the file.
+fn file_offset(offset: u64, data: &[u8]) -> Option<usize> {
+    if offset < data.len() as u64 {
+        Some(offset as usize)
+    } else {
+        None
+    }
+}
+
+/// Rows of the structure view: header, load commands, segments, sections, and
+/// libraries.
+pub fn rows(macho: &MachO, data: &[u8]) -> Vec<StructureRow> {
+    let mut rows = Vec::new();
+    let header = &macho.header;
+
+    rows.push(StructureRow::Heading("Header".to_owned()));
+    let endianness = if macho.big_endian { "big" } else { "little" };
+    rows.push(field("Magic", format!("64-bit, {} endian", endianness), Some(0)));
+    let cpu = match header.cpu_type_str() {
+        Some(name) =>
+            format!("{} (0x{:x}), subtype 0x{:x}", name, header.cpu_type, header.cpu_subtype),
+        None =>
+            format!("0x{:x}, subtype 0x{:x}", header.cpu_type, header.cpu_subtype),
+    };
+    rows.push(field("CPU", cpu, Some(4)));
+    let file_type = match header.file_type_str() {
+        Some(name) =>
+            name.to_owned(),
+        None =>
+            format!("0x{:x}", header.file_type),
+    };
+    rows.push(field("File type", file_type, Some(12)));
+    rows.push(field(
+        "Load commands",
+        format!("{} ({} bytes)", header.n_cmds, header.size_of_cmds),
+        Some(16),
+    ));
+    rows.push(field("Flags", header.flags_str(), Some(24)));
+    if let Some((_, entry_offset)) = macho.main() {
+        let value = match macho.offset_addr(entry_offset) {
+            Some(addr) =>
+                format!("0x{:x}", addr),
+            None =>
+                format!("file offset 0x{:x}", entry_offset),
+        };
+        rows.push(field("Entry point", value, file_offset(entry_offset, data)));
+    }
+
+    rows.push(StructureRow::Heading(format!("Load commands ({})", macho.load_commands.len())));
+    for cmd in &macho.load_commands {
+        let name = match cmd.cmd_str() {
+            Some(name) =>
+                name.to_owned(),
+            None =>
+                format!("0x{:x}", cmd.cmd),
+        };
+        let value = match cmd.kind {
+            LoadCommandKind::Main { stack_size, .. } =>
+                format!("{} bytes, stack size 0x{:x}", cmd.size, stack_size),
+            _ =>
+                format!("{} bytes", cmd.size),
+        };
+        rows.push(field(&name, value, Some(cmd.offset)));
+    }
+
+    let segments = macho.segments();
+    rows.push(StructureRow::Heading(format!("Segments ({})", segments.len())));
+    for segment in &segments {
+        let value = format!(
+            "{}/{} addr 0x{:08x}  size 0x{:<8x} file size 0x{:x}",
+            prot_str(segment.init_prot),
+            prot_str(segment.max_prot),
+            segment.vm_addr,
+            segment.vm_size,
+            segment.file_size
+        );
+        let offset = if segment.file_size == 0 {
+            None
+        } else {
+            file_offset(segment.file_offset, data)
+        };
+        // Segments of object files are unnamed
+        let name = if segment.name.is_empty() { "(unnamed)" } else { &segment.name };
+        rows.push(field(name, value, offset));
+    }
+
+    let n_sections = segments.iter().map(|segment| segment.sections.len()).sum::<usize>();
+    rows.push(StructureRow::Heading(format!("Sections ({})", n_sections)));
+    for section in segments.iter().flat_map(|segment| segment.sections.iter()) {
+        let zerofill = section.is_zerofill();
+        let value = format!(
+            "addr 0x{:08x}  size 0x{:<8x} align 2^{}{}",
+            section.addr,
+            section.size,
+            section.align,
+            if zerofill { ", zero-filled" } else { "" }
+        );
+        let offset = if zerofill || section.offset == 0 {
+            None
+        } else {
+            file_offset(u64::from(section.offset), data)
+        };
+        rows.push(field(&format!("{},{}", section.segment_name, section.name), value, offset));
+    }
+
+    let dylibs = macho.dylibs();
+    rows.push(StructureRow::Heading(format!("Libraries ({})", dylibs.len())));
+    for (cmd, dylib) in dylibs {
+        let kind = match cmd.cmd {
+            LC_LOAD_WEAK_DYLIB =>
+                ", weak",
+            LC_REEXPORT_DYLIB =>
+                ", re-exported",
+            _ =>
+                "",
+        };
+        let value = format!(
+            "{}, current {}, compatibility {}{}",
+            dylib.name,
+            version_str(dylib.current_version),
+            version_str(dylib.compatibility_version),
+            kind
+        );
+        let file_name = dylib.name.rsplit('/').next().unwrap_or("");
+        rows.push(field(file_name, value, Some(cmd.offset)));
+    }
+
+    rows
+}
@@ -0,0 +1,414 @@
+use std::cmp;
+use std::collections::BTreeMap;
+use std::fs;
+
+use colors;
+use persist::{self, Record, Session, SessionError};
+use utils::*;
+
+use term_input::{Arrow, Key};
+use termbox_simple::*;
+
+#[derive(Clone)]
+pub struct Mark {
+    /// Byte offset.
+    pub offset: usize,
+    pub label: String,
+}
+
+/// Number of backups kept, see `Marks::backup()`.
+const BACKUPS: usize = 5;
+
+/// vim-style marks (`m<letter>` to set, `'<letter>` to jump). Persisted per
+/// file in the session format, see `persist::Session`.
+pub struct Marks {
+    marks: BTreeMap<char, Mark>,
+
+    /// Records of the sidecar that we don't know, e.g. written by a newer
+    /// rhex. Written back as they are.
+    other: Vec<Record>,
+
+    /// The sidecar couldn't be read, don't overwrite it.
+    read_only: bool,
+
+    /// Problem with the sidecar, to be shown to the user.
+    warning: Option<String>,
+
+    /// Changed since the last backup?
+    changed: bool,
+}
+
+impl Marks {
+    /// Load marks of the file from its sidecar. Returns an empty set when
+    /// there isn't one, or when it can't be read (see `take_warning()`).
+    pub fn load(path: &str) -> Marks {
+        let mut ret = Marks::new();
+        let session = match persist::load_session("marks", path) {
+            Ok(Some(session)) =>
+                session,
+            Ok(None) =>
+                return ret,
+            Err(SessionError::Corrupt) => {
+                // Keep a copy, the sidecar is overwritten on the next save
+                let contents = persist::sidecar_path("marks", path)
+                    .and_then(|p| fs::read(p).ok());
+                if let Some(contents) = contents {
+                    persist::backup("marks", path, &contents, BACKUPS);
+                }
+                ret.warning =
+                    Some("Marks file is corrupt, saved a copy in marks-backup".to_owned());
+                return ret;
+            }
+            Err(err) => {
+                ret.read_only = true;
+                ret.warning = Some(format!("Can't read marks: {}, marks won't be saved", err));
+                return ret;
+            }
+        };
+        ret.add_session(session);
+        ret
+    }
+
+    fn new() -> Marks {
+        Marks {
+            marks: BTreeMap::new(),
+            other: Vec::new(),
+            read_only: false,
+            warning: None,
+            changed: false,
+        }
+    }
+
+    fn add_session(&mut self, session: Session) {
+        for record in session.records {
+            // Before the session format, the file had a line per mark:
+            // '<letter> <offset> <label>'
+            let mark_fields = if session.version == 0 {
+                Some(format!("{} {}", record.tag, record.fields))
+            } else if record.tag == "mark" {
+                Some(record.fields.clone())
+            } else {
+                None
+            };
+
+            // 'mark <letter> <offset> <label>'
+            match mark_fields.as_ref().and_then(|fields| parse_mark(fields)) {
+                Some((ch, mark)) => {
+                    self.marks.insert(ch, mark);
+                }
+                None =>
+                    if session.version != 0 {
+                        self.other.push(record);
+                    },
+            }
+        }
+    }
+
+    /// Message about a problem with the sidecar, once.
+    pub fn take_warning(&mut self) -> Option<String> {
+        self.warning.take()
+    }
+
+    /// Write marks to the file's sidecar. Errors are ignored, marks just
+    /// won't survive a restart.
+    pub fn save(&self, path: &str) {
+        if !self.read_only {
+            let _ = persist::save_session("marks", path, &self.to_session());
+        }
+    }
+
+    /// Save a timestamped copy of the marks if they changed since the last
+    /// backup, so that marks deleted by accident can be recovered. Only the
+    /// last few backups are kept, see `persist::backup()`.
+    pub fn backup(&mut self, path: &str) {
+        if self.changed {
+            persist::backup("marks", path, &self.to_session().serialize(), BACKUPS);
+            self.changed = false;
+        }
+    }
+
+    fn to_session(&self) -> Session {
+        let mut session = Session::new();
+        for (ch, mark) in &self.marks {
+            session.records.push(Record {
+                tag: "mark".to_owned(),
+                fields: format!("{} {} {}", ch, mark.offset, mark.label),
+            });
+        }
+        session.records.extend(self.other.iter().cloned());
+        session
+    }
+
+    pub fn get(&self, ch: char) -> Option<&Mark> {
+        self.marks.get(&ch)
+    }
+
+    pub fn set(&mut self, ch: char, offset: usize) {
+        // Keep the label when moving an existing mark
+        let label = self.marks
+            .get(&ch)
+            .map(|m| m.label.clone())
+            .unwrap_or_default();
+        self.marks.insert(ch, Mark { offset, label });
+        self.changed = true;
+    }
+
+    pub fn remove(&mut self, ch: char) {
+        self.marks.remove(&ch);
+        self.changed = true;
+    }
+
+    pub fn set_label(&mut self, ch: char, label: String) {
+        if let Some(mark) = self.marks.get_mut(&ch) {
+            mark.label = label;
+            self.changed = true;
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<(char, Mark)> {
+        self.marks.iter().map(|(ch, m)| (*ch, m.clone())).collect()
+    }
+}
+
+/// `<letter> <offset> <label>`
+fn parse_mark(fields: &str) -> Option<(char, Mark)> {
+    let mut words = fields.splitn(3, ' ');
+    let ch = words.next().and_then(|w| w.chars().next())?;
+    let offset = words.next().and_then(|w| w.parse().ok())?;
+    let label = words.next().unwrap_or("").to_owned();
+    Some((ch, Mark { offset, label }))
+}
+
+/// Return value of the overlay. Returned by `keypressed()` method.
+pub enum MarksRet {
+    /// Jump to the offset.
+    Jump(usize),
+
+    /// User deleted the mark.
+    Delete(char),
+
+    /// User updated the label of the mark.
+    Label(char, String),
+
+    /// User cancelled.
+    Abort,
+
+    /// Overlay still has focus.
+    Continue,
+}
+
+/// Lists all marks. Enter jumps to the selected mark, 'd' deletes it, 'e'
+/// edits its label.
+pub struct MarksOverlay {
+    pos_x: i32,
+    pos_y: i32,
+    width: i32,
+    height: i32,
+
+    marks: Vec<(char, Mark)>,
+    selected: usize,
+
+    /// When editing a label, contents of the input.
+    label_input: Option<String>,
+}
+
+impl MarksOverlay {
+    pub fn new(
+        width: i32,
+        height: i32,
+        pos_x: i32,
+        pos_y: i32,
+        marks: Vec<(char, Mark)>,
+    ) -> MarksOverlay {
+        let width_ = cmp::min(width, 60);
+        let height_ = cmp::min(height, cmp::max(marks.len() as i32, 1) + 4);
+
+        let pos_x = pos_x + (width - width_) / 2;
+        let pos_y = pos_y + (height - height_) / 2;
+
+        MarksOverlay {
+            pos_x,
+            pos_y,
+            width: width_,
+            height: height_,
+            marks,
+            selected: 0,
+            label_input: None,
+        }
+    }
+
+    pub fn draw(&self, tb: &mut Termbox) {
+        draw_box(tb, self.pos_x, self.pos_y, self.width, self.height);
+
+        if self.marks.is_empty() {
+            print(tb, self.pos_x + 2, self.pos_y + 1, colors::palette().default, "No marks");
+            return;
+        }
+
+        let rows = (self.height - 4) as usize;
+        let first = if self.selected >= rows {
+            self.selected + 1 - rows
+        } else {
+            0
+        };
+
+        for (idx, &(ch, ref mark)) in self.marks.iter().enumerate().skip(first).take(rows) {
+            let style = if idx == self.selected {
+                colors::palette().cursor_focus
+            } else {
+                colors::palette().default
+            };
+            let line = format!("{}  0x{:08x}  {}", ch, mark.offset, mark.label);
+            let line: String = line.chars().take((self.width - 2) as usize).collect();
+            print(tb, self.pos_x + 1, self.pos_y + 1 + (idx - first) as i32, style, &line);
+        }
+
+        let bottom = self.pos_y + self.height - 2;
+        match self.label_input {
+            Some(ref input) => {
+                print(tb, self.pos_x + 1, bottom, colors::palette().default, "Label: ");
+                print(tb, self.pos_x + 8, bottom, colors::palette().default, input);
+                tb.change_cell(
+                    self.pos_x + 8 + input.chars().count() as i32,
+                    bottom,
+                    ' ',
+                    colors::palette().cursor_focus.fg,
+                    colors::palette().cursor_focus.bg,
+                );
+            }
+            None => {
+                print(
+                    tb,
+                    self.pos_x + 1,
+                    bottom,
+                    colors::palette().default,
+                    "Enter: jump  d: delete  e: edit label",
+                );
+            }
+        }
+    }
+
+    pub fn keypressed(&mut self, key: Key) -> MarksRet {
+        if self.label_input.is_some() {
+            return self.keypressed_label(key);
+        }
+
+        match key {
+            Key::Arrow(Arrow::Up) | Key::Char('k') => {
+                if self.selected > 0 {
+                    self.selected -= 1;
+                }
+                MarksRet::Continue
+            }
+            Key::Arrow(Arrow::Down) | Key::Char('j') => {
+                if self.selected + 1 < self.marks.len() {
+                    self.selected += 1;
+                }
+                MarksRet::Continue
+            }
+            Key::Char('\r') =>
+                match self.marks.get(self.selected) {
+                    Some((_, mark)) =>
+                        MarksRet::Jump(mark.offset),
+                    None =>
+                        MarksRet::Abort,
+                },
+            Key::Char('d') => {
+                if self.selected < self.marks.len() {
+                    let (ch, _) = self.marks.remove(self.selected);
+                    if self.selected > 0 && self.selected == self.marks.len() {
+                        self.selected -= 1;
+                    }
+                    MarksRet::Delete(ch)
+                } else {
+                    MarksRet::Continue
+                }
+            }
+            Key::Char('e') => {
+                if let Some((_, mark)) = self.marks.get(self.selected) {
+                    self.label_input = Some(mark.label.clone());
+                }
+                MarksRet::Continue
+            }
+            Key::Esc | Key::Char('q') =>
+                MarksRet::Abort,
+            _ =>
+                MarksRet::Continue,
+        }
+    }
+
+    fn keypressed_label(&mut self, key: Key) -> MarksRet {
+        match key {
+            Key::Esc => {
+                self.label_input = None;
+            }
+            Key::Char('\r') => {
+                let label = self.label_input.take().unwrap();
+                let mark = &mut self.marks[self.selected];
+                mark.1.label = label.clone();
+                return MarksRet::Label(mark.0, label);
+            }
+            Key::Backspace => {
+                self.label_input.as_mut().unwrap().pop();
+            }
+            Key::Char(ch) => {
+                self.label_input.as_mut().unwrap().push(ch);
+            }
+            _ =>
+                {}
+        }
+        MarksRet::Continue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn marks_of(contents: &[u8]) -> Marks {
+        let mut marks = Marks::new();
+        match Session::parse(contents) {
+            Ok(session) =>
+                marks.add_session(session),
+            Err(err) =>
+                panic!("can't parse marks: {}", err),
+        }
+        marks
+    }
+
+    #[test]
+    fn legacy_marks() {
+        let marks = marks_of(b"a 16 start of header\nb 32\nnot a mark\n");
+        let marks_vec = marks.to_vec();
+        assert_eq!(marks_vec.len(), 2);
+        assert_eq!(marks_vec[0].0, 'a');
+        assert_eq!(marks_vec[0].1.offset, 16);
+        assert_eq!(marks_vec[0].1.label, "start of header");
+        assert_eq!(marks_vec[1].0, 'b');
+        assert_eq!(marks_vec[1].1.offset, 32);
+        assert_eq!(marks_vec[1].1.label, "");
+
+        // Saved in the current format, without the junk lines
+        let session = marks.to_session();
+        assert_eq!(session.version, persist::SESSION_VERSION);
+        assert_eq!(session.records.len(), 2);
+        assert!(session.records.iter().all(|r| r.tag == "mark"));
+        assert_eq!(session.records[0].fields, "a 16 start of header");
+    }
+
+    #[test]
+    fn unknown_records_kept() {
+        let mut session = Session::new();
+        session.records.push(Record {
+            tag: "mark".to_owned(),
+            fields: "a 16 start".to_owned(),
+        });
+        session.records.push(Record {
+            tag: "from-the-future".to_owned(),
+            fields: "1 2 3".to_owned(),
+        });
+        let marks = marks_of(&session.serialize());
+        assert_eq!(marks.to_vec().len(), 1);
+        assert_eq!(marks.to_session().serialize(), session.serialize());
+    }
+}
@@ -1,49 +1,401 @@
 mod ascii_view;
+mod command;
+mod diff;
+mod disas;
+mod dynamic;
+mod elf_header;
+mod elf_regions;
+mod elf_structure;
+mod encoding;
+mod entropy;
+mod follow;
+mod footer;
+pub mod glyphs;
+mod got;
 mod goto;
 mod hex_grid;
+mod highlights;
+mod histogram;
 mod info_line;
-mod lines;
-mod search;
+mod int_table;
+mod jobs;
+mod jump_list;
+pub mod lines;
+mod macho;
+mod marks;
+mod notes;
+mod panel;
+mod path_input;
+mod pe;
+mod records;
+mod relocations;
+mod reports;
+pub mod search;
+mod scan;
+mod sections;
+mod session;
+mod squeeze;
+mod status;
+mod structure;
+mod symbols;
+pub mod tutor;
+mod value_edit;
 
+use std::cmp;
+use std::ffi::{CString, OsString};
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+
+use bell;
+use buffer::Buffer;
+use clipboard;
 use colors;
+use gui::check_palette;
+use gui::event_loop::Waker;
+use gui::mouse::MouseEvent;
+use config::{self, parse_alias, BellEvent, ColumnSum, Config, Encoding,
+             NonPrintable, OffsetFormat};
+use debug_file;
+use detect::{self, detect, FileKind};
+use dump::{self, Format};
+use generate::{cyclic_find, Generator, CYCLIC_N};
+use hash::{find_checksum_file, read_checksum, to_hex, HashKind};
+use json::Json;
+use parser::elf::{self, DynValue, Elf, SectionHeader};
+use parser::macho::MachO;
+use parser::pe::Pe;
+use parser::Report;
+use patch::{self, Patch, PatchFormat};
+use persist;
+use procmem::Region;
+use revert;
+use templates::{Field, Template, TemplateError};
+use utils::{parse_bytes, parse_number, parse_range};
 use self::ascii_view::AsciiView;
-use self::goto::{GotoOverlay, OverlayRet};
-use self::hex_grid::HexGrid;
+use self::command::{CommandLine, CommandRet};
+use self::disas::{Arch, DisasElf};
+use self::dynamic::{DynamicOverlay, DynamicRet};
+use self::elf_header::{ElfHeaderOverlay, ElfHeaderRet};
+use self::elf_regions::ElfRegion;
+use self::entropy::{Minimap, MINIMAP_WIDTH};
+use self::follow::{FileStamp, Follow};
+use self::goto::{find_symbol, parse_target, GotoOverlay, OverlayRet};
+use self::path_input::{PathOverlay, PathRet};
+use self::hex_grid::{byte_width, HexGrid};
+use self::highlights::{ByteHighlights, Highlights};
+use self::histogram::{HistogramOverlay, HistogramRet};
 use self::info_line::InfoLine;
-use self::lines::Lines;
-use self::search::{SearchOverlay, SearchRet};
+use self::int_table::{IntTableOverlay, IntTableRet};
+use self::jobs::{Job, JobState, Output, Task, BACKGROUND_SIZE};
+use self::jump_list::JumpList;
+use self::lines::{format_relative_offset, Lines, OffsetStyle};
+use self::marks::{Marks, MarksOverlay, MarksRet};
+use self::notes::{NoteRow, NotesOverlay, NotesRet};
+use self::panel::{Panel, PanelKind};
+use self::records::{RecordsOverlay, RecordsRet, MIN_RECORD_SIZE, SAMPLE_SIZE};
+use self::relocations::{RelocationsOverlay, RelocationsRet};
+use self::search::{Pattern, SearchOverlay, SearchRet, SearchScope};
+use self::sections::{SectionsOverlay, SectionsRet};
+use self::session::ViewState;
+use self::squeeze::Row;
+use self::status::Status;
+use self::structure::{StructureOverlay, StructureRet};
+use self::symbols::{SymbolsOverlay, SymbolsRet};
+use self::tutor::{Progress, Tutor};
+use self::value_edit::{Unit, ValueOverlay, ValueRet};
+
+pub use self::command::{is_builtin, split_commands};
+pub use self::status::unknown_fields as unknown_status_fields;
 
 use libc;
-use nix::poll::{poll, PollFd, POLLIN};
-use term_input::{Event, Input, Key};
+use term_input::Key;
 use termbox_simple::*;
 
-/// GUI is the main thing that owns every widget. It's also responsible for
-/// ncurses initialization and finalization.
-pub struct HexGui<'gui> {
-    tb: Termbox,
+/// Lines scrolled by a mouse wheel step.
+const WHEEL_LINES: isize = 3;
+
+/// Return value of `HexGui::keypressed()`. Buffer switching is handled by the
+/// parent `Gui`.
+pub enum HexGuiRet {
+    Continue,
+    /// Quit, unless another buffer has unsaved changes.
+    Quit,
+    /// Quit even when buffers have unsaved changes (`:q!`).
+    ForceQuit,
+    NextBuffer,
+    PrevBuffer,
+    BufferList,
+    /// Open a new buffer, e.g. the memory image of an ELF file. `Gui` calls
+    /// `init()` after moving it to its place.
+    Open(Box<HexGui>),
+}
+
+/// HexGui owns every widget of a single buffer.
+pub struct HexGui {
     width: i32,
     height: i32,
 
-    hex_grid: HexGrid<'gui>,
+    hex_grid: HexGrid,
     lines: Lines,
-    ascii_view: AsciiView<'gui>,
+    ascii_view: AsciiView,
     info_line: InfoLine,
-    overlay: Overlay<'gui>,
-    contents: &'gui [u8],
+    overlay: Overlay,
+    buffer: Buffer,
+    path: String,
+
+    /// Locked ranges (`:lock`), sorted. Edits in them are rejected. The
+    /// ranges don't move with edits, so edits that would move the locked
+    /// bytes (inserting or deleting before them) are rejected too.
+    locks: Vec<(usize, usize)>,
+
+    /// Follow mode (`F`, `--follow`): the file as of the last check. When it
+    /// changes the buffer is reloaded, see `poll_file`.
+    follow: Option<Follow>,
+
+    /// The buffer is a part of a process's memory (`--pid`), not a file.
+    process: Option<Region>,
+
+    /// The buffer is a part of the file (`--skip`, `--length`).
+    partial: bool,
+
+    /// `config.readonly` is set because the buffer couldn't be saved (see
+    /// `set_unsaveable`), not by `-R` or `:set readonly`.
+    unsaveable: bool,
+
+    /// Insert mode (`i`, `a`), typed hex digits are inserted as bytes.
+    insert: Option<InsertState>,
+
+    /// `:s` with confirmation, waiting for y/n/a/q.
+    replace: Option<ReplaceState>,
+
+    /// Matches of the last search.
+    highlight: Highlights,
+
+    /// Last search. When there are more matches than `config.max_matches`,
+    /// only the first ones are in `highlight` and n/N search for the rest.
+    search: Option<Pattern>,
+    highlight_capped: bool,
+    /// Bytes the last search was restricted to (end exclusive), see
+    /// `SearchScope::View`. n/N stay in the range.
+    search_range: Option<(usize, usize)>,
+
+    marks: Marks,
+    jump_list: JumpList,
+
+    layout_strategy: LayoutStrategy,
+
+    config: Config,
+
+    /// Show addresses relative to `config.base` in the offset column.
+    show_base: bool,
+
+    /// Where the selection started (`v`). The selection is between this and
+    /// the cursor, inclusive.
+    selection_anchor: Option<usize>,
+
+    /// Where the left mouse button was pressed, while it's held down.
+    /// Dragging selects from here.
+    mouse_press: Option<usize>,
+
+    /// Entropy minimap on the right, when enabled.
+    minimap: Option<Minimap>,
+
+    /// Lower pane of the horizontal split (`p`), below the hex view.
+    panel: Option<Panel>,
+
+    /// Percentage of the height the panel takes.
+    panel_percent: i32,
+
+    /// Minimap and panel before entering zen mode (`Z`), restored when
+    /// leaving it.
+    pre_zen: Option<PreZenLayout>,
+
+    /// Ranges of the buffer that are not in a file, shown differently. Set in
+    /// memory images of ELF files (`A`), for `.bss` etc., and for parts of
+    /// files and process memory (`--pid`) that couldn't be read.
+    virtual_ranges: Vec<(usize, usize)>,
+
+    /// Template applied with `:template`, and the offset it's applied at.
+    template: Option<(Template, usize)>,
+    /// Fields of the applied template, and the ranges of the fields with
+    /// values, colored in the hex and ASCII views.
+    template_fields: Vec<Field>,
+    template_ranges: Vec<(usize, usize)>,
+
+    /// Parts of the file when it's an ELF file and `elf-regions` is on.
+    elf_regions: Vec<ElfRegion>,
+
+    /// Contents of the file given with `--compare`. Bytes that differ from
+    /// it are colored, `]d`/`[d` jump between them.
+    reference: Option<Arc<Vec<u8>>>,
+
+    /// Operations running in the background, see `start_task`.
+    jobs: Vec<Job>,
+    /// Given to jobs, to wake up the main loop when they finish.
+    waker: Option<Waker>,
+
+    /// Errors of the parsers (ELF, PE, Mach-O, templates), listed with
+    /// `:report`.
+    reports: Vec<Report>,
+
+    /// Architecture of the disassembly panel (`d`), guessed from the file
+    /// header and changed with `:disas`.
+    disas_arch: Arch,
+    /// The file parsed as ELF while the disassembly is shown, for
+    /// translating between file offsets and virtual addresses and naming
+    /// branch targets.
+    disas_elf: Option<DisasElf>,
+    /// Symbols of the ELF file for goto (`g`, `:goto`), with their file
+    /// offsets, sorted by name. Parsed on first use, reset after edits.
+    goto_symbols: Option<Rc<Vec<(String, usize)>>>,
+
+    /// Earlier commands run with `:`, oldest first.
+    command_history: Vec<String>,
 
-    highlight: Vec<usize>,
-    highlight_len: usize,
+    /// Highlight bytes with the same value as the byte under the cursor.
+    highlight_same_byte: bool,
 
-    z_pressed: bool,
+    /// Temporary origin set by the user. Offsets are shown relative to this
+    /// when set.
+    origin: Option<usize>,
+
+    pending_key: PendingKey,
+
+    /// `<path>.sha256` or `<path>.md5`, when one exists. Checked with
+    /// `:verify`.
+    checksum_file: Option<(String, HashKind)>,
+
+    tutor: Option<Tutor>,
+
+    /// Guessed encoding of the file, shown in the info line on startup.
+    encoding_notice: Option<String>,
+
+    /// Type of the file, from the magic number. Updated after edits.
+    file_kind: Option<FileKind>,
+
+    /// Type and endianness last used with `c`. Until then the type follows
+    /// the group size.
+    value_unit: Option<(Unit, bool)>,
 }
 
-pub enum Overlay<'overlay> {
+pub enum Overlay {
     NoOverlay,
-    SearchOverlay(SearchOverlay<'overlay>),
+    SearchOverlay(SearchOverlay),
     GotoOverlay(GotoOverlay),
+    MarksOverlay(MarksOverlay),
+    HistogramOverlay(HistogramOverlay),
+    IntTableOverlay(IntTableOverlay),
+    SymbolsOverlay(SymbolsOverlay),
+    SectionsOverlay(SectionsOverlay),
+    DynamicOverlay(DynamicOverlay),
+    RelocationsOverlay(RelocationsOverlay),
+    NotesOverlay(NotesOverlay),
+    StructureOverlay(StructureOverlay),
+    ElfHeaderOverlay(ElfHeaderOverlay),
+    ValueOverlay(ValueOverlay),
+    PathOverlay(PathOverlay),
+    RecordsOverlay(RecordsOverlay),
+    CommandLine(CommandLine),
+}
+
+/// State of insert mode. A byte is inserted on the first hex digit, the second
+/// digit fills in its lower nibble.
+struct InsertState {
+    /// Where the next byte is inserted.
+    offset: usize,
+
+    /// Got the first digit of the byte at `offset`?
+    low_nibble: bool,
+}
+
+/// State of `:s/.../.../c`. Matches are visited in order, the current one is
+/// highlighted.
+struct ReplaceState {
+    pattern: Pattern,
+    replacement: Vec<u8>,
+
+    /// Offset of the current match.
+    current: usize,
+
+    /// End of the range. Updated as replacements change the length.
+    end: usize,
+
+    /// 1-based index of the current match, and number of matches in the range
+    /// when the command was run.
+    idx: usize,
+    total: usize,
+
+    replaced: usize,
+}
+
+struct PreZenLayout {
+    minimap: bool,
+    panel: Option<PanelKind>,
+}
+
+/// First key of a two-key command.
+enum PendingKey {
+    None,
+    /// 'z' of 'zz'
+    Z,
+    /// 'm', waiting for the mark letter
+    SetMark,
+    /// '\'', waiting for the mark letter
+    JumpMark,
+    /// 'x' or 'd' with the whole buffer selected, waiting for 'y'
+    ConfirmDeleteAll,
+    /// Found a checksum file on startup, waiting for 'y'
+    ConfirmVerify,
+    /// Found a template for the file type on startup (path of the template),
+    /// waiting for 'y'
+    ConfirmTemplate(String),
+    /// `:saveas` to an existing file (from the prompt), waiting for 'y'
+    ConfirmOverwrite(String),
+    /// ']' of ']d'
+    Next,
+    /// '[' of '[d'
+    Prev,
+}
+
+/// How `layout()` decides how many bytes to show in a line.
+#[derive(Clone, Copy, PartialEq)]
+enum LayoutStrategy {
+    /// As many bytes as the width allows.
+    Fill,
+
+    /// Largest power of two that fits the width (8, 16, 32, ...). Offsets of
+    /// rows are then round numbers, which makes offset arithmetic easier.
+    Pow2,
+
+    /// Exactly this many bytes, regardless of the width. Lines that don't fit
+    /// are truncated.
+    Fixed(i32),
 }
 
+/// Commands kept in the history of the command line.
+const MAX_COMMAND_HISTORY: usize = 100;
+
+/// Aliases can run aliases, up to this depth. Deeper aliases are most likely
+/// recursive.
+const MAX_ALIAS_DEPTH: usize = 10;
+
+/// Jumps numbered in the gutter, `1` to this.
+const JUMP_MARKERS: usize = 9;
+
+/// `:scan` lists at most this many signatures.
+const MAX_SCAN_HITS: usize = 10_000;
+
+/// Sizes `+` and `-` switch between.
+const FIXED_BYTES_PER_LINE: [i32; 3] = [8, 16, 32];
+
 struct Layout {
     lines_width: i32,
     hex_grid_x: i32,
@@ -52,10 +404,29 @@ struct Layout {
     ascii_view_width: i32,
 }
 
-fn layout(w: i32, content_size: usize) -> Layout {
-    // Calculate cols needed for showing the addresses
-    let hex_digits_needed = (content_size as f32).log(16.0f32) as i32;
-    let lines_width_pre = hex_digits_needed + 2; // take 0x prefix into account
+fn layout(
+    w: i32,
+    lines_width_pre: i32,
+    strategy: LayoutStrategy,
+    group_size: i32,
+    byte_width: i32,
+    zen: bool,
+) -> Layout {
+    // Including the space after the group
+    let group_width = group_size * byte_width + 1;
+    if zen {
+        // Only the hex view
+        let unit_column = bytes_per_line(strategy, cmp::max(w / group_width, 1), group_size);
+        return Layout {
+            lines_width: 0,
+            hex_grid_x: 0,
+            hex_grid_width: unit_column / group_size * group_width,
+            // Not drawn, but still follows the cursor
+            ascii_view_x: w,
+            ascii_view_width: unit_column,
+        };
+    }
+
     let lines_width = if lines_width_pre as f32 > w as f32 / 40.0 * 100.0 {
         0
     } else {
@@ -66,10 +437,12 @@ fn layout(w: i32, content_size: usize) -> Layout {
     // Another -1 for a vertical line between lines and hex view if we draw lines
     let grid_width = w - lines_width - 1 - if lines_width == 0 { 1 } else { 0 };
 
-    // Every byte takes 3 characters in hex view and 1 character in ascii view.
-    // So we have this 3/1 ratio.
-    let unit_column = grid_width / 4;
-    let hex_grid_width = unit_column * 3;
+    // A group of N bytes takes 2N+1 characters in hex view (9N+1 in bit mode)
+    // and N characters in ascii view. At least one group, the line overflows
+    // when it doesn't fit.
+    let groups = cmp::max(grid_width / (group_width + group_size), 1);
+    let unit_column = bytes_per_line(strategy, groups, group_size);
+    let hex_grid_width = unit_column / group_size * group_width;
     Layout {
         lines_width,
         hex_grid_x: lines_width + 1,
@@ -79,42 +452,122 @@ fn layout(w: i32, content_size: usize) -> Layout {
     }
 }
 
+/// Bytes in a line when `groups` groups fit the width.
+fn bytes_per_line(strategy: LayoutStrategy, groups: i32, group_size: i32) -> i32 {
+    match strategy {
+        LayoutStrategy::Fill =>
+            groups * group_size,
+        LayoutStrategy::Pow2 =>
+            prev_power_of_two(groups * group_size),
+        LayoutStrategy::Fixed(bytes) =>
+            // Round to whole groups
+            cmp::max(bytes / group_size, 1) * group_size,
+    }
+}
+
+fn prev_power_of_two(n: i32) -> i32 {
+    if n < 1 {
+        n
+    } else {
+        1 << (31 - n.leading_zeros())
+    }
+}
+
+fn mk_widgets(
+    width: i32,
+    height: i32,
+    len: usize,
+    strategy: LayoutStrategy,
+    config: &Config,
+    base: Option<usize>,
+    origin: Option<usize>,
+) -> (HexGrid, Lines, AsciiView) {
+    let sector_size = if config.sectors { Some(config.sector_size) } else { None };
+    let offset_style = OffsetStyle {
+        format: config.offset_format,
+        base: base.unwrap_or(0),
+        origin,
+        sector_size,
+    };
+    let lines_width = Lines::width_needed(len, offset_style);
+    let layout = layout(
+        width,
+        lines_width,
+        strategy,
+        config.group_size,
+        byte_width(config.bits),
+        config.zen,
+    );
+
+    let hex_grid = HexGrid::new(
+        layout.hex_grid_width,
+        height - 1,
+        layout.hex_grid_x,
+        0,
+        len,
+        config,
+    );
+    let lines = Lines::new(
+        hex_grid.bytes_per_line(),
+        len,
+        layout.lines_width,
+        height,
+        offset_style,
+    );
+    let ascii_view = AsciiView::new(
+        layout.ascii_view_width,
+        height - 1,
+        layout.ascii_view_x,
+        0,
+        config.encoding,
+        config.non_printable,
+    );
+    (hex_grid, lines, ascii_view)
+}
+
 // WARNING: Moving this after init() will cause a segfault. Not calling init()
 // will cause a segfault.
 
-impl<'gui> HexGui<'gui> {
+impl HexGui {
     pub fn new(
-        tb: Termbox,
-        contents: &'gui [u8],
-        path: &'gui str,
+        contents: Vec<u8>,
+        path: String,
+        mut config: Config,
         width: i32,
         height: i32,
-    ) -> HexGui<'gui> {
-        let layout = layout(width, contents.len());
-        let hex_grid = HexGrid::new(
-            layout.hex_grid_width,
-            height - 1,
-            layout.hex_grid_x,
-            0,
-            contents,
-            path,
-        );
-        let lines = Lines::new(
-            hex_grid.bytes_per_line(),
-            contents.len() as i32,
-            layout.lines_width,
-            height,
+    ) -> HexGui {
+        let encoding_notice = guess_encoding(&contents, &mut config);
+        let layout_strategy = match config.cols {
+            Some(cols) =>
+                LayoutStrategy::Fixed(cols),
+            None =>
+                LayoutStrategy::Fill,
+        };
+        // Widgets take the info line's row too in zen mode
+        let widgets_height = if config.zen { height + 1 } else { height };
+        let (hex_grid, lines, ascii_view) = mk_widgets(
+            width,
+            widgets_height - footer::rows(&config),
+            contents.len(),
+            layout_strategy,
+            &config,
+            config.base,
+            None,
         );
-        let ascii_view = AsciiView::new(
-            layout.ascii_view_width,
-            height - 1,
-            layout.ascii_view_x,
+        let info_line = InfoLine::new(
+            width,
             0,
-            contents,
+            height - 1,
+            format!("{} - {}", path, offset_info(0, contents.first().cloned())),
         );
-        let info_line = InfoLine::new(width, 0, height - 1, format!("{} - 0: 0", path));
+        let disas_arch = Arch::guess(&contents);
+        let file_kind = detect(&contents);
+        // Edits couldn't be saved
+        let unsaveable = !config.readonly && !is_writable(&path);
+        if unsaveable {
+            config.readonly = true;
+        }
         HexGui {
-            tb: tb,
             width: width,
             height: height,
 
@@ -123,140 +576,812 @@ impl<'gui> HexGui<'gui> {
             ascii_view: ascii_view,
             info_line: info_line,
             overlay: Overlay::NoOverlay,
-            contents: contents,
+            buffer: Buffer::new(contents),
+            marks: Marks::load(&path),
+            checksum_file: find_checksum_file(&path),
+            locks: Vec::new(),
+            follow: if config.follow {
+                FileStamp::read(&path).ok().map(Follow::File)
+            } else {
+                None
+            },
+            process: None,
+            partial: false,
+            unsaveable,
+            path,
+            insert: None,
+            replace: None,
+
+            highlight: Highlights::new(),
+            search: None,
+            search_range: None,
+            highlight_capped: false,
+
+            jump_list: JumpList::new(),
 
-            highlight: Vec::new(),
-            highlight_len: 0,
+            layout_strategy,
 
-            z_pressed: false,
+            show_base: config.base.is_some(),
+            config,
+            selection_anchor: None,
+            mouse_press: None,
+            minimap: None,
+            panel: None,
+            panel_percent: 35,
+            pre_zen: None,
+            virtual_ranges: Vec::new(),
+            template: None,
+            template_fields: Vec::new(),
+            template_ranges: Vec::new(),
+            elf_regions: Vec::new(),
+            reference: None,
+            jobs: Vec::new(),
+            waker: None,
+            reports: Vec::new(),
+            disas_arch,
+            disas_elf: None,
+            goto_symbols: None,
+            command_history: Vec::new(),
+            highlight_same_byte: false,
+            origin: None,
+
+            pending_key: PendingKey::None,
+
+            tutor: None,
+
+            encoding_notice,
+
+            file_kind,
+            value_unit: None,
         }
     }
 
     pub fn init(&mut self) {
         let self_ptr = self as *mut HexGui;
         self.hex_grid.set_gui(self_ptr);
+        let squeezed = if self.config.squeeze {
+            squeeze::squeezed_lines(self.buffer.as_slice(), self.hex_grid.bytes_per_line())
+        } else {
+            Vec::new()
+        };
+        self.hex_grid.set_squeezed(squeezed);
+        self.elf_regions = if self.config.elf_regions {
+            match Elf::parse(self.buffer.as_slice()) {
+                Ok(elf) =>
+                    elf_regions::regions(&elf, self.buffer.len()),
+                Err(_) =>
+                    Vec::new(),
+            }
+        } else {
+            Vec::new()
+        };
+        self.hex_grid.update_info_line();
+        if let Some(notice) = self.encoding_notice.take() {
+            self.info_line.set_text(notice);
+        }
+        if let Some(warning) = self.marks.take_warning() {
+            self.info_line.set_text(warning);
+        }
+    }
+
+    /// Re-create widgets after a layout change, keeping the cursor position.
+    fn relayout(&mut self) {
+        let cursor = self.hex_grid.get_byte_idx();
+        let width = if self.minimap.is_some() {
+            self.width - MINIMAP_WIDTH
+        } else {
+            self.width
+        };
+        let widgets_height = self.widgets_height();
+        let (hex_grid, lines, ascii_view) = mk_widgets(
+            width,
+            widgets_height + 1 - footer::rows(&self.config),
+            self.buffer.len(),
+            self.layout_strategy,
+            &self.config,
+            self.shown_base(),
+            self.origin,
+        );
+        self.hex_grid = hex_grid;
+        self.lines = lines;
+        self.ascii_view = ascii_view;
+        let rows = self.rows();
+        self.panel = self.panel.as_ref().map(|panel| {
+            Panel::new(
+                self.width,
+                cmp::max(rows - widgets_height, 0),
+                widgets_height,
+                panel.kind(),
+            )
+        });
+        self.init();
+        self.hex_grid.move_cursor_offset(cursor);
+    }
+
+    /// Mark rows in the line between the offset column and the hex view:
+    /// rows with locked bytes, and rows Ctrl-o goes back to, numbered (the
+    /// number goes over the lock mark).
+    fn draw_gutter(&self, tb: &mut Termbox, rows: &[Row]) {
+        let jumps = self.jump_list
+            .recent(self.hex_grid.get_byte_idx(), JUMP_MARKERS);
+        if self.locks.is_empty() && jumps.is_empty() {
+            return;
+        }
+        let bpl = self.hex_grid.bytes_per_line();
+        for (row_idx, row) in rows.iter().enumerate() {
+            if let Row::Line(line) = *row {
+                let (start, end) = (line * bpl, (line + 1) * bpl);
+                let locked = self.locks
+                    .iter()
+                    .any(|&(lock_start, lock_end)| lock_start < end && lock_end > start);
+                let jump = jumps.iter().position(|&offset| offset >= start && offset < end);
+                let style = if locked {
+                    colors::palette().diff
+                } else {
+                    colors::palette().highlight
+                };
+                let ch = match jump {
+                    Some(idx) =>
+                        (b'1' + idx as u8) as char,
+                    None if locked =>
+                        '┃',
+                    None =>
+                        continue,
+                };
+                tb.change_cell(self.lines.width(), row_idx as i32, ch, style.fg, style.bg);
+            }
+        }
+    }
+
+    /// Rows above the info line, or all rows in zen mode.
+    fn rows(&self) -> i32 {
+        if self.config.zen {
+            self.height
+        } else {
+            self.height - 1
+        }
+    }
+
+    /// Rows available to the hex view and the footer, above the panel (when
+    /// shown) and the info line.
+    fn widgets_height(&self) -> i32 {
+        let rows = self.rows();
+        if self.panel.is_some() {
+            // Keep at least a few lines in both parts
+            let panel_rows = cmp::max(rows * self.panel_percent / 100, 3);
+            cmp::max(rows - panel_rows, 3)
+        } else {
+            rows
+        }
+    }
+
+    /// Resize the minimap, when shown, and compute it again after the buffer
+    /// or the layout changes. The old one is shown until it's done.
+    fn update_minimap(&mut self) {
+        let height = self.widgets_height();
+        if let Some(ref mut minimap) = self.minimap {
+            minimap.resize(self.width - MINIMAP_WIDTH, height);
+        } else {
+            return;
+        }
+        self.start_task(Task::Minimap);
+    }
+
+    /// Show only the hex view (`Z`), or go back to the layout before.
+    fn toggle_zen(&mut self) {
+        if self.config.zen {
+            self.config.zen = false;
+            if let Some(layout) = self.pre_zen.take() {
+                self.panel = layout.panel.map(|kind| Panel::new(0, 0, 0, kind));
+                if layout.minimap {
+                    self.minimap = Some(Minimap::new(self.width - MINIMAP_WIDTH, 0));
+                    self.update_minimap();
+                }
+            }
+        } else {
+            self.pre_zen = Some(PreZenLayout {
+                minimap: self.minimap.is_some(),
+                panel: self.panel.as_ref().map(|panel| panel.kind()),
+            });
+            self.config.zen = true;
+            self.minimap = None;
+            self.panel = None;
+        }
+        self.relayout();
+    }
+
+    /// Show, hide, or resize the panel.
+    fn set_panel(&mut self, kind: Option<PanelKind>) {
+        // Sizes are updated in `relayout()`
+        self.panel = kind.map(|kind| Panel::new(0, 0, 0, kind));
+        self.update_disas_elf();
+        self.update_minimap();
+        self.relayout();
+    }
+
+    /// Show the disassembly panel (`d`), or hide it when it's shown.
+    fn toggle_disassembly(&mut self) {
+        let shown = self.panel_kind() == Some(PanelKind::Disassembly);
+        self.set_panel(if shown { None } else { Some(PanelKind::Disassembly) });
+    }
+
+    /// Parse the ELF headers again when the disassembly is shown, after it's
+    /// shown or the buffer changes.
+    fn update_disas_elf(&mut self) {
+        self.disas_elf = if self.panel_kind() == Some(PanelKind::Disassembly) {
+            let data = self.buffer.as_slice();
+            Elf::parse(data).ok().map(|elf| DisasElf::new(elf, data))
+        } else {
+            None
+        };
+    }
+
+    fn panel_kind(&self) -> Option<PanelKind> {
+        self.panel.as_ref().map(|panel| panel.kind())
+    }
+
+    /// Jump to the target of the jump or call at the cursor in the
+    /// disassembly (Enter). Ctrl-o jumps back.
+    fn follow_branch(&mut self) {
+        let cursor = self.hex_grid.get_byte_idx();
+        let insn = match self.panel {
+            Some(ref panel) =>
+                panel.instruction(&self.buffer, cursor, self.disas_arch, self.disas_elf.as_ref()),
+            None =>
+                None,
+        };
+        let target = match insn.and_then(|insn| insn.target) {
+            Some(target) =>
+                target,
+            None => {
+                self.info_line.set_text("Not a jump or call to an address".to_owned());
+                return;
+            }
+        };
+        // Without ELF headers addresses are file offsets
+        let offset = match self.disas_elf {
+            Some(ref elf) =>
+                elf.elf.load_offset(target),
+            None =>
+                Some(target as usize),
+        };
+        match offset {
+            Some(offset) if offset < self.buffer.len() =>
+                self.jump(offset),
+            _ =>
+                self.info_line.set_text(format!("Target 0x{:x} is not in the file", target)),
+        }
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.buffer.is_modified()
+    }
+
+    pub fn show_message(&mut self, msg: String) {
+        self.info_line.set_text(msg);
+    }
+
+    /// Save the buffer to the `recover` sidecar when it has unsaved changes.
+    /// Called when rhex is killed, see `Gui::mainloop`.
+    pub fn save_recovery(&mut self) {
+        if !self.buffer.is_modified() || self.process.is_some() {
+            return;
+        }
+        if let Some(path) = persist::sidecar_path("recover", &self.path) {
+            let _ = fs::write(path, self.buffer.as_slice());
+        }
+    }
+
+    /// Tell the user about changes saved by `save_recovery` in an earlier
+    /// session.
+    pub fn check_recovery(&mut self) {
+        if let Some(path) = persist::sidecar_path("recover", &self.path) {
+            if path.is_file() {
+                self.show_message(format!(
+                    "Unsaved changes of a killed session are in {}",
+                    path.display()
+                ));
+            }
+        }
+    }
+
+    /// Ask to verify the file when there's a checksum file next to it.
+    pub fn offer_verify(&mut self) {
+        if let Some((ref checksum_path, _)) = self.checksum_file {
+            self.info_line.set_text(format!("Found {}, verify? (y/n)", checksum_path));
+            self.pending_key = PendingKey::ConfirmVerify;
+        }
+    }
+
+    /// Ask to apply the template for the file type (see
+    /// `config::template_path`), unless already asking to verify the file.
+    pub fn offer_template(&mut self) {
+        if let PendingKey::None = self.pending_key {
+        } else {
+            return;
+        }
+        let kind = match self.file_kind {
+            Some(kind) =>
+                kind,
+            None =>
+                return,
+        };
+        if let Some(path) = config::template_path(kind.template_name()) {
+            let path = path.to_string_lossy().into_owned();
+            self.info_line.set_text(format!("{} file, apply {}? (y/n)", kind.name(), path));
+            self.pending_key = PendingKey::ConfirmTemplate(path);
+        }
+    }
+
+    pub fn enable_tutor(&mut self) {
+        self.tutor = Some(Tutor::new());
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Back up marks, if they changed. Called periodically and on quit.
+    pub fn autosave(&mut self) {
+        self.marks.backup(&self.path);
+    }
+
+    /// Save the cursor, the scroll, and the options, to restore them when the
+    /// file is opened again. Called on quit.
+    pub fn save_session(&self) {
+        if self.process.is_some() || self.partial {
+            return;
+        }
+        let options = set_options(&self.config, self.layout_strategy)
+            .into_iter()
+            .filter(|&(name, _)| name != "readonly")
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        let state = ViewState::new(
+            self.hex_grid.get_byte_idx(),
+            self.hex_grid.get_scroll(),
+            options,
+        );
+        state.save(&self.path);
+    }
+
+    /// Restore the state saved by `save_session()`. Options given on the
+    /// command line are kept.
+    pub fn restore_session(&mut self) {
+        if self.process.is_some() || self.partial {
+            return;
+        }
+        let state = match ViewState::load(&self.path) {
+            Some(state) =>
+                state,
+            None =>
+                return,
+        };
+        let defaults = set_options(&Config::default(), LayoutStrategy::Fill);
+        let current = set_options(&self.config, self.layout_strategy);
+        for option in &state.options {
+            let name = option.split('=').next().unwrap();
+            let changed = defaults
+                .iter()
+                .zip(&current)
+                .any(|(default, current)| default.0 == name && default.1 != current.1);
+            if name != "readonly" && !changed {
+                self.set_cmd(&[option]);
+            }
+        }
+        self.hex_grid.move_cursor_offset(state.cursor);
+        self.hex_grid.set_scroll(state.scroll);
     }
 
     pub fn get_lines(&mut self) -> &mut Lines {
         &mut self.lines
     }
 
-    pub fn get_ascii_view(&mut self) -> &mut AsciiView<'gui> {
+    pub fn get_ascii_view(&mut self) -> &mut AsciiView {
         &mut self.ascii_view
     }
 
-    pub fn get_info_line(&mut self) -> &mut InfoLine {
-        &mut self.info_line
+    pub fn update_info_line(&mut self) {
+        let offset = self.hex_grid.get_byte_idx();
+
+        if let Some(ref fmt) = self.config.status_format {
+            let text = status::format(
+                fmt,
+                &Status {
+                    path: &self.path,
+                    modified: self.buffer.is_modified(),
+                    insert: self.insert.is_some(),
+                    offset,
+                    base: self.shown_base().unwrap_or(0),
+                    len: self.buffer.len(),
+                    selection: self.selection(),
+                    value: self.buffer.get(offset),
+                    row: self.hex_grid.get_row(),
+                    column: self.hex_grid.get_column(),
+                    scroll: self.hex_grid.get_scroll(),
+                    search_match: self.search_match(offset),
+                    matches_capped: self.highlight_capped,
+                    elf_region: self.elf_region(offset),
+                    file_kind: self.file_kind.map(FileKind::name),
+                },
+            );
+            self.info_line.set_text(text);
+            return;
+        }
+
+        let mut text = format!(
+            "{}{}{} - ",
+            self.path,
+            if self.config.readonly { " [RO]" } else { "" },
+            if self.buffer.is_modified() { " [+]" } else { "" }
+        );
+        if let Some(kind) = self.file_kind {
+            text.push_str(&format!("{} - ", kind.name()));
+        }
+        if self.follow.is_some() {
+            text.push_str("-- FOLLOW -- ");
+        }
+        if self.insert.is_some() {
+            text.push_str("-- INSERT -- ");
+        }
+        if let Some(base) = self.shown_base() {
+            text.push_str(&format!("address 0x{:x} - ", base + offset));
+        }
+        if self.config.sectors {
+            text.push_str(&format!("sector {} - ", offset / self.config.sector_size));
+        }
+        if let Some((start, end)) = self.selection() {
+            text.push_str(&format!(
+                "selection 0x{:x}-0x{:x} ({} bytes) - ",
+                start,
+                end - 1,
+                end - start
+            ));
+        }
+        if let Some(ref reference) = self.reference {
+            if self.buffer.get(offset) != reference.get(offset).cloned() {
+                match reference.get(offset) {
+                    Some(byte) =>
+                        text.push_str(&format!("reference 0x{:02x} - ", byte)),
+                    None =>
+                        text.push_str("after the end of reference - "),
+                }
+            }
+        }
+        if let Some((idx, total)) = self.search_match(offset) {
+            text.push_str(&format!(
+                "match {}/{}{} - ",
+                idx,
+                total,
+                if self.highlight_capped { "+" } else { "" }
+            ));
+        }
+        if let Some(name) = self.elf_region(offset) {
+            text.push_str(&format!("{} - ", name));
+        }
+        if let Some(origin) = self.origin {
+            text.push_str(&format!(
+                "{} from origin 0x{:x} - ",
+                format_relative_offset(offset, origin, OffsetFormat::Hex, 0),
+                origin
+            ));
+        }
+        text.push_str(&offset_info(offset, self.buffer.get(offset)));
+
+        self.info_line.set_text(text);
+    }
+
+    /// Name of the part of the ELF file at the offset.
+    fn elf_region(&self, offset: usize) -> Option<&str> {
+        let idx = match self.elf_regions.binary_search_by(|region| region.start.cmp(&offset)) {
+            Ok(idx) =>
+                idx,
+            Err(0) =>
+                return None,
+            Err(idx) =>
+                idx - 1,
+        };
+        let region = &self.elf_regions[idx];
+        if offset < region.end {
+            Some(&region.name)
+        } else {
+            None
+        }
+    }
+
+    /// Index (from 1) of the search match at the offset, and the number of
+    /// highlighted matches.
+    fn search_match(&self, offset: usize) -> Option<(usize, usize)> {
+        // Highlights of e.g. `:s///c` are not search matches
+        self.search.as_ref()?;
+        self.highlight
+            .position(offset)
+            .map(|idx| (idx + 1, self.highlight.len()))
     }
 
-    pub fn draw(&mut self) {
-        self.tb.clear();
+    pub fn draw(&self, tb: &mut Termbox) {
+        let zen = self.config.zen;
+        let widgets_height = self.widgets_height();
+        let rows = self.hex_grid.rows();
+
+        if !zen {
+            self.lines.draw(tb, &rows);
+            let vsplit_x = self.lines.width();
+            let style = colors::palette().default;
+            for y in 0..widgets_height {
+                tb.change_cell(vsplit_x, y, '│', style.fg, style.bg);
+            }
+            self.draw_gutter(tb, &rows);
+        }
+
+        // Only the visible part is drawn, so this doesn't scan the whole file
+        let same_byte = if self.highlight_same_byte {
+            self.buffer.get(self.hex_grid.get_byte_idx())
+        } else {
+            None
+        };
+
+        let selection = self.selection();
+        let elf_ranges: Vec<(usize, usize)> =
+            self.elf_regions.iter().map(|region| (region.start, region.end)).collect();
+
+        let highlights = ByteHighlights {
+            hl: &self.highlight,
+            same_byte,
+            selection,
+            virtual_ranges: &self.virtual_ranges,
+            template_ranges: &self.template_ranges,
+            elf_ranges: &elf_ranges,
+            reference: self.reference.as_ref().map(|r| r.as_slice()),
+            byte_colors: &self.config.byte_colors,
+        };
+        self.hex_grid.draw(tb, &self.buffer, &highlights);
+
+        if !zen {
+            let vsplit_x = self.lines.width() + self.hex_grid.width();
+            let style = colors::palette().default;
+            for y in 0..widgets_height {
+                tb.change_cell(vsplit_x, y, '│', style.fg, style.bg);
+            }
 
-        self.lines.draw(&mut self.tb);
+            self.ascii_view.draw(tb, &rows, &self.buffer, &highlights);
+        }
 
-        let vsplit_x = self.lines.width();
-        for y in 0..self.height - 1 {
-            self.tb
-                .change_cell(vsplit_x, y, '│', colors::DEFAULT.fg, colors::DEFAULT.bg);
+        if let Some(kind) = self.config.footer {
+            let width = if self.minimap.is_some() {
+                self.width - MINIMAP_WIDTH
+            } else {
+                self.width
+            };
+            let checksum_x = if zen {
+                None
+            } else {
+                Some(self.lines.width() + self.hex_grid.width() + 1)
+            };
+            footer::draw(
+                tb,
+                widgets_height - 1,
+                width,
+                kind,
+                &self.buffer,
+                &self.hex_grid,
+                checksum_x,
+            );
         }
 
-        self.hex_grid
-            .draw(&mut self.tb, &self.highlight, self.highlight_len);
+        if let Some(ref minimap) = self.minimap {
+            minimap.draw(tb, self.hex_grid.get_byte_idx());
+        }
 
-        let vsplit_x = vsplit_x + self.hex_grid.width();
-        for y in 0..self.height - 1 {
-            self.tb
-                .change_cell(vsplit_x, y, '│', colors::DEFAULT.fg, colors::DEFAULT.bg);
+        if let Some(ref panel) = self.panel {
+            panel.draw(
+                tb,
+                &self.buffer,
+                self.hex_grid.get_byte_idx(),
+                &self.template_fields,
+                self.disas_arch,
+                self.disas_elf.as_ref(),
+            );
         }
 
-        self.ascii_view
-            .draw(&mut self.tb, &self.highlight, self.highlight_len);
+        // Questions are shown in zen mode too, over the last row
+        let asking = matches!(
+            self.pending_key,
+            PendingKey::ConfirmDeleteAll
+                | PendingKey::ConfirmVerify
+                | PendingKey::ConfirmTemplate(_)
+                | PendingKey::ConfirmOverwrite(_)
+        );
+        if !zen || asking {
+            match self.jobs.last() {
+                Some(job) if !asking =>
+                    self.info_line.draw_with(tb, &job.status()),
+                _ =>
+                    self.info_line.draw(tb),
+            }
+        }
 
-        self.info_line.draw(&mut self.tb);
+        if let Some(ref tutor) = self.tutor {
+            tutor.draw(tb, self.width, self.height);
+        }
 
         match self.overlay {
             Overlay::NoOverlay =>
                 {}
             Overlay::SearchOverlay(ref o) =>
-                o.draw(&mut self.tb),
+                o.draw(tb),
             Overlay::GotoOverlay(ref o) =>
-                o.draw(&mut self.tb),
+                o.draw(tb),
+            Overlay::MarksOverlay(ref o) =>
+                o.draw(tb),
+            Overlay::HistogramOverlay(ref o) =>
+                o.draw(tb),
+            Overlay::IntTableOverlay(ref o) =>
+                o.draw(tb, &self.buffer),
+            Overlay::SymbolsOverlay(ref o) =>
+                o.draw(tb),
+            Overlay::SectionsOverlay(ref o) =>
+                o.draw(tb),
+            Overlay::DynamicOverlay(ref o) =>
+                o.draw(tb),
+            Overlay::RelocationsOverlay(ref o) =>
+                o.draw(tb),
+            Overlay::NotesOverlay(ref o) =>
+                o.draw(tb),
+            Overlay::StructureOverlay(ref o) =>
+                o.draw(tb),
+            Overlay::ElfHeaderOverlay(ref o) =>
+                o.draw(tb),
+            Overlay::ValueOverlay(ref o) =>
+                o.draw(tb, &self.buffer),
+            Overlay::PathOverlay(ref o) =>
+                o.draw(tb),
+            Overlay::RecordsOverlay(ref o) =>
+                o.draw(tb),
+            Overlay::CommandLine(ref o) =>
+                o.draw(tb),
         }
-
-        self.tb.present();
     }
 
-    pub fn mainloop(&mut self) {
-        let mut input = Input::new();
-        let mut evs = Vec::with_capacity(10);
-        self.draw();
-
-        loop {
-            let mut fds = [PollFd::new(libc::STDIN_FILENO, POLLIN)];
-            let _ = poll(&mut fds, -1);
-
-            input.read_input_events(&mut evs);
+    /// Clicks in the hex and ASCII views move the cursor, dragging selects,
+    /// the wheel scrolls. Ignored while an overlay or a mode is active.
+    pub fn mouse(&mut self, ev: MouseEvent) {
+        match self.overlay {
+            Overlay::NoOverlay =>
+                {}
+            _ =>
+                return,
+        }
+        if self.insert.is_some() || self.replace.is_some() {
+            return;
+        }
 
-            let mut brk = false;
-            for ev in evs.drain(..) {
-                brk |= self.handle_event(ev);
-            }
-            if brk {
-                break;
+        match ev {
+            MouseEvent::WheelUp =>
+                self.hex_grid.scroll_lines(-WHEEL_LINES),
+            MouseEvent::WheelDown =>
+                self.hex_grid.scroll_lines(WHEEL_LINES),
+            MouseEvent::Press(x, y) =>
+                if let Some((offset, digit)) = self.offset_at(x, y) {
+                    self.selection_anchor = None;
+                    self.mouse_press = Some(offset);
+                    self.hex_grid.move_cursor_digit(offset, digit);
+                },
+            MouseEvent::Drag(x, y) =>
+                if let Some(press) = self.mouse_press {
+                    if let Some((offset, digit)) = self.offset_at(x, y) {
+                        if offset != press {
+                            self.selection_anchor = Some(press);
+                        }
+                        self.hex_grid.move_cursor_digit(offset, digit);
+                    }
+                },
+            MouseEvent::Release(_, _) => {
+                self.mouse_press = None;
             }
-            self.draw();
         }
     }
 
-    fn handle_event(&mut self, ev: Event) -> bool {
-        match ev {
-            Event::Key(key) =>
-                self.keypressed(key),
-            Event::String(_) |
-            Event::Resize |
-            Event::FocusGained |
-            Event::FocusLost |
-            Event::Unknown(_) =>
-                false,
+    /// Byte and digit at a screen position in the hex or ASCII view.
+    fn offset_at(&self, x: i32, y: i32) -> Option<(usize, usize)> {
+        if let Some(ret) = self.hex_grid.offset_at(x, y) {
+            return Some(ret);
+        }
+        match self.ascii_view.offset_at(x, y, &self.hex_grid.rows()) {
+            Some(offset) if offset < self.buffer.len() =>
+                Some((offset, 0)),
+            _ =>
+                None,
         }
     }
 
-    fn keypressed(&mut self, key: Key) -> bool {
+    /// `time`: when the key was read. Held motion keys move faster.
+    pub fn keypressed(&mut self, key: Key, time: Instant) -> HexGuiRet {
+        if key == Key::Ctrl('c') && !self.jobs.is_empty() {
+            let names: Vec<&str> = self.jobs.iter().map(|job| job.name()).collect();
+            let msg = format!("Cancelled: {}", names.join(", "));
+            self.cancel_jobs();
+            self.info_line.set_text(msg);
+            return HexGuiRet::Continue;
+        }
+
         let mut reset_overlay = false;
+        let mut ret = HexGuiRet::Continue;
         match self.overlay {
             Overlay::NoOverlay => {
-                if key == Key::Char('q') {
-                    return true;
+                if self.insert.is_some() {
+                    self.keypressed_insert(key);
+                } else if self.replace.is_some() {
+                    self.keypressed_replace(key);
+                } else if key == Key::Char('q') {
+                    if !self.buffer.is_modified() {
+                        return HexGuiRet::Quit;
+                    }
+                    let msg = self.unsaved_msg();
+                    self.info_line.set_text(msg.to_owned());
+                } else {
+                    ret = self.keypressed_no_overlay(key, time);
                 }
-                self.keypressed_no_overlay(key)
             }
 
             Overlay::GotoOverlay(ref mut o) =>
                 match o.keypressed(key) {
                     OverlayRet::Ret(offset) => {
-                        self.hex_grid.move_cursor_offset(offset);
+                        if offset < self.buffer.len() || offset == 0 {
+                            self.jump(offset);
+                        } else {
+                            self.fail(BellEvent::InvalidGoto, "Offset is out of the file");
+                        }
+                        reset_overlay = true;
+                    }
+                    OverlayRet::Invalid => {
+                        let msg = if self.shown_base().is_some() {
+                            "Invalid address"
+                        } else {
+                            "Invalid offset"
+                        };
+                        self.fail(BellEvent::InvalidGoto, msg);
+                        reset_overlay = true;
+                    }
+                    OverlayRet::UnknownSymbol(name) => {
+                        self.fail(BellEvent::InvalidGoto, &format!("No symbol: {}", name));
                         reset_overlay = true;
                     }
                     OverlayRet::GotoBeginning => {
-                        self.hex_grid.move_cursor_offset(0);
+                        self.jump(0);
                         reset_overlay = true;
                     }
+                    OverlayRet::NextBuffer => {
+                        self.overlay = Overlay::NoOverlay;
+                        return HexGuiRet::NextBuffer;
+                    }
+                    OverlayRet::PrevBuffer => {
+                        self.overlay = Overlay::NoOverlay;
+                        return HexGuiRet::PrevBuffer;
+                    }
+                    OverlayRet::BufferList => {
+                        self.overlay = Overlay::NoOverlay;
+                        return HexGuiRet::BufferList;
+                    }
                     OverlayRet::Continue =>
                         {}
                     OverlayRet::Abort => {
                         reset_overlay = true;
                     }
                 },
-
             Overlay::SearchOverlay(ref mut o) => {
                 match o.keypressed(key) {
-                    SearchRet::Highlight {
-                        all_bytes: bs,
-                        len: l,
-                        ..
-                    } => {
-                        self.highlight = bs;
-                        self.highlight_len = l;
+                    SearchRet::Search(pattern, scope) => {
+                        let range = match scope {
+                            SearchScope::File =>
+                                None,
+                            SearchScope::View =>
+                                Some(
+                                    self.selection()
+                                        .unwrap_or_else(|| self.hex_grid.visible_range()),
+                                ),
+                        };
+                        self.search(pattern, range);
                         reset_overlay = true;
                     }
                     SearchRet::Abort => {
@@ -266,87 +1391,3656 @@ impl<'gui> HexGui<'gui> {
                     { /* nothing to do */ }
                 }
             }
-        };
 
-        if reset_overlay {
-            self.overlay = Overlay::NoOverlay;
-        }
+            Overlay::MarksOverlay(ref mut o) =>
+                match o.keypressed(key) {
+                    MarksRet::Jump(offset) => {
+                        self.jump(offset);
+                        reset_overlay = true;
+                    }
+                    MarksRet::Delete(ch) => {
+                        self.marks.remove(ch);
+                        self.marks.save(&self.path);
+                    }
+                    MarksRet::Label(ch, label) => {
+                        self.marks.set_label(ch, label);
+                        self.marks.save(&self.path);
+                    }
+                    MarksRet::Abort => {
+                        reset_overlay = true;
+                    }
+                    MarksRet::Continue =>
+                        {}
+                },
 
-        false
+            Overlay::HistogramOverlay(ref mut o) =>
+                match o.keypressed(key) {
+                    HistogramRet::Abort => {
+                        reset_overlay = true;
+                    }
+                    HistogramRet::Continue =>
+                        {}
+                },
+
+            Overlay::IntTableOverlay(ref mut o) =>
+                match o.keypressed(key, &self.buffer) {
+                    IntTableRet::Jump(offset) => {
+                        self.jump(offset);
+                        reset_overlay = true;
+                    }
+                    IntTableRet::Abort => {
+                        reset_overlay = true;
+                    }
+                    IntTableRet::Continue =>
+                        {}
+                },
+
+            Overlay::SymbolsOverlay(ref mut o) =>
+                match o.keypressed(key) {
+                    SymbolsRet::Jump(offset) => {
+                        self.jump(offset);
+                        reset_overlay = true;
+                    }
+                    SymbolsRet::Abort => {
+                        reset_overlay = true;
+                    }
+                    SymbolsRet::Continue =>
+                        {}
+                },
+
+            Overlay::SectionsOverlay(ref mut o) =>
+                match o.keypressed(key) {
+                    SectionsRet::Jump(offset) => {
+                        self.jump(offset);
+                        reset_overlay = true;
+                    }
+                    SectionsRet::Abort => {
+                        reset_overlay = true;
+                    }
+                    SectionsRet::Continue =>
+                        {}
+                },
+
+            Overlay::DynamicOverlay(ref mut o) =>
+                match o.keypressed(key) {
+                    DynamicRet::Jump(offset) => {
+                        self.jump(offset);
+                        reset_overlay = true;
+                    }
+                    DynamicRet::Abort => {
+                        reset_overlay = true;
+                    }
+                    DynamicRet::Continue =>
+                        {}
+                },
+
+            Overlay::RelocationsOverlay(ref mut o) =>
+                match o.keypressed(key) {
+                    RelocationsRet::Jump(offset) => {
+                        self.jump(offset);
+                        reset_overlay = true;
+                    }
+                    RelocationsRet::Abort => {
+                        reset_overlay = true;
+                    }
+                    RelocationsRet::Continue =>
+                        {}
+                },
+
+            Overlay::NotesOverlay(ref mut o) =>
+                match o.keypressed(key) {
+                    NotesRet::Jump(offset) => {
+                        self.jump(offset);
+                        reset_overlay = true;
+                    }
+                    NotesRet::Abort => {
+                        reset_overlay = true;
+                    }
+                    NotesRet::Continue =>
+                        {}
+                },
+
+            Overlay::StructureOverlay(ref mut o) =>
+                match o.keypressed(key) {
+                    StructureRet::Jump(offset) => {
+                        self.jump(offset);
+                        reset_overlay = true;
+                    }
+                    StructureRet::Abort => {
+                        reset_overlay = true;
+                    }
+                    StructureRet::Continue =>
+                        {}
+                },
+
+            Overlay::ElfHeaderOverlay(ref mut o) =>
+                match o.keypressed(key) {
+                    ElfHeaderRet::Write { offset, bytes } =>
+                        self.write_header_field(offset, &bytes),
+                    ElfHeaderRet::Jump(offset) => {
+                        self.jump(offset);
+                        reset_overlay = true;
+                    }
+                    ElfHeaderRet::Abort => {
+                        reset_overlay = true;
+                    }
+                    ElfHeaderRet::Continue =>
+                        {}
+                },
+
+            Overlay::ValueOverlay(ref mut o) =>
+                match o.keypressed(key) {
+                    ValueRet::Write {
+                        offset,
+                        unit,
+                        big_endian,
+                        bytes,
+                    } => {
+                        self.set_value(offset, unit, big_endian, &bytes);
+                        reset_overlay = true;
+                    }
+                    ValueRet::Abort => {
+                        reset_overlay = true;
+                    }
+                    ValueRet::Continue =>
+                        {}
+                },
+
+            Overlay::PathOverlay(ref mut o) =>
+                match o.keypressed(key) {
+                    PathRet::Path(path) => {
+                        if Path::new(&path).exists() {
+                            self.info_line.set_text(format!("{} exists, overwrite? (y/n)", path));
+                            self.pending_key = PendingKey::ConfirmOverwrite(path);
+                        } else {
+                            let msg = self.save_as(&path, false);
+                            self.info_line.set_text(msg);
+                        }
+                        reset_overlay = true;
+                    }
+                    PathRet::Abort => {
+                        reset_overlay = true;
+                    }
+                    PathRet::Continue =>
+                        {}
+                },
+
+            Overlay::RecordsOverlay(ref mut o) =>
+                match o.keypressed(key) {
+                    RecordsRet::Accept(size) => {
+                        let msg = self.show_records(size);
+                        self.info_line.set_text(msg);
+                        reset_overlay = true;
+                    }
+                    RecordsRet::Abort => {
+                        reset_overlay = true;
+                    }
+                    RecordsRet::Continue =>
+                        {}
+                },
+
+            Overlay::CommandLine(ref mut o) =>
+                match o.keypressed(key) {
+                    CommandRet::Run(cmd) => {
+                        self.overlay = Overlay::NoOverlay;
+                        self.add_to_history(&cmd);
+                        match self.run_command(&cmd) {
+                            ret @ HexGuiRet::Quit | ret @ HexGuiRet::ForceQuit =>
+                                return ret,
+                            _ =>
+                                {}
+                        }
+                    }
+                    CommandRet::Abort => {
+                        reset_overlay = true;
+                    }
+                    CommandRet::Continue =>
+                        {}
+                },
+        };
+
+        if reset_overlay {
+            self.overlay = Overlay::NoOverlay;
+        }
+
+        if let Some(ref mut tutor) = self.tutor {
+            tutor.update(&Progress {
+                cursor: self.hex_grid.get_byte_idx(),
+                last_byte: self.buffer.len().saturating_sub(1),
+                highlights: self.highlight.len(),
+                mark_a: self.marks.get('a').map(|m| m.offset),
+            });
+        }
+
+        ret
     }
 
-    fn keypressed_no_overlay(&mut self, key: Key) {
+    fn keypressed_no_overlay(&mut self, key: Key, time: Instant) -> HexGuiRet {
+        match mem::replace(&mut self.pending_key, PendingKey::None) {
+            PendingKey::None =>
+                {}
+            PendingKey::Z =>
+                if key == Key::Char('z') {
+                    self.hex_grid.try_center_scroll();
+                    return HexGuiRet::Continue;
+                },
+            PendingKey::SetMark => {
+                if let Key::Char(ch) = key {
+                    if ch.is_ascii_alphabetic() {
+                        self.marks.set(ch, self.hex_grid.get_byte_idx());
+                        self.marks.save(&self.path);
+                    }
+                }
+                return HexGuiRet::Continue;
+            }
+            PendingKey::JumpMark => {
+                if let Key::Char(ch) = key {
+                    if let Some(offset) = self.marks.get(ch).map(|m| m.offset) {
+                        self.jump(offset);
+                    }
+                }
+                return HexGuiRet::Continue;
+            }
+            PendingKey::ConfirmDeleteAll => {
+                if key == Key::Char('y') {
+                    let len = self.buffer.len();
+                    self.delete(0, len);
+                } else {
+                    self.update_info_line();
+                }
+                return HexGuiRet::Continue;
+            }
+            PendingKey::ConfirmVerify => {
+                if key == Key::Char('y') {
+                    self.verify();
+                } else {
+                    self.update_info_line();
+                }
+                return HexGuiRet::Continue;
+            }
+            PendingKey::ConfirmTemplate(path) => {
+                if key == Key::Char('y') {
+                    let msg = self.template_cmd(&[&path, "0"]);
+                    self.info_line.set_text(msg);
+                } else {
+                    self.update_info_line();
+                }
+                return HexGuiRet::Continue;
+            }
+            PendingKey::ConfirmOverwrite(path) => {
+                if key == Key::Char('y') {
+                    let msg = self.save_as(&path, true);
+                    self.info_line.set_text(msg);
+                } else {
+                    self.update_info_line();
+                }
+                return HexGuiRet::Continue;
+            }
+            PendingKey::Next =>
+                if key == Key::Char('d') {
+                    self.jump_to_diff(true);
+                    return HexGuiRet::Continue;
+                },
+            PendingKey::Prev =>
+                if key == Key::Char('d') {
+                    self.jump_to_diff(false);
+                    return HexGuiRet::Continue;
+                },
+        }
+
         match key {
             Key::Char('g') => {
-                self.z_pressed = false;
                 self.mk_goto_overlay();
             }
             Key::Char('/') => {
-                self.z_pressed = false;
                 self.mk_search_overlay();
             }
-            Key::Char('z') =>
-                if self.z_pressed {
-                    self.hex_grid.try_center_scroll();
-                    self.lines.set_scroll(self.hex_grid.get_scroll());
-                    self.ascii_view.set_scroll(self.hex_grid.get_scroll());
-                    self.z_pressed = false;
+            Key::Char('z') => {
+                self.pending_key = PendingKey::Z;
+            }
+            Key::Char('m') => {
+                self.pending_key = PendingKey::SetMark;
+            }
+            Key::Char('\'') => {
+                self.pending_key = PendingKey::JumpMark;
+            }
+            Key::Char('`') => {
+                self.mk_marks_overlay();
+            }
+            Key::Char(']') => {
+                self.pending_key = PendingKey::Next;
+            }
+            Key::Char('[') => {
+                self.pending_key = PendingKey::Prev;
+            }
+            Key::Char('=') => {
+                self.layout_strategy = match self.layout_strategy {
+                    LayoutStrategy::Fill =>
+                        LayoutStrategy::Pow2,
+                    LayoutStrategy::Pow2 | LayoutStrategy::Fixed(_) =>
+                        LayoutStrategy::Fill,
+                };
+                self.relayout();
+            }
+            Key::Char('+') => {
+                let bpl = self.hex_grid.bytes_per_line();
+                let bytes = FIXED_BYTES_PER_LINE
+                    .iter()
+                    .cloned()
+                    .find(|&b| b as usize > bpl)
+                    .unwrap_or(FIXED_BYTES_PER_LINE[FIXED_BYTES_PER_LINE.len() - 1]);
+                self.layout_strategy = LayoutStrategy::Fixed(bytes);
+                self.relayout();
+            }
+            Key::Char('-') => {
+                let bpl = self.hex_grid.bytes_per_line();
+                let bytes = FIXED_BYTES_PER_LINE
+                    .iter()
+                    .cloned()
+                    .rev()
+                    .find(|&b| (b as usize) < bpl)
+                    .unwrap_or(FIXED_BYTES_PER_LINE[0]);
+                self.layout_strategy = LayoutStrategy::Fixed(bytes);
+                self.relayout();
+            }
+            Key::Char('E') => {
+                if self.minimap.is_some() {
+                    self.minimap = None;
+                    self.cancel_task(Task::Minimap.name());
                 } else {
-                    self.z_pressed = true;
-                },
-            Key::Char('n') => {
-                self.z_pressed = false;
-                let hls = &self.highlight;
-                let byte_idx = self.hex_grid.get_byte_idx() as usize;
-                for &hl_offset in hls {
-                    if hl_offset > byte_idx {
-                        self.hex_grid.move_cursor_offset(hl_offset as i32);
-                        return;
-                    }
+                    self.minimap = Some(Minimap::new(self.width - MINIMAP_WIDTH, 0));
+                    self.update_minimap();
                 }
-                // We couldn't jump to a match, start from the beginning
-                if let Some(&hl_offset) = hls.get(0) {
-                    self.hex_grid.move_cursor_offset(hl_offset as i32);
+                self.relayout();
+            }
+            Key::Char('p') => {
+                let kind = match self.panel {
+                    Some(_) =>
+                        None,
+                    None =>
+                        Some(PanelKind::Inspector),
+                };
+                self.set_panel(kind);
+            }
+            Key::Char('P') => {
+                let kind = self.panel.as_ref().map(|panel| panel.kind().next());
+                if kind.is_some() {
+                    self.set_panel(kind);
                 }
             }
-            Key::Char('N') => {
-                self.z_pressed = false;
-                let hls = &self.highlight;
-                let byte_idx = self.hex_grid.get_byte_idx() as usize;
-                for &hl_offset in hls.iter().rev() {
-                    if hl_offset < byte_idx {
-                        self.hex_grid.move_cursor_offset(hl_offset as i32);
-                        return;
+            Key::Char('{') | Key::Char('}') => {
+                if self.panel.is_some() {
+                    // Moves the split up or down
+                    let step = if key == Key::Char('{') { 10 } else { -10 };
+                    self.panel_percent = (self.panel_percent + step).clamp(20, 80);
+                    self.update_minimap();
+                    self.relayout();
+                }
+            }
+            Key::Char('#') => {
+                self.start_task(Task::Histogram);
+            }
+            Key::Char('Z') => {
+                self.toggle_zen();
+            }
+            Key::Char('F') => {
+                self.toggle_follow();
+            }
+            Key::Char(':') => {
+                let command_line =
+                    CommandLine::new(self.width, 0, self.height - 1, self.command_history.clone());
+                self.overlay = Overlay::CommandLine(command_line);
+            }
+            Key::Char('v') => {
+                if self.buffer.is_empty() {
+                    self.fail(BellEvent::Edge, "Empty file");
+                    return HexGuiRet::Continue;
+                }
+                self.selection_anchor = match self.selection_anchor {
+                    Some(_) =>
+                        None,
+                    None =>
+                        Some(self.hex_grid.get_byte_idx()),
+                };
+                self.update_info_line();
+            }
+            Key::Esc => {
+                if self.selection_anchor.take().is_some() {
+                    self.update_info_line();
+                } else {
+                    self.clear_highlights();
+                }
+            }
+            Key::Char('I') => {
+                self.mk_int_table_overlay();
+            }
+            Key::Char('c') => {
+                self.mk_value_overlay();
+            }
+            Key::Char('S') =>
+                match self.file_kind {
+                    Some(FileKind::Pe) =>
+                        self.mk_pe_overlay(),
+                    Some(FileKind::MachO) =>
+                        self.mk_macho_overlay(),
+                    Some(kind) if !kind.has_parser() => {
+                        let msg = match config::template_path(kind.template_name()) {
+                            Some(path) =>
+                                format!(
+                                    "{} file, :template {} to see its structure",
+                                    kind.name(),
+                                    path.display()
+                                ),
+                            None =>
+                                format!(
+                                    "No structure view for {} files, :template <file> applies \
+                                     a template",
+                                    kind.name()
+                                ),
+                        };
+                        self.info_line.set_text(msg);
                     }
+                    _ =>
+                        self.mk_elf_overlay(),
+                },
+            Key::Char('D') => {
+                self.mk_dynamic_overlay();
+            }
+            Key::Char('J') => {
+                self.mk_sections_overlay();
+            }
+            Key::Char('X') => {
+                self.mk_relocations_overlay();
+            }
+            Key::Char('B') => {
+                self.mk_notes_overlay();
+            }
+            Key::Char('A') => {
+                return self.open_memory_image();
+            }
+            Key::Char('i') => {
+                let offset = self.hex_grid.get_byte_idx();
+                self.enter_insert(offset);
+            }
+            Key::Char('a') => {
+                let offset = if self.buffer.is_empty() {
+                    0
+                } else {
+                    self.hex_grid.get_byte_idx() + 1
+                };
+                self.enter_insert(offset);
+            }
+            Key::Char('x') => {
+                match self.selection() {
+                    Some((start, end)) =>
+                        self.delete_selection(start, end),
+                    None =>
+                        if !self.buffer.is_empty() {
+                            let cursor = self.hex_grid.get_byte_idx();
+                            self.delete(cursor, cursor + 1);
+                        },
+                }
+            }
+            Key::Char('d') =>
+                match self.selection() {
+                    Some((start, end)) =>
+                        self.delete_selection(start, end),
+                    None =>
+                        self.toggle_disassembly(),
+                },
+            Key::Char('%') => {
+                self.select_all();
+            }
+            Key::Char('*') => {
+                self.highlight_same_byte = !self.highlight_same_byte;
+            }
+            Key::Char('r') => {
+                self.origin = Some(self.hex_grid.get_byte_idx());
+                self.relayout();
+            }
+            Key::Char('R') => {
+                if self.origin.is_some() {
+                    self.origin = None;
+                    self.relayout();
                 }
-                // We couldn't jump to a match, start from the beginning
-                if let Some(&hl_offset) = hls.get(hls.len() - 1) {
-                    self.hex_grid.move_cursor_offset(hl_offset as i32);
+            }
+            Key::Char('y') => {
+                let offset = self.hex_grid.get_byte_idx();
+                self.copy_to_clipboard(format!("0x{:x}", offset));
+            }
+            Key::Char('Y') => {
+                let addr = self.config.base.unwrap_or(0) + self.hex_grid.get_byte_idx();
+                self.copy_to_clipboard(format!("0x{:x}", addr));
+            }
+            Key::Char('w') => {
+                self.config.group_size = match self.config.group_size {
+                    1 =>
+                        2,
+                    2 =>
+                        4,
+                    4 =>
+                        8,
+                    _ =>
+                        1,
+                };
+                self.relayout();
+            }
+            Key::Char('o') => {
+                self.config.offset_format = self.config.offset_format.next();
+                self.relayout();
+            }
+            Key::Char('C') => {
+                self.config.non_printable = self.config.non_printable.next();
+                self.relayout();
+            }
+            Key::Char('b') => {
+                self.config.bits = !self.config.bits;
+                self.relayout();
+            }
+            Key::Char(' ') if self.config.bits =>
+                self.toggle_bit(),
+            Key::Char('u') =>
+                self.undo(),
+            Key::Ctrl('r') =>
+                self.redo(),
+            Key::Char('O') => {
+                if self.config.base.is_some() {
+                    self.show_base = !self.show_base;
+                    self.relayout();
+                }
+            }
+            Key::Char('\r') => {
+                if self.panel_kind() == Some(PanelKind::Disassembly) {
+                    self.follow_branch();
+                }
+            }
+            Key::Ctrl('o') => {
+                let current = self.hex_grid.get_byte_idx();
+                if let Some(offset) = self.jump_list.back(current) {
+                    self.hex_grid.move_cursor_offset(offset);
+                }
+            }
+            Key::Tab => {
+                if let Some(offset) = self.jump_list.forward() {
+                    self.hex_grid.move_cursor_offset(offset);
+                }
+            }
+            Key::Char(ch @ '1'..='9') => {
+                let current = self.hex_grid.get_byte_idx();
+                let n = ch as usize - '0' as usize;
+                if let Some(offset) = self.jump_list.back_n(current, n) {
+                    self.hex_grid.move_cursor_offset(offset);
                 }
             }
+            Key::Char('G') => {
+                let last = self.buffer.len().saturating_sub(1);
+                self.jump(last);
+            }
+            Key::Char('n') =>
+                match self.next_match() {
+                    Some((hl_offset, wrapped)) =>
+                        self.jump_to_match(hl_offset, wrapped),
+                    None =>
+                        self.fail(BellEvent::NotFound, "Pattern not found"),
+                },
+            Key::Char('N') =>
+                match self.prev_match() {
+                    Some((hl_offset, wrapped)) =>
+                        self.jump_to_match(hl_offset, wrapped),
+                    None =>
+                        self.fail(BellEvent::NotFound, "Pattern not found"),
+                },
             _ => {
-                self.z_pressed = false;
-                self.hex_grid.keypressed(key);
+                let cursor = self.hex_grid.get_byte_idx();
+                let scroll = self.hex_grid.get_scroll();
+                if self.hex_grid.keypressed(key, time)
+                    && self.hex_grid.get_byte_idx() == cursor
+                    && self.hex_grid.get_scroll() == scroll
+                {
+                    // Didn't move. Only an error at the beginning or end of
+                    // the buffer, not e.g. when moving left at the beginning
+                    // of a line.
+                    if self.buffer.is_empty() {
+                        self.fail(BellEvent::Edge, "Empty file");
+                    } else if cursor == 0 {
+                        self.fail(BellEvent::Edge, "Beginning of buffer");
+                    } else if cursor + 1 >= self.buffer.len() {
+                        self.fail(BellEvent::Edge, "End of buffer");
+                    }
+                }
             }
         }
+        HexGuiRet::Continue
     }
 
-    fn mk_goto_overlay(&mut self) {
-        self.overlay = Overlay::GotoOverlay(GotoOverlay::new(
-            self.width / 2,
-            self.height / 2,
-            self.width / 4,
-            self.height / 4,
-        ));
+    fn undo(&mut self) {
+        match self.buffer.undo() {
+            Ok(Some(offset)) => {
+                self.edited(offset);
+                let msg = self.history_msg();
+                self.info_line.set_text(msg);
+            }
+            Ok(None) =>
+                self.fail(BellEvent::Edge, "Already at oldest change"),
+            Err(err) =>
+                self.info_line.set_text(format!("Can't read undo history: {}", err)),
+        }
     }
 
-    fn mk_search_overlay(&mut self) {
-        self.overlay = Overlay::SearchOverlay(SearchOverlay::new(
-            self.width / 2,
-            self.height / 2,
-            self.width / 4,
-            self.height / 4,
-            self.contents,
-        ));
+    fn redo(&mut self) {
+        match self.buffer.redo() {
+            Some(offset) => {
+                self.edited(offset);
+                let msg = self.history_msg();
+                self.info_line.set_text(msg);
+            }
+            None =>
+                self.fail(BellEvent::Edge, "Already at newest change"),
+        }
+    }
+
+    /// Depth of the undo history, e.g. "12 changes to undo (10 on disk), 1 to
+    /// redo".
+    fn history_msg(&self) -> String {
+        let history = self.buffer.history();
+        let mut msg = format!("{} changes to undo", history.depth());
+        if history.spilled() != 0 {
+            msg.push_str(&format!(" ({} on disk)", history.spilled()));
+        }
+        msg.push_str(&format!(", {} to redo", history.redo_depth()));
+        msg
+    }
+
+    /// Flip the bit under the cursor, in bit mode.
+    fn toggle_bit(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let offset = self.hex_grid.get_byte_idx();
+        let bit = self.hex_grid.get_digit();
+        if !self.edit_allowed(offset, offset + 1, 1) {
+            return;
+        }
+        let byte = self.buffer.get(offset).unwrap() ^ (0x80 >> bit);
+        self.buffer.set(offset, byte);
+        self.edited(offset);
+        self.hex_grid.move_cursor_digit(offset, bit);
+    }
+
+    fn enter_insert(&mut self, offset: usize) {
+        if !self.edit_allowed(offset, offset, 1) {
+            return;
+        }
+        self.insert = Some(InsertState {
+            offset,
+            low_nibble: false,
+        });
+        self.update_info_line();
+    }
+
+    fn keypressed_insert(&mut self, key: Key) {
+        let digit = match key {
+            Key::Esc => {
+                // A half-typed byte is kept, with 0 as the lower nibble
+                self.insert = None;
+                self.update_info_line();
+                return;
+            }
+            Key::Char(ch) =>
+                match ch.to_digit(16) {
+                    Some(digit) =>
+                        digit as u8,
+                    None =>
+                        return,
+                },
+            _ =>
+                return,
+        };
+
+        let (offset, low_nibble) = match self.insert {
+            Some(ref insert) =>
+                (insert.offset, insert.low_nibble),
+            None =>
+                return,
+        };
+
+        if low_nibble {
+            let byte = self.buffer.get(offset).unwrap() | digit;
+            self.buffer.set(offset, byte);
+            self.insert = Some(InsertState {
+                offset: offset + 1,
+                low_nibble: false,
+            });
+            self.edited(offset + 1);
+        } else if self.edit_allowed(offset, offset, 1) {
+            self.buffer.insert(offset, digit << 4);
+            self.insert = Some(InsertState {
+                offset,
+                low_nibble: true,
+            });
+            self.edited(offset);
+        }
+    }
+
+    /// Locked range that replacing `start..end` with `len` bytes would change,
+    /// if any.
+    fn locked(&self, start: usize, end: usize, len: usize) -> Option<(usize, usize)> {
+        let moves = len != end - start;
+        self.locks
+            .iter()
+            .cloned()
+            .find(|&(lock_start, lock_end)| start < lock_end && (end > lock_start || moves))
+    }
+
+    /// Why replacing `start..end` with `len` bytes is not allowed: the buffer
+    /// is read-only, or the edit would change a locked range.
+    fn edit_error(&self, start: usize, end: usize, len: usize) -> Option<String> {
+        if self.config.readonly {
+            return Some("Read-only, :set readonly=off to edit".to_owned());
+        }
+        self.locked(start, end, len).map(locked_msg)
+    }
+
+    /// Can `start..end` be replaced with `len` bytes? Shows a message when
+    /// it's not.
+    fn edit_allowed(&mut self, start: usize, end: usize, len: usize) -> bool {
+        match self.edit_error(start, end, len) {
+            Some(err) => {
+                self.info_line.set_text(err);
+                false
+            }
+            None =>
+                true,
+        }
+    }
+
+    /// `:lock`: lock the selection. Without a selection, list the locks.
+    fn lock_cmd(&mut self) -> String {
+        let (start, end) = match self.selection() {
+            Some(range) =>
+                range,
+            None =>
+                return if self.locks.is_empty() {
+                    "Nothing is locked, select the bytes to lock first (v)".to_owned()
+                } else {
+                    let locks: Vec<String> = self.locks
+                        .iter()
+                        .map(|&(start, end)| format!("0x{:x}-0x{:x}", start, end))
+                        .collect();
+                    format!("Locked: {}", locks.join(", "))
+                },
+        };
+        self.locks.push((start, end));
+        self.locks.sort();
+        let mut merged: Vec<(usize, usize)> = Vec::new();
+        for &(start, end) in &self.locks {
+            match merged.last_mut() {
+                Some(last) if start <= last.1 =>
+                    last.1 = cmp::max(last.1, end),
+                _ =>
+                    merged.push((start, end)),
+            }
+        }
+        self.locks = merged;
+        self.selection_anchor = None;
+        format!("Locked 0x{:x}-0x{:x}", start, end)
+    }
+
+    /// `:unlock`: unlock the locks in the selection or under the cursor.
+    /// `:unlock all` unlocks everything.
+    fn unlock_cmd(&mut self, args: &[&str]) -> String {
+        let (start, end) = match *args {
+            [] =>
+                match self.selection() {
+                    Some(range) =>
+                        range,
+                    None => {
+                        let cursor = self.hex_grid.get_byte_idx();
+                        (cursor, cursor + 1)
+                    }
+                },
+            ["all"] =>
+                (0, usize::MAX),
+            _ =>
+                return "Usage: :unlock [all]".to_owned(),
+        };
+        let before = self.locks.len();
+        self.locks.retain(|&(lock_start, lock_end)| lock_end <= start || lock_start >= end);
+        match before - self.locks.len() {
+            0 =>
+                "Not locked".to_owned(),
+            1 =>
+                "Unlocked 1 range".to_owned(),
+            n =>
+                format!("Unlocked {} ranges", n),
+        }
+    }
+
+    /// Like `delete()`, but asks for confirmation when the whole buffer is
+    /// selected.
+    fn delete_selection(&mut self, start: usize, end: usize) {
+        if start == 0 && end == self.buffer.len() {
+            self.pending_key = PendingKey::ConfirmDeleteAll;
+            self.info_line.set_text(format!("Delete all {} bytes? (y/n)", self.buffer.len()));
+        } else {
+            self.delete(start, end);
+        }
+    }
+
+    /// Remove bytes in `start..end`.
+    fn delete(&mut self, start: usize, end: usize) {
+        if !self.edit_allowed(start, end, 0) {
+            return;
+        }
+        self.buffer.remove(start, end);
+        self.edited(start);
+    }
+
+    /// Update widgets after the buffer is edited.
+    fn edited(&mut self, cursor: usize) {
+        // Match offsets are no longer valid. Let n/N search from the cursor
+        // instead.
+        if !self.highlight.is_empty() {
+            self.highlight.clear();
+            self.highlight_capped = true;
+        }
+        self.selection_anchor = None;
+        // Offsets may have changed
+        self.virtual_ranges.clear();
+        // Results would be for the old contents
+        self.cancel_jobs();
+        self.file_kind = detect(self.buffer.as_slice());
+        self.goto_symbols = None;
+        self.apply_template();
+        self.update_disas_elf();
+        self.update_minimap();
+        self.relayout();
+        self.hex_grid.move_cursor_offset(cursor);
+    }
+
+    /// Show the message in the info line, and ring the bell if enabled for
+    /// the event.
+    fn fail(&mut self, event: BellEvent, msg: &str) {
+        self.info_line.set_text(msg.to_owned());
+        if self.config.bell_events.contains(&event) {
+            let _ = bell::ring(self.config.bell_style);
+        }
+    }
+
+    /// Search the whole buffer, or only the range.
+    fn search(&mut self, pattern: Pattern, range: Option<(usize, usize)>) {
+        let max_matches = self.config.max_matches;
+        self.start_task(Task::Search { pattern, max_matches, range });
+    }
+
+    fn search_done(
+        &mut self,
+        pattern: Pattern,
+        range: Option<(usize, usize)>,
+        matches: Vec<usize>,
+        capped: bool,
+    ) {
+        let in_range = match range {
+            Some((start, end)) =>
+                format!(" in 0x{:x}-0x{:x}", start, end),
+            None =>
+                String::new(),
+        };
+        if matches.is_empty() {
+            self.fail(BellEvent::NotFound, &format!("Pattern not found{}", in_range));
+        } else if capped {
+            self.info_line.set_text(format!(
+                "Showing first {} matches{}, n/N search for the rest",
+                matches.len(),
+                in_range
+            ));
+        } else {
+            self.info_line.set_text(format!("{} matches{}", matches.len(), in_range));
+        }
+
+        let len = pattern.len();
+        self.highlight.set(matches.into_iter().map(|offset| (offset, offset + len)));
+        self.highlight_capped = capped;
+        self.search = Some(pattern);
+        self.search_range = range;
+
+        if self.config.search_jump {
+            if let Some((offset, wrapped)) = self.next_match() {
+                // Keep the number of matches in the info line
+                let msg = self.info_line.text().to_owned();
+                self.jump_to_match(offset, false);
+                self.info_line.set_text(if wrapped {
+                    format!("Search wrapped - {}", msg)
+                } else {
+                    msg
+                });
+            }
+        }
+    }
+
+    /// Next match after the cursor, and whether the search wrapped around
+    /// the end of the buffer.
+    fn next_match(&mut self) -> Option<(usize, bool)> {
+        let byte_idx = self.hex_grid.get_byte_idx();
+        if let Some(next_highlight) = self.highlight.next_start(byte_idx) {
+            return Some((next_highlight, false));
+        }
+        if let Some(ref pattern) = self.search {
+            if self.highlight_capped {
+                let (start, end) = self.search_bounds();
+                let buffer = &self.buffer.as_slice()[..end];
+                let from = cmp::max(byte_idx + 1, start);
+                if let Some(next_match) = pattern.find_next(buffer, from) {
+                    return Some((next_match, false));
+                }
+                // Highlights are the first matches, unless they were cleared
+                return self.highlight
+                    .first_start()
+                    .or_else(|| pattern.find_next(buffer, start))
+                    .map(|offset| (offset, true));
+            }
+        }
+        // We couldn't jump to a match, start from the beginning
+        self.highlight.first_start().map(|offset| (offset, true))
+    }
+
+    /// Previous match before the cursor, and whether the search wrapped
+    /// around the beginning of the buffer.
+    fn prev_match(&mut self) -> Option<(usize, bool)> {
+        let byte_idx = self.hex_grid.get_byte_idx();
+        let (start, end) = self.search_bounds();
+        let buffer = &self.buffer.as_slice()[..end];
+        let len = buffer.len();
+        let first_highlight = self.highlight.first_start();
+        let last_highlight = self.highlight.last_start();
+        let find_prev = |pattern: &Pattern, before: usize| {
+            pattern.find_prev(buffer, before).filter(|&offset| offset >= start)
+        };
+        match self.search {
+            // Past the matches we have, search. Start from the end when
+            // there are no matches before the cursor (highlights cleared).
+            Some(ref pattern) if self.highlight_capped && last_highlight < Some(byte_idx) =>
+                match find_prev(pattern, byte_idx) {
+                    Some(offset) =>
+                        Some((offset, false)),
+                    None =>
+                        find_prev(pattern, len).map(|offset| (offset, true)),
+                },
+            // Before the first match, start from the end
+            Some(ref pattern) if self.highlight_capped && first_highlight >= Some(byte_idx) =>
+                find_prev(pattern, len).map(|offset| (offset, true)),
+            _ =>
+                match self.highlight.prev_start(byte_idx) {
+                    Some(offset) =>
+                        Some((offset, false)),
+                    // We couldn't jump to a match, start from the end
+                    None =>
+                        last_highlight.map(|offset| (offset, true)),
+                },
+        }
+    }
+
+    /// Start and (exclusive) end of the bytes n/N search in.
+    fn search_bounds(&self) -> (usize, usize) {
+        let len = self.buffer.len();
+        match self.search_range {
+            Some((start, end)) => {
+                let end = cmp::min(end, len);
+                (cmp::min(start, end), end)
+            }
+            None =>
+                (0, len),
+        }
+    }
+
+    /// Jump to a match found with n/N, telling when the search wrapped.
+    fn jump_to_match(&mut self, offset: usize, wrapped: bool) {
+        self.jump(offset);
+        if self.config.search_center {
+            self.hex_grid.try_center_scroll();
+        }
+        if wrapped {
+            let text = format!("Search wrapped - {}", self.info_line.text());
+            self.info_line.set_text(text);
+        }
+    }
+
+    /// Remove the highlights of the last search (`:nohl`, Esc). n/N still
+    /// search for the pattern.
+    fn clear_highlights(&mut self) {
+        if !self.highlight.is_empty() {
+            self.highlight.clear();
+            self.highlight_capped = true;
+            self.update_info_line();
+        }
+    }
+
+    /// Search for the pattern and jump to the match at the offset
+    /// (`--find <pattern> --open`).
+    pub fn open_match(&mut self, pattern: Vec<u8>, offset: usize) {
+        self.search(Pattern::new(pattern, false), None);
+        self.jump(offset);
+    }
+
+    /// Text pasted in the terminal. Only the command line takes it.
+    pub fn paste(&mut self, text: &str) {
+        if let Overlay::CommandLine(ref mut o) = self.overlay {
+            o.paste(text);
+        }
+    }
+
+    /// Run the task in the background, or right away when the buffer is
+    /// small. A running task of the same kind is cancelled.
+    fn start_task(&mut self, task: Task) {
+        self.cancel_task(task.name());
+        if task.total(self.buffer.len()) < BACKGROUND_SIZE {
+            let output = task.run(self.buffer.as_slice(), &jobs::Progress::new());
+            // Only cancelled jobs return `None`
+            self.task_done(output.unwrap());
+        } else {
+            let job = Job::start(task, self.buffer.as_slice().to_vec(), self.waker.clone());
+            self.jobs.push(job);
+        }
+    }
+
+    fn cancel_task(&mut self, name: &str) {
+        self.jobs.retain(|job| {
+            if job.name() == name {
+                job.cancel();
+                false
+            } else {
+                true
+            }
+        });
+    }
+
+    fn cancel_jobs(&mut self) {
+        for job in self.jobs.drain(..) {
+            job.cancel();
+        }
+    }
+
+    fn task_done(&mut self, output: Output) {
+        match output {
+            Output::Search { pattern, range, matches, capped } =>
+                self.search_done(pattern, range, matches, capped),
+            Output::Hash { kind, start, end, hash } => {
+                let msg = self.hash_done(kind, start, end, &hash);
+                self.info_line.set_text(msg);
+            }
+            Output::Verify { kind, expected, actual } => {
+                let msg = self.verify_done(kind, &expected, &actual);
+                self.info_line.set_text(msg);
+            }
+            Output::Histogram(counts, total) =>
+                self.mk_histogram_overlay(counts, total),
+            Output::Minimap(blocks) =>
+                if let Some(ref mut minimap) = self.minimap {
+                    minimap.set_blocks(blocks);
+                },
+            Output::Diff(found) =>
+                self.jump_to_diff_done(found),
+        }
+    }
+
+    pub fn set_waker(&mut self, waker: Waker) {
+        self.waker = Some(waker);
+    }
+
+    pub fn has_jobs(&self) -> bool {
+        !self.jobs.is_empty()
+    }
+
+    /// Show the results of finished jobs. Returns whether we need to redraw,
+    /// which is always the case while jobs are running, for the progress bar.
+    pub fn poll_jobs(&mut self) -> bool {
+        if self.jobs.is_empty() {
+            return false;
+        }
+        let mut idx = 0;
+        while idx < self.jobs.len() {
+            match self.jobs[idx].poll() {
+                JobState::Running => {
+                    idx += 1;
+                }
+                JobState::Done(output) => {
+                    self.jobs.remove(idx);
+                    self.task_done(output);
+                }
+                JobState::Cancelled => {
+                    let job = self.jobs.remove(idx);
+                    self.info_line.set_text(format!("{} failed", job.name()));
+                }
+            }
+        }
+        true
+    }
+
+    pub fn is_following(&self) -> bool {
+        self.follow.is_some()
+    }
+
+    /// `F`: toggle follow mode. The buffer catches up with the file when
+    /// enabled.
+    fn toggle_follow(&mut self) {
+        if self.follow.take().is_some() {
+            self.update_info_line();
+            return;
+        }
+        if self.process.is_some() {
+            self.follow = Some(Follow::Process);
+            self.update_info_line();
+            return;
+        }
+        if !self.virtual_ranges.is_empty() {
+            self.info_line.set_text("Can't follow a memory image".to_owned());
+            return;
+        }
+        match FileStamp::read(&self.path) {
+            Ok(mut stamp) => {
+                // Reloaded (or extended) on the next check if the file changed
+                // since it was loaded
+                stamp.len = self.buffer.len() as u64;
+                self.follow = Some(Follow::File(stamp));
+                self.update_info_line();
+            }
+            Err(err) =>
+                self.info_line.set_text(format!("Can't follow {}: {}", self.path, err)),
+        }
+    }
+
+    /// In follow mode, check the file (or re-read the process's memory) and
+    /// reload the buffer if it changed. Returns whether anything changed.
+    pub fn poll_file(&mut self) -> bool {
+        match self.follow {
+            Some(Follow::File(stamp)) =>
+                self.reload_file(stamp),
+            Some(Follow::Process) =>
+                self.reload_process(),
+            None =>
+                false,
+        }
+    }
+
+    /// Bytes appended to the file are appended to the buffer, and when the
+    /// cursor is at the last byte it moves to the new end, like `tail -f`.
+    /// Buffers with unsaved edits are not reloaded.
+    fn reload_file(&mut self, old: FileStamp) -> bool {
+        let new = match FileStamp::read(&self.path) {
+            Ok(stamp) =>
+                stamp,
+            // E.g. the file is being replaced, try again on the next check
+            Err(_) =>
+                return false,
+        };
+        if new == old {
+            return false;
+        }
+        self.follow = Some(Follow::File(new));
+
+        if self.buffer.is_modified() {
+            self.info_line.set_text(
+                "File changed on disk, not reloaded as the buffer has unsaved changes".to_owned(),
+            );
+            return true;
+        }
+
+        let cursor = self.hex_grid.get_byte_idx();
+        let old_len = self.buffer.len();
+        let pinned = cursor + 1 >= old_len;
+
+        if new.len > old.len && new.len > old_len as u64 {
+            match follow::read_from(&self.path, old_len as u64) {
+                Ok(bytes) =>
+                    self.buffer.append_loaded(&bytes),
+                Err(err) => {
+                    self.info_line.set_text(format!("Can't read {}: {}", self.path, err));
+                    return true;
+                }
+            }
+            let len = self.buffer.len();
+            self.update_minimap();
+            self.relayout();
+            if pinned {
+                self.hex_grid.move_cursor_offset(len - 1);
+            }
+        } else {
+            match follow::read_from(&self.path, 0) {
+                Ok(contents) =>
+                    self.buffer = Buffer::new(contents),
+                Err(err) => {
+                    self.info_line.set_text(format!("Can't read {}: {}", self.path, err));
+                    return true;
+                }
+            }
+            // Cursor is moved into the buffer if it got smaller
+            let cursor = if pinned { self.buffer.len() } else { cursor };
+            self.edited(cursor);
+        }
+        true
+    }
+
+    /// The region has the same size, so offsets (of the cursor, highlights
+    /// etc.) stay the same. Edits are kept until they're exported with `:w`.
+    fn reload_process(&mut self) -> bool {
+        let region = match self.process {
+            Some(region) =>
+                region,
+            None =>
+                return false,
+        };
+        if self.buffer.is_modified() {
+            return false;
+        }
+        let (contents, unreadable) = match region.read() {
+            Ok(ret) =>
+                ret,
+            Err(err) => {
+                // Most likely the process exited
+                self.follow = None;
+                self.info_line.set_text(format!(
+                    "Can't read memory of process {}, stopped following: {}",
+                    region.pid,
+                    err
+                ));
+                return true;
+            }
+        };
+        if contents.as_slice() == self.buffer.as_slice() && unreadable == self.virtual_ranges {
+            return false;
+        }
+        self.buffer = Buffer::new(contents);
+        self.virtual_ranges = unreadable;
+        self.cancel_jobs();
+        self.update_minimap();
+        self.update_info_line();
+        true
+    }
+
+    /// Ranges that couldn't be read from the file (e.g. bad sectors of a
+    /// disk), zeros in the buffer.
+    pub fn set_unreadable(&mut self, ranges: Vec<(usize, usize)>) {
+        if ranges.is_empty() {
+            return;
+        }
+        let bytes: usize = ranges.iter().map(|&(start, end)| end - start).sum();
+        self.virtual_ranges = ranges;
+        self.info_line.set_text(format!(
+            "{} bytes couldn't be read, they're zeros (shown in gray)",
+            bytes
+        ));
+    }
+
+    /// The buffer is a part of the file (`--skip`, `--length`). Saving it
+    /// would replace the file with the part, so it's read-only.
+    pub fn set_partial(&mut self) {
+        self.set_unsaveable();
+        self.partial = true;
+        self.follow = None;
+        self.update_info_line();
+    }
+
+    /// Edits can't be saved to the buffer's file, make the buffer read-only
+    /// until `:saveas`. Read-only set by the user stays after `:saveas`.
+    fn set_unsaveable(&mut self) {
+        if !self.config.readonly {
+            self.config.readonly = true;
+            self.unsaveable = true;
+        }
+    }
+
+    /// Show the memory of a process (`--pid`). `unreadable` ranges (e.g. guard
+    /// pages) are zeros in the buffer.
+    pub fn set_process(&mut self, region: Region, unreadable: Vec<(usize, usize)>) {
+        self.set_unsaveable();
+        self.virtual_ranges = unreadable;
+        self.process = Some(region);
+        if self.config.follow {
+            self.follow = Some(Follow::Process);
+        }
+        self.update_info_line();
+    }
+
+    /// Compare with this file (`--compare`).
+    pub fn set_reference(&mut self, reference: Vec<u8>) {
+        self.reference = Some(Arc::new(reference));
+        self.update_info_line();
+    }
+
+    /// Jump to the next (`]d`) or previous (`[d`) run of bytes that differ
+    /// from the reference file.
+    fn jump_to_diff(&mut self, next: bool) {
+        let reference = match self.reference {
+            None => {
+                self.info_line.set_text("No reference file (--compare <file>)".to_owned());
+                return;
+            }
+            Some(ref reference) =>
+                reference.clone(),
+        };
+        let cursor = self.hex_grid.get_byte_idx();
+        self.start_task(Task::Diff { reference, cursor, next });
+    }
+
+    fn jump_to_diff_done(&mut self, found: Option<usize>) {
+        match found {
+            Some(offset) =>
+                self.jump(offset),
+            None =>
+                self.fail(BellEvent::NotFound, "No more differences"),
+        }
+    }
+
+    /// Select the whole buffer (`%`), for commands that work on the
+    /// selection (`:w`, `:s`, `x`).
+    fn select_all(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        self.selection_anchor = Some(0);
+        let last = self.buffer.len() - 1;
+        self.jump(last);
+    }
+
+    /// Start and (exclusive) end of the selection.
+    fn selection(&self) -> Option<(usize, usize)> {
+        if self.buffer.is_empty() {
+            // Nothing to select, the cursor is at the placeholder
+            return None;
+        }
+        self.selection_anchor.map(|anchor| {
+            let cursor = self.hex_grid.get_byte_idx();
+            (cmp::min(anchor, cursor), cmp::max(anchor, cursor) + 1)
+        })
+    }
+
+    /// Run commands entered in the command line (`:`), separated with ';'.
+    /// The result of the last one is shown in the info line.
+    fn run_command(&mut self, cmds: &str) -> HexGuiRet {
+        self.run_commands(cmds, 0)
+    }
+
+    /// Aliases are expanded, arguments after the name of an alias are added
+    /// to its last command.
+    fn run_commands(&mut self, cmds: &str, depth: usize) -> HexGuiRet {
+        for cmd in split_commands(cmds) {
+            let cmd = cmd.trim();
+            let name = cmd.split_whitespace().next().unwrap_or("");
+            let expansion = if is_builtin(name) {
+                None
+            } else {
+                self.config
+                    .aliases
+                    .iter()
+                    .find(|(alias, _)| alias == name)
+                    .map(|(_, commands)| commands.clone())
+            };
+            let ret = match expansion {
+                Some(_) if depth == MAX_ALIAS_DEPTH => {
+                    self.info_line.set_text(format!("Aliases nested too deep in {}", name));
+                    return HexGuiRet::Continue;
+                }
+                Some(commands) => {
+                    let args = cmd[name.len()..].trim();
+                    if args.is_empty() {
+                        self.run_commands(&commands, depth + 1)
+                    } else {
+                        self.run_commands(&format!("{} {}", commands, args), depth + 1)
+                    }
+                }
+                None =>
+                    self.run_single_command(cmd),
+            };
+            match ret {
+                HexGuiRet::Continue =>
+                    {}
+                // Quit, or opened a new buffer
+                ret =>
+                    return ret,
+            }
+        }
+        HexGuiRet::Continue
+    }
+
+    fn run_single_command(&mut self, cmd: &str) -> HexGuiRet {
+        // Patterns can have spaces, so these are not split into words
+        if cmd.starts_with("s/") || cmd.starts_with("%s/") {
+            let msg = self.replace_cmd(cmd);
+            self.info_line.set_text(msg);
+            return HexGuiRet::Continue;
+        }
+
+        if cmd == "cyclic" || cmd.starts_with("cyclic ") {
+            let msg = self.cyclic_cmd(cmd["cyclic".len()..].trim());
+            self.info_line.set_text(msg);
+            return HexGuiRet::Continue;
+        }
+
+        if cmd == "search" || cmd.starts_with("search ") {
+            match self.search_pattern(cmd["search".len()..].trim()) {
+                Ok(pattern) =>
+                    self.search(pattern, None),
+                Err(msg) =>
+                    self.info_line.set_text(msg),
+            }
+            return HexGuiRet::Continue;
+        }
+
+        let words: Vec<&str> = cmd.split_whitespace().collect();
+        let msg = match words.split_first() {
+            None =>
+                return HexGuiRet::Continue,
+            Some((&"w", args)) | Some((&"write", args)) =>
+                self.write_cmd(args, false),
+            Some((&"w!", args)) | Some((&"write!", args)) =>
+                self.write_cmd(args, true),
+            Some((&"dump", args)) =>
+                self.dump_cmd(args, false),
+            Some((&"dump!", args)) =>
+                self.dump_cmd(args, true),
+            Some((&"read-dump", args)) =>
+                self.read_dump_cmd(args),
+            Some((&"export-patch", args)) =>
+                self.export_patch_cmd(args, false),
+            Some((&"export-patch!", args)) =>
+                self.export_patch_cmd(args, true),
+            Some((&"saveas", args)) =>
+                self.saveas_cmd(args, false),
+            Some((&"saveas!", args)) =>
+                self.saveas_cmd(args, true),
+            Some((&"q", _)) | Some((&"quit", _)) =>
+                if self.buffer.is_modified() {
+                    self.unsaved_msg().to_owned()
+                } else {
+                    return HexGuiRet::Quit;
+                },
+            Some((&"q!", _)) | Some((&"quit!", _)) =>
+                return HexGuiRet::ForceQuit,
+            Some((&"verify", _)) => {
+                self.verify();
+                return HexGuiRet::Continue;
+            }
+            Some((&"template", args)) =>
+                self.template_cmd(args),
+            Some((&"report", args)) =>
+                self.report_cmd(args),
+            Some((&"disas", args)) =>
+                self.disas_cmd(args),
+            Some((&"goto", args)) =>
+                match self.goto_offset(args) {
+                    Ok(offset) => {
+                        self.jump(offset);
+                        return HexGuiRet::Continue;
+                    }
+                    Err(msg) =>
+                        msg,
+                },
+            Some((&"set", args)) =>
+                self.set_cmd(args),
+            Some((&"theme", args)) =>
+                self.theme_cmd(args),
+            Some((&"hash", args)) => {
+                self.hash_cmd(args);
+                return HexGuiRet::Continue;
+            }
+            Some((&"elf", _)) =>
+                self.mk_elf_header_overlay(),
+            Some((&"got", _)) =>
+                self.mk_got_overlay(),
+            Some((&"symbols", _)) => {
+                self.mk_symbols_overlay();
+                return HexGuiRet::Continue;
+            }
+            Some((&"va2off", args)) =>
+                self.va2off_cmd(args),
+            Some((&"off2va", args)) =>
+                self.off2va_cmd(args),
+            Some((&"debug", args)) =>
+                return self.debug_cmd(args),
+            Some((&"reverse", _)) =>
+                self.reverse_selection(),
+            Some((&"scan", _)) =>
+                self.scan_cmd(),
+            Some((&"records", _)) =>
+                self.records_cmd(),
+            Some((&"lock", _)) =>
+                self.lock_cmd(),
+            Some((&"unlock", args)) =>
+                self.unlock_cmd(args),
+            Some((&"gen", args)) =>
+                self.gen_cmd(args),
+            Some((&"alias", _)) =>
+                self.alias_cmd(cmd["alias".len()..].trim()),
+            Some((&"nohl", _)) | Some((&"nohlsearch", _)) => {
+                self.clear_highlights();
+                return HexGuiRet::Continue;
+            }
+            Some((&"wq", _)) =>
+                match self.save() {
+                    Ok(()) =>
+                        return HexGuiRet::Quit,
+                    Err(err) =>
+                        format!("Can't write {}: {}", self.path, err),
+                },
+            Some((cmd, _)) =>
+                format!("Unknown command: {}", cmd),
+        };
+        self.info_line.set_text(msg);
+        HexGuiRet::Continue
+    }
+
+    fn add_to_history(&mut self, cmd: &str) {
+        let cmd = cmd.trim();
+        if cmd.is_empty() || self.command_history.last().map(|last| last.as_str()) == Some(cmd) {
+            return;
+        }
+        if self.command_history.len() == MAX_COMMAND_HISTORY {
+            self.command_history.remove(0);
+        }
+        self.command_history.push(cmd.to_owned());
+    }
+
+    /// `:search <pattern>`: hex bytes or text in double quotes, as in `:s`.
+    fn search_pattern(&self, arg: &str) -> Result<Pattern, String> {
+        match parse_bytes(arg) {
+            Some(ref bytes) if bytes.is_empty() =>
+                Err("Usage: :search <hex bytes or \"text\">".to_owned()),
+            Some(bytes) =>
+                Ok(Pattern::new(bytes, false)),
+            None =>
+                Err(format!("Invalid pattern: {}", arg)),
+        }
+    }
+
+    /// `:goto <offset>`, or an address with `--base` when addresses are
+    /// shown, or `sector:N`, as in the goto overlay (`g`).
+    fn goto_offset(&mut self, args: &[&str]) -> Result<usize, String> {
+        let arg = match *args {
+            [arg] =>
+                arg,
+            _ =>
+                return Err("Usage: :goto <offset|sector:N|symbol>".to_owned()),
+        };
+        let target = parse_target(arg, self.shown_base(), self.config.sector_size)
+            .or_else(|| find_symbol(&self.goto_symbols(), arg));
+        match target {
+            Some(offset) if offset < self.buffer.len() || offset == 0 =>
+                Ok(offset),
+            Some(_) =>
+                Err("Offset is out of the file".to_owned()),
+            None =>
+                Err(format!("Invalid offset: {}", arg)),
+        }
+    }
+
+    /// Symbols for goto, see the `goto_symbols` field. Empty when the file is
+    /// not ELF or has no symbols in the file.
+    fn goto_symbols(&mut self) -> Rc<Vec<(String, usize)>> {
+        if let Some(ref symbols) = self.goto_symbols {
+            return symbols.clone();
+        }
+        let mut symbols = Vec::new();
+        if self.file_kind == Some(FileKind::Elf) {
+            if let Ok(elf) = Elf::parse(self.buffer.as_slice()) {
+                for sym in elf.symbols(self.buffer.as_slice()) {
+                    if sym.name.is_empty() {
+                        continue;
+                    }
+                    match elf.symbol_offset(&sym) {
+                        Some(offset) if offset < self.buffer.len() =>
+                            symbols.push((sym.name, offset)),
+                        _ =>
+                            {}
+                    }
+                }
+            }
+        }
+        // Sorted for lookups. Names in both .symtab and .dynsym (or local
+        // symbols with the same name) go to the first one.
+        symbols.sort_by(|a, b| a.0.cmp(&b.0));
+        symbols.dedup_by(|a, b| a.0 == b.0);
+        let symbols = Rc::new(symbols);
+        self.goto_symbols = Some(symbols.clone());
+        symbols
+    }
+
+    /// `:set [<option>=<value> ...]`: change the settings, or show them.
+    fn set_cmd(&mut self, args: &[&str]) -> String {
+        for arg in args {
+            let (name, value) = match arg.find('=') {
+                Some(idx) =>
+                    (&arg[..idx], &arg[idx + 1..]),
+                None =>
+                    return "Usage: :set [cols|group|offsets|max-matches|sector|squeeze|\
+                            search-jump|search-center|nonprint|encoding|footer|readonly=<value> \
+                            ...]"
+                        .to_owned(),
+            };
+            match name {
+                "cols" =>
+                    self.layout_strategy = match value {
+                        "auto" =>
+                            LayoutStrategy::Fill,
+                        "pow2" =>
+                            LayoutStrategy::Pow2,
+                        _ =>
+                            match value.parse() {
+                                Ok(cols) if cols > 0 =>
+                                    LayoutStrategy::Fixed(cols),
+                                _ =>
+                                    return format!(
+                                        "Invalid cols: {} (a number, auto, or pow2)",
+                                        value
+                                    ),
+                            },
+                    },
+                "group" =>
+                    self.config.group_size = match value.parse() {
+                        Ok(size @ 1) | Ok(size @ 2) | Ok(size @ 4) | Ok(size @ 8) =>
+                            size,
+                        _ =>
+                            return format!("Invalid group: {} (1, 2, 4, or 8)", value),
+                    },
+                "offsets" =>
+                    self.config.offset_format = match OffsetFormat::parse(value) {
+                        Some(format) =>
+                            format,
+                        None =>
+                            return format!("Invalid offsets: {} (hex, dec, or oct)", value),
+                    },
+                "max-matches" =>
+                    self.config.max_matches = match value.parse() {
+                        Ok(max) if max > 0 =>
+                            max,
+                        _ =>
+                            return format!("Invalid max-matches: {}", value),
+                    },
+                "sector" =>
+                    match (value, parse_number(value)) {
+                        ("off", _) =>
+                            self.config.sectors = false,
+                        (_, Some(size)) if size > 0 => {
+                            self.config.sectors = true;
+                            self.config.sector_size = size;
+                        }
+                        _ =>
+                            return format!("Invalid sector: {} (a size, or off)", value),
+                    },
+                "footer" =>
+                    self.config.footer = match (value, ColumnSum::parse(value)) {
+                        ("off", _) =>
+                            None,
+                        (_, Some(kind)) =>
+                            Some(kind),
+                        _ =>
+                            return format!("Invalid footer: {} (xor, sum, or off)", value),
+                    },
+                "encoding" =>
+                    self.config.encoding = match (value, Encoding::parse(value)) {
+                        ("auto", _) =>
+                            encoding::guess(self.buffer.as_slice())
+                                .map_or(Encoding::Ascii, |guess| guess.encoding),
+                        (_, Some(encoding)) =>
+                            encoding,
+                        _ =>
+                            return format!(
+                                "Invalid encoding: {} (auto, ascii, utf8, utf16le, utf16be, \
+                                 latin1, ebcdic, or sjis)",
+                                value
+                            ),
+                    },
+                "nonprint" =>
+                    self.config.non_printable = match NonPrintable::parse(value) {
+                        Some(non_printable) =>
+                            non_printable,
+                        None =>
+                            return format!(
+                                "Invalid nonprint: {} (dot, pictures, or cp437)",
+                                value
+                            ),
+                    },
+                "squeeze" | "search-jump" | "search-center" | "bits" | "elf-regions"
+                | "readonly" => {
+                    let on = match value {
+                        "on" =>
+                            true,
+                        "off" =>
+                            false,
+                        _ =>
+                            return format!("Invalid {}: {} (on or off)", name, value),
+                    };
+                    match name {
+                        "squeeze" =>
+                            self.config.squeeze = on,
+                        "search-jump" =>
+                            self.config.search_jump = on,
+                        "bits" =>
+                            self.config.bits = on,
+                        "elf-regions" =>
+                            self.config.elf_regions = on,
+                        "readonly" => {
+                            self.config.readonly = on;
+                            self.unsaveable = false;
+                        }
+                        _ =>
+                            self.config.search_center = on,
+                    }
+                }
+                _ =>
+                    return format!("Unknown option: {}", name),
+            }
+        }
+        if !args.is_empty() {
+            self.relayout();
+        }
+
+        let options: Vec<String> = set_options(&self.config, self.layout_strategy)
+            .into_iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        options.join(" ")
+    }
+
+    /// `:theme <name>`: switch the palette of all buffers.
+    fn theme_cmd(&mut self, args: &[&str]) -> String {
+        let name = match *args {
+            [name] =>
+                name,
+            _ =>
+                return format!("Usage: :theme <{}>", colors::THEMES.join("|")),
+        };
+        if !colors::set_theme(name) {
+            return format!("Unknown theme: {} ({})", name, colors::THEMES.join(", "));
+        }
+        check_palette(self.config.contrast).unwrap_or_else(|| format!("Theme {}", name))
+    }
+
+    /// `:hash [md5|sha256]`: hash of the selection, or of the whole buffer.
+    fn hash_cmd(&mut self, args: &[&str]) {
+        let kind = match *args {
+            [] =>
+                HashKind::Sha256,
+            [name] =>
+                match HashKind::parse(name) {
+                    Some(kind) =>
+                        kind,
+                    None => {
+                        self.info_line.set_text(format!("Unknown hash: {} (md5 or sha256)", name));
+                        return;
+                    }
+                },
+            _ => {
+                self.info_line.set_text("Usage: :hash [md5|sha256]".to_owned());
+                return;
+            }
+        };
+        let (start, end) = self.selection().unwrap_or((0, self.buffer.len()));
+        self.start_task(Task::Hash { kind, start, end });
+    }
+
+    fn hash_done(&self, kind: HashKind, start: usize, end: usize, hash: &[u8]) -> String {
+        let hash = to_hex(hash);
+        if start != 0 || end != self.buffer.len() {
+            format!("{} of 0x{:x}-0x{:x}: {}", kind.name(), start, end, hash)
+        } else {
+            format!("{}: {}", kind.name(), hash)
+        }
+    }
+
+    /// `:reverse`: reverse the order of the selected bytes, e.g. to swap the
+    /// endianness of a value. Reversing again restores the bytes.
+    fn reverse_selection(&mut self) -> String {
+        let (start, end) = match self.selection() {
+            Some(range) =>
+                range,
+            None =>
+                return "Select the bytes to reverse first (v)".to_owned(),
+        };
+        if let Some(err) = self.edit_error(start, end, end - start) {
+            return err;
+        }
+        let mut bytes = self.buffer.as_slice()[start..end].to_vec();
+        bytes.reverse();
+        self.buffer.replace(start, end, &bytes);
+        self.edited(start);
+        format!("Reversed {} bytes", end - start)
+    }
+
+    /// `:scan`: list the files embedded in the buffer, found by their
+    /// signatures.
+    fn scan_cmd(&mut self) -> String {
+        let (found, capped) = detect::scan(self.buffer.as_slice(), MAX_SCAN_HITS);
+        if found.is_empty() {
+            return "No signatures found".to_owned();
+        }
+        self.overlay = Overlay::StructureOverlay(StructureOverlay::new(
+            self.width * 3 / 4,
+            self.height * 3 / 4,
+            self.width / 8,
+            self.height / 8,
+            " Embedded files ",
+            scan::rows(&found, capped),
+        ));
+        String::new()
+    }
+
+    /// `:records`: guess the size of fixed-size records in the selection, or
+    /// after the cursor, and offer to show a record per line.
+    fn records_cmd(&mut self) -> String {
+        let (start, end) = self.selection()
+            .unwrap_or_else(|| (self.hex_grid.get_byte_idx(), self.buffer.len()));
+        let end = cmp::min(end, start + SAMPLE_SIZE);
+        if end - start < 2 * MIN_RECORD_SIZE {
+            return "Not enough bytes to find records".to_owned();
+        }
+        let candidates = records::candidates(&self.buffer.as_slice()[start..end]);
+        if candidates.is_empty() {
+            return "No repeating records found".to_owned();
+        }
+        self.overlay = Overlay::RecordsOverlay(RecordsOverlay::new(
+            self.width / 2,
+            self.height / 2,
+            self.width / 4,
+            self.height / 4,
+            candidates,
+            end - start,
+        ));
+        String::new()
+    }
+
+    /// Show `size` bytes per line. Groups are of single bytes when the size is
+    /// not a multiple of the group size, as lines are of whole groups.
+    fn show_records(&mut self, size: usize) -> String {
+        self.layout_strategy = LayoutStrategy::Fixed(size as i32);
+        if !size.is_multiple_of(self.config.group_size as usize) {
+            self.config.group_size = 1;
+        }
+        self.relayout();
+        format!("Records of {} bytes, cols={} group={}", size, size, self.config.group_size)
+    }
+
+    /// `:gen <pattern> [<len>] [<param>=<value> ...]`: fill the selection with
+    /// a generated pattern, or insert `len` bytes of it at the cursor. The
+    /// message shows the parameters (e.g. the seed of random bytes) so that
+    /// the same bytes can be generated again.
+    fn gen_cmd(&mut self, args: &[&str]) -> String {
+        let usage = "Usage: :gen cyclic|counter|random [<len>] [<param>=<value> ...]";
+        let (kind, args) = match args.split_first() {
+            Some((kind, args)) =>
+                (kind, args),
+            None =>
+                return usage.to_owned(),
+        };
+        let (len, params) = match args.split_first() {
+            Some((len, params)) if !len.contains('=') =>
+                match parse_number(len) {
+                    Some(len) if len > 0 =>
+                        (Some(len), params),
+                    _ =>
+                        return format!("Invalid length: {}", len),
+                },
+            _ =>
+                (None, args),
+        };
+        let gen = match Generator::parse(kind, params) {
+            Ok(gen) =>
+                gen,
+            Err(err) =>
+                return err,
+        };
+
+        match (self.selection(), len) {
+            (Some((start, end)), None) => {
+                if let Some(err) = self.edit_error(start, end, end - start) {
+                    return err;
+                }
+                let bytes = gen.generate(end - start);
+                self.buffer.replace(start, end, &bytes);
+                self.edited(start);
+                format!("Filled {} bytes: {}", end - start, gen)
+            }
+            (Some(_), Some(_)) =>
+                "The selection is filled, give a length only without a selection".to_owned(),
+            (None, Some(len)) => {
+                let cursor = self.hex_grid.get_byte_idx();
+                if let Some(err) = self.edit_error(cursor, cursor, len) {
+                    return err;
+                }
+                let bytes = gen.generate(len);
+                self.buffer.replace(cursor, cursor, &bytes);
+                self.edited(cursor);
+                format!("Inserted {} bytes: {}", len, gen)
+            }
+            (None, None) =>
+                "Give a length, or select the bytes to fill (v)".to_owned(),
+        }
+    }
+
+    /// `:alias [<name> [<commands>]]`: define an alias that runs the commands
+    /// (separated with ';'), show an alias, or list them.
+    fn alias_cmd(&mut self, args: &str) -> String {
+        if args.is_empty() {
+            if self.config.aliases.is_empty() {
+                return "No aliases (:alias <name> <commands>)".to_owned();
+            }
+            let aliases: Vec<String> = self.config
+                .aliases
+                .iter()
+                .map(|(name, commands)| format!("{}: {}", name, commands))
+                .collect();
+            return aliases.join(" | ");
+        }
+        if !args.contains(char::is_whitespace) {
+            return match self.config.aliases.iter().find(|(name, _)| name == args) {
+                Some((name, commands)) =>
+                    format!("{}: {}", name, commands),
+                None =>
+                    format!("No alias {}", args),
+            };
+        }
+
+        let (name, commands) = match parse_alias(args) {
+            Some(alias) =>
+                alias,
+            None =>
+                return "Usage: :alias <name> <command>[; <command> ...]".to_owned(),
+        };
+        if is_builtin(&name) {
+            return format!("{} is a command, pick another name", name);
+        }
+        let msg = format!("Alias {}: {}", name, commands);
+        self.config.aliases.retain(|(alias, _)| *alias != name);
+        self.config.aliases.push((name, commands));
+        msg
+    }
+
+    /// `:cyclic <value> [n=<n>]`: offset of the value in the cyclic pattern
+    /// (`:gen cyclic`), e.g. a register value after a crash. The value is a
+    /// 0x-prefixed number (little endian, as in a register), hex bytes, or
+    /// "text". The offset is copied to the clipboard.
+    fn cyclic_cmd(&mut self, args: &str) -> String {
+        let usage = "Usage: :cyclic <0x value|hex bytes|\"text\"> [n=<n>]";
+        let (value, n) = match args.rfind(" n=") {
+            Some(idx) =>
+                match args[idx + 3..].parse() {
+                    Ok(n) if (1..=8).contains(&n) =>
+                        (args[..idx].trim(), n),
+                    _ =>
+                        return usage.to_owned(),
+                },
+            None =>
+                (args, CYCLIC_N),
+        };
+        let bytes = if value.starts_with("0x") || value.starts_with("0X") {
+            match u64::from_str_radix(&value[2..], 16) {
+                Ok(value) =>
+                    (0..8).map(|i| (value >> (i * 8)) as u8).collect(),
+                Err(_) =>
+                    return format!("Invalid value: {}", value),
+            }
+        } else {
+            match parse_bytes(value) {
+                Some(ref bytes) if bytes.is_empty() =>
+                    return usage.to_owned(),
+                Some(bytes) =>
+                    bytes,
+                None =>
+                    return format!("Invalid value: {}", value),
+            }
+        };
+        if bytes.len() < n {
+            return format!("Need at least {} bytes for n={}", n, n);
+        }
+
+        match cyclic_find(&bytes, n) {
+            None =>
+                format!("Not in the cyclic pattern (n={})", n),
+            Some(offset) => {
+                let copied = match clipboard::copy(&offset.to_string()) {
+                    Ok(()) =>
+                        "copied to clipboard".to_owned(),
+                    Err(err) =>
+                        format!("can't copy to clipboard: {}", err),
+                };
+                format!("Offset {} (0x{:x}) in cyclic n={}, {}", offset, offset, n, copied)
+            }
+        }
+    }
+
+    /// `:template [<file> [<offset>]]`: apply the template at the offset, or
+    /// at the cursor. Without arguments the template is removed.
+    fn template_cmd(&mut self, args: &[&str]) -> String {
+        let (path, offset) = match *args {
+            [] => {
+                self.template = None;
+                self.apply_template();
+                return "Template removed".to_owned();
+            }
+            [path] =>
+                (path, self.hex_grid.get_byte_idx()),
+            [path, offset] =>
+                match parse_number(offset) {
+                    Some(offset) =>
+                        (path, offset),
+                    None =>
+                        return format!("Invalid offset: {}", offset),
+                },
+            _ =>
+                return "Usage: :template [<file> [<offset>]]".to_owned(),
+        };
+
+        let template = match Template::load(path) {
+            Ok(template) =>
+                template,
+            Err(err) =>
+                return err,
+        };
+        self.template = Some((template, offset));
+        let err = self.apply_template();
+        if self.panel.as_ref().map(|panel| panel.kind()) != Some(PanelKind::Template) {
+            self.set_panel(Some(PanelKind::Template));
+        }
+        match err {
+            Some(err) => {
+                let report = err.report(self.buffer.as_slice());
+                let msg = report.message.clone();
+                self.add_report(report);
+                msg
+            }
+            None =>
+                format!("{} fields", self.template_fields.len()),
+        }
+    }
+
+    /// Record a parse error for `:report`. Repeated errors (e.g. from trying
+    /// the same view again) are recorded once.
+    fn add_report(&mut self, report: Report) {
+        let repeated = self.reports.last().is_some_and(|last| {
+            last.message == report.message && last.offset == report.offset
+        });
+        if !repeated {
+            self.reports.push(report);
+        }
+    }
+
+    /// Show a parse error in the info line and record it.
+    fn parse_failed(&mut self, report: Report) {
+        self.info_line.set_text(report.message.clone());
+        self.add_report(report);
+    }
+
+    /// `:report [<file>]`: list the parse errors, or write them to the file as
+    /// a JSON array.
+    fn report_cmd(&mut self, args: &[&str]) -> String {
+        match *args {
+            [] => {
+                if self.reports.is_empty() {
+                    return "No parse errors".to_owned();
+                }
+                self.overlay = Overlay::StructureOverlay(StructureOverlay::new(
+                    self.width * 3 / 4,
+                    self.height * 3 / 4,
+                    self.width / 8,
+                    self.height / 8,
+                    " Parse errors ",
+                    reports::rows(&self.reports),
+                ));
+                String::new()
+            }
+            [path] => {
+                let json = Json::Arr(self.reports.iter().map(|report| report.to_json()).collect());
+                match write_file(path, format!("{}\n", json).as_bytes(), true) {
+                    Ok(()) =>
+                        format!("Wrote {} parse errors to {}", self.reports.len(), path),
+                    Err(err) =>
+                        format!("Can't write {}: {}", path, err),
+                }
+            }
+            _ =>
+                "Usage: :report [<file>]".to_owned(),
+        }
+    }
+
+    /// `:disas [<arch>]`: show the disassembly panel for the architecture, or
+    /// for the current one.
+    fn disas_cmd(&mut self, args: &[&str]) -> String {
+        match *args {
+            [] =>
+                {}
+            [name] =>
+                match Arch::from_name(name) {
+                    Some(arch) =>
+                        self.disas_arch = arch,
+                    None =>
+                        return format!(
+                            "Unknown architecture: {} (x86_64, x86, arm, aarch64)",
+                            name
+                        ),
+                },
+            _ =>
+                return "Usage: :disas [x86_64|x86|arm|aarch64]".to_owned(),
+        }
+        // A new panel also decodes from the cursor again, instructions of the
+        // old architecture may not be aligned for the new one
+        self.set_panel(Some(PanelKind::Disassembly));
+        format!("Disassembling as {}", self.disas_arch.name())
+    }
+
+    /// Update the template fields after the template or the buffer changes.
+    fn apply_template(&mut self) -> Option<TemplateError> {
+        let (fields, err) = match self.template {
+            Some((ref template, offset)) =>
+                template.apply(self.buffer.as_slice(), offset),
+            None =>
+                (Vec::new(), None),
+        };
+        self.template_ranges = fields
+            .iter()
+            .filter(|field| field.value.is_some() && field.size != 0)
+            .map(|field| (field.offset, field.offset + field.size))
+            .collect();
+        self.template_fields = fields;
+        err
+    }
+
+    /// `:verify`: check the buffer against the checksum file found on
+    /// startup.
+    fn verify(&mut self) {
+        let (checksum_path, kind) = match self.checksum_file {
+            Some((ref checksum_path, kind)) =>
+                (checksum_path.clone(), kind),
+            None => {
+                let msg = format!(
+                    "No checksum file ({}.sha256 or {}.md5) found",
+                    self.path,
+                    self.path
+                );
+                self.info_line.set_text(msg);
+                return;
+            }
+        };
+
+        match read_checksum(&checksum_path, &self.path) {
+            Ok(expected) =>
+                self.start_task(Task::Verify { kind, expected }),
+            Err(err) =>
+                self.info_line.set_text(err),
+        }
+    }
+
+    fn verify_done(&self, kind: HashKind, expected: &str, actual: &[u8]) -> String {
+        let actual = to_hex(actual);
+        let note = if self.buffer.is_modified() {
+            " (of the modified buffer)"
+        } else {
+            ""
+        };
+        if actual == expected {
+            format!("{} OK{}", kind.name(), note)
+        } else {
+            format!("{} mismatch{}: expected {}, got {}", kind.name(), note, expected, actual)
+        }
+    }
+
+    /// `:[%]s/<pattern>/<replacement>/[c]`: replace matches of the pattern in
+    /// the selection, or in the whole buffer with `%`. Patterns are hex bytes
+    /// or text in double quotes, see `parse_bytes()`. With `c` each match is
+    /// confirmed with y/n/a/q.
+    fn replace_cmd(&mut self, cmd: &str) -> String {
+        let usage = "Usage: :[%]s/<pattern>/<replacement>/[c]";
+
+        let whole_buffer = cmd.starts_with('%');
+        // `run_command()` checks that there's a '/'
+        let args = &cmd[cmd.find('/').unwrap() + 1..];
+        let parts: Vec<&str> = args.split('/').collect();
+        if parts.len() < 2 || parts.len() > 3 {
+            return usage.to_owned();
+        }
+        let confirm = match parts.get(2) {
+            None | Some(&"") =>
+                false,
+            Some(&"c") =>
+                true,
+            Some(_) =>
+                return usage.to_owned(),
+        };
+        let pattern = match parse_bytes(parts[0]) {
+            Some(ref bytes) if bytes.is_empty() =>
+                return "Empty pattern".to_owned(),
+            Some(bytes) =>
+                Pattern::new(bytes, false),
+            None =>
+                return format!("Invalid pattern: {}", parts[0]),
+        };
+        let replacement = match parse_bytes(parts[1]) {
+            Some(bytes) =>
+                bytes,
+            None =>
+                return format!("Invalid replacement: {}", parts[1]),
+        };
+
+        let (start, end) = if whole_buffer {
+            (0, self.buffer.len())
+        } else {
+            match self.selection() {
+                Some(range) =>
+                    range,
+                None =>
+                    return "No selection (select with v, or use :%s for the whole buffer)"
+                        .to_owned(),
+            }
+        };
+
+        if !confirm {
+            let n = match self.replace_all(&pattern, &replacement, start, end) {
+                Ok(n) =>
+                    n,
+                Err(msg) =>
+                    return msg,
+            };
+            let cursor = self.hex_grid.get_byte_idx();
+            self.edited(cursor);
+            return format!("Replaced {} matches", n);
+        }
+
+        let (matches, _) = pattern.find_all(&self.buffer.as_slice()[start..end], usize::MAX);
+        match matches.first() {
+            None =>
+                "Pattern not found".to_owned(),
+            Some(&first) => {
+                self.replace = Some(ReplaceState {
+                    pattern,
+                    replacement,
+                    current: start + first,
+                    end,
+                    idx: 1,
+                    total: matches.len(),
+                    replaced: 0,
+                });
+                self.show_replace_match()
+            }
+        }
+    }
+
+    /// Replace non-overlapping matches in `start..end`. Returns number of
+    /// replaced matches, or an error when a match is locked (nothing is
+    /// replaced then).
+    fn replace_all(
+        &mut self,
+        pattern: &Pattern,
+        replacement: &[u8],
+        start: usize,
+        end: usize,
+    ) -> Result<usize, String> {
+        let mut new = Vec::with_capacity(end - start);
+        let mut n = 0;
+        // Changed bytes, relative to `start`
+        let mut first_match = None;
+        let mut last_match_end = 0;
+        {
+            let contents = &self.buffer.as_slice()[start..end];
+            let mut offset = 0;
+            while let Some(match_offset) = pattern.find_next(contents, offset) {
+                new.extend_from_slice(&contents[offset..match_offset]);
+                new.extend_from_slice(replacement);
+                offset = match_offset + pattern.len();
+                n += 1;
+                first_match = first_match.or(Some(match_offset));
+                last_match_end = offset;
+            }
+            new.extend_from_slice(&contents[offset..]);
+        }
+        if let Some(first) = first_match {
+            let new_len = last_match_end - first + new.len() - (end - start);
+            if let Some(err) = self.edit_error(start + first, start + last_match_end, new_len) {
+                return Err(err);
+            }
+            self.buffer.replace(start, end, &new);
+        }
+        Ok(n)
+    }
+
+    fn keypressed_replace(&mut self, key: Key) {
+        let mut state = match self.replace.take() {
+            Some(state) =>
+                state,
+            None =>
+                return,
+        };
+        let pattern_len = state.pattern.len();
+
+        let next = match key {
+            Key::Char('y') => {
+                let err = self.edit_error(
+                    state.current,
+                    state.current + pattern_len,
+                    state.replacement.len(),
+                );
+                if let Some(err) = err {
+                    self.info_line.set_text(err);
+                    self.replace = Some(state);
+                    return;
+                }
+                self.buffer.replace(state.current, state.current + pattern_len, &state.replacement);
+                state.end = state.end + state.replacement.len() - pattern_len;
+                state.replaced += 1;
+                state.current + state.replacement.len()
+            }
+            Key::Char('n') =>
+                state.current + pattern_len,
+            Key::Char('a') => {
+                let replaced =
+                    self.replace_all(&state.pattern, &state.replacement, state.current, state.end);
+                match replaced {
+                    Ok(n) => {
+                        state.replaced += n;
+                        state.end
+                    }
+                    Err(msg) => {
+                        self.info_line.set_text(msg);
+                        self.replace = Some(state);
+                        return;
+                    }
+                }
+            }
+            Key::Char('q') | Key::Esc =>
+                state.end,
+            _ => {
+                // Keep waiting
+                self.replace = Some(state);
+                return;
+            }
+        };
+
+        let found = state.pattern.find_next(&self.buffer.as_slice()[..state.end], next);
+
+        self.highlight.clear();
+        self.edited(cmp::min(next, state.end));
+
+        let msg = match found {
+            Some(offset) => {
+                state.current = offset;
+                state.idx += 1;
+                self.replace = Some(state);
+                self.show_replace_match()
+            }
+            None => {
+                // Highlights of the last search were replaced with the
+                // current match, let n/N search for them
+                self.highlight_capped = true;
+                format!("Replaced {} of {} matches", state.replaced, state.total)
+            }
+        };
+        self.info_line.set_text(msg);
+    }
+
+    /// Move the cursor to the current match of `:s/.../.../c` and highlight
+    /// it. Returns the prompt to show.
+    fn show_replace_match(&mut self) -> String {
+        let (current, len, idx, total) = match self.replace {
+            Some(ref state) =>
+                (state.current, state.pattern.len(), state.idx, state.total),
+            None =>
+                return String::new(),
+        };
+        self.highlight.set(vec![(current, current + len)]);
+        self.hex_grid.move_cursor_offset(current);
+        format!("Replace match {} of {}? (y/n/a/q)", idx, total)
+    }
+
+    /// Write the buffer to its file.
+    fn save(&mut self) -> io::Result<()> {
+        if self.config.readonly {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "read-only, :w <file> to export the edits",
+            ));
+        }
+        // Even after `:set readonly=off`, the file is not the buffer
+        if self.partial || self.process.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "not a whole file, :w <file> to export the edits",
+            ));
+        }
+        write_file(&self.path, self.buffer.as_slice(), true)?;
+        self.buffer.set_saved();
+        // Don't reload what we just wrote
+        if self.follow.is_some() {
+            self.follow = FileStamp::read(&self.path).ok().map(Follow::File);
+        }
+        self.update_info_line();
+        Ok(())
+    }
+
+    /// Shown when quitting with unsaved edits.
+    fn unsaved_msg(&self) -> &'static str {
+        if self.config.readonly || self.partial || self.process.is_some() {
+            "No write since last change (:w <file> to export, :q! to quit)"
+        } else {
+            "No write since last change (:w to save, :q! to quit)"
+        }
+    }
+
+    /// `:w[rite][!] [[<offset> <len>] <file>]`: write the range, or the
+    /// selection when a range is not given, to the file. Without '!' the file
+    /// is not overwritten. Without arguments the buffer is saved to its file.
+    fn write_cmd(&mut self, args: &[&str], overwrite: bool) -> String {
+        let (start, len, path) = match args.len() {
+            0 =>
+                return match self.save() {
+                    Ok(()) =>
+                        format!("Wrote {} bytes to {}", self.buffer.len(), self.path),
+                    Err(err) =>
+                        format!("Can't write {}: {}", self.path, err),
+                },
+            1 =>
+                match self.selection() {
+                    Some((start, end)) =>
+                        (start, end - start, args[0]),
+                    None =>
+                        return "No selection (select with v, or give offset and length)".to_owned(),
+                },
+            3 =>
+                match (parse_number(args[0]), parse_number(args[1])) {
+                    (Some(offset), Some(len)) =>
+                        (offset, len, args[2]),
+                    _ =>
+                        return format!("Invalid offset or length: {} {}", args[0], args[1]),
+                },
+            _ =>
+                return "Usage: :w[rite][!] [[<offset> <len>] <file>]".to_owned(),
+        };
+
+        if start.checked_add(len).is_none_or(|end| end > self.buffer.len()) {
+            return "Range is out of the file".to_owned();
+        }
+
+        match write_file(path, &self.buffer.as_slice()[start..start + len], overwrite) {
+            Ok(()) =>
+                format!("Wrote {} bytes to {}", len, path),
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists =>
+                format!("{} exists (add ! to overwrite)", path),
+            Err(err) =>
+                format!("Can't write {}: {}", path, err),
+        }
+    }
+
+    /// `:dump[!] [plain] [<start>:<end>] [<file>]`: the range (the selection,
+    /// or the whole buffer when not given) as text, as printed by `--dump`.
+    /// Written to the file (overwritten only with '!'), or copied to the
+    /// clipboard.
+    fn dump_cmd(&mut self, args: &[&str], overwrite: bool) -> String {
+        let (format, args) = match args.split_first() {
+            Some((&"plain", rest)) =>
+                (Format::Plain, rest),
+            _ =>
+                (Format::Lines, args),
+        };
+        let (range, path) = match *args {
+            [] =>
+                (None, None),
+            [arg] =>
+                match parse_range(arg) {
+                    Some(range) =>
+                        (Some(range), None),
+                    None =>
+                        (None, Some(arg)),
+                },
+            [range, path] =>
+                match parse_range(range) {
+                    Some(range) =>
+                        (Some(range), Some(path)),
+                    None =>
+                        return format!("Invalid range: {}", range),
+                },
+            _ =>
+                return "Usage: :dump[!] [plain] [<start>:<end>] [<file>]".to_owned(),
+        };
+        let (start, end) = range
+            .or_else(|| self.selection())
+            .unwrap_or((0, self.buffer.len()));
+        if end > self.buffer.len() {
+            return "Range is out of the file".to_owned();
+        }
+
+        // Offsets as in the hex view
+        let mut config = self.config.clone();
+        config.base = self.shown_base();
+        let mut text = Vec::new();
+        let bytes = &self.buffer.as_slice()[start..end];
+        if let Err(err) = dump::dump(bytes, &mut text, &config, start, end, format, false) {
+            return format!("Can't dump: {}", err);
+        }
+
+        match path {
+            Some(path) =>
+                match write_file(path, &text, overwrite) {
+                    Ok(()) =>
+                        format!("Wrote dump of {} bytes to {}", end - start, path),
+                    Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists =>
+                        format!("{} exists (add ! to overwrite)", path),
+                    Err(err) =>
+                        format!("Can't write {}: {}", path, err),
+                },
+            None =>
+                match clipboard::copy(&String::from_utf8_lossy(&text)) {
+                    Ok(()) =>
+                        format!("Copied dump of {} bytes to clipboard", end - start),
+                    Err(err) =>
+                        format!("Can't copy to clipboard: {}", err),
+                },
+        }
+    }
+
+    /// `:read-dump <file>`: write the bytes of a hex dump (e.g. one written
+    /// with `:dump` and edited) to the buffer, at the offsets of its lines, as
+    /// one edit. Dumps without offsets (`plain`) are written at the cursor.
+    fn read_dump_cmd(&mut self, args: &[&str]) -> String {
+        let path = match *args {
+            [path] =>
+                path,
+            _ =>
+                return "Usage: :read-dump <file>".to_owned(),
+        };
+        let text = match fs::read_to_string(path) {
+            Ok(text) =>
+                text,
+            Err(err) =>
+                return format!("Can't read {}: {}", path, err),
+        };
+        let dump = match revert::parse(&text) {
+            Ok(dump) =>
+                dump,
+            Err(err) =>
+                return format!("{}: {}", path, err),
+        };
+
+        // Offsets as in the hex view, see `dump_cmd`
+        let (start, end) = dump.span();
+        let at = if dump.has_offsets {
+            match start.checked_sub(self.shown_base().unwrap_or(0)) {
+                Some(at) =>
+                    at,
+                None =>
+                    return format!("Dump starts before the buffer, at 0x{:x}", start),
+            }
+        } else {
+            self.hex_grid.get_byte_idx()
+        };
+        let len = self.buffer.len();
+        if at > len {
+            return format!("Dump starts after the end of the buffer, at 0x{:x}", start);
+        }
+        let replaced = cmp::min(at + end - start, len);
+        if let Some(err) = self.edit_error(at, replaced, end - start) {
+            return err;
+        }
+        let bytes = dump.bytes(start, end, &self.buffer.as_slice()[at..replaced]);
+        self.buffer.replace(at, replaced, &bytes);
+        self.edited(at);
+        format!("Read {} bytes from {} to 0x{:x}-0x{:x}", bytes.len(), path, at, at + bytes.len())
+    }
+
+    /// `:export-patch[!] <file>`: write the changes from the file on disk to
+    /// the buffer as a patch, IPS for `.ips` files, text otherwise. See
+    /// `patch`.
+    fn export_patch_cmd(&mut self, args: &[&str], overwrite: bool) -> String {
+        let path = match *args {
+            [path] =>
+                path,
+            _ =>
+                return "Usage: :export-patch[!] <file>".to_owned(),
+        };
+        if self.process.is_some() {
+            return "Process memory has no file to compare with".to_owned();
+        }
+        if self.partial {
+            return "Can't export a patch of a part of a file".to_owned();
+        }
+        let original = match fs::read(&self.path) {
+            Ok(original) =>
+                original,
+            Err(err) =>
+                return format!("Can't read {}: {}", self.path, err),
+        };
+        let new = self.buffer.as_slice();
+        let patch = patch::diff(&original, new);
+        if patch.changes.is_empty() {
+            return format!("No changes from {}", self.path);
+        }
+        let bytes = match patch.write(PatchFormat::from_path(path), new) {
+            Ok(bytes) =>
+                bytes,
+            Err(err) =>
+                return err,
+        };
+        match write_file(path, &bytes, overwrite) {
+            Ok(()) =>
+                format!(
+                    "Wrote patch of {} changes ({} bytes) to {}",
+                    patch.changes.len(),
+                    patch.size(),
+                    path
+                ),
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists =>
+                format!("{} exists (add ! to overwrite)", path),
+            Err(err) =>
+                format!("Can't write {}: {}", path, err),
+        }
+    }
+
+    /// Apply the patch (`--apply-patch`) as one edit. The file is not written.
+    pub fn apply_patch(&mut self, patch: &Patch) {
+        let msg = match patch.apply(self.buffer.as_slice()) {
+            Ok((start, end, bytes)) =>
+                if let Some(err) = self.edit_error(start, end, bytes.len()) {
+                    err
+                } else {
+                    self.buffer.replace(start, end, &bytes);
+                    self.edited(start);
+                    format!(
+                        "Applied patch of {} changes to 0x{:x}-0x{:x} (:w to save)",
+                        patch.changes.len(),
+                        start,
+                        start + bytes.len()
+                    )
+                },
+            Err(err) =>
+                format!("Can't apply patch: {}", err),
+        };
+        self.info_line.set_text(msg);
+    }
+
+    /// `:saveas[!] [<file>]`: write the buffer to the file and edit that file
+    /// from now on. The original file is not changed. Without a file name
+    /// asks for one.
+    fn saveas_cmd(&mut self, args: &[&str], overwrite: bool) -> String {
+        match *args {
+            [] => {
+                self.overlay = Overlay::PathOverlay(PathOverlay::new(
+                    self.width / 2,
+                    self.height / 2,
+                    self.width / 4,
+                    self.height / 4,
+                    "Save as (Tab completes):",
+                    self.path.clone(),
+                ));
+                String::new()
+            }
+            [path] =>
+                self.save_as(path, overwrite),
+            _ =>
+                "Usage: :saveas[!] [<file>]".to_owned(),
+        }
+    }
+
+    fn save_as(&mut self, path: &str, overwrite: bool) -> String {
+        match write_file(path, self.buffer.as_slice(), overwrite) {
+            Ok(()) =>
+                {}
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists =>
+                return format!("{} exists (add ! to overwrite)", path),
+            Err(err) =>
+                return format!("Can't write {}: {}", path, err),
+        }
+        self.path = path.to_owned();
+        self.buffer.set_saved();
+        // A complete copy in a file we could create, even if the buffer was
+        // a part of a file or process memory. `-R` stays.
+        if self.unsaveable {
+            self.config.readonly = false;
+            self.unsaveable = false;
+        }
+        self.process = None;
+        self.partial = false;
+        self.checksum_file = None;
+        self.marks.save(&self.path);
+        if self.follow.is_some() {
+            self.follow = FileStamp::read(&self.path).ok().map(Follow::File);
+        }
+        self.update_info_line();
+        format!("Wrote {} bytes to {}, editing it now", self.buffer.len(), path)
+    }
+
+    fn copy_to_clipboard(&mut self, text: String) {
+        let msg = match clipboard::copy(&text) {
+            Ok(()) =>
+                format!("Copied {} to clipboard", text),
+            Err(err) =>
+                format!("Can't copy to clipboard: {}", err),
+        };
+        self.info_line.set_text(msg);
+    }
+
+    /// Move the cursor, recording current position in the jump list.
+    fn jump(&mut self, offset: usize) {
+        let current = self.hex_grid.get_byte_idx();
+        self.jump_list.push(current);
+        self.hex_grid.move_cursor_offset(offset);
+    }
+
+    /// Base address, if we're showing addresses rather than file offsets.
+    fn shown_base(&self) -> Option<usize> {
+        if self.show_base {
+            self.config.base
+        } else {
+            None
+        }
+    }
+
+    fn mk_goto_overlay(&mut self) {
+        let symbols = self.goto_symbols();
+        self.overlay = Overlay::GotoOverlay(GotoOverlay::new(
+            self.width / 2,
+            self.height / 2,
+            self.width / 4,
+            self.height / 4,
+            self.shown_base(),
+            self.config.sector_size,
+            symbols,
+        ));
+    }
+
+    fn mk_marks_overlay(&mut self) {
+        self.overlay = Overlay::MarksOverlay(MarksOverlay::new(
+            self.width / 2,
+            self.height / 2,
+            self.width / 4,
+            self.height / 4,
+            self.marks.to_vec(),
+        ));
+    }
+
+    fn mk_histogram_overlay(&mut self, counts: Box<[usize; 256]>, total: usize) {
+        self.overlay = Overlay::HistogramOverlay(HistogramOverlay::new(
+            self.width / 2,
+            self.height / 2,
+            self.width / 4,
+            self.height / 4,
+            counts,
+            total,
+        ));
+    }
+
+    fn mk_int_table_overlay(&mut self) {
+        self.overlay = Overlay::IntTableOverlay(IntTableOverlay::new(
+            self.width / 2,
+            self.height / 2,
+            self.width / 4,
+            self.height / 4,
+            self.buffer.len(),
+            self.hex_grid.get_byte_idx(),
+            &self.config,
+        ));
+    }
+
+    fn mk_value_overlay(&mut self) {
+        if self.buffer.is_empty() {
+            self.fail(BellEvent::Edge, "Empty file");
+            return;
+        }
+        let (unit, big_endian) = self.value_unit
+            .unwrap_or((Unit::for_group(self.config.group_size), self.config.big_endian));
+        self.overlay = Overlay::ValueOverlay(ValueOverlay::new(
+            self.width / 2,
+            self.height / 2,
+            self.width / 4,
+            self.height / 4,
+            self.hex_grid.get_byte_idx(),
+            unit,
+            big_endian,
+        ));
+    }
+
+    /// Write a value entered with `c` at the offset, as one edit.
+    fn set_value(&mut self, offset: usize, unit: Unit, big_endian: bool, bytes: &[u8]) {
+        self.value_unit = Some((unit, big_endian));
+        let end = offset + bytes.len();
+        if end > self.buffer.len() {
+            self.fail(BellEvent::Edge, &format!("A {} doesn't fit before the end", unit.name()));
+            return;
+        }
+        if !self.edit_allowed(offset, end, bytes.len()) {
+            return;
+        }
+        self.buffer.replace(offset, end, bytes);
+        self.edited(offset);
+        self.info_line.set_text(format!(
+            "Wrote {} ({}, {} endian) at 0x{:x}",
+            unit.decode(bytes, big_endian),
+            unit.name(),
+            if big_endian { "big" } else { "little" },
+            offset
+        ));
+    }
+
+    /// Parse the buffer as an ELF file. Errors are shown in the info line.
+    fn parse_elf(&mut self) -> Option<Elf> {
+        match Elf::parse(self.buffer.as_slice()) {
+            Ok(elf) =>
+                Some(elf),
+            Err(err) => {
+                let report = err.report("ELF", self.buffer.as_slice());
+                self.parse_failed(report);
+                None
+            }
+        }
+    }
+
+    fn mk_symbols_overlay(&mut self) {
+        let symbols = {
+            let elf = match self.parse_elf() {
+                Some(elf) =>
+                    elf,
+                None =>
+                    return,
+            };
+            let contents = self.buffer.as_slice();
+            let symbols: Vec<_> = elf.symbols(contents)
+                .into_iter()
+                .filter(|sym| !sym.name.is_empty())
+                .map(|sym| {
+                    let offset = elf.symbol_offset(&sym);
+                    (sym, offset)
+                })
+                .collect();
+            symbols
+        };
+
+        if symbols.is_empty() {
+            self.info_line.set_text("No symbols".to_owned());
+            return;
+        }
+
+        // Wider than other overlays, for long (e.g. C++) names
+        self.overlay = Overlay::SymbolsOverlay(SymbolsOverlay::new(
+            self.width * 3 / 4,
+            self.height * 3 / 4,
+            self.width / 8,
+            self.height / 8,
+            symbols,
+        ));
+    }
+
+    fn mk_sections_overlay(&mut self) {
+        let entries = match self.parse_elf() {
+            Some(elf) =>
+                sections::entries(&elf),
+            None =>
+                return,
+        };
+
+        if entries.is_empty() {
+            self.info_line.set_text("No sections or segments".to_owned());
+            return;
+        }
+
+        self.overlay = Overlay::SectionsOverlay(SectionsOverlay::new(
+            self.width / 2,
+            self.height * 3 / 4,
+            self.width / 4,
+            self.height / 8,
+            entries,
+        ));
+    }
+
+    fn mk_dynamic_overlay(&mut self) {
+        let entries = {
+            let elf = match self.parse_elf() {
+                Some(elf) =>
+                    elf,
+                None =>
+                    return,
+            };
+            let contents = self.buffer.as_slice();
+            let entries: Vec<_> = elf.dynamic(contents)
+                .into_iter()
+                .map(|entry| {
+                    let offset = match entry.value {
+                        DynValue::Addr(addr) =>
+                            elf.addr_offset(addr),
+                        _ =>
+                            None,
+                    };
+                    (entry, offset)
+                })
+                .collect();
+            entries
+        };
+
+        if entries.is_empty() {
+            self.info_line.set_text("No dynamic section".to_owned());
+            return;
+        }
+
+        self.overlay = Overlay::DynamicOverlay(DynamicOverlay::new(
+            self.width * 3 / 4,
+            self.height * 3 / 4,
+            self.width / 8,
+            self.height / 8,
+            entries,
+        ));
+    }
+
+    fn mk_relocations_overlay(&mut self) {
+        let relocations = {
+            let elf = match self.parse_elf() {
+                Some(elf) =>
+                    elf,
+                None =>
+                    return,
+            };
+            let contents = self.buffer.as_slice();
+            let relocations: Vec<_> = elf.relocations(contents)
+                .into_iter()
+                .map(|rel| {
+                    let offset = elf.relocation_offset(&rel);
+                    (rel, offset)
+                })
+                .collect();
+            relocations
+        };
+
+        if relocations.is_empty() {
+            self.info_line.set_text("No relocations".to_owned());
+            return;
+        }
+
+        self.overlay = Overlay::RelocationsOverlay(RelocationsOverlay::new(
+            self.width * 3 / 4,
+            self.height * 3 / 4,
+            self.width / 8,
+            self.height / 8,
+            relocations,
+        ));
+    }
+
+    /// Open the memory image of the ELF file in a new buffer, with addresses
+    /// in the offset column. Parts that are not in the file are shown in
+    /// gray.
+    fn open_memory_image(&mut self) -> HexGuiRet {
+        let image = match self.parse_elf() {
+            Some(elf) =>
+                elf.memory_image(self.buffer.as_slice()),
+            None =>
+                return HexGuiRet::Continue,
+        };
+        let image = match image {
+            Ok(image) =>
+                image,
+            Err(msg) => {
+                self.info_line.set_text(msg.to_owned());
+                return HexGuiRet::Continue;
+            }
+        };
+
+        let mut config = self.config.clone();
+        config.base = Some(image.base as usize);
+        let mut hex_gui = Box::new(HexGui::new(
+            image.data,
+            format!("{}.image", self.path),
+            config,
+            self.width,
+            self.height,
+        ));
+        hex_gui.virtual_ranges = image.virtual_ranges;
+        hex_gui.set_unsaveable();
+        hex_gui.follow = None;
+        HexGuiRet::Open(hex_gui)
+    }
+
+    /// `:debug [open]`: show the path of the separate debug info file of a
+    /// stripped ELF file, or open it in a new buffer.
+    fn debug_cmd(&mut self, args: &[&str]) -> HexGuiRet {
+        let open = match *args {
+            [] =>
+                false,
+            ["open"] =>
+                true,
+            _ => {
+                self.info_line.set_text("Usage: :debug [open]".to_owned());
+                return HexGuiRet::Continue;
+            }
+        };
+        let found = match self.parse_elf() {
+            Some(elf) =>
+                debug_file::find(&self.path, &elf, self.buffer.as_slice()),
+            None =>
+                return HexGuiRet::Continue,
+        };
+        let debug_file = match found {
+            Ok(debug_file) =>
+                debug_file,
+            Err(msg) => {
+                self.info_line.set_text(msg);
+                return HexGuiRet::Continue;
+            }
+        };
+
+        if !open {
+            self.info_line.set_text(format!(
+                "Debug file (by {}): {} - :debug open opens it",
+                debug_file.found_by,
+                debug_file.path
+            ));
+            return HexGuiRet::Continue;
+        }
+        HexGuiRet::Open(Box::new(HexGui::new(
+            debug_file.contents,
+            debug_file.path,
+            self.config.clone(),
+            self.width,
+            self.height,
+        )))
+    }
+
+    fn mk_notes_overlay(&mut self) {
+        let notes = {
+            let elf = match self.parse_elf() {
+                Some(elf) =>
+                    elf,
+                None =>
+                    return,
+            };
+            let contents = self.buffer.as_slice();
+            let notes = match elf.notes(contents) {
+                Ok(notes) =>
+                    notes,
+                Err(err) => {
+                    self.info_line.set_text(format!("Can't read notes: {}", err));
+                    return;
+                }
+            };
+            let notes: Vec<_> = notes
+                .into_iter()
+                .map(|note| NoteRow {
+                    ty: match note.type_str() {
+                        Some(name) =>
+                            name.to_owned(),
+                        None =>
+                            format!("{} {}", note.owner, note.ty),
+                    },
+                    value: elf.note_value(&note),
+                    offset: note.desc_offset,
+                    section: note.section,
+                })
+                .collect();
+            notes
+        };
+
+        if notes.is_empty() {
+            self.info_line.set_text("No notes".to_owned());
+            return;
+        }
+
+        self.overlay = Overlay::NotesOverlay(NotesOverlay::new(
+            self.width * 3 / 4,
+            self.height * 3 / 4,
+            self.width / 8,
+            self.height / 8,
+            notes,
+        ));
+    }
+
+    /// `:elf`: edit the ELF header.
+    fn mk_elf_header_overlay(&mut self) -> String {
+        let fields = match elf::header_fields(self.buffer.as_slice()) {
+            Some(fields) =>
+                fields,
+            None =>
+                return "Not an ELF file".to_owned(),
+        };
+        self.overlay = Overlay::ElfHeaderOverlay(ElfHeaderOverlay::new(
+            self.width * 3 / 4,
+            self.height * 3 / 4,
+            self.width / 8,
+            self.height / 8,
+            fields,
+        ));
+        String::new()
+    }
+
+    /// `:got`: PLT stubs and GOT slots of ELF files, with the functions they
+    /// resolve to.
+    fn mk_got_overlay(&mut self) -> String {
+        let rows = {
+            let elf = match self.parse_elf() {
+                Some(elf) =>
+                    elf,
+                None =>
+                    return self.info_line.text().to_owned(),
+            };
+            got::rows(&elf, self.buffer.as_slice())
+        };
+        let rows = match rows {
+            Some(rows) =>
+                rows,
+            None =>
+                return "No GOT or PLT sections".to_owned(),
+        };
+        self.overlay = Overlay::StructureOverlay(StructureOverlay::new(
+            self.width * 3 / 4,
+            self.height * 3 / 4,
+            self.width / 8,
+            self.height / 8,
+            " GOT/PLT ",
+            rows,
+        ));
+        String::new()
+    }
+
+    /// `:va2off <vaddr> [jump]`: file offset of a virtual address, from the
+    /// program headers. Addresses are hex, as in objdump output, `0x` is
+    /// optional.
+    fn va2off_cmd(&mut self, args: &[&str]) -> String {
+        let (arg, jump) = match *args {
+            [arg] =>
+                (arg, false),
+            [arg, "jump"] =>
+                (arg, true),
+            _ =>
+                return "Usage: :va2off <vaddr> [jump]".to_owned(),
+        };
+        let hex = if arg.starts_with("0x") || arg.starts_with("0X") { &arg[2..] } else { arg };
+        let addr = match u64::from_str_radix(hex, 16) {
+            Ok(addr) =>
+                addr,
+            Err(_) =>
+                return format!("Invalid address: {}", arg),
+        };
+        let (msg, offset) = {
+            let elf = match self.parse_elf() {
+                Some(elf) =>
+                    elf,
+                None =>
+                    return self.info_line.text().to_owned(),
+            };
+            let segment = match elf.load_segment(addr) {
+                Some(segment) =>
+                    segment,
+                None =>
+                    return format!("0x{:x} is not in a loaded segment", addr),
+            };
+            let location = segment_location(segment, elf.addr_section(addr));
+            match elf.load_offset(addr) {
+                Some(offset) if offset < self.buffer.len() =>
+                    (format!("0x{:x} is at offset 0x{:x} ({})", addr, offset, location), offset),
+                _ =>
+                    return format!(
+                        "0x{:x} is not in the file, it's zero-filled ({})",
+                        addr,
+                        location
+                    ),
+            }
+        };
+        if jump {
+            self.jump(offset);
+        }
+        msg
+    }
+
+    /// `:off2va [<offset>] [jump]`: virtual address of a file offset (the
+    /// cursor by default), from the program headers.
+    fn off2va_cmd(&mut self, args: &[&str]) -> String {
+        let (arg, jump) = match *args {
+            [] =>
+                (None, false),
+            ["jump"] =>
+                return "Usage: :off2va [<offset>] [jump]".to_owned(),
+            [arg] =>
+                (Some(arg), false),
+            [arg, "jump"] =>
+                (Some(arg), true),
+            _ =>
+                return "Usage: :off2va [<offset>] [jump]".to_owned(),
+        };
+        let offset = match arg {
+            Some(arg) =>
+                match parse_number(arg) {
+                    Some(offset) if offset < self.buffer.len() =>
+                        offset,
+                    Some(_) =>
+                        return "Offset is out of the file".to_owned(),
+                    None =>
+                        return format!("Invalid offset: {}", arg),
+                },
+            None =>
+                self.hex_grid.get_byte_idx(),
+        };
+        let msg = {
+            let elf = match self.parse_elf() {
+                Some(elf) =>
+                    elf,
+                None =>
+                    return self.info_line.text().to_owned(),
+            };
+            match (elf.offset_segment(offset), elf.offset_addr(offset)) {
+                (Some(segment), Some(addr)) =>
+                    format!(
+                        "Offset 0x{:x} is at 0x{:x} ({})",
+                        offset,
+                        addr,
+                        segment_location(segment, elf.addr_section(addr))
+                    ),
+                _ =>
+                    return format!("Offset 0x{:x} is not in a loaded segment", offset),
+            }
+        };
+        if jump {
+            self.jump(offset);
+        }
+        msg
+    }
+
+    /// Write the new value of an ELF header field, and show the values after
+    /// the write.
+    fn write_header_field(&mut self, offset: usize, bytes: &[u8]) {
+        if !self.edit_allowed(offset, offset + bytes.len(), bytes.len()) {
+            return;
+        }
+        self.buffer.replace(offset, offset + bytes.len(), bytes);
+        self.edited(offset);
+        if let Some(fields) = elf::header_fields(self.buffer.as_slice()) {
+            if let Overlay::ElfHeaderOverlay(ref mut o) = self.overlay {
+                o.set_fields(fields);
+            }
+        }
+    }
+
+    fn mk_pe_overlay(&mut self) {
+        let rows = match Pe::parse(self.buffer.as_slice()) {
+            Ok(pe) =>
+                pe::rows(&pe, self.buffer.as_slice()),
+            Err(err) => {
+                let report = err.report("PE", self.buffer.as_slice());
+                self.parse_failed(report);
+                return;
+            }
+        };
+
+        self.overlay = Overlay::StructureOverlay(StructureOverlay::new(
+            self.width * 3 / 4,
+            self.height * 3 / 4,
+            self.width / 8,
+            self.height / 8,
+            " PE ",
+            rows,
+        ));
+    }
+
+    fn mk_elf_overlay(&mut self) {
+        let rows = match self.parse_elf() {
+            Some(elf) =>
+                elf_structure::rows(&elf, self.buffer.as_slice()),
+            None =>
+                return,
+        };
+
+        self.overlay = Overlay::StructureOverlay(StructureOverlay::new(
+            self.width * 3 / 4,
+            self.height * 3 / 4,
+            self.width / 8,
+            self.height / 8,
+            " ELF ",
+            rows,
+        ));
+    }
+
+    fn mk_macho_overlay(&mut self) {
+        let rows = match MachO::parse(self.buffer.as_slice()) {
+            Ok(macho) =>
+                macho::rows(&macho, self.buffer.as_slice()),
+            Err(err) => {
+                let report = err.report("Mach-O", self.buffer.as_slice());
+                self.parse_failed(report);
+                return;
+            }
+        };
+
+        self.overlay = Overlay::StructureOverlay(StructureOverlay::new(
+            self.width * 3 / 4,
+            self.height * 3 / 4,
+            self.width / 8,
+            self.height / 8,
+            " Mach-O ",
+            rows,
+        ));
+    }
+
+    fn mk_search_overlay(&mut self) {
+        self.overlay = Overlay::SearchOverlay(SearchOverlay::new(
+            self.width / 2,
+            self.height / 2,
+            self.width / 4,
+            self.height / 4,
+            self.selection().is_some(),
+        ));
+    }
+}
+
+/// Offset and the byte at the offset, in hex and decimal, for the info line.
+/// Select the guessed encoding of a text file with `--encoding auto`,
+/// otherwise suggest it. Returns the message for the info line.
+fn guess_encoding(contents: &[u8], config: &mut Config) -> Option<String> {
+    let guess = encoding::guess(contents)?;
+    if config.auto_encoding {
+        config.encoding = guess.encoding;
+        Some(format!("Guessed encoding: {}", guess.name))
+    } else if guess.encoding != config.encoding {
+        Some(format!(
+            "Looks like {} text, :set encoding={} to decode it",
+            guess.name,
+            guess.encoding.name()
+        ))
+    } else {
+        None
+    }
+}
+
+/// Values of the `:set` options, as they're given to `:set`.
+fn set_options(config: &Config, layout_strategy: LayoutStrategy) -> Vec<(&'static str, String)> {
+    let cols = match layout_strategy {
+        LayoutStrategy::Fill =>
+            "auto".to_owned(),
+        LayoutStrategy::Pow2 =>
+            "pow2".to_owned(),
+        LayoutStrategy::Fixed(cols) =>
+            cols.to_string(),
+    };
+    let sector = if config.sectors {
+        config.sector_size.to_string()
+    } else {
+        "off".to_owned()
+    };
+    vec![
+        ("cols", cols),
+        ("group", config.group_size.to_string()),
+        ("offsets", config.offset_format.name().to_owned()),
+        ("max-matches", config.max_matches.to_string()),
+        ("sector", sector),
+        ("squeeze", on_off(config.squeeze).to_owned()),
+        ("search-jump", on_off(config.search_jump).to_owned()),
+        ("search-center", on_off(config.search_center).to_owned()),
+        ("nonprint", config.non_printable.name().to_owned()),
+        ("encoding", config.encoding.name().to_owned()),
+        ("footer", config.footer.map_or("off", |kind| kind.name()).to_owned()),
+        ("bits", on_off(config.bits).to_owned()),
+        ("elf-regions", on_off(config.elf_regions).to_owned()),
+        ("readonly", on_off(config.readonly).to_owned()),
+    ]
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn locked_msg((start, end): (usize, usize)) -> String {
+    format!("0x{:x}-0x{:x} is locked, :unlock to edit it", start, end)
+}
+
+/// Where an address is, for `:va2off` and `:off2va`: "segment 3, .text".
+/// Segments are numbered as in `readelf -l`.
+fn segment_location(segment: usize, section: Option<&SectionHeader>) -> String {
+    match section {
+        Some(sh) if !sh.name.is_empty() =>
+            format!("segment {}, {}", segment, sh.name),
+        _ =>
+            format!("segment {}", segment),
+    }
+}
+
+fn offset_info(offset: usize, byte: Option<u8>) -> String {
+    match byte {
+        Some(byte) =>
+            format!("offset 0x{:x} ({}) - byte 0x{:02x} ({})", offset, offset, byte, byte),
+        None =>
+            format!("offset 0x{:x} ({})", offset, offset),
+    }
+}
+
+/// Can we write to the file? Files that don't exist can be created.
+fn is_writable(path: &str) -> bool {
+    if !Path::new(path).exists() {
+        return true;
+    }
+    can_write(Path::new(path))
+}
+
+/// `access(path, W_OK)`
+fn can_write(path: &Path) -> bool {
+    match CString::new(path.as_os_str().as_bytes()) {
+        Ok(c_path) =>
+            unsafe { libc::access(c_path.as_ptr(), libc::W_OK) == 0 },
+        Err(_) =>
+            false,
+    }
+}
+
+/// Should an existing file be written in place rather than replaced by a new
+/// file? Replacing a device doesn't write to it, replacing a hard link breaks
+/// it, and replacing someone else's file changes its owner. Without write
+/// access to the directory the new file can't be created.
+fn write_in_place(path: &Path, old: &fs::Metadata) -> bool {
+    if !old.is_file() || old.nlink() > 1 || old.uid() != unsafe { libc::geteuid() } {
+        return true;
+    }
+    match path.parent() {
+        Some(dir) if dir != Path::new("") =>
+            !can_write(dir),
+        _ =>
+            !can_write(Path::new(".")),
+    }
+}
+
+/// Write the file through a temporary file in the same directory, renamed over
+/// it when it's written, so that a failed write (e.g. a full disk) doesn't
+/// leave a truncated file. Symlinks are followed, the permissions of an
+/// existing file are kept. Files that can't be replaced (see `write_in_place`)
+/// are written in place.
+fn write_file(path: &str, bytes: &[u8], overwrite: bool) -> io::Result<()> {
+    let old = fs::metadata(path).ok();
+    if old.is_some() && !overwrite {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, "File exists"));
+    }
+    let path = match old {
+        Some(ref old) => {
+            let path = fs::canonicalize(path)?;
+            if write_in_place(&path, old) {
+                return OpenOptions::new()
+                    .write(true)
+                    .truncate(old.is_file())
+                    .open(&path)?
+                    .write_all(bytes);
+            }
+            path
+        }
+        None =>
+            PathBuf::from(path),
+    };
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Not a file name"))?;
+    let mut tmp_name = OsString::from(".");
+    tmp_name.push(name);
+    tmp_name.push(".rhex-tmp");
+    let tmp = path.with_file_name(tmp_name);
+
+    let written = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&tmp)
+        .and_then(|mut file| {
+            file.write_all(bytes)?;
+            if let Some(ref old) = old {
+                file.set_permissions(old.permissions())?;
+            }
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp, &path));
+    if written.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use term_input::Arrow;
+
+    const MOTIONS: &[Key] = &[
+        Key::Char('j'),
+        Key::Char('k'),
+        Key::Char('l'),
+        Key::Char('h'),
+        Key::Char('G'),
+        Key::Char('0'),
+        Key::Char('$'),
+        Key::Char('H'),
+        Key::Char('M'),
+        Key::Char('L'),
+        Key::PageDown,
+        Key::PageUp,
+        Key::Home,
+        Key::End,
+        Key::Arrow(Arrow::Down),
+        Key::Arrow(Arrow::Right),
+    ];
+
+    fn hex_gui(contents: &[u8]) -> Box<HexGui> {
+        let config = Config {
+            autosave_secs: 0,
+            ..Config::default()
+        };
+        let mut hex_gui =
+            Box::new(HexGui::new(contents.to_vec(), "[test]".to_owned(), config, 80, 24));
+        hex_gui.init();
+        hex_gui
+    }
+
+    fn press(hex_gui: &mut HexGui, keys: &[Key]) {
+        for &key in keys {
+            hex_gui.keypressed(key, Instant::now());
+        }
+    }
+
+    #[test]
+    fn layout_of_tiny_buffers() {
+        for len in 0..2 {
+            let (hex_grid, _, _) =
+                mk_widgets(80, 24, len, LayoutStrategy::Fill, &Config::default(), None, None);
+            assert!(hex_grid.bytes_per_line() > 0);
+            assert_eq!(hex_grid.rows().len(), len);
+            assert_eq!(hex_grid.visible_range(), (0, len));
+            assert_eq!(hex_grid.get_byte_idx(), 0);
+        }
+    }
+
+    #[test]
+    fn motions_in_empty_buffer() {
+        let mut hex_gui = hex_gui(&[]);
+        for &key in MOTIONS {
+            press(&mut hex_gui, &[key]);
+            assert_eq!(hex_gui.hex_grid.get_byte_idx(), 0, "{:?}", key);
+        }
+        // Moving right doesn't leave the first (nonexistent) byte
+        for _ in 0..40 {
+            press(&mut hex_gui, &[Key::Char('l')]);
+        }
+        assert_eq!(hex_gui.hex_grid.get_byte_idx(), 0);
+        // Nothing to delete
+        press(&mut hex_gui, &[Key::Char('x')]);
+        assert!(hex_gui.buffer.is_empty());
+        assert!(!hex_gui.buffer.is_modified());
+    }
+
+    #[test]
+    fn motions_in_one_byte_buffer() {
+        let mut hex_gui = hex_gui(&[0xab]);
+        for &key in MOTIONS {
+            press(&mut hex_gui, &[key]);
+            assert_eq!(hex_gui.hex_grid.get_byte_idx(), 0, "{:?}", key);
+        }
+        press(&mut hex_gui, &[Key::Char('0'), Key::Char('l')]);
+        assert_eq!(hex_gui.hex_grid.get_digit(), 1);
+        press(&mut hex_gui, &[Key::Char('l')]);
+        assert_eq!(hex_gui.hex_grid.get_digit(), 1);
+    }
+
+    #[test]
+    fn screen_motions() {
+        let contents: Vec<u8> = (0..4096).map(|i| (i % 251) as u8).collect();
+        let mut hex_gui = hex_gui(&contents);
+        let bpl = hex_gui.hex_grid.bytes_per_line();
+        let rows = hex_gui.hex_grid.rows().len();
+        assert!(rows > 2);
+
+        // Keeps the column
+        press(&mut hex_gui, &[Key::Char('l'), Key::Char('l'), Key::Char('L')]);
+        assert_eq!(hex_gui.hex_grid.get_byte_idx(), (rows - 1) * bpl + 1);
+        press(&mut hex_gui, &[Key::Char('M')]);
+        assert_eq!(hex_gui.hex_grid.get_byte_idx(), (rows - 1) / 2 * bpl + 1);
+        press(&mut hex_gui, &[Key::Char('H')]);
+        assert_eq!(hex_gui.hex_grid.get_byte_idx(), 1);
+
+        // Doesn't scroll
+        press(&mut hex_gui, &[Key::PageDown, Key::Char('H')]);
+        let (start, _) = hex_gui.hex_grid.visible_range();
+        assert_eq!(hex_gui.hex_grid.get_byte_idx(), start + 1);
+    }
+
+    #[test]
+    fn delete_last_byte() {
+        let mut hex_gui = hex_gui(&[0xab]);
+        press(&mut hex_gui, &[Key::Char('x')]);
+        assert!(hex_gui.buffer.is_empty());
+        assert_eq!(hex_gui.hex_grid.get_byte_idx(), 0);
+        assert!(hex_gui.hex_grid.rows().is_empty());
+        for &key in MOTIONS {
+            press(&mut hex_gui, &[key]);
+            assert_eq!(hex_gui.hex_grid.get_byte_idx(), 0, "{:?}", key);
+        }
+        press(&mut hex_gui, &[Key::Char('u')]);
+        assert_eq!(hex_gui.buffer.as_slice(), &[0xab]);
+    }
+
+    #[test]
+    fn write_file_keeps_hard_links() {
+        let dir = ::std::env::temp_dir().join(format!("rhex-test-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("file");
+        let link = dir.join("link");
+        fs::write(&file, b"old contents").unwrap();
+        fs::hard_link(&file, &link).unwrap();
+        write_file(file.to_str().unwrap(), b"new", true).unwrap();
+        assert_eq!(fs::read(&link).unwrap(), b"new");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn saveas_keeps_user_readonly() {
+        let dir = ::std::env::temp_dir().join(format!("rhex-saveas-{}", ::std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let copy = dir.join("copy");
+        let copy = copy.to_str().unwrap();
+
+        let mut gui = hex_gui(&[1, 2, 3]);
+        gui.set_partial();
+        gui.save_as(copy, true);
+        assert!(!gui.config.readonly);
+
+        let mut gui = hex_gui(&[1, 2, 3]);
+        gui.config.readonly = true;
+        gui.set_partial();
+        gui.save_as(copy, true);
+        assert!(gui.config.readonly);
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 }
@@ -0,0 +1,128 @@
+use std::cmp;
+
+use colors;
+use utils::*;
+
+use term_input::{Arrow, Key};
+use termbox_simple::*;
+
+pub enum NotesRet {
+    /// Jump to the byte offset.
+    Jump(usize),
+
+    /// User cancelled.
+    Abort,
+
+    /// Overlay still has focus.
+    Continue,
+}
+
+/// A note, as shown in the overlay.
+pub struct NoteRow {
+    pub section: String,
+    /// `NT_*` name, or owner and type number for unknown notes.
+    pub ty: String,
+    /// Decoded descriptor, e.g. the build-id in hex.
+    pub value: String,
+    /// File offset of the descriptor.
+    pub offset: usize,
+}
+
+/// Shows ELF notes: build-id, ABI tag, GNU properties etc. Enter jumps to the
+/// descriptor of the selected note.
+pub struct NotesOverlay {
+    pos_x: i32,
+    pos_y: i32,
+    width: i32,
+    height: i32,
+
+    notes: Vec<NoteRow>,
+
+    selected: usize,
+}
+
+impl NotesOverlay {
+    pub fn new(
+        width: i32,
+        height: i32,
+        pos_x: i32,
+        pos_y: i32,
+        notes: Vec<NoteRow>,
+    ) -> NotesOverlay {
+        let width_ = cmp::min(width, 120);
+        let height_ = cmp::min(height, 20);
+
+        let pos_x = pos_x + (width - width_) / 2;
+        let pos_y = pos_y + (height - height_) / 2;
+
+        NotesOverlay {
+            pos_x,
+            pos_y,
+            width: width_,
+            height: height_,
+            notes,
+            selected: 0,
+        }
+    }
+
+    pub fn draw(&self, tb: &mut Termbox) {
+        draw_box(tb, self.pos_x, self.pos_y, self.width, self.height);
+
+        let title = format!(" Notes ({}) ", self.notes.len());
+        print(tb, self.pos_x + 2, self.pos_y, colors::palette().default, &title);
+
+        let rows = (self.height - 4) as usize;
+        let first = if self.selected >= rows {
+            self.selected + 1 - rows
+        } else {
+            0
+        };
+
+        for (row, note) in self.notes.iter().enumerate().skip(first).take(rows) {
+            let style = if row == self.selected {
+                colors::palette().cursor_focus
+            } else {
+                colors::palette().default
+            };
+            let line = format!("{:<20} {:<23} {}", note.section, note.ty, note.value);
+            let line: String = line.chars().take((self.width - 2) as usize).collect();
+            print(tb, self.pos_x + 1, self.pos_y + 1 + (row - first) as i32, style, &line);
+        }
+
+        print(
+            tb,
+            self.pos_x + 1,
+            self.pos_y + self.height - 2,
+            colors::palette().default,
+            "Enter: jump to descriptor",
+        );
+    }
+
+    pub fn keypressed(&mut self, key: Key) -> NotesRet {
+        match key {
+            Key::Arrow(Arrow::Up) | Key::Char('k') => {
+                if self.selected > 0 {
+                    self.selected -= 1;
+                }
+                NotesRet::Continue
+            }
+            Key::Arrow(Arrow::Down) | Key::Char('j') => {
+                if self.selected + 1 < self.notes.len() {
+                    self.selected += 1;
+                }
+                NotesRet::Continue
+            }
+            Key::Char('\r') =>
+                match self.notes.get(self.selected) {
+                    Some(note) =>
+                        NotesRet::Jump(note.offset),
+                    None =>
+                        NotesRet::Continue,
+                },
+            Key::Esc | Key::Char('q') =>
+                NotesRet::Abort,
+            _ =>
+                NotesRet::Continue,
+        }
+    }
+}
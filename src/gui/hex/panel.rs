@@ -0,0 +1,319 @@
+////////////////////////////////////////////////////////////////////////////////
+// Lower pane of the horizontal split (`p`). Shows information about the bytes
+// at the cursor, updated as the cursor moves.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cell::Cell;
+use std::cmp;
+use std::fmt;
+
+use buffer::Buffer;
+use colors;
+use gui::hex::disas::{self, Arch, DisasElf, Instruction};
+use templates::Field;
+use utils::*;
+
+use termbox_simple::*;
+
+/// What the panel shows. `P` cycles through these.
+#[derive(Clone, Copy, PartialEq)]
+pub enum PanelKind {
+    /// Bytes at the cursor as integers and floats of different sizes, in both
+    /// endiannesses.
+    Inspector,
+
+    /// Bytes starting at the cursor in binary, one byte per row.
+    Bits,
+
+    /// Fields of the applied template (`:template`), around the field at the
+    /// cursor.
+    Template,
+
+    /// Instructions around the cursor (`d`), the one at the cursor
+    /// highlighted.
+    Disassembly,
+}
+
+impl PanelKind {
+    pub fn next(self) -> PanelKind {
+        match self {
+            PanelKind::Inspector =>
+                PanelKind::Bits,
+            PanelKind::Bits =>
+                PanelKind::Template,
+            PanelKind::Template =>
+                PanelKind::Disassembly,
+            PanelKind::Disassembly =>
+                PanelKind::Inspector,
+        }
+    }
+
+    fn title(self, arch: Arch) -> String {
+        match self {
+            PanelKind::Inspector =>
+                " Inspector ".to_owned(),
+            PanelKind::Bits =>
+                " Bits ".to_owned(),
+            PanelKind::Template =>
+                " Template ".to_owned(),
+            PanelKind::Disassembly =>
+                format!(" Disassembly ({}) ", arch.name()),
+        }
+    }
+}
+
+pub struct Panel {
+    pos_y: i32,
+    width: i32,
+    height: i32,
+    kind: PanelKind,
+    /// Offset of the first shown instruction in the disassembly. Updated in
+    /// `draw()` as the cursor moves.
+    disas_start: Cell<usize>,
+}
+
+/// Width of a value column in the inspector. u64::MAX has 20 digits.
+const COLUMN_WIDTH: usize = 22;
+
+impl Panel {
+    pub fn new(width: i32, height: i32, pos_y: i32, kind: PanelKind) -> Panel {
+        Panel {
+            pos_y,
+            width,
+            height,
+            kind,
+            disas_start: Cell::new(0),
+        }
+    }
+
+    pub fn kind(&self) -> PanelKind {
+        self.kind
+    }
+
+    /// Rows below the title line. 0 in tiny terminals, where the panel is
+    /// not shown.
+    fn rows(&self) -> usize {
+        cmp::max(self.height - 1, 0) as usize
+    }
+
+    pub fn draw(
+        &self,
+        tb: &mut Termbox,
+        data: &Buffer,
+        cursor: usize,
+        fields: &[Field],
+        arch: Arch,
+        elf: Option<&DisasElf>,
+    ) {
+        let rows = self.rows();
+        if rows == 0 {
+            return;
+        }
+
+        let style = colors::palette().default;
+        for x in 0..self.width {
+            tb.change_cell(x, self.pos_y, '─', style.fg, style.bg);
+        }
+        print(tb, 2, self.pos_y, colors::palette().default, &self.kind.title(arch));
+
+        // Row of the current item, highlighted
+        let (lines, current) = match self.kind {
+            PanelKind::Inspector =>
+                (inspector_lines(data, cursor), None),
+            PanelKind::Bits =>
+                (bits_lines(data, cursor, rows), None),
+            PanelKind::Template =>
+                template_lines(fields, cursor, rows),
+            PanelKind::Disassembly =>
+                disas::lines(data, cursor, rows, arch, elf, &self.disas_start),
+        };
+
+        for (y, line) in lines.iter().take(rows).enumerate() {
+            let style = if current == Some(y) {
+                colors::palette().cursor_no_focus
+            } else {
+                colors::palette().default
+            };
+            let line: String = line.chars().take(self.width as usize).collect();
+            print(tb, 1, self.pos_y + 1 + y as i32, style, &line);
+        }
+    }
+
+    /// The instruction at the cursor, as shown in the disassembly.
+    pub fn instruction(
+        &self,
+        data: &Buffer,
+        cursor: usize,
+        arch: Arch,
+        elf: Option<&DisasElf>,
+    ) -> Option<Instruction> {
+        let rows = self.rows();
+        if rows == 0 {
+            return None;
+        }
+        let (mut insns, current) =
+            disas::instructions(data, cursor, rows, arch, elf, &self.disas_start).ok()?;
+        current.map(|idx| insns.swap_remove(idx))
+    }
+}
+
+/// `n` bytes at `offset`, when there are that many.
+fn bytes_at(data: &Buffer, offset: usize, n: usize) -> Option<Vec<u8>> {
+    (offset..offset + n).map(|idx| data.get(idx)).collect()
+}
+
+/// Little and big endian values of the bytes, as unsigned integers.
+fn le_be(bytes: &[u8]) -> (u64, u64) {
+    let mut le = 0u64;
+    let mut be = 0u64;
+    for (i, &byte) in bytes.iter().enumerate() {
+        le |= u64::from(byte) << (8 * i);
+        be = (be << 8) | u64::from(byte);
+    }
+    (le, be)
+}
+
+fn inspector_lines(data: &Buffer, cursor: usize) -> Vec<String> {
+    let mut ret = Vec::new();
+
+    let byte = match data.get(cursor) {
+        Some(byte) =>
+            byte,
+        None => {
+            ret.push("Empty buffer".to_owned());
+            return ret;
+        }
+    };
+    let ch = if byte.is_ascii_graphic() || byte == b' ' {
+        format!("'{}'", byte as char)
+    } else {
+        "-".to_owned()
+    };
+    ret.push(format!("u8 {}  i8 {}  bin {:08b}  ascii {}", byte, byte as i8, byte, ch));
+
+    ret.push(format!(
+        "     {:<w$}{:<w$}{:<w$}{:<w$}",
+        "LE unsigned",
+        "LE signed",
+        "BE unsigned",
+        "BE signed",
+        w = COLUMN_WIDTH
+    ));
+
+    for &size in &[2, 4, 8] {
+        let bits = size * 8;
+        let line = match bytes_at(data, cursor, size) {
+            None =>
+                format!("{:<5}-", bits),
+            Some(bytes) => {
+                let (le, be) = le_be(&bytes);
+                // Shift the sign bit to the top, then back with sign extension
+                let shift = 64 - bits;
+                let signed = |n: u64| ((n << shift) as i64) >> shift;
+                format!(
+                    "{:<5}{:<w$}{:<w$}{:<w$}{:<w$}",
+                    bits,
+                    le,
+                    signed(le),
+                    be,
+                    signed(be),
+                    w = COLUMN_WIDTH
+                )
+            }
+        };
+        ret.push(line);
+    }
+
+    let f32_line = match bytes_at(data, cursor, 4) {
+        None =>
+            "f32  -".to_owned(),
+        Some(bytes) => {
+            let (le, be) = le_be(&bytes);
+            format!(
+                "f32  {:<w$}{:<w$}",
+                format_float(f32::from_bits(le as u32)),
+                format_float(f32::from_bits(be as u32)),
+                w = COLUMN_WIDTH * 2
+            )
+        }
+    };
+    ret.push(f32_line);
+
+    let f64_line = match bytes_at(data, cursor, 8) {
+        None =>
+            "f64  -".to_owned(),
+        Some(bytes) => {
+            let (le, be) = le_be(&bytes);
+            format!(
+                "f64  {:<w$}{:<w$}",
+                format_float(f64::from_bits(le)),
+                format_float(f64::from_bits(be)),
+                w = COLUMN_WIDTH * 2
+            )
+        }
+    };
+    ret.push(f64_line);
+
+    ret
+}
+
+/// Very small and large numbers are shown in scientific notation, otherwise
+/// e.g. most random bytes are shown as "0.0000000000000..." as f32.
+fn format_float<T: fmt::Display + fmt::LowerExp + Into<f64> + Copy>(f: T) -> String {
+    let abs = f.into().abs();
+    if abs != 0.0 && !(1e-4..1e16).contains(&abs) {
+        format!("{:e}", f)
+    } else {
+        format!("{}", f)
+    }
+}
+
+fn bits_lines(data: &Buffer, cursor: usize, rows: usize) -> Vec<String> {
+    let mut ret = vec!["offset      7654 3210  hex  dec".to_owned()];
+    for offset in cursor..cursor + rows.saturating_sub(1) {
+        match data.get(offset) {
+            None =>
+                break,
+            Some(byte) =>
+                ret.push(format!(
+                    "0x{:08x}  {:04b} {:04b}  {:02x}   {}",
+                    offset,
+                    byte >> 4,
+                    byte & 0xf,
+                    byte,
+                    byte
+                )),
+        }
+    }
+    ret
+}
+
+/// Fields around the innermost field at the cursor, and the row of that field.
+fn template_lines(fields: &[Field], cursor: usize, rows: usize) -> (Vec<String>, Option<usize>) {
+    if fields.is_empty() {
+        return (vec!["No template, :template <file> applies one".to_owned()], None);
+    }
+
+    // Elements come after their structs and arrays, so the last field at the
+    // cursor is the innermost one
+    let current = fields
+        .iter()
+        .rposition(|field| cursor >= field.offset && cursor - field.offset < field.size);
+    let first = match current {
+        Some(idx) if idx >= rows / 2 =>
+            cmp::min(idx - rows / 2, fields.len().saturating_sub(rows)),
+        _ =>
+            0,
+    };
+
+    let lines = fields[first..]
+        .iter()
+        .take(rows)
+        .map(|field| {
+            let name = format!("{}{}", "  ".repeat(field.depth), field.name);
+            let value = field.value.as_ref().map_or("", |value| value.as_str());
+            format!("0x{:08x} {:>6}  {:<32} {}", field.offset, field.size, name, value)
+        })
+        .collect();
+    (lines, current.map(|idx| idx - first))
+}
@@ -0,0 +1,201 @@
+////////////////////////////////////////////////////////////////////////////////
+// Prompt for a file path (`:saveas` without a path). Tab completes file and
+// directory names, like the command line completes commands.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cmp;
+use std::fs;
+use std::path::Path;
+
+use colors;
+use utils::*;
+
+use term_input::Key;
+use termbox_simple::*;
+
+pub enum PathRet {
+    /// User submitted the path.
+    Path(String),
+
+    /// User cancelled.
+    Abort,
+
+    /// Overlay still has focus.
+    Continue,
+}
+
+pub struct PathOverlay {
+    pos_x: i32,
+    pos_y: i32,
+    width: i32,
+    height: i32,
+    title: &'static str,
+    input: String,
+
+    /// Paths completing the input, and the one shown. Tab cycles through
+    /// them.
+    completions: Vec<String>,
+    completion_idx: Option<usize>,
+}
+
+/// Paths of the files in the directory part of `input` whose names start with
+/// the rest, sorted. Directories end with '/'. Hidden files are only included
+/// when the name starts with '.'.
+pub fn complete_path(input: &str) -> Vec<String> {
+    let (dir, prefix) = match input.rfind('/') {
+        Some(idx) =>
+            (&input[..idx + 1], &input[idx + 1..]),
+        None =>
+            ("", input),
+    };
+    let dir_path = if dir.is_empty() { Path::new(".") } else { Path::new(dir) };
+    let entries = match fs::read_dir(dir_path) {
+        Ok(entries) =>
+            entries,
+        Err(_) =>
+            return Vec::new(),
+    };
+    let mut ret: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            // Follows symlinks, so links to directories complete as
+            // directories
+            let is_dir = entry.path().is_dir();
+            Some(format!("{}{}{}", dir, name, if is_dir { "/" } else { "" }))
+        })
+        .collect();
+    ret.sort();
+    ret
+}
+
+impl PathOverlay {
+    /// `input`: initial input, e.g. the current path.
+    pub fn new(
+        width: i32,
+        height: i32,
+        pos_x: i32,
+        pos_y: i32,
+        title: &'static str,
+        input: String,
+    ) -> PathOverlay {
+        let width_ = cmp::min(width, 70);
+        let height_ = cmp::min(height, 10);
+
+        let pos_x = pos_x + (width - width_) / 2;
+        let pos_y = pos_y + (height - height_) / 2;
+
+        PathOverlay {
+            pos_x,
+            pos_y,
+            width: width_,
+            height: height_,
+            title,
+            input,
+            completions: Vec::new(),
+            completion_idx: None,
+        }
+    }
+
+    pub fn draw(&self, tb: &mut Termbox) {
+        draw_box(tb, self.pos_x, self.pos_y, self.width, self.height);
+        print(tb, self.pos_x + 5, self.pos_y + 3, colors::palette().default, self.title);
+        print(tb, self.pos_x + 5, self.pos_y + 5, colors::palette().default, ">");
+
+        // Show the end of long paths
+        let room = cmp::max(self.width - 14, 1) as usize;
+        let chars = self.input.chars().count();
+        let shown: String = self.input.chars().skip(chars.saturating_sub(room)).collect();
+        print(tb, self.pos_x + 7, self.pos_y + 5, colors::palette().default, &shown);
+        tb.change_cell(
+            self.pos_x + 7 + shown.chars().count() as i32,
+            self.pos_y + 5,
+            ' ',
+            colors::palette().cursor_focus.fg,
+            colors::palette().cursor_focus.bg,
+        );
+
+        // Completions, by name, the shown one highlighted
+        let mut x = self.pos_x + 5;
+        for (idx, completion) in self.completions.iter().enumerate() {
+            let name = file_name(completion);
+            let len = name.chars().count() as i32;
+            if x + len > self.pos_x + self.width - 2 {
+                break;
+            }
+            let style = if self.completion_idx == Some(idx) {
+                colors::palette().cursor_no_focus
+            } else {
+                colors::palette().default
+            };
+            print(tb, x, self.pos_y + 7, style, name);
+            x += len + 2;
+        }
+    }
+
+    pub fn keypressed(&mut self, key: Key) -> PathRet {
+        if key != Key::Tab {
+            self.completions.clear();
+            self.completion_idx = None;
+        }
+        match key {
+            Key::Esc =>
+                PathRet::Abort,
+            Key::Char('\r') =>
+                if self.input.is_empty() {
+                    PathRet::Continue
+                } else {
+                    PathRet::Path(self.input.clone())
+                },
+            Key::Backspace => {
+                self.input.pop();
+                PathRet::Continue
+            }
+            Key::Tab => {
+                self.complete();
+                PathRet::Continue
+            }
+            Key::Char(ch) => {
+                self.input.push(ch);
+                PathRet::Continue
+            }
+            _ =>
+                PathRet::Continue,
+        }
+    }
+
+    fn complete(&mut self) {
+        match self.completion_idx {
+            None => {
+                self.completions = complete_path(&self.input);
+                if self.completions.is_empty() {
+                    return;
+                }
+                self.completion_idx = Some(0);
+            }
+            Some(idx) =>
+                self.completion_idx = Some((idx + 1) % self.completions.len()),
+        }
+        self.input = self.completions[self.completion_idx.unwrap()].clone();
+        // A single completion is done, the next Tab completes in the
+        // directory
+        if self.completions.len() == 1 {
+            self.completions.clear();
+            self.completion_idx = None;
+        }
+    }
+}
+
+/// Last component of a completion, with the '/' of directories.
+fn file_name(path: &str) -> &str {
+    let trimmed = path.trim_end_matches('/');
+    match trimmed.rfind('/') {
+        Some(idx) =>
+            &path[idx + 1..],
+        None =>
+            path,
+    }
+}
@@ -0,0 +1,112 @@
+use parser::pe::Pe;
+
+use gui::hex::structure::{field, StructureRow};
+
+/// Rows of the structure view: headers, section table, imports, and exports.
+pub fn rows(pe: &Pe, data: &[u8]) -> Vec<StructureRow> {
+    let mut rows = Vec::new();
+
+    rows.push(StructureRow::Heading("Headers".to_owned()));
+    rows.push(field("DOS header", "MZ".to_owned(), Some(0)));
+    rows.push(field("PE header", format!("0x{:x}", pe.pe_offset), Some(pe.pe_offset)));
+
+    let coff = pe.pe_offset + 4;
+    let header = &pe.coff_header;
+    let machine = match header.machine_str() {
+        Some(name) =>
+            format!("{} (0x{:x})", name, header.machine),
+        None =>
+            format!("0x{:x}", header.machine),
+    };
+    rows.push(field("Machine", machine, Some(coff)));
+    rows.push(field("Sections", header.number_of_sections.to_string(), Some(coff + 2)));
+    rows.push(field("Time stamp", format!("0x{:08x}", header.time_date_stamp), Some(coff + 4)));
+    rows.push(field("Characteristics", header.characteristics_str(), Some(coff + 18)));
+
+    if let Some(ref opt) = pe.optional_header {
+        let opt_offset = coff + 20;
+        let magic = if opt.is_pe32_plus() { "PE32+" } else { "PE32" };
+        rows.push(field("Magic", magic.to_owned(), Some(opt_offset)));
+        // DLLs without initialization functions have 0
+        let entry = opt.address_of_entry_point;
+        let entry_offset = if entry == 0 { None } else { pe.rva_offset(entry) };
+        rows.push(field("Entry point", format!("0x{:x}", entry), entry_offset));
+        let image_base_offset = if opt.is_pe32_plus() { 24 } else { 28 };
+        rows.push(field(
+            "Image base",
+            format!("0x{:x}", opt.image_base),
+            Some(opt_offset + image_base_offset),
+        ));
+        rows.push(field(
+            "Section alignment",
+            format!("0x{:x}", opt.section_alignment),
+            Some(opt_offset + 32),
+        ));
+        rows.push(field(
+            "File alignment",
+            format!("0x{:x}", opt.file_alignment),
+            Some(opt_offset + 36),
+        ));
+        let subsystem = match opt.subsystem_str() {
+            Some(name) =>
+                name.to_owned(),
+            None =>
+                opt.subsystem.to_string(),
+        };
+        rows.push(field("Subsystem", subsystem, Some(opt_offset + 68)));
+        rows.push(field(
+            "DLL characteristics",
+            opt.dll_characteristics_str(),
+            Some(opt_offset + 70),
+        ));
+    }
+
+    rows.push(StructureRow::Heading(format!("Sections ({})", pe.sections.len())));
+    for section in &pe.sections {
+        let value = format!(
+            "{} RVA 0x{:08x}  size 0x{:<8x} file size 0x{:x}",
+            section.perms_str(),
+            section.virtual_address,
+            section.virtual_size,
+            section.size_of_raw_data
+        );
+        let offset = if section.size_of_raw_data == 0 {
+            None
+        } else {
+            Some(section.pointer_to_raw_data as usize)
+        };
+        rows.push(field(&section.name, value, offset));
+    }
+
+    let imports = pe.imports(data);
+    rows.push(StructureRow::Heading(format!("Imports ({})", imports.len())));
+    for import in imports {
+        let value = match import.name {
+            Some(name) =>
+                name,
+            None =>
+                format!("#{}", import.ordinal),
+        };
+        rows.push(field(&import.dll, value, import.iat_offset));
+    }
+
+    let exports = pe.exports(data);
+    rows.push(StructureRow::Heading(format!("Exports ({})", exports.len())));
+    for export in exports {
+        let name = match export.name {
+            Some(name) =>
+                name,
+            None =>
+                format!("#{}", export.ordinal),
+        };
+        let (value, offset) = match export.forwarder {
+            Some(forwarder) =>
+                (format!("-> {}", forwarder), None),
+            None =>
+                (format!("0x{:x}", export.rva), pe.rva_offset(export.rva)),
+        };
+        rows.push(field(&name, value, offset));
+    }
+
+    rows
+}
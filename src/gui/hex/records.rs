@@ -0,0 +1,198 @@
+////////////////////////////////////////////////////////////////////////////////
+// Record size detection (`:records`): files of fixed-size records repeat with
+// the record size, so bytes one record apart are equal more often than bytes
+// picked at random. Candidate sizes are scored by this autocorrelation, and
+// accepting one shows a record per line.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cmp;
+
+use colors;
+use gui::hex::entropy::byte_counts;
+use utils::*;
+
+use term_input::{Arrow, Key};
+use termbox_simple::*;
+
+/// Sizes tried, in bytes.
+pub const MIN_RECORD_SIZE: usize = 2;
+pub const MAX_RECORD_SIZE: usize = 512;
+
+/// Only this many bytes are analyzed, enough for records of the maximum size
+/// to repeat many times.
+pub const SAMPLE_SIZE: usize = 64 * 1024;
+
+/// A size needs this many records in the sample to be a candidate.
+const MIN_RECORDS: usize = 4;
+
+/// Candidates with lower scores are not shown.
+const MIN_SCORE: f32 = 0.1;
+
+const MAX_CANDIDATES: usize = 10;
+
+/// Multiples of a candidate score as well as the candidate, and are dropped
+/// unless they score better by this much.
+const MULTIPLE_MARGIN: f32 = 0.05;
+
+#[derive(Clone, Copy)]
+pub struct Candidate {
+    pub size: usize,
+    /// 0.0 - 1.0.
+    pub score: f32,
+}
+
+/// Likely record sizes in `data`, best first. The score of a size is how
+/// often bytes that far apart are equal, scaled so that random bytes with the
+/// same byte frequencies score 0 and a perfect repetition 1.
+pub fn candidates(data: &[u8]) -> Vec<Candidate> {
+    let counts = byte_counts(data);
+    let total = data.len() as f32;
+    // Probability that two random bytes of the data are equal
+    let baseline: f32 = counts
+        .iter()
+        .map(|&count| {
+            let p = count as f32 / total;
+            p * p
+        })
+        .sum();
+    if baseline >= 1.0 {
+        // All bytes are the same, every size repeats
+        return Vec::new();
+    }
+
+    let max_size = cmp::min(MAX_RECORD_SIZE, data.len() / MIN_RECORDS);
+    let mut scores: Vec<Candidate> = Vec::new();
+    for size in MIN_RECORD_SIZE..max_size + 1 {
+        let pairs = data.len() - size;
+        let equal = data.iter().zip(&data[size..]).filter(|&(a, b)| a == b).count();
+        let ratio = equal as f32 / pairs as f32;
+        let score = ((ratio - baseline) / (1.0 - baseline)).max(0.0);
+        let multiple = scores.iter().any(|c| {
+            size % c.size == 0 && score < c.score + MULTIPLE_MARGIN
+        });
+        if score >= MIN_SCORE && !multiple {
+            scores.push(Candidate { size, score });
+        }
+    }
+
+    scores.sort_by(|c1, c2| c2.score.partial_cmp(&c1.score).unwrap());
+    scores.truncate(MAX_CANDIDATES);
+    scores
+}
+
+pub enum RecordsRet {
+    /// Show records of this size, one per line.
+    Accept(usize),
+
+    /// User cancelled.
+    Abort,
+
+    /// Overlay still has focus.
+    Continue,
+}
+
+/// Lists the candidate record sizes with their scores.
+pub struct RecordsOverlay {
+    pos_x: i32,
+    pos_y: i32,
+    width: i32,
+    height: i32,
+
+    candidates: Vec<Candidate>,
+
+    /// Bytes analyzed, for the title.
+    sample: usize,
+
+    selected: usize,
+}
+
+impl RecordsOverlay {
+    pub fn new(
+        width: i32,
+        height: i32,
+        pos_x: i32,
+        pos_y: i32,
+        candidates: Vec<Candidate>,
+        sample: usize,
+    ) -> RecordsOverlay {
+        let width_ = cmp::min(width, 50);
+        let height_ = cmp::min(height, MAX_CANDIDATES as i32 + 4);
+
+        let pos_x = pos_x + (width - width_) / 2;
+        let pos_y = pos_y + (height - height_) / 2;
+
+        RecordsOverlay {
+            pos_x,
+            pos_y,
+            width: width_,
+            height: height_,
+            candidates,
+            sample,
+            selected: 0,
+        }
+    }
+
+    pub fn draw(&self, tb: &mut Termbox) {
+        draw_box(tb, self.pos_x, self.pos_y, self.width, self.height);
+
+        let title = format!(" Record sizes - {} bytes analyzed ", self.sample);
+        print(tb, self.pos_x + 2, self.pos_y, colors::palette().default, &title);
+
+        let rows = (self.height - 4) as usize;
+        let first = if self.selected >= rows {
+            self.selected + 1 - rows
+        } else {
+            0
+        };
+        let bar_width = cmp::max(self.width - 24, 1) as usize;
+
+        for idx in first..cmp::min(first + rows, self.candidates.len()) {
+            let candidate = self.candidates[idx];
+            let style = if idx == self.selected {
+                colors::palette().cursor_focus
+            } else {
+                colors::palette().default
+            };
+            let filled = (candidate.score * bar_width as f32).round() as usize;
+            let line = format!(
+                "{:>5} bytes  {:>3}%  {}",
+                candidate.size,
+                (candidate.score * 100.0).round() as usize,
+                "█".repeat(filled)
+            );
+            let line: String = line.chars().take((self.width - 2) as usize).collect();
+            print(tb, self.pos_x + 1, self.pos_y + 1 + (idx - first) as i32, style, &line);
+        }
+
+        print(
+            tb,
+            self.pos_x + 1,
+            self.pos_y + self.height - 2,
+            colors::palette().default,
+            "Enter: a record per line",
+        );
+    }
+
+    pub fn keypressed(&mut self, key: Key) -> RecordsRet {
+        match key {
+            Key::Arrow(Arrow::Up) | Key::Char('k') => {
+                if self.selected > 0 {
+                    self.selected -= 1;
+                }
+                RecordsRet::Continue
+            }
+            Key::Arrow(Arrow::Down) | Key::Char('j') => {
+                if self.selected + 1 < self.candidates.len() {
+                    self.selected += 1;
+                }
+                RecordsRet::Continue
+            }
+            Key::Char('\r') =>
+                RecordsRet::Accept(self.candidates[self.selected].size),
+            Key::Esc | Key::Char('q') =>
+                RecordsRet::Abort,
+            _ =>
+                RecordsRet::Continue,
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::cmp;
+
+use colors;
+use parser::elf::Relocation;
+use utils::*;
+
+use term_input::{Arrow, Key};
+use termbox_simple::*;
+
+pub enum RelocationsRet {
+    /// Jump to the byte offset.
+    Jump(usize),
+
+    /// User cancelled.
+    Abort,
+
+    /// Overlay still has focus.
+    Continue,
+}
+
+/// Lists ELF relocations. Typing filters the list by symbol and type names,
+/// Enter jumps to the location the selected relocation modifies.
+pub struct RelocationsOverlay {
+    pos_x: i32,
+    pos_y: i32,
+    width: i32,
+    height: i32,
+
+    /// Relocations and file offsets of their locations.
+    relocations: Vec<(Relocation, Option<usize>)>,
+
+    filter: String,
+
+    /// Indices of relocations matching the filter.
+    shown: Vec<usize>,
+
+    /// Index in `shown`.
+    selected: usize,
+}
+
+fn type_str(rel: &Relocation) -> String {
+    match rel.type_name {
+        Some(name) =>
+            name.to_owned(),
+        None =>
+            format!("{}", rel.ty),
+    }
+}
+
+/// Symbol and addend, like "puts + 0".
+fn target_str(rel: &Relocation) -> String {
+    let mut ret = match rel.symbol_name {
+        Some(ref name) if !name.is_empty() =>
+            name.clone(),
+        _ if rel.sym != 0 =>
+            format!("sym#{}", rel.sym),
+        _ =>
+            String::new(),
+    };
+    if let Some(addend) = rel.addend {
+        if !ret.is_empty() {
+            ret.push(' ');
+        }
+        if addend < 0 {
+            ret.push_str(&format!("- 0x{:x}", (addend.wrapping_neg() as u64)));
+        } else {
+            ret.push_str(&format!("+ 0x{:x}", addend));
+        }
+    }
+    ret
+}
+
+impl RelocationsOverlay {
+    pub fn new(
+        width: i32,
+        height: i32,
+        pos_x: i32,
+        pos_y: i32,
+        relocations: Vec<(Relocation, Option<usize>)>,
+    ) -> RelocationsOverlay {
+        let width_ = cmp::min(width, 100);
+        let height_ = cmp::min(height, 30);
+
+        let pos_x = pos_x + (width - width_) / 2;
+        let pos_y = pos_y + (height - height_) / 2;
+
+        let shown = (0..relocations.len()).collect();
+        RelocationsOverlay {
+            pos_x,
+            pos_y,
+            width: width_,
+            height: height_,
+            relocations,
+            filter: String::new(),
+            shown,
+            selected: 0,
+        }
+    }
+
+    fn update_filter(&mut self) {
+        let filter = &self.filter;
+        self.shown = self.relocations
+            .iter()
+            .enumerate()
+            .filter(|(_, (rel, _))| {
+                rel.symbol_name.as_ref().is_some_and(|name| name.contains(filter.as_str()))
+                    || type_str(rel).contains(filter.as_str())
+            })
+            .map(|(idx, _)| idx)
+            .collect();
+        self.selected = 0;
+    }
+
+    pub fn draw(&self, tb: &mut Termbox) {
+        draw_box(tb, self.pos_x, self.pos_y, self.width, self.height);
+
+        let title = format!(" Relocations ({}/{}) ", self.shown.len(), self.relocations.len());
+        print(tb, self.pos_x + 2, self.pos_y, colors::palette().default, &title);
+
+        let rows = (self.height - 4) as usize;
+        let first = if self.selected >= rows {
+            self.selected + 1 - rows
+        } else {
+            0
+        };
+
+        for (row, &idx) in self.shown.iter().enumerate().skip(first).take(rows) {
+            let style = if row == self.selected {
+                colors::palette().cursor_focus
+            } else {
+                colors::palette().default
+            };
+            let (ref rel, offset) = self.relocations[idx];
+            let offset = match offset {
+                Some(offset) =>
+                    format!("0x{:08x}", offset),
+                None =>
+                    "-".to_owned(),
+            };
+            let line = format!(
+                "0x{:08x} {:<10} {:<22} {:<14} {}",
+                rel.offset,
+                offset,
+                type_str(rel),
+                rel.section,
+                target_str(rel)
+            );
+            let line: String = line.chars().take((self.width - 2) as usize).collect();
+            print(tb, self.pos_x + 1, self.pos_y + 1 + (row - first) as i32, style, &line);
+        }
+
+        let bottom = self.pos_y + self.height - 2;
+        print(tb, self.pos_x + 1, bottom, colors::palette().default, "Filter: ");
+        print(tb, self.pos_x + 9, bottom, colors::palette().default, &self.filter);
+        tb.change_cell(
+            self.pos_x + 9 + self.filter.chars().count() as i32,
+            bottom,
+            ' ',
+            colors::palette().cursor_focus.fg,
+            colors::palette().cursor_focus.bg,
+        );
+    }
+
+    pub fn keypressed(&mut self, key: Key) -> RelocationsRet {
+        match key {
+            // Letters go to the filter, so only arrows and Ctrl-p/n move
+            Key::Arrow(Arrow::Up) | Key::Ctrl('p') => {
+                if self.selected > 0 {
+                    self.selected -= 1;
+                }
+                RelocationsRet::Continue
+            }
+            Key::Arrow(Arrow::Down) | Key::Ctrl('n') => {
+                if self.selected + 1 < self.shown.len() {
+                    self.selected += 1;
+                }
+                RelocationsRet::Continue
+            }
+            Key::Char('\r') =>
+                match self.shown.get(self.selected).and_then(|&idx| self.relocations[idx].1) {
+                    Some(offset) =>
+                        RelocationsRet::Jump(offset),
+                    None =>
+                        RelocationsRet::Continue,
+                },
+            Key::Esc =>
+                RelocationsRet::Abort,
+            Key::Backspace => {
+                self.filter.pop();
+                self.update_filter();
+                RelocationsRet::Continue
+            }
+            Key::Char(ch) => {
+                self.filter.push(ch);
+                self.update_filter();
+                RelocationsRet::Continue
+            }
+            _ =>
+                RelocationsRet::Continue,
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use parser::Report;
+
+use gui::hex::structure::{field, StructureRow};
+
+/// Rows of the parse error list (`:report`), one group per error. Enter jumps
+/// to the offset of the error.
+pub fn rows(reports: &[Report]) -> Vec<StructureRow> {
+    let mut rows = Vec::new();
+    for report in reports {
+        rows.push(StructureRow::Heading(format!("{}: {}", report.parser, report.message)));
+        rows.push(field("Stage", report.stage.clone(), report.offset));
+        rows.push(field("Expected", report.expected.clone(), report.offset));
+        rows.push(field("Actual", report.actual.clone(), report.offset));
+    }
+    rows
+}
@@ -0,0 +1,17 @@
+use detect::Embedded;
+
+use gui::hex::structure::{field, StructureRow};
+
+/// Rows of the list of embedded files (`:scan`). Enter jumps to the file.
+pub fn rows(found: &[Embedded], capped: bool) -> Vec<StructureRow> {
+    let mut rows = Vec::with_capacity(found.len() + 1);
+    rows.push(StructureRow::Heading(format!(
+        "Signatures ({}{})",
+        found.len(),
+        if capped { ", stopped there" } else { "" }
+    )));
+    for embedded in found {
+        rows.push(field(embedded.kind, embedded.info.clone(), Some(embedded.offset)));
+    }
+    rows
+}
@@ -7,17 +7,8 @@ use term_input::Key;
 use termbox_simple::*;
 
 pub enum SearchRet {
-    /// Highlight these bytes.
-    Highlight {
-        /// Byte in focus.
-        focus: usize,
-
-        /// All matching byte offsets.
-        all_bytes: Vec<usize>,
-
-        /// Length of searched bytes.
-        len: usize,
-    },
+    /// Search for the pattern.
+    Search(Pattern, SearchScope),
 
     /// User cancelled.
     Abort,
@@ -26,6 +17,15 @@ pub enum SearchRet {
     Continue,
 }
 
+/// Where to search.
+#[derive(Clone, Copy, PartialEq)]
+pub enum SearchScope {
+    File,
+
+    /// Only the bytes on the screen, or the selection when there is one.
+    View,
+}
+
 enum SearchMode {
     Ascii,
     Hex,
@@ -38,7 +38,93 @@ enum NibbleCursor {
     LS,
 }
 
-pub struct SearchOverlay<'overlay> {
+/// A search query.
+#[derive(Clone)]
+pub struct Pattern {
+    bytes: Vec<u8>,
+
+    /// Ignore case of ASCII letters when matching.
+    ignore_case: bool,
+}
+
+impl Pattern {
+    pub fn new(bytes: Vec<u8>, ignore_case: bool) -> Pattern {
+        Pattern { bytes, ignore_case }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Find non-overlapping matches, at most `max` of them. The `bool` is
+    /// `true` when there are more matches than `max`.
+    pub fn find_all(&self, contents: &[u8], max: usize) -> (Vec<usize>, bool) {
+        let mut ret = Vec::new();
+
+        let first_byte = self.bytes[0];
+
+        // It seems like Vec API doesn't help us here. As a first
+        // implementation, I do a O(n * k) search here.
+        let mut byte_offset = 0;
+        while byte_offset < contents.len() {
+            let byte = unsafe { *contents.get_unchecked(byte_offset) };
+            if self.byte_eq(byte, first_byte)
+                && self.try_match(&contents[byte_offset + 1..], &self.bytes[1..])
+            {
+                if ret.len() == max {
+                    return (ret, true);
+                }
+                ret.push(byte_offset);
+                byte_offset += self.bytes.len();
+                continue;
+            }
+
+            byte_offset += 1;
+        }
+
+        (ret, false)
+    }
+
+    /// First match at or after `from`.
+    pub fn find_next(&self, contents: &[u8], from: usize) -> Option<usize> {
+        (from..contents.len()).find(|&offset| self.matches_at(contents, offset))
+    }
+
+    /// Last match before `before`.
+    pub fn find_prev(&self, contents: &[u8], before: usize) -> Option<usize> {
+        (0..cmp::min(before, contents.len()))
+            .rev()
+            .find(|&offset| self.matches_at(contents, offset))
+    }
+
+    fn matches_at(&self, contents: &[u8], offset: usize) -> bool {
+        self.try_match(&contents[offset..], &self.bytes)
+    }
+
+    fn byte_eq(&self, byte1: u8, byte2: u8) -> bool {
+        if self.ignore_case {
+            byte1.eq_ignore_ascii_case(&byte2)
+        } else {
+            byte1 == byte2
+        }
+    }
+
+    fn try_match(&self, s1: &[u8], s2: &[u8]) -> bool {
+        if s2.len() > s1.len() {
+            false
+        } else {
+            for (byte1, byte2) in s1.iter().zip(s2.iter()) {
+                if !self.byte_eq(*byte1, *byte2) {
+                    return false;
+                }
+            }
+
+            true
+        }
+    }
+}
+
+pub struct SearchOverlay {
     pos_x: i32,
     pos_y: i32,
     width: i32,
@@ -52,17 +138,24 @@ pub struct SearchOverlay<'overlay> {
     byte_cursor: usize,
     nibble_cursor: NibbleCursor,
 
-    contents: &'overlay [u8],
+    /// Ignore case of ASCII letters when matching.
+    ignore_case: bool,
+
+    /// Ctrl-v toggles.
+    scope: SearchScope,
+
+    /// For the scope indicator, the view is the selection.
+    has_selection: bool,
 }
 
-impl<'overlay> SearchOverlay<'overlay> {
+impl SearchOverlay {
     pub fn new(
         width: i32,
         height: i32,
         pos_x: i32,
         pos_y: i32,
-        contents: &'overlay [u8],
-    ) -> SearchOverlay<'overlay> {
+        has_selection: bool,
+    ) -> SearchOverlay {
         let width_ = cmp::min(width, 50);
         let height_ = cmp::min(height, 10);
 
@@ -79,8 +172,9 @@ impl<'overlay> SearchOverlay<'overlay> {
             buffer: Vec::new(),
             byte_cursor: 0,
             nibble_cursor: NibbleCursor::MS,
-
-            contents: contents,
+            ignore_case: false,
+            scope: SearchScope::File,
+            has_selection,
         }
     }
 
@@ -90,26 +184,50 @@ impl<'overlay> SearchOverlay<'overlay> {
             self.pos_x + self.width / 2,
             self.pos_y,
             '┬',
-            colors::DEFAULT.fg,
-            colors::DEFAULT.bg,
+            colors::palette().default.fg,
+            colors::palette().default.bg,
         );
         for y in 1..self.height - 1 {
             tb.change_cell(
                 self.pos_x + self.width / 2,
                 self.pos_y + y,
                 '│',
-                colors::DEFAULT.fg,
-                colors::DEFAULT.bg,
+                colors::palette().default.fg,
+                colors::palette().default.bg,
             );
         }
         tb.change_cell(
             self.pos_x + self.width / 2,
             self.pos_y + self.height - 1,
             '┴',
-            colors::DEFAULT.fg,
-            colors::DEFAULT.bg,
+            colors::palette().default.fg,
+            colors::palette().default.bg,
         );
 
+        let case_str = if self.ignore_case {
+            " ignore case "
+        } else {
+            " match case "
+        };
+        let bottom = self.pos_y + self.height - 1;
+        print(tb, self.pos_x + 2, bottom, colors::palette().default, case_str);
+
+        let scope_str = match self.scope {
+            SearchScope::File =>
+                " whole file ",
+            SearchScope::View if self.has_selection =>
+                " selection only ",
+            SearchScope::View =>
+                " screen only ",
+        };
+        let style = match self.scope {
+            SearchScope::File =>
+                colors::palette().default,
+            SearchScope::View =>
+                colors::palette().highlight,
+        };
+        print(tb, self.pos_x + self.width / 2 + 2, self.pos_y + self.height - 1, style, scope_str);
+
         self.draw_hex(tb);
         self.draw_ascii(tb);
     }
@@ -119,17 +237,17 @@ impl<'overlay> SearchOverlay<'overlay> {
         // Not the most efficient way to draw, but be fine at this scale
         // (e.g. for a couple of characters at most)
         let width = ((self.width - 1) / 2) as usize;
-        for (byte_offset, byte) in self.buffer.iter().enumerate() {
+        let chars = ascii_chars(&self.buffer);
+        for (byte_offset, ch) in chars.iter().enumerate() {
             let pos_x = ((byte_offset % width) + 1) as i32;
             let pos_y = ((byte_offset / width) + 1) as i32;
 
-
             tb.change_cell(
                 self.pos_x + pos_x,
                 self.pos_y + pos_y,
-                *byte as char,
-                colors::DEFAULT.fg,
-                colors::DEFAULT.bg,
+                *ch,
+                colors::palette().default.fg,
+                colors::palette().default.bg,
             );
         }
 
@@ -137,23 +255,23 @@ impl<'overlay> SearchOverlay<'overlay> {
         let cursor_x = (self.byte_cursor % width) + 1;
         let cursor_y = self.byte_cursor / width;
 
-        let byte = if self.byte_cursor >= self.buffer.len() {
-            b' '
+        let ch = if self.byte_cursor >= chars.len() {
+            ' '
         } else {
-            self.buffer[self.byte_cursor]
+            chars[self.byte_cursor]
         };
 
         let cursor_style = match self.mode {
             SearchMode::Ascii =>
-                colors::CURSOR_FOCUS,
+                colors::palette().cursor_focus,
             SearchMode::Hex =>
-                colors::CURSOR_NO_FOCUS,
+                colors::palette().cursor_no_focus,
         };
 
         tb.change_cell(
             self.pos_x + cursor_x as i32,
             self.pos_y + cursor_y as i32 + 1,
-            byte as char,
+            ch,
             cursor_style.fg,
             cursor_style.bg,
         );
@@ -183,15 +301,15 @@ impl<'overlay> SearchOverlay<'overlay> {
                 self.pos_x + start_column + col,
                 self.pos_y + row,
                 nibble1 as char,
-                colors::DEFAULT.fg,
-                colors::DEFAULT.bg,
+                colors::palette().default.fg,
+                colors::palette().default.bg,
             );
             tb.change_cell(
                 self.pos_x + start_column + col + 1,
                 self.pos_y + row,
                 nibble2 as char,
-                colors::DEFAULT.fg,
-                colors::DEFAULT.bg,
+                colors::palette().default.fg,
+                colors::palette().default.bg,
             );
 
             col += 3;
@@ -223,9 +341,9 @@ impl<'overlay> SearchOverlay<'overlay> {
 
         let cursor_style = match self.mode {
             SearchMode::Hex =>
-                colors::CURSOR_FOCUS,
+                colors::palette().cursor_focus,
             SearchMode::Ascii =>
-                colors::CURSOR_NO_FOCUS,
+                colors::palette().cursor_no_focus,
         };
 
         tb.change_cell(
@@ -247,15 +365,26 @@ impl<'overlay> SearchOverlay<'overlay> {
             }
             Key::Char('\r') => {
                 if !self.buffer.is_empty() {
-                    // do the search
-                    let offsets = self.find_offsets();
-                    return SearchRet::Highlight {
-                        focus: self.byte_cursor,
-                        all_bytes: offsets,
-                        len: self.buffer.len(),
-                    };
+                    return SearchRet::Search(
+                        Pattern {
+                            bytes: self.buffer.clone(),
+                            ignore_case: self.ignore_case,
+                        },
+                        self.scope,
+                    );
                 }
             }
+            Key::Ctrl('t') => {
+                self.ignore_case = !self.ignore_case;
+            }
+            Key::Ctrl('v') => {
+                self.scope = match self.scope {
+                    SearchScope::File =>
+                        SearchScope::View,
+                    SearchScope::View =>
+                        SearchScope::File,
+                };
+            }
             Key::Tab => {
                 let new_sm = match self.mode {
                     SearchMode::Ascii =>
@@ -267,15 +396,19 @@ impl<'overlay> SearchOverlay<'overlay> {
             }
             Key::Backspace =>
                 match self.mode {
-                    SearchMode::Ascii =>
-                        match self.buffer.pop() {
-                            None =>
-                                {}
-                            Some(_) =>
-                                if self.byte_cursor != 0 {
-                                    self.byte_cursor -= 1;
-                                },
-                        },
+                    SearchMode::Ascii => {
+                        // Remove a whole UTF-8 encoded character: continuation
+                        // bytes, then the first byte
+                        while let Some(&byte) = self.buffer.last() {
+                            if byte & 0b1100_0000 != 0b1000_0000 {
+                                break;
+                            }
+                            self.buffer.pop();
+                        }
+                        self.buffer.pop();
+                        self.byte_cursor = self.buffer.len();
+                        self.nibble_cursor = NibbleCursor::MS;
+                    }
                     SearchMode::Hex =>
                         match self.nibble_cursor {
                             NibbleCursor::LS => {
@@ -303,17 +436,17 @@ impl<'overlay> SearchOverlay<'overlay> {
                                 },
                         },
                 },
-            Key::Char(ch) => {
-                // FIXME non-ascii chars
-                let ch = ch as u32;
+            Key::Char(ch) =>
                 match self.mode {
-                    SearchMode::Ascii =>
-                        if ch <= 0xFF {
-                            self.buffer.push(ch as u8);
-                            self.byte_cursor += 1;
-                            self.nibble_cursor = NibbleCursor::MS;
-                        },
+                    SearchMode::Ascii => {
+                        let mut bytes = [0; 4];
+                        let bytes = ch.encode_utf8(&mut bytes).as_bytes();
+                        self.buffer.extend_from_slice(bytes);
+                        self.byte_cursor = self.buffer.len();
+                        self.nibble_cursor = NibbleCursor::MS;
+                    }
                     SearchMode::Hex => {
+                        let ch = ch as u32;
                         let nibble = match ch {
                             65...70 => {
                                 // A ... F
@@ -362,50 +495,57 @@ impl<'overlay> SearchOverlay<'overlay> {
                             }
                         }
                     }
-                }
-            }
+                },
             _ =>
                 {}
         }
 
         SearchRet::Continue
     }
+}
 
-    fn find_offsets(&self) -> Vec<usize> {
-        let mut ret = Vec::new();
-
-        let first_byte = self.buffer[0];
-
-        // It seems like Vec API doesn't help us here. As a first
-        // implementation, I do a O(n * k) search here.
-        let mut byte_offset = 0;
-        while byte_offset < self.contents.len() {
-            let byte = unsafe { *self.contents.get_unchecked(byte_offset) };
-            if byte == first_byte && try_match(&self.contents[byte_offset + 1..], &self.buffer[1..])
-            {
-                ret.push(byte_offset);
-                byte_offset += self.buffer.len();
-                continue;
-            }
-
-            byte_offset += 1;
+/// Characters to show for the bytes in the ASCII part, one for each byte.
+/// UTF-8 encoded characters are shown at their first byte, followed by spaces
+/// for the rest of the bytes. Other non-printable bytes are shown as '.'.
+fn ascii_chars(bytes: &[u8]) -> Vec<char> {
+    let mut ret = Vec::with_capacity(bytes.len());
+    let mut byte_idx = 0;
+    while byte_idx < bytes.len() {
+        let byte = bytes[byte_idx];
+        if (32..=126).contains(&byte) {
+            ret.push(byte as char);
+            byte_idx += 1;
+            continue;
         }
 
-        // writeln!(&mut ::std::io::stderr(), "find_offsets: {:?}", ret);
-        ret
-    }
-}
+        let char_len = match byte {
+            0b1100_0000..=0b1101_1111 =>
+                2,
+            0b1110_0000..=0b1110_1111 =>
+                3,
+            0b1111_0000..=0b1111_0111 =>
+                4,
+            _ =>
+                0,
+        };
 
-fn try_match(s1: &[u8], s2: &[u8]) -> bool {
-    if s2.len() > s1.len() {
-        false
-    } else {
-        for (byte1, byte2) in s1.iter().zip(s2.iter()) {
-            if byte1 != byte2 {
-                return false;
+        let decoded = bytes
+            .get(byte_idx..byte_idx + char_len)
+            .and_then(|char_bytes| ::std::str::from_utf8(char_bytes).ok())
+            .and_then(|str| str.chars().next());
+
+        match decoded {
+            Some(ch) => {
+                ret.push(ch);
+                let len = ret.len() + char_len - 1;
+                ret.resize(len, ' ');
+                byte_idx += char_len;
+            }
+            None => {
+                ret.push('.');
+                byte_idx += 1;
             }
         }
-
-        true
     }
+    ret
 }
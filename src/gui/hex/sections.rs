@@ -0,0 +1,233 @@
+use std::cmp;
+
+use colors;
+use parser::elf::{Elf, SectionType};
+use utils::*;
+
+use term_input::{Arrow, Key};
+use termbox_simple::*;
+
+pub enum SectionsRet {
+    /// Jump to the byte offset.
+    Jump(usize),
+
+    /// User cancelled.
+    Abort,
+
+    /// Overlay still has focus.
+    Continue,
+}
+
+/// A section or a segment.
+pub struct Entry {
+    /// "section" or "segment".
+    kind: &'static str,
+    name: String,
+    offset: usize,
+    size: usize,
+}
+
+/// Sections and segments (program headers) of an ELF file, in the order of
+/// their headers. `SHT_NULL` sections are skipped.
+pub fn entries(elf: &Elf) -> Vec<Entry> {
+    let mut ret = Vec::new();
+    for sh in &elf.section_headers {
+        if sh.sh_type == SectionType::Null {
+            continue;
+        }
+        ret.push(Entry {
+            kind: "section",
+            name: sh.name.clone(),
+            offset: sh.sh_offset as usize,
+            // No bytes in the file
+            size: if sh.sh_type == SectionType::Nobits { 0 } else { sh.sh_size as usize },
+        });
+    }
+    for (idx, ph) in elf.program_headers.iter().enumerate() {
+        ret.push(Entry {
+            kind: "segment",
+            name: format!("{} {}", idx, ph.p_type.name()),
+            offset: ph.p_offset as usize,
+            size: ph.p_filesz as usize,
+        });
+    }
+    ret
+}
+
+/// Lists sections and segments. Typing filters the list by name (fuzzy: the
+/// typed characters in order, case-insensitive), closer matches first. Enter
+/// jumps to the selected one.
+pub struct SectionsOverlay {
+    pos_x: i32,
+    pos_y: i32,
+    width: i32,
+    height: i32,
+
+    entries: Vec<Entry>,
+
+    filter: String,
+
+    /// Indices of entries matching the filter.
+    shown: Vec<usize>,
+
+    /// Index in `shown`.
+    selected: usize,
+}
+
+impl SectionsOverlay {
+    pub fn new(
+        width: i32,
+        height: i32,
+        pos_x: i32,
+        pos_y: i32,
+        entries: Vec<Entry>,
+    ) -> SectionsOverlay {
+        let width_ = cmp::min(width, 80);
+        let height_ = cmp::min(height, 30);
+
+        let pos_x = pos_x + (width - width_) / 2;
+        let pos_y = pos_y + (height - height_) / 2;
+
+        let shown = (0..entries.len()).collect();
+        SectionsOverlay {
+            pos_x,
+            pos_y,
+            width: width_,
+            height: height_,
+            entries,
+            filter: String::new(),
+            shown,
+            selected: 0,
+        }
+    }
+
+    fn update_filter(&mut self) {
+        let filter = self.filter.to_lowercase();
+        let mut matches: Vec<(usize, usize)> = self.entries
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, entry)| fuzzy_span(&filter, &entry.name).map(|span| (span, idx)))
+            .collect();
+        // Stable, entries with the same span stay in order
+        matches.sort_by_key(|&(span, _)| span);
+        self.shown = matches.into_iter().map(|(_, idx)| idx).collect();
+        self.selected = 0;
+    }
+
+    pub fn draw(&self, tb: &mut Termbox) {
+        draw_box(tb, self.pos_x, self.pos_y, self.width, self.height);
+
+        let title = format!(
+            " Sections and segments ({}/{}) ",
+            self.shown.len(),
+            self.entries.len()
+        );
+        print(tb, self.pos_x + 2, self.pos_y, colors::palette().default, &title);
+
+        let rows = (self.height - 4) as usize;
+        let first = if self.selected >= rows {
+            self.selected + 1 - rows
+        } else {
+            0
+        };
+
+        for (row, &idx) in self.shown.iter().enumerate().skip(first).take(rows) {
+            let style = if row == self.selected {
+                colors::palette().cursor_focus
+            } else {
+                colors::palette().default
+            };
+            let entry = &self.entries[idx];
+            let line = format!(
+                "{:<7} 0x{:08x} {:>10} {}",
+                entry.kind,
+                entry.offset,
+                entry.size,
+                entry.name
+            );
+            let line: String = line.chars().take((self.width - 2) as usize).collect();
+            print(tb, self.pos_x + 1, self.pos_y + 1 + (row - first) as i32, style, &line);
+        }
+
+        let bottom = self.pos_y + self.height - 2;
+        print(tb, self.pos_x + 1, bottom, colors::palette().default, "Filter: ");
+        print(tb, self.pos_x + 9, bottom, colors::palette().default, &self.filter);
+        tb.change_cell(
+            self.pos_x + 9 + self.filter.chars().count() as i32,
+            bottom,
+            ' ',
+            colors::palette().cursor_focus.fg,
+            colors::palette().cursor_focus.bg,
+        );
+    }
+
+    pub fn keypressed(&mut self, key: Key) -> SectionsRet {
+        match key {
+            // Letters go to the filter, so only arrows and Ctrl-p/n move
+            Key::Arrow(Arrow::Up) | Key::Ctrl('p') => {
+                if self.selected > 0 {
+                    self.selected -= 1;
+                }
+                SectionsRet::Continue
+            }
+            Key::Arrow(Arrow::Down) | Key::Ctrl('n') => {
+                if self.selected + 1 < self.shown.len() {
+                    self.selected += 1;
+                }
+                SectionsRet::Continue
+            }
+            Key::Char('\r') =>
+                match self.shown.get(self.selected) {
+                    Some(&idx) =>
+                        SectionsRet::Jump(self.entries[idx].offset),
+                    None =>
+                        SectionsRet::Continue,
+                },
+            Key::Esc =>
+                SectionsRet::Abort,
+            Key::Backspace => {
+                self.filter.pop();
+                self.update_filter();
+                SectionsRet::Continue
+            }
+            Key::Char(ch) => {
+                self.filter.push(ch);
+                self.update_filter();
+                SectionsRet::Continue
+            }
+            _ =>
+                SectionsRet::Continue,
+        }
+    }
+}
+
+/// Length of the shortest part of `name` that has the characters of `filter`
+/// (lowercase) in order, or `None` if it doesn't have them.
+fn fuzzy_span(filter: &str, name: &str) -> Option<usize> {
+    if filter.is_empty() {
+        return Some(0);
+    }
+    let name: Vec<char> = name.to_lowercase().chars().collect();
+    let filter: Vec<char> = filter.chars().collect();
+    let mut best: Option<usize> = None;
+    for start in 0..name.len() {
+        if name[start] != filter[0] {
+            continue;
+        }
+        let mut matched = 1;
+        let mut end = start + 1;
+        while matched < filter.len() && end < name.len() {
+            if name[end] == filter[matched] {
+                matched += 1;
+            }
+            end += 1;
+        }
+        if matched < filter.len() {
+            // Later starts can't match either
+            break;
+        }
+        let span = end - start;
+        best = Some(best.map_or(span, |best| cmp::min(best, span)));
+    }
+    best
+}
@@ -0,0 +1,86 @@
+////////////////////////////////////////////////////////////////////////////////
+// State of a file's view, saved on quit and restored when the file is opened
+// again, like vim's viminfo: the cursor, the scroll position, and the `:set`
+// options. Stored in the session format (see `persist::Session`) in
+// `sessions`. Marks have their own sidecar, see `marks`.
+////////////////////////////////////////////////////////////////////////////////
+
+use persist::{self, Record, Session};
+
+const KIND: &str = "sessions";
+
+pub struct ViewState {
+    /// Byte offset.
+    pub cursor: usize,
+
+    /// Line at the top of the view.
+    pub scroll: usize,
+
+    /// `:set` options, as `<name>=<value>`.
+    pub options: Vec<String>,
+
+    /// Records we don't know, e.g. written by a newer rhex. Written back as
+    /// they are.
+    other: Vec<Record>,
+}
+
+impl ViewState {
+    pub fn new(cursor: usize, scroll: usize, options: Vec<String>) -> ViewState {
+        ViewState {
+            cursor,
+            scroll,
+            options,
+            other: Vec::new(),
+        }
+    }
+
+    /// State of the file when it was last closed. `None` when there isn't
+    /// one, or it can't be read.
+    pub fn load(path: &str) -> Option<ViewState> {
+        let session = match persist::load_session(KIND, path) {
+            Ok(Some(session)) =>
+                session,
+            _ =>
+                return None,
+        };
+        let mut ret = ViewState::new(0, 0, Vec::new());
+        for record in session.records {
+            match record.tag.as_str() {
+                "cursor" =>
+                    if let Ok(cursor) = record.fields.parse() {
+                        ret.cursor = cursor;
+                    },
+                "scroll" =>
+                    if let Ok(scroll) = record.fields.parse() {
+                        ret.scroll = scroll;
+                    },
+                "set" =>
+                    ret.options.push(record.fields),
+                _ =>
+                    ret.other.push(record),
+            }
+        }
+        Some(ret)
+    }
+
+    /// Errors are ignored, the file just opens at the start next time.
+    pub fn save(&self, path: &str) {
+        let mut session = Session::new();
+        session.records.push(Record {
+            tag: "cursor".to_owned(),
+            fields: self.cursor.to_string(),
+        });
+        session.records.push(Record {
+            tag: "scroll".to_owned(),
+            fields: self.scroll.to_string(),
+        });
+        for option in &self.options {
+            session.records.push(Record {
+                tag: "set".to_owned(),
+                fields: option.clone(),
+            });
+        }
+        session.records.extend(self.other.iter().cloned());
+        let _ = persist::save_session(KIND, path, &session);
+    }
+}
@@ -0,0 +1,38 @@
+////////////////////////////////////////////////////////////////////////////////
+// Squeeze mode (`--squeeze`, `:set squeeze=on`): like `hexdump` and `xxd -a`,
+// lines that are the same as the line before them (e.g. zero padding) are
+// shown as a single `*` row. The cursor skips these rows. When it's moved to
+// a byte in a squeezed line (e.g. with goto or search) that line is shown.
+////////////////////////////////////////////////////////////////////////////////
+
+/// A row of the hex view, offset column, and ASCII view.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Row {
+    /// Line number (line `n` starts at byte `n * bytes_per_line`).
+    Line(usize),
+
+    /// Lines that are the same as the line before them.
+    Squeezed,
+}
+
+/// Ranges of lines (start inclusive, end exclusive) that are the same as the
+/// line before the range. Only full lines are squeezed.
+pub fn squeezed_lines(data: &[u8], bytes_per_line: usize) -> Vec<(usize, usize)> {
+    let mut ret: Vec<(usize, usize)> = Vec::new();
+    if bytes_per_line == 0 {
+        return ret;
+    }
+    let full_lines = data.len() / bytes_per_line;
+    for line in 1..full_lines {
+        let start = line * bytes_per_line;
+        if data[start..start + bytes_per_line] == data[start - bytes_per_line..start] {
+            match ret.last_mut() {
+                Some(last) if last.1 == line =>
+                    last.1 = line + 1,
+                _ =>
+                    ret.push((line, line + 1)),
+            }
+        }
+    }
+    ret
+}
@@ -0,0 +1,144 @@
+////////////////////////////////////////////////////////////////////////////////
+// Info line format strings (`--status`), like vim's 'statusline'.
+//
+//   %f  path                      %z  file size in bytes
+//   %m  [+] when modified         %p  cursor position in the file, in percent
+//   %i  -- INSERT -- in insert    %s  selection size in bytes, empty without
+//       mode                          a selection
+//   %o  offset in hex             %v  byte under the cursor in hex
+//   %d  offset in decimal         %V  byte under the cursor in decimal
+//   %a  address (with --base)     %r  row, %c column, %S scroll
+//   %n  search match under the    %e  part of the ELF file under the
+//       cursor, e.g. 3/17             cursor, e.g. section .text
+//   %t  file type, e.g. PNG       %%  a '%'
+//
+// Other characters, and '%' followed by anything else, are shown as is.
+////////////////////////////////////////////////////////////////////////////////
+
+/// What the info line can show.
+pub struct Status<'a> {
+    pub path: &'a str,
+    pub modified: bool,
+    pub insert: bool,
+    pub offset: usize,
+    pub base: usize,
+    pub len: usize,
+    pub selection: Option<(usize, usize)>,
+    pub value: Option<u8>,
+    pub row: usize,
+    pub column: i32,
+    pub scroll: usize,
+    /// Index (from 1) of the search match at the cursor, and the number of
+    /// matches.
+    pub search_match: Option<(usize, usize)>,
+    /// There are more matches than highlighted (`--max-matches`).
+    pub matches_capped: bool,
+    pub elf_region: Option<&'a str>,
+    /// Detected from the magic number.
+    pub file_kind: Option<&'a str>,
+}
+
+pub fn format(fmt: &str, status: &Status) -> String {
+    let mut ret = String::new();
+    let mut chars = fmt.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            ret.push(ch);
+            continue;
+        }
+        let field = match chars.next() {
+            Some(field) =>
+                field,
+            None => {
+                ret.push('%');
+                break;
+            }
+        };
+        match field {
+            'f' =>
+                ret.push_str(status.path),
+            'm' =>
+                if status.modified {
+                    ret.push_str("[+]");
+                },
+            'i' =>
+                if status.insert {
+                    ret.push_str("-- INSERT --");
+                },
+            'o' =>
+                ret.push_str(&format!("0x{:x}", status.offset)),
+            'd' =>
+                ret.push_str(&status.offset.to_string()),
+            'a' =>
+                ret.push_str(&format!("0x{:x}", status.base + status.offset)),
+            'z' =>
+                ret.push_str(&status.len.to_string()),
+            'p' => {
+                let percent = if status.len <= 1 {
+                    100
+                } else {
+                    status.offset * 100 / (status.len - 1)
+                };
+                ret.push_str(&format!("{}%", percent));
+            }
+            's' =>
+                if let Some((start, end)) = status.selection {
+                    ret.push_str(&(end - start).to_string());
+                },
+            'v' =>
+                if let Some(value) = status.value {
+                    ret.push_str(&format!("0x{:02x}", value));
+                },
+            'V' =>
+                if let Some(value) = status.value {
+                    ret.push_str(&value.to_string());
+                },
+            'r' =>
+                ret.push_str(&status.row.to_string()),
+            'c' =>
+                ret.push_str(&status.column.to_string()),
+            'S' =>
+                ret.push_str(&status.scroll.to_string()),
+            'n' =>
+                if let Some((idx, total)) = status.search_match {
+                    ret.push_str(&format!(
+                        "{}/{}{}",
+                        idx,
+                        total,
+                        if status.matches_capped { "+" } else { "" }
+                    ));
+                },
+            'e' =>
+                ret.push_str(status.elf_region.unwrap_or("")),
+            't' =>
+                ret.push_str(status.file_kind.unwrap_or("")),
+            '%' =>
+                ret.push('%'),
+            _ => {
+                ret.push('%');
+                ret.push(field);
+            }
+        }
+    }
+    ret
+}
+
+/// Fields in `fmt` that `format` doesn't know, shown as is.
+pub fn unknown_fields(fmt: &str) -> Vec<char> {
+    let mut ret = Vec::new();
+    let mut chars = fmt.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            continue;
+        }
+        match chars.next() {
+            Some(field) =>
+                if !"fmioadzpsvVrcSnet%".contains(field) {
+                    ret.push(field);
+                },
+            None =>
+                break,
+        }
+    }
+    ret
+}
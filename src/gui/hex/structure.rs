@@ -0,0 +1,161 @@
+use std::cmp;
+
+use colors;
+use utils::*;
+
+use term_input::{Arrow, Key};
+use termbox_simple::*;
+
+pub enum StructureRet {
+    /// Jump to the byte offset.
+    Jump(usize),
+
+    /// User cancelled.
+    Abort,
+
+    /// Overlay still has focus.
+    Continue,
+}
+
+/// A row of a structure view.
+pub enum StructureRow {
+    /// Title of a group of rows, e.g. "Sections (5)".
+    Heading(String),
+    Field {
+        name: String,
+        value: String,
+        /// Where Enter jumps to.
+        offset: Option<usize>,
+    },
+}
+
+pub fn field(name: &str, value: String, offset: Option<usize>) -> StructureRow {
+    StructureRow::Field {
+        name: name.to_owned(),
+        value,
+        offset,
+    }
+}
+
+/// Structure of executable files (ELF, PE, Mach-O) as headings and fields. Enter
+/// jumps to the selected field or table entry.
+pub struct StructureOverlay {
+    pos_x: i32,
+    pos_y: i32,
+    width: i32,
+    height: i32,
+
+    /// Shown in the border, e.g. " PE ".
+    title: &'static str,
+
+    rows: Vec<StructureRow>,
+
+    selected: usize,
+}
+
+impl StructureOverlay {
+    pub fn new(
+        width: i32,
+        height: i32,
+        pos_x: i32,
+        pos_y: i32,
+        title: &'static str,
+        rows: Vec<StructureRow>,
+    ) -> StructureOverlay {
+        let width_ = cmp::min(width, 110);
+        let height_ = cmp::min(height, 40);
+
+        let pos_x = pos_x + (width - width_) / 2;
+        let pos_y = pos_y + (height - height_) / 2;
+
+        StructureOverlay {
+            pos_x,
+            pos_y,
+            width: width_,
+            height: height_,
+            title,
+            rows,
+            selected: 0,
+        }
+    }
+
+    pub fn draw(&self, tb: &mut Termbox) {
+        draw_box(tb, self.pos_x, self.pos_y, self.width, self.height);
+
+        print(tb, self.pos_x + 2, self.pos_y, colors::palette().default, self.title);
+
+        let rows = (self.height - 4) as usize;
+        let first = if self.selected >= rows {
+            self.selected + 1 - rows
+        } else {
+            0
+        };
+
+        for (row_idx, row) in self.rows.iter().enumerate().skip(first).take(rows) {
+            let style = if row_idx == self.selected {
+                colors::palette().cursor_focus
+            } else {
+                colors::palette().default
+            };
+            let line = match *row {
+                StructureRow::Heading(ref title) =>
+                    title.clone(),
+                StructureRow::Field {
+                    ref name,
+                    ref value,
+                    offset,
+                } => {
+                    let offset = match offset {
+                        Some(offset) =>
+                            format!("0x{:08x}", offset),
+                        None =>
+                            "-".to_owned(),
+                    };
+                    format!("  {:<24} {:<10} {}", name, offset, value)
+                }
+            };
+            let line: String = line.chars().take((self.width - 2) as usize).collect();
+            print(tb, self.pos_x + 1, self.pos_y + 1 + (row_idx - first) as i32, style, &line);
+        }
+
+        print(
+            tb,
+            self.pos_x + 1,
+            self.pos_y + self.height - 2,
+            colors::palette().default,
+            "Enter: jump to offset",
+        );
+    }
+
+    pub fn keypressed(&mut self, key: Key) -> StructureRet {
+        match key {
+            Key::Arrow(Arrow::Up) | Key::Char('k') => {
+                if self.selected > 0 {
+                    self.selected -= 1;
+                }
+                StructureRet::Continue
+            }
+            Key::Arrow(Arrow::Down) | Key::Char('j') => {
+                if self.selected + 1 < self.rows.len() {
+                    self.selected += 1;
+                }
+                StructureRet::Continue
+            }
+            Key::Char('\r') =>
+                match self.rows.get(self.selected) {
+                    Some(&StructureRow::Field {
+                        offset: Some(offset),
+                        ..
+                    }) =>
+                        StructureRet::Jump(offset),
+                    _ =>
+                        StructureRet::Continue,
+                },
+            // `S` opens the view, and switches back to the hex view
+            Key::Esc | Key::Char('q') | Key::Char('S') =>
+                StructureRet::Abort,
+            _ =>
+                StructureRet::Continue,
+        }
+    }
+}
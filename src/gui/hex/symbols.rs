@@ -0,0 +1,168 @@
+use std::cmp;
+
+use colors;
+use parser::elf::Symbol;
+use utils::*;
+
+use term_input::{Arrow, Key};
+use termbox_simple::*;
+
+pub enum SymbolsRet {
+    /// Jump to the byte offset.
+    Jump(usize),
+
+    /// User cancelled.
+    Abort,
+
+    /// Overlay still has focus.
+    Continue,
+}
+
+/// Lists ELF symbols. Typing filters the list by name, Enter jumps to the
+/// selected symbol when it's defined in the file.
+pub struct SymbolsOverlay {
+    pos_x: i32,
+    pos_y: i32,
+    width: i32,
+    height: i32,
+
+    /// Symbols and their file offsets.
+    symbols: Vec<(Symbol, Option<usize>)>,
+
+    filter: String,
+
+    /// Indices of symbols matching the filter.
+    shown: Vec<usize>,
+
+    /// Index in `shown`.
+    selected: usize,
+}
+
+impl SymbolsOverlay {
+    pub fn new(
+        width: i32,
+        height: i32,
+        pos_x: i32,
+        pos_y: i32,
+        symbols: Vec<(Symbol, Option<usize>)>,
+    ) -> SymbolsOverlay {
+        let width_ = cmp::min(width, 100);
+        let height_ = cmp::min(height, 30);
+
+        let pos_x = pos_x + (width - width_) / 2;
+        let pos_y = pos_y + (height - height_) / 2;
+
+        let shown = (0..symbols.len()).collect();
+        SymbolsOverlay {
+            pos_x,
+            pos_y,
+            width: width_,
+            height: height_,
+            symbols,
+            filter: String::new(),
+            shown,
+            selected: 0,
+        }
+    }
+
+    fn update_filter(&mut self) {
+        let filter = &self.filter;
+        self.shown = self.symbols
+            .iter()
+            .enumerate()
+            .filter(|(_, (sym, _))| sym.name.contains(filter.as_str()))
+            .map(|(idx, _)| idx)
+            .collect();
+        self.selected = 0;
+    }
+
+    pub fn draw(&self, tb: &mut Termbox) {
+        draw_box(tb, self.pos_x, self.pos_y, self.width, self.height);
+
+        let title = format!(" Symbols ({}/{}) ", self.shown.len(), self.symbols.len());
+        print(tb, self.pos_x + 2, self.pos_y, colors::palette().default, &title);
+
+        let rows = (self.height - 4) as usize;
+        let first = if self.selected >= rows {
+            self.selected + 1 - rows
+        } else {
+            0
+        };
+
+        for (row, &idx) in self.shown.iter().enumerate().skip(first).take(rows) {
+            let style = if row == self.selected {
+                colors::palette().cursor_focus
+            } else {
+                colors::palette().default
+            };
+            let (ref sym, offset) = self.symbols[idx];
+            let offset = match offset {
+                Some(offset) =>
+                    format!("0x{:08x}", offset),
+                None =>
+                    "-".to_owned(),
+            };
+            let line = format!(
+                "0x{:08x} {:>6} {:<7} {:<6} {:<10} {}",
+                sym.value,
+                sym.size,
+                sym.type_str(),
+                sym.bind_str(),
+                offset,
+                sym.name
+            );
+            let line: String = line.chars().take((self.width - 2) as usize).collect();
+            print(tb, self.pos_x + 1, self.pos_y + 1 + (row - first) as i32, style, &line);
+        }
+
+        let bottom = self.pos_y + self.height - 2;
+        print(tb, self.pos_x + 1, bottom, colors::palette().default, "Filter: ");
+        print(tb, self.pos_x + 9, bottom, colors::palette().default, &self.filter);
+        tb.change_cell(
+            self.pos_x + 9 + self.filter.chars().count() as i32,
+            bottom,
+            ' ',
+            colors::palette().cursor_focus.fg,
+            colors::palette().cursor_focus.bg,
+        );
+    }
+
+    pub fn keypressed(&mut self, key: Key) -> SymbolsRet {
+        match key {
+            // Letters go to the filter, so only arrows and Ctrl-p/n move
+            Key::Arrow(Arrow::Up) | Key::Ctrl('p') => {
+                if self.selected > 0 {
+                    self.selected -= 1;
+                }
+                SymbolsRet::Continue
+            }
+            Key::Arrow(Arrow::Down) | Key::Ctrl('n') => {
+                if self.selected + 1 < self.shown.len() {
+                    self.selected += 1;
+                }
+                SymbolsRet::Continue
+            }
+            Key::Char('\r') =>
+                match self.shown.get(self.selected).and_then(|&idx| self.symbols[idx].1) {
+                    Some(offset) =>
+                        SymbolsRet::Jump(offset),
+                    None =>
+                        SymbolsRet::Continue,
+                },
+            Key::Esc =>
+                SymbolsRet::Abort,
+            Key::Backspace => {
+                self.filter.pop();
+                self.update_filter();
+                SymbolsRet::Continue
+            }
+            Key::Char(ch) => {
+                self.filter.push(ch);
+                self.update_filter();
+                SymbolsRet::Continue
+            }
+            _ =>
+                SymbolsRet::Continue,
+        }
+    }
+}
@@ -0,0 +1,162 @@
+////////////////////////////////////////////////////////////////////////////////
+// `rhex --tutor`: a vimtutor-like walk through the basic features
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cmp;
+
+use colors;
+use utils::*;
+
+use termbox_simple::*;
+
+/// Offsets of the search target in the sample buffer.
+const SECRET_OFFSETS: [usize; 2] = [0x1a0, 0x2f0];
+const SECRET: &[u8] = b"SECRET";
+
+/// The buffer we open in tutor mode.
+pub fn sample_buffer() -> Vec<u8> {
+    let mut buf: Vec<u8> = (0..1024).map(|i| (i % 256) as u8).collect();
+
+    let title = b"Welcome to the rhex tutor!\n";
+    buf[..title.len()].copy_from_slice(title);
+
+    for &offset in &SECRET_OFFSETS {
+        buf[offset..offset + SECRET.len()].copy_from_slice(SECRET);
+    }
+
+    buf
+}
+
+/// What lessons look at to decide whether the user did what they were asked.
+pub struct Progress {
+    pub cursor: usize,
+    pub last_byte: usize,
+    pub highlights: usize,
+    pub mark_a: Option<usize>,
+}
+
+struct Lesson {
+    text: &'static [&'static str],
+    done: fn(&Progress) -> bool,
+}
+
+static LESSONS: [Lesson; 9] = [
+    Lesson {
+        text: &[
+            "Move the cursor with h, j, k, l (or arrow keys).",
+            "Move it anywhere to continue.",
+        ],
+        done: lesson_moved,
+    },
+    Lesson {
+        text: &["Press G to jump to the last byte of the file."],
+        done: lesson_at_end,
+    },
+    Lesson {
+        text: &["Press gg to go back to the beginning."],
+        done: lesson_at_beginning,
+    },
+    Lesson {
+        text: &[
+            "Press g, type 256 and press Enter to go to",
+            "byte offset 256.",
+        ],
+        done: lesson_at_256,
+    },
+    Lesson {
+        text: &[
+            "Press /, type SECRET and press Enter to search.",
+            "Tab switches between ASCII and hex input.",
+        ],
+        done: lesson_searched,
+    },
+    Lesson {
+        text: &["Press n to jump to the next match (N for previous)."],
+        done: lesson_on_match,
+    },
+    Lesson {
+        text: &["Press ma to set mark 'a' here."],
+        done: lesson_mark_set,
+    },
+    Lesson {
+        text: &["Press gg to move away from the mark."],
+        done: lesson_at_beginning,
+    },
+    Lesson {
+        text: &["Press 'a to jump back to the mark."],
+        done: lesson_at_mark,
+    },
+];
+
+fn lesson_moved(p: &Progress) -> bool {
+    p.cursor != 0
+}
+
+fn lesson_at_end(p: &Progress) -> bool {
+    p.cursor == p.last_byte
+}
+
+fn lesson_at_beginning(p: &Progress) -> bool {
+    p.cursor == 0
+}
+
+fn lesson_at_256(p: &Progress) -> bool {
+    p.cursor == 256
+}
+
+fn lesson_searched(p: &Progress) -> bool {
+    p.highlights != 0
+}
+
+fn lesson_on_match(p: &Progress) -> bool {
+    SECRET_OFFSETS.contains(&p.cursor)
+}
+
+fn lesson_mark_set(p: &Progress) -> bool {
+    p.mark_a.is_some()
+}
+
+fn lesson_at_mark(p: &Progress) -> bool {
+    p.mark_a == Some(p.cursor)
+}
+
+pub struct Tutor {
+    /// Current lesson. `LESSONS.len()` when all done.
+    lesson: usize,
+}
+
+impl Tutor {
+    pub fn new() -> Tutor {
+        Tutor { lesson: 0 }
+    }
+
+    /// Called after every key press.
+    pub fn update(&mut self, progress: &Progress) {
+        if self.lesson < LESSONS.len() && (LESSONS[self.lesson].done)(progress) {
+            self.lesson += 1;
+        }
+    }
+
+    /// Draw the current lesson in a box at the bottom of the given area.
+    pub fn draw(&self, tb: &mut Termbox, width: i32, height: i32) {
+        let box_width = cmp::min(width, 56);
+        let box_height = 5;
+        let pos_x = width - box_width;
+        let pos_y = cmp::max(height - 1 - box_height, 0);
+
+        draw_box(tb, pos_x, pos_y, box_width, box_height);
+
+        let title = format!(" Lesson {}/{} ", cmp::min(self.lesson + 1, LESSONS.len()), LESSONS.len());
+        print(tb, pos_x + 2, pos_y, colors::palette().default, &title);
+
+        let text: &[&str] = if self.lesson < LESSONS.len() {
+            LESSONS[self.lesson].text
+        } else {
+            &["That's it! Press q to quit."]
+        };
+
+        for (line_idx, line) in text.iter().enumerate() {
+            print(tb, pos_x + 2, pos_y + 1 + line_idx as i32, colors::palette().default, line);
+        }
+    }
+}
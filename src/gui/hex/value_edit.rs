@@ -0,0 +1,314 @@
+use std::cmp;
+
+use buffer::Buffer;
+use colors;
+use utils::*;
+
+use term_input::Key;
+use termbox_simple::*;
+
+/// Type of the value edited with `c`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Unit {
+    U8,
+    U16,
+    U32,
+    U64,
+    F32,
+    F64,
+}
+
+impl Unit {
+    /// Integer of the size of the groups in the hex view.
+    pub fn for_group(group_size: i32) -> Unit {
+        match group_size {
+            2 =>
+                Unit::U16,
+            4 =>
+                Unit::U32,
+            8 =>
+                Unit::U64,
+            _ =>
+                Unit::U8,
+        }
+    }
+
+    pub fn size(self) -> usize {
+        match self {
+            Unit::U8 =>
+                1,
+            Unit::U16 =>
+                2,
+            Unit::U32 | Unit::F32 =>
+                4,
+            Unit::U64 | Unit::F64 =>
+                8,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Unit::U8 =>
+                "u8",
+            Unit::U16 =>
+                "u16",
+            Unit::U32 =>
+                "u32",
+            Unit::U64 =>
+                "u64",
+            Unit::F32 =>
+                "f32",
+            Unit::F64 =>
+                "f64",
+        }
+    }
+
+    fn next(self) -> Unit {
+        match self {
+            Unit::U8 =>
+                Unit::U16,
+            Unit::U16 =>
+                Unit::U32,
+            Unit::U32 =>
+                Unit::U64,
+            Unit::U64 =>
+                Unit::F32,
+            Unit::F32 =>
+                Unit::F64,
+            Unit::F64 =>
+                Unit::U8,
+        }
+    }
+
+    /// Bytes of a value typed by the user. Integers are decimal (negative
+    /// ones are stored in two's complement) or hex with `0x`. Floats are
+    /// anything Rust parses as a float (`1.5`, `-2e10`, `inf`, `nan`).
+    pub fn encode(self, input: &str, big_endian: bool) -> Result<Vec<u8>, String> {
+        let input = input.trim();
+        let bits = match self {
+            Unit::F32 =>
+                match input.parse::<f64>() {
+                    Ok(f) if f.is_finite() && (f as f32).is_infinite() =>
+                        return Err(format!("{} is out of range for f32", input)),
+                    Ok(f) =>
+                        u64::from((f as f32).to_bits()),
+                    Err(_) =>
+                        return Err(format!("Not a number: {}", input)),
+                },
+            Unit::F64 =>
+                match input.parse::<f64>() {
+                    Ok(f) =>
+                        f.to_bits(),
+                    Err(_) =>
+                        return Err(format!("Not a number: {}", input)),
+                },
+            _ =>
+                parse_int(input, self.size()).ok_or_else(|| {
+                    format!("Not a {} (decimal, or hex with 0x): {}", self.name(), input)
+                })?,
+        };
+        let size = self.size();
+        Ok(if big_endian {
+            bits.to_be_bytes()[8 - size..].to_vec()
+        } else {
+            bits.to_le_bytes()[..size].to_vec()
+        })
+    }
+
+    /// The value of the bytes, as shown before editing.
+    pub fn decode(self, bytes: &[u8], big_endian: bool) -> String {
+        let mut msb_first = bytes.to_vec();
+        if !big_endian {
+            msb_first.reverse();
+        }
+        let bits = msb_first.iter().fold(0u64, |bits, &byte| (bits << 8) | u64::from(byte));
+        match self {
+            Unit::F32 =>
+                format!("{}", f32::from_bits(bits as u32)),
+            Unit::F64 =>
+                format!("{}", f64::from_bits(bits)),
+            _ => {
+                let size_bits = self.size() as u32 * 8;
+                if bits >> (size_bits - 1) == 1 {
+                    // Also as a signed value
+                    let signed = (bits << (64 - size_bits)) as i64 >> (64 - size_bits);
+                    format!("{} (0x{:x}, signed {})", bits, bits, signed)
+                } else {
+                    format!("{} (0x{:x})", bits, bits)
+                }
+            }
+        }
+    }
+}
+
+/// An integer of `size` bytes, negative ones in two's complement.
+fn parse_int(input: &str, size: usize) -> Option<u64> {
+    let (negative, digits) = match input.strip_prefix('-') {
+        Some(digits) =>
+            (true, digits),
+        None =>
+            (false, input),
+    };
+    let magnitude = if digits.starts_with("0x") || digits.starts_with("0X") {
+        u64::from_str_radix(&digits[2..], 16).ok()?
+    } else {
+        digits.parse::<u64>().ok()?
+    };
+    let max = if size == 8 { u64::MAX } else { (1 << (size * 8)) - 1 };
+    if !negative {
+        if magnitude > max {
+            None
+        } else {
+            Some(magnitude)
+        }
+    } else if magnitude > (max >> 1) + 1 {
+        None
+    } else {
+        Some(magnitude.wrapping_neg() & max)
+    }
+}
+
+pub enum ValueRet {
+    /// Write the bytes, the encoded value, at the offset.
+    Write {
+        offset: usize,
+        unit: Unit,
+        big_endian: bool,
+        bytes: Vec<u8>,
+    },
+
+    /// User cancelled.
+    Abort,
+
+    /// Overlay still has focus.
+    Continue,
+}
+
+/// Prompt for a new value of the unit at the cursor. Tab switches the type,
+/// Ctrl-e the endianness.
+pub struct ValueOverlay {
+    pos_x: i32,
+    pos_y: i32,
+    width: i32,
+    height: i32,
+
+    offset: usize,
+    unit: Unit,
+    big_endian: bool,
+
+    input: String,
+
+    /// Why the last input couldn't be encoded.
+    error: Option<String>,
+}
+
+impl ValueOverlay {
+    pub fn new(
+        width: i32,
+        height: i32,
+        pos_x: i32,
+        pos_y: i32,
+        offset: usize,
+        unit: Unit,
+        big_endian: bool,
+    ) -> ValueOverlay {
+        let width_ = cmp::min(width, 60);
+        let height_ = cmp::min(height, 10);
+
+        let pos_x = pos_x + (width - width_) / 2;
+        let pos_y = pos_y + (height - height_) / 2;
+
+        ValueOverlay {
+            pos_x,
+            pos_y,
+            width: width_,
+            height: height_,
+            offset,
+            unit,
+            big_endian,
+            input: String::new(),
+            error: None,
+        }
+    }
+
+    pub fn draw(&self, tb: &mut Termbox, data: &Buffer) {
+        draw_box(tb, self.pos_x, self.pos_y, self.width, self.height);
+
+        let title = format!(" Edit value at 0x{:x} ", self.offset);
+        print(tb, self.pos_x + 2, self.pos_y, colors::palette().default, &title);
+
+        let x = self.pos_x + 3;
+        let unit = format!(
+            "{}, {} endian (Tab: type, Ctrl-e: endianness)",
+            self.unit.name(),
+            if self.big_endian { "big" } else { "little" }
+        );
+        print(tb, x, self.pos_y + 2, colors::palette().default, &unit);
+
+        let end = self.offset + self.unit.size();
+        let current = if end <= data.len() {
+            let bytes: Vec<u8> = (self.offset..end).map(|i| data.get(i).unwrap()).collect();
+            format!("Current: {}", self.unit.decode(&bytes, self.big_endian))
+        } else {
+            "Current: past the end of the buffer".to_owned()
+        };
+        print(tb, x, self.pos_y + 3, colors::palette().default, &current);
+
+        print(tb, x, self.pos_y + 5, colors::palette().default, ">");
+        print(tb, x + 2, self.pos_y + 5, colors::palette().default, &self.input);
+        tb.change_cell(
+            x + 2 + self.input.chars().count() as i32,
+            self.pos_y + 5,
+            ' ',
+            colors::palette().cursor_focus.fg,
+            colors::palette().cursor_focus.bg,
+        );
+
+        if let Some(ref error) = self.error {
+            let error: String = error.chars().take((self.width - 4) as usize).collect();
+            print(tb, x, self.pos_y + 7, colors::palette().default, &error);
+        }
+    }
+
+    pub fn keypressed(&mut self, key: Key) -> ValueRet {
+        match key {
+            Key::Char('\r') =>
+                match self.unit.encode(&self.input, self.big_endian) {
+                    Ok(bytes) =>
+                        ValueRet::Write {
+                            offset: self.offset,
+                            unit: self.unit,
+                            big_endian: self.big_endian,
+                            bytes,
+                        },
+                    Err(err) => {
+                        self.error = Some(err);
+                        ValueRet::Continue
+                    }
+                },
+            Key::Tab => {
+                self.unit = self.unit.next();
+                self.error = None;
+                ValueRet::Continue
+            }
+            Key::Ctrl('e') => {
+                self.big_endian = !self.big_endian;
+                ValueRet::Continue
+            }
+            Key::Esc =>
+                ValueRet::Abort,
+            Key::Backspace => {
+                self.input.pop();
+                self.error = None;
+                ValueRet::Continue
+            }
+            Key::Char(ch) => {
+                self.input.push(ch);
+                self.error = None;
+                ValueRet::Continue
+            }
+            _ =>
+                ValueRet::Continue,
+        }
+    }
+}
@@ -1,26 +1,380 @@
+mod buffer_list;
+mod event_loop;
 pub mod hex;
+mod mouse;
 
+use self::buffer_list::{BufferListOverlay, BufferListRet};
+use self::event_loop::{EventLoop, Msg, Timer};
+use self::hex::HexGuiRet;
+use self::mouse::{InputEvent, MouseEvent};
+
+use std::time::{Duration, Instant};
+
+use colors;
+use config::{Config, ContrastCheck};
+use patch::Patch;
+use procmem::Region;
+use utils::*;
+
+use term_input::{Event, Key};
 use termbox_simple::*;
 
-pub struct Gui<'gui> {
-    hex_gui: hex::HexGui<'gui>,
+/// How often files are checked in follow mode (`F`).
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often background jobs are checked, and their progress redrawn.
+const JOB_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Owns the terminal and one `HexGui` per open file. Only the current buffer
+/// is drawn and receives key presses; the rest keep their state (cursor,
+/// scroll, highlights) until we switch back to them.
+pub struct Gui {
+    tb: Termbox,
+    width: i32,
+    height: i32,
+
+    // HexGuis are boxed because widgets hold pointers to their parent HexGui
+    // (see `HexGui::init()`), so they shouldn't move when the vector grows.
+    #[allow(clippy::vec_box)]
+    hex_guis: Vec<Box<hex::HexGui>>,
+    current: usize,
+
+    buffer_list: Option<BufferListOverlay>,
+
+    event_loop: EventLoop,
+
+    /// Min. time between two redraws.
+    frame_time: Duration,
+
+    /// Time between two backups of marks. `None` means no backups.
+    autosave_interval: Option<Duration>,
 }
 
-impl<'gui> Gui<'gui> {
-    pub fn new_hex_gui(
+impl Gui {
+    pub fn new(
         tb: Termbox,
-        contents: &'gui [u8],
-        path: &'gui str,
+        files: Vec<(String, Vec<u8>)>,
+        config: &Config,
         width: i32,
         height: i32,
-    ) -> Gui<'gui> {
+    ) -> Gui {
+        let contrast_warning = check_palette(config.contrast);
+
+        let event_loop = EventLoop::new();
+        let mut hex_guis = Vec::with_capacity(files.len());
+        for (path, contents) in files {
+            let mut hex_gui = Box::new(hex::HexGui::new(contents, path, config.clone(), width, height));
+            hex_gui.init();
+            hex_gui.set_waker(event_loop.waker());
+            if let Some(ref msg) = contrast_warning {
+                hex_gui.show_message(msg.clone());
+            }
+            hex_gui.check_recovery();
+            hex_gui.offer_verify();
+            hex_gui.offer_template();
+            hex_guis.push(hex_gui);
+        }
+
         Gui {
-            hex_gui: hex::HexGui::new(tb, contents, path, width, height),
+            tb,
+            width,
+            height,
+            hex_guis,
+            current: 0,
+            buffer_list: None,
+            event_loop,
+            frame_time: if config.fps == 0 {
+                Duration::from_millis(0)
+            } else {
+                Duration::from_millis(1000 / u64::from(config.fps))
+            },
+            autosave_interval: if config.autosave_secs == 0 {
+                None
+            } else {
+                Some(Duration::from_secs(config.autosave_secs))
+            },
+        }
+    }
+
+    /// Show tutor lessons in the current buffer. See `hex::tutor`.
+    pub fn enable_tutor(&mut self) {
+        self.hex_guis[self.current].enable_tutor();
+    }
+
+    /// Highlight the pattern in the current buffer and jump to the match at
+    /// the offset (`--find <pattern> --open`).
+    pub fn open_match(&mut self, pattern: Vec<u8>, offset: usize) {
+        self.hex_guis[self.current].open_match(pattern, offset);
+    }
+
+    /// The buffer is memory of a process (`--pid`).
+    pub fn set_process(&mut self, region: Region, unreadable: Vec<(usize, usize)>) {
+        self.hex_guis[self.current].set_process(region, unreadable);
+    }
+
+    /// Parts of the buffer that couldn't be read from the file.
+    pub fn set_unreadable(&mut self, idx: usize, ranges: Vec<(usize, usize)>) {
+        self.hex_guis[idx].set_unreadable(ranges);
+    }
+
+    /// The buffer is a part of the file (`--skip`, `--length`).
+    pub fn set_partial(&mut self) {
+        self.hex_guis[self.current].set_partial();
+    }
+
+    /// Restore the cursors and the options of the files from when they were
+    /// last closed.
+    pub fn restore_sessions(&mut self) {
+        for hex_gui in &mut self.hex_guis {
+            hex_gui.restore_session();
+        }
+    }
+
+    /// Apply the patch to the current buffer (`--apply-patch`).
+    pub fn apply_patch(&mut self, patch: &Patch) {
+        self.hex_guis[self.current].apply_patch(patch);
+    }
+
+    /// Compare the buffers with the reference file (`--compare`).
+    pub fn set_reference(&mut self, reference: Vec<u8>) {
+        for hex_gui in &mut self.hex_guis {
+            hex_gui.set_reference(reference.clone());
         }
     }
 
     pub fn mainloop(&mut self) {
-        self.hex_gui.init();
-        self.hex_gui.mainloop();
+        let _ = mouse::enable();
+        self.draw();
+        let mut last_draw = Instant::now();
+        let mut last_autosave = Instant::now();
+
+        // Did we handle an event since the last redraw?
+        let mut dirty = false;
+
+        loop {
+            // When we have something to draw, wake up for the next frame
+            let next_frame = if dirty { Some(last_draw + self.frame_time) } else { None };
+            self.event_loop.set_deadline(Timer::Frame, next_frame);
+            let following = self.hex_guis.iter().any(|hex_gui| hex_gui.is_following());
+            self.event_loop
+                .set_interval(Timer::Follow, if following { Some(FOLLOW_INTERVAL) } else { None });
+            let jobs = self.hex_guis.iter().any(|hex_gui| hex_gui.has_jobs());
+            self.event_loop
+                .set_interval(Timer::Jobs, if jobs { Some(JOB_INTERVAL) } else { None });
+
+            let (msgs, now) = self.event_loop.wait();
+
+            let mut brk = false;
+            for msg in msgs {
+                match msg {
+                    Msg::Input(InputEvent::Term(ev)) => {
+                        brk |= self.handle_event(ev, now);
+                        dirty = true;
+                    }
+                    Msg::Input(InputEvent::Mouse(ev)) => {
+                        self.handle_mouse(ev);
+                        dirty = true;
+                    }
+                    Msg::Timer(Timer::Frame) =>
+                        {}
+                    Msg::Timer(Timer::Follow) =>
+                        for hex_gui in &mut self.hex_guis {
+                            dirty |= hex_gui.poll_file();
+                        },
+                    // Progress, or a job finished
+                    Msg::Timer(Timer::Jobs) | Msg::Wake =>
+                        for hex_gui in &mut self.hex_guis {
+                            dirty |= hex_gui.poll_jobs();
+                        },
+                    // Killed, or the terminal is gone. Save what we can, the
+                    // terminal is restored when the `Termbox` is dropped.
+                    Msg::Terminate => {
+                        for hex_gui in &mut self.hex_guis {
+                            hex_gui.save_recovery();
+                        }
+                        brk = true;
+                    }
+                }
+            }
+            if brk {
+                self.autosave();
+                for hex_gui in &self.hex_guis {
+                    hex_gui.save_session();
+                }
+                break;
+            }
+
+            // Marks only change on key presses, so no need to wake up for
+            // this when idle
+            if let Some(interval) = self.autosave_interval {
+                if last_autosave.elapsed() >= interval {
+                    self.autosave();
+                    last_autosave = Instant::now();
+                }
+            }
+
+            // A single key press after a quiet period is drawn immediately,
+            // bursts (key repeat, pastes) are drawn once per frame.
+            if dirty && last_draw.elapsed() >= self.frame_time {
+                self.draw();
+                last_draw = Instant::now();
+                dirty = false;
+            }
+        }
+
+        let _ = mouse::disable();
+    }
+
+    fn autosave(&mut self) {
+        if self.autosave_interval.is_some() {
+            for hex_gui in &mut self.hex_guis {
+                hex_gui.autosave();
+            }
+        }
+    }
+
+    fn draw(&mut self) {
+        self.tb.clear();
+
+        self.hex_guis[self.current].draw(&mut self.tb);
+
+        if self.hex_guis.len() > 1 {
+            let indicator = format!("[{}/{}]", self.current + 1, self.hex_guis.len());
+            let x = self.width - indicator.len() as i32;
+            print(&mut self.tb, x, self.height - 1, colors::palette().status_bar, &indicator);
+        }
+
+        if let Some(ref o) = self.buffer_list {
+            o.draw(&mut self.tb);
+        }
+
+        self.tb.present();
+    }
+
+    fn handle_event(&mut self, ev: Event, time: Instant) -> bool {
+        match ev {
+            Event::Key(key) =>
+                self.keypressed(key, time),
+            // term_input only knows about a few Ctrl combinations, others
+            // (e.g. Ctrl-o) are reported as unknown bytes
+            Event::Unknown(ref bytes) if bytes.len() == 1 && bytes[0] > 0 && bytes[0] < 27 =>
+                self.keypressed(Key::Ctrl((bytes[0] + 96) as char), time),
+            Event::String(ref text) => {
+                if self.buffer_list.is_none() {
+                    self.hex_guis[self.current].paste(text);
+                }
+                false
+            }
+            Event::Resize |
+            Event::FocusGained |
+            Event::FocusLost |
+            Event::Unknown(_) =>
+                false,
+        }
+    }
+
+    fn handle_mouse(&mut self, ev: MouseEvent) {
+        if self.buffer_list.is_none() {
+            self.hex_guis[self.current].mouse(ev);
+        }
+    }
+
+    fn keypressed(&mut self, key: Key, time: Instant) -> bool {
+        let mut reset_overlay = false;
+        if let Some(ref mut o) = self.buffer_list {
+            match o.keypressed(key) {
+                BufferListRet::Select(idx) => {
+                    self.current = idx;
+                    reset_overlay = true;
+                }
+                BufferListRet::Abort => {
+                    reset_overlay = true;
+                }
+                BufferListRet::Continue =>
+                    {}
+            }
+        } else {
+            return self.keypressed_hex_gui(key, time);
+        }
+
+        if reset_overlay {
+            self.buffer_list = None;
+        }
+
+        false
+    }
+
+    fn keypressed_hex_gui(&mut self, key: Key, time: Instant) -> bool {
+        match self.hex_guis[self.current].keypressed(key, time) {
+            HexGuiRet::Continue =>
+                {}
+            HexGuiRet::Quit =>
+                // Show a buffer with unsaved changes instead of losing them
+                match self.hex_guis.iter().position(|hex_gui| hex_gui.is_modified()) {
+                    Some(idx) => {
+                        self.current = idx;
+                        let msg = format!(
+                            "No write since last change in {} (:q! to quit)",
+                            self.hex_guis[idx].path()
+                        );
+                        self.hex_guis[idx].show_message(msg);
+                    }
+                    None =>
+                        return true,
+                },
+            HexGuiRet::ForceQuit =>
+                return true,
+            HexGuiRet::NextBuffer => {
+                self.current = (self.current + 1) % self.hex_guis.len();
+            }
+            HexGuiRet::PrevBuffer => {
+                self.current = (self.current + self.hex_guis.len() - 1) % self.hex_guis.len();
+            }
+            HexGuiRet::Open(mut hex_gui) => {
+                hex_gui.init();
+                hex_gui.set_waker(self.event_loop.waker());
+                self.hex_guis.push(hex_gui);
+                self.current = self.hex_guis.len() - 1;
+            }
+            HexGuiRet::BufferList => {
+                let paths = self.hex_guis.iter().map(|g| g.path().to_owned()).collect();
+                self.buffer_list = Some(BufferListOverlay::new(
+                    self.width / 2,
+                    self.height / 2,
+                    self.width / 4,
+                    self.height / 4,
+                    paths,
+                    self.current,
+                ));
+            }
+        }
+        false
+    }
+}
+
+/// Check (or fix) the contrast of the current palette. Called on startup and
+/// after every palette change (`:theme`). Returns a warning about
+/// low-contrast styles.
+fn check_palette(contrast: ContrastCheck) -> Option<String> {
+    match contrast {
+        ContrastCheck::Fix => {
+            colors::check_contrast(true);
+            None
+        }
+        ContrastCheck::Warn =>
+            contrast_warning(&colors::check_contrast(false)),
+        ContrastCheck::Off =>
+            None,
+    }
+}
+
+fn contrast_warning(low_contrast: &[(&str, f64)]) -> Option<String> {
+    if low_contrast.is_empty() {
+        return None;
     }
+    let styles: Vec<String> = low_contrast
+        .iter()
+        .map(|&(name, ratio)| format!("{} ({:.1}:1)", name, ratio))
+        .collect();
+    Some(format!("Low contrast: {} (--contrast fix to fix)", styles.join(", ")))
 }
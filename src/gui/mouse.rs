@@ -0,0 +1,133 @@
+////////////////////////////////////////////////////////////////////////////////
+// Mouse reporting
+//
+// Neither termbox_simple nor term_input know about the mouse, so we enable
+// xterm's SGR mouse mode ourselves. term_input reads a report
+// `ESC [ < b ; x ; y M` as Alt-[ followed by the rest as a string, `decode`
+// turns those back into mouse events.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+
+use term_input::{Event, Key};
+
+/// Positions are 0-based cells.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MouseEvent {
+    Press(i32, i32),
+    /// Mouse moved with the left button down.
+    Drag(i32, i32),
+    Release(i32, i32),
+    WheelUp,
+    WheelDown,
+}
+
+pub enum InputEvent {
+    Term(Event),
+    Mouse(MouseEvent),
+}
+
+/// Report button presses, releases, and motion while a button is held, in
+/// the SGR format (no limits on coordinates).
+pub fn enable() -> io::Result<()> {
+    // Like the clipboard, write to /dev/tty directly so that this doesn't
+    // interfere with termbox's output buffer
+    let mut tty = OpenOptions::new().write(true).open("/dev/tty")?;
+    tty.write_all(b"\x1b[?1002h\x1b[?1006h")?;
+    tty.flush()
+}
+
+pub fn disable() -> io::Result<()> {
+    let mut tty = OpenOptions::new().write(true).open("/dev/tty")?;
+    tty.write_all(b"\x1b[?1006l\x1b[?1002l")?;
+    tty.flush()
+}
+
+/// Drain `evs`, replacing mouse reports with mouse events.
+pub fn decode(evs: &mut Vec<Event>) -> Vec<InputEvent> {
+    let mut ret = Vec::with_capacity(evs.len());
+    let mut iter = evs.drain(..).peekable();
+    while let Some(ev) = iter.next() {
+        if ev != Event::Key(Key::AltChar('[')) {
+            ret.push(InputEvent::Term(ev));
+            continue;
+        }
+
+        let decoded = match iter.peek() {
+            Some(Event::String(s)) =>
+                decode_reports(s),
+            _ =>
+                None,
+        };
+
+        match decoded {
+            None =>
+                ret.push(InputEvent::Term(ev)),
+            Some((mouse_evs, rest)) => {
+                iter.next();
+                ret.extend(mouse_evs.into_iter().map(InputEvent::Mouse));
+                // Keys pressed right after the mouse event end up in the same
+                // string
+                ret.extend(rest.chars().map(|c| InputEvent::Term(Event::Key(Key::Char(c)))));
+            }
+        }
+    }
+    ret
+}
+
+/// Decode mouse reports in a string that follows Alt-[. Reports after the
+/// first one start with `ESC [`. Returns the events and the rest of the
+/// string, or `None` if the string doesn't start with a report.
+fn decode_reports(s: &str) -> Option<(Vec<MouseEvent>, String)> {
+    let mut evs = Vec::new();
+    let mut s = s;
+    loop {
+        let (ev, rest) = decode_report(s)?;
+        if let Some(ev) = ev {
+            evs.push(ev);
+        }
+        s = rest;
+        if s.starts_with("\x1b[<") {
+            s = &s[2..];
+        } else {
+            return Some((evs, s.to_owned()));
+        }
+    }
+}
+
+/// Decode `<b;x;yM` (press or motion) or `<b;x;ym` (release). Events we
+/// don't handle (e.g. right clicks) are `None`.
+fn decode_report(s: &str) -> Option<(Option<MouseEvent>, &str)> {
+    if !s.starts_with('<') {
+        return None;
+    }
+    let end = s.find(['M', 'm'])?;
+    let release = &s[end..end + 1] == "m";
+
+    let mut fields = s[1..end].split(';').map(|f| f.parse::<i32>());
+    let (b, x, y) = match (fields.next(), fields.next(), fields.next(), fields.next()) {
+        (Some(Ok(b)), Some(Ok(x)), Some(Ok(y)), None) =>
+            (b, x - 1, y - 1),
+        _ =>
+            return None,
+    };
+
+    // Modifiers (shift, alt, ctrl) are in bits 2-4, ignore them
+    let ev = match b & !0b1_1100 {
+        0 if release =>
+            Some(MouseEvent::Release(x, y)),
+        0 =>
+            Some(MouseEvent::Press(x, y)),
+        32 =>
+            Some(MouseEvent::Drag(x, y)),
+        64 =>
+            Some(MouseEvent::WheelUp),
+        65 =>
+            Some(MouseEvent::WheelDown),
+        _ =>
+            None,
+    };
+    Some((ev, &s[end + 1..]))
+}
@@ -0,0 +1,323 @@
+////////////////////////////////////////////////////////////////////////////////
+// Hash functions, for verifying files against checksums
+////////////////////////////////////////////////////////////////////////////////
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// See `HashKind::hash_with_progress`.
+const PROGRESS_STEP: usize = 1024 * 1024;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum HashKind {
+    Md5,
+    Sha256,
+}
+
+impl HashKind {
+    pub fn parse(s: &str) -> Option<HashKind> {
+        match s {
+            "md5" =>
+                Some(HashKind::Md5),
+            "sha256" =>
+                Some(HashKind::Sha256),
+            _ =>
+                None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            HashKind::Md5 =>
+                "MD5",
+            HashKind::Sha256 =>
+                "SHA-256",
+        }
+    }
+
+    /// Extension of checksum files, as created by `md5sum`/`sha256sum`.
+    pub fn extension(self) -> &'static str {
+        match self {
+            HashKind::Md5 =>
+                "md5",
+            HashKind::Sha256 =>
+                "sha256",
+        }
+    }
+
+    pub fn hash(self, data: &[u8]) -> Vec<u8> {
+        self.hash_with_progress(data, |_| true).unwrap()
+    }
+
+    /// `hash`, calling `progress` with the number of bytes hashed so far
+    /// every `PROGRESS_STEP` bytes. Stops when it returns false.
+    pub fn hash_with_progress<P: FnMut(usize) -> bool>(
+        self,
+        data: &[u8],
+        progress: P,
+    ) -> Option<Vec<u8>> {
+        match self {
+            HashKind::Md5 =>
+                md5(data, progress).map(|hash| hash.to_vec()),
+            HashKind::Sha256 =>
+                sha256_with_progress(data, progress).map(|hash| hash.to_vec()),
+        }
+    }
+}
+
+/// CRC-32 as in zlib, used by `.gnu_debuglink`.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut c = i as u32;
+        for _ in 0..8 {
+            c = if c & 1 == 1 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+        }
+        *entry = c;
+    }
+
+    let mut crc = !0u32;
+    for &byte in data {
+        crc = table[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Checksum file next to the file, as created by e.g. `sha256sum foo >
+/// foo.sha256`.
+pub fn find_checksum_file(path: &str) -> Option<(String, HashKind)> {
+    for &kind in &[HashKind::Sha256, HashKind::Md5] {
+        let checksum_path = format!("{}.{}", path, kind.extension());
+        if Path::new(&checksum_path).is_file() {
+            return Some((checksum_path, kind));
+        }
+    }
+    None
+}
+
+/// Find the checksum of the file in the contents of a checksum file. Lines are
+/// in `md5sum`/`sha256sum` format, "<checksum>  <file name>" (or with '*'
+/// before the name in binary mode). A file with one checksum is used for the
+/// file regardless of the name, as files are often renamed after download.
+fn expected_checksum(contents: &str, file_name: &str) -> Option<String> {
+    let mut entries = Vec::new();
+    for line in contents.lines() {
+        let mut words = line.trim().splitn(2, char::is_whitespace);
+        let checksum = match words.next() {
+            Some(checksum) if !checksum.is_empty() =>
+                checksum.to_lowercase(),
+            _ =>
+                continue,
+        };
+        let name = words.next().unwrap_or("").trim_start().trim_start_matches('*');
+        let base_name = Path::new(name).file_name().map(|name| name.to_string_lossy());
+        if name == file_name || base_name.is_some_and(|name| name == file_name) {
+            return Some(checksum);
+        }
+        entries.push(checksum);
+    }
+    if entries.len() == 1 {
+        entries.pop()
+    } else {
+        None
+    }
+}
+
+/// Checksum of the file at `path` in the checksum file. Errors are shown to
+/// the user.
+pub fn read_checksum(checksum_path: &str, path: &str) -> Result<String, String> {
+    let mut contents = String::new();
+    let read = File::open(checksum_path).and_then(|mut f| f.read_to_string(&mut contents));
+    if let Err(err) = read {
+        return Err(format!("Can't read {}: {}", checksum_path, err));
+    }
+    let file_name = Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    expected_checksum(&contents, &file_name)
+        .ok_or_else(|| format!("No checksum for {} in {}", file_name, checksum_path))
+}
+
+/// Call `f` with 64-byte blocks of the message, padded as in MD5 and SHA-2: a
+/// 1 bit, zeros, and the length in bits as a 64-bit integer. Only the last
+/// block(s) are copied, so this doesn't need a copy of the file.
+/// Returns false when `progress` stopped it.
+fn for_each_block<P, F>(data: &[u8], big_endian_len: bool, mut progress: P, mut f: F) -> bool
+where
+    P: FnMut(usize) -> bool,
+    F: FnMut(&[u8]),
+{
+    let full = data.len() / 64 * 64;
+    for (idx, block) in data[..full].chunks(64).enumerate() {
+        if idx % (PROGRESS_STEP / 64) == 0 && !progress(idx * 64) {
+            return false;
+        }
+        f(block);
+    }
+
+    let mut tail = data[full..].to_vec();
+    tail.push(0x80);
+    while tail.len() % 64 != 56 {
+        tail.push(0);
+    }
+    let bits = (data.len() as u64).wrapping_mul(8);
+    for i in 0..8 {
+        let shift = if big_endian_len { 56 - 8 * i } else { 8 * i };
+        tail.push((bits >> shift) as u8);
+    }
+    for block in tail.chunks(64) {
+        f(block);
+    }
+    true
+}
+
+static MD5_S: [u32; 64] = [
+    7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+    5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20,
+    4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+    6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+static MD5_K: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+fn md5<P: FnMut(usize) -> bool>(data: &[u8], progress: P) -> Option<[u8; 16]> {
+    let mut state: [u32; 4] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476];
+
+    let done = for_each_block(data, false, progress, |chunk| {
+        let mut m = [0u32; 16];
+        for (i, word) in chunk.chunks(4).enumerate() {
+            m[i] = u32::from(word[0])
+                | u32::from(word[1]) << 8
+                | u32::from(word[2]) << 16
+                | u32::from(word[3]) << 24;
+        }
+
+        let (mut a, mut b, mut c, mut d) = (state[0], state[1], state[2], state[3]);
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 =>
+                    ((b & c) | (!b & d), i),
+                1 =>
+                    ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 =>
+                    (b ^ c ^ d, (3 * i + 5) % 16),
+                _ =>
+                    (c ^ (b | !d), (7 * i) % 16),
+            };
+            let f = f.wrapping_add(a).wrapping_add(MD5_K[i]).wrapping_add(m[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(f.rotate_left(MD5_S[i]));
+        }
+
+        state[0] = state[0].wrapping_add(a);
+        state[1] = state[1].wrapping_add(b);
+        state[2] = state[2].wrapping_add(c);
+        state[3] = state[3].wrapping_add(d);
+    });
+    if !done {
+        return None;
+    }
+
+    let mut ret = [0u8; 16];
+    for (i, word) in state.iter().enumerate() {
+        for j in 0..4 {
+            ret[i * 4 + j] = (word >> (8 * j)) as u8;
+        }
+    }
+       Some(ret)
+}
+
+static SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    sha256_with_progress(data, |_| true).unwrap()
+}
+
+fn sha256_with_progress<P: FnMut(usize) -> bool>(data: &[u8], progress: P) -> Option<[u8; 32]> {
+    let mut state: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
+        0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+    ];
+
+    let done = for_each_block(data, true, progress, |chunk| {
+        let mut w = [0u32; 64];
+        for (i, word) in chunk.chunks(4).enumerate() {
+            w[i] = u32::from(word[0]) << 24
+                | u32::from(word[1]) << 16
+                | u32::from(word[2]) << 8
+                | u32::from(word[3]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let mut v = state;
+        for i in 0..64 {
+            let s1 = v[4].rotate_right(6) ^ v[4].rotate_right(11) ^ v[4].rotate_right(25);
+            let ch = (v[4] & v[5]) ^ (!v[4] & v[6]);
+            let t1 = v[7]
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = v[0].rotate_right(2) ^ v[0].rotate_right(13) ^ v[0].rotate_right(22);
+            let maj = (v[0] & v[1]) ^ (v[0] & v[2]) ^ (v[1] & v[2]);
+            let t2 = s0.wrapping_add(maj);
+
+            v[7] = v[6];
+            v[6] = v[5];
+            v[5] = v[4];
+            v[4] = v[3].wrapping_add(t1);
+            v[3] = v[2];
+            v[2] = v[1];
+            v[1] = v[0];
+            v[0] = t1.wrapping_add(t2);
+        }
+
+        for (s, v) in state.iter_mut().zip(v.iter()) {
+            *s = s.wrapping_add(*v);
+        }
+    });
+    if !done {
+        return None;
+    }
+
+    let mut ret = [0u8; 32];
+    for (i, word) in state.iter().enumerate() {
+        for j in 0..4 {
+            ret[i * 4 + j] = (word >> (24 - 8 * j)) as u8;
+        }
+    }
+       Some(ret)
+}
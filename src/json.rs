@@ -0,0 +1,79 @@
+////////////////////////////////////////////////////////////////////////////////
+// JSON values, for `--json-events` output
+////////////////////////////////////////////////////////////////////////////////
+
+use std::fmt;
+
+pub enum Json {
+    Null,
+    Bool(bool),
+    Num(u64),
+    Str(String),
+    Arr(Vec<Json>),
+    /// Keys are printed in this order.
+    Obj(Vec<(&'static str, Json)>),
+}
+
+impl fmt::Display for Json {
+    /// Compact, on one line.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Json::Null =>
+                write!(f, "null"),
+            Json::Bool(b) =>
+                write!(f, "{}", b),
+            Json::Num(n) =>
+                write!(f, "{}", n),
+            Json::Str(ref s) =>
+                write_str(f, s),
+            Json::Arr(ref values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", value)?;
+                }
+                write!(f, "]")
+            }
+            Json::Obj(ref fields) => {
+                write!(f, "{{")?;
+                for (i, &(key, ref value)) in fields.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ",")?;
+                    }
+                    write_str(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for ch in s.chars() {
+        match ch {
+            '"' =>
+                write!(f, "\\\"")?,
+            '\\' =>
+                write!(f, "\\\\")?,
+            '\n' =>
+                write!(f, "\\n")?,
+            '\r' =>
+                write!(f, "\\r")?,
+            '\t' =>
+                write!(f, "\\t")?,
+            ch if (ch as u32) < 0x20 =>
+                write!(f, "\\u{:04x}", ch as u32)?,
+            ch =>
+                write!(f, "{}", ch)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+pub fn str(s: &str) -> Json {
+    Json::Str(s.to_owned())
+}
@@ -0,0 +1,225 @@
+////////////////////////////////////////////////////////////////////////////////
+// `rhex --json-events <file>`: run commands read from stdin, one per line, and
+// print results as JSON objects on stdout, one per line. For running rhex from
+// scripts and editors.
+//
+// Commands:
+//
+//   search <pattern>       Offsets of matches. Pattern syntax is as in `:s`.
+//   hash md5|sha256        Digest of the file.
+//   verify                 Check the file against <file>.sha256 or <file>.md5.
+//   symbols                ELF symbols.
+//   dynamic                Entries of the ELF dynamic section.
+//   q, quit                Exit. rhex also exits at the end of the input.
+//
+// Every object has an "event" field: "open" (printed on startup), the command
+// name, or "error".
+////////////////////////////////////////////////////////////////////////////////
+
+use std::io;
+use std::io::{BufRead, Write};
+
+use config::Config;
+use gui::hex::search::Pattern;
+use hash::{find_checksum_file, read_checksum, to_hex, HashKind};
+use json::{self, Json};
+use parser::elf::{DynValue, Elf};
+use utils::parse_bytes;
+
+pub fn run(path: &str, contents: &[u8], config: &Config) {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    let checksum_file = find_checksum_file(path);
+    let open = Json::Obj(vec![
+        ("event", json::str("open")),
+        ("path", json::str(path)),
+        ("len", Json::Num(contents.len() as u64)),
+        (
+            "checksum_file",
+            match checksum_file {
+                Some((ref checksum_path, _)) =>
+                    json::str(checksum_path),
+                None =>
+                    Json::Null,
+            },
+        ),
+    ]);
+    if writeln!(out, "{}", open).and_then(|()| out.flush()).is_err() {
+        return;
+    }
+
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) =>
+                line,
+            Err(_) =>
+                return,
+        };
+        let line = line.trim();
+        let (cmd, args) = match line.find(char::is_whitespace) {
+            Some(idx) =>
+                (&line[..idx], line[idx..].trim()),
+            None =>
+                (line, ""),
+        };
+
+        let ret = match cmd {
+            "" =>
+                continue,
+            "q" | "quit" =>
+                return,
+            "search" =>
+                search(contents, args, config),
+            "hash" =>
+                hash(contents, args),
+            "verify" =>
+                verify(path, contents, &checksum_file),
+            "symbols" =>
+                symbols(contents),
+            "dynamic" =>
+                dynamic(contents),
+            _ =>
+                Err(format!("Unknown command: {}", cmd)),
+        };
+
+        let json = match ret {
+            Ok(mut fields) => {
+                fields.insert(0, ("event", json::str(cmd)));
+                Json::Obj(fields)
+            }
+            Err(msg) =>
+                Json::Obj(vec![
+                    ("event", json::str("error")),
+                    ("command", json::str(line)),
+                    ("message", Json::Str(msg)),
+                ]),
+        };
+        if writeln!(out, "{}", json).and_then(|()| out.flush()).is_err() {
+            return;
+        }
+    }
+}
+
+/// Fields of a result object, other than "event". Errors are reported as
+/// "error" events.
+type CmdRet = Result<Vec<(&'static str, Json)>, String>;
+
+fn search(contents: &[u8], args: &str, config: &Config) -> CmdRet {
+    let pattern = match parse_bytes(args) {
+        Some(ref bytes) if bytes.is_empty() =>
+            return Err("Empty pattern".to_owned()),
+        Some(bytes) =>
+            Pattern::new(bytes, false),
+        None =>
+            return Err(format!("Invalid pattern: {}", args)),
+    };
+    let (matches, capped) = pattern.find_all(contents, config.max_matches);
+    Ok(vec![
+        ("pattern", json::str(args)),
+        (
+            "matches",
+            Json::Arr(matches.into_iter().map(|offset| Json::Num(offset as u64)).collect()),
+        ),
+        ("capped", Json::Bool(capped)),
+    ])
+}
+
+fn hash(contents: &[u8], args: &str) -> CmdRet {
+    let kind = match HashKind::parse(args) {
+        Some(kind) =>
+            kind,
+        None =>
+            return Err("Usage: hash md5|sha256".to_owned()),
+    };
+    Ok(vec![
+        ("algorithm", json::str(kind.name())),
+        ("digest", Json::Str(to_hex(&kind.hash(contents)))),
+    ])
+}
+
+fn verify(path: &str, contents: &[u8], checksum_file: &Option<(String, HashKind)>) -> CmdRet {
+    let (checksum_path, kind) = match *checksum_file {
+        Some((ref checksum_path, kind)) =>
+            (checksum_path, kind),
+        None =>
+            return Err(format!("No checksum file ({}.sha256 or {}.md5) found", path, path)),
+    };
+    let expected = read_checksum(checksum_path, path)?;
+    let actual = to_hex(&kind.hash(contents));
+    Ok(vec![
+        ("algorithm", json::str(kind.name())),
+        ("checksum_file", json::str(checksum_path)),
+        ("ok", Json::Bool(actual == expected)),
+        ("expected", Json::Str(expected)),
+        ("actual", Json::Str(actual)),
+    ])
+}
+
+fn parse_elf(contents: &[u8]) -> Result<Elf, String> {
+    Elf::parse(contents).map_err(|err| err.to_string())
+}
+
+/// Offsets are null when the symbol or address is not in the file.
+fn offset_json(offset: Option<usize>) -> Json {
+    match offset {
+        Some(offset) =>
+            Json::Num(offset as u64),
+        None =>
+            Json::Null,
+    }
+}
+
+fn symbols(contents: &[u8]) -> CmdRet {
+    let elf = parse_elf(contents)?;
+    let symbols = elf.symbols(contents)
+        .into_iter()
+        .filter(|sym| !sym.name.is_empty())
+        .map(|sym| {
+            let offset = elf.symbol_offset(&sym);
+            Json::Obj(vec![
+                ("name", json::str(&sym.name)),
+                ("value", Json::Num(sym.value)),
+                ("size", Json::Num(sym.size)),
+                ("type", json::str(sym.type_str())),
+                ("bind", json::str(sym.bind_str())),
+                ("dynamic", Json::Bool(sym.dynamic)),
+                ("offset", offset_json(offset)),
+            ])
+        })
+        .collect();
+    Ok(vec![("symbols", Json::Arr(symbols))])
+}
+
+fn dynamic(contents: &[u8]) -> CmdRet {
+    let elf = parse_elf(contents)?;
+    let entries = elf.dynamic(contents)
+        .into_iter()
+        .map(|entry| {
+            let (value, offset) = match entry.value {
+                DynValue::Str(ref name) =>
+                    (json::str(name), None),
+                DynValue::Addr(addr) =>
+                    (Json::Num(addr), elf.addr_offset(addr)),
+                DynValue::Num(n) =>
+                    (Json::Num(n), None),
+            };
+            Json::Obj(vec![
+                ("tag", Json::Num(entry.tag)),
+                (
+                    "name",
+                    match entry.tag_str() {
+                        Some(name) =>
+                            json::str(name),
+                        None =>
+                            Json::Null,
+                    },
+                ),
+                ("value", value),
+                ("offset", offset_json(offset)),
+            ])
+        })
+        .collect();
+    Ok(vec![("entries", Json::Arr(entries))])
+}
@@ -0,0 +1,129 @@
+////////////////////////////////////////////////////////////////////////////////
+// Reading files into buffers
+//
+// Files are read a chunk at a time. When a chunk can't be read (e.g. bad
+// sectors of a disk) it's read again a sector at a time, and the sectors that
+// can't be read are zeros in the buffer, instead of failing to open the file.
+// Block devices (`/dev/sdX`) don't have a size in their metadata, the size is
+// found by seeking to the end.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cmp;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::os::unix::fs::{FileExt, FileTypeExt};
+use std::path::Path;
+
+/// Bytes read at a time.
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Unreadable parts of a chunk are found a sector at a time.
+const SECTOR_SIZE: usize = 512;
+
+/// Larger block devices are opened a part at a time, with `--skip` and
+/// `--length`.
+const MAX_DEVICE_LOAD: usize = 1024 * 1024 * 1024;
+
+pub struct Loaded {
+    pub contents: Vec<u8>,
+
+    /// Ranges of `contents` that couldn't be read, zeros.
+    pub unreadable: Vec<(usize, usize)>,
+}
+
+/// Size of a file or block device.
+pub fn file_size(file: &mut File) -> io::Result<u64> {
+    let metadata = file.metadata()?;
+    if metadata.file_type().is_block_device() {
+        let size = file.seek(SeekFrom::End(0))?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(size)
+    } else {
+        Ok(metadata.len())
+    }
+}
+
+/// Read `length` bytes (or to the end of the file) starting at `skip`.
+pub fn load(path: &Path, skip: usize, length: Option<usize>) -> io::Result<Loaded> {
+    let mut file = File::open(path)?;
+    let file_type = file.metadata()?.file_type();
+    if !file_type.is_file() && !file_type.is_block_device() {
+        // Pipes, character devices etc. can't seek, and don't have a size
+        return load_stream(file, skip, length);
+    }
+
+    let size = file_size(&mut file)? as usize;
+    let start = cmp::min(skip, size);
+    let end = match length {
+        Some(length) =>
+            cmp::min(start.saturating_add(length), size),
+        None =>
+            size,
+    };
+    if file_type.is_block_device() && length.is_none() && end - start > MAX_DEVICE_LOAD {
+        return Err(io::Error::other(format!(
+            "device is {} bytes, open a part of it with --skip <offset> --length <n>",
+            size
+        )));
+    }
+
+    let mut contents = vec![0; end - start];
+    let mut unreadable: Vec<(usize, usize)> = Vec::new();
+    let mut offset = 0;
+    while offset < contents.len() {
+        let chunk_end = cmp::min(offset + CHUNK_SIZE, contents.len());
+        if read_at(&file, &mut contents[offset..chunk_end], start + offset).is_err() {
+            let mut sector = offset;
+            while sector < chunk_end {
+                let sector_end = cmp::min(sector + SECTOR_SIZE, chunk_end);
+                if read_at(&file, &mut contents[sector..sector_end], start + sector).is_err() {
+                    for byte in &mut contents[sector..sector_end] {
+                        *byte = 0;
+                    }
+                    match unreadable.last_mut() {
+                        Some(last) if last.1 == sector =>
+                            last.1 = sector_end,
+                        _ =>
+                            unreadable.push((sector, sector_end)),
+                    }
+                }
+                sector = sector_end;
+            }
+        }
+        offset = chunk_end;
+    }
+
+    Ok(Loaded { contents, unreadable })
+}
+
+fn load_stream(mut file: File, skip: usize, length: Option<usize>) -> io::Result<Loaded> {
+    io::copy(&mut (&mut file).take(skip as u64), &mut io::sink())?;
+    let mut contents = Vec::new();
+    match length {
+        Some(length) =>
+            file.take(length as u64).read_to_end(&mut contents)?,
+        None =>
+            file.read_to_end(&mut contents)?,
+    };
+    Ok(Loaded {
+        contents,
+        unreadable: Vec::new(),
+    })
+}
+
+fn read_at(file: &File, buf: &mut [u8], offset: usize) -> io::Result<()> {
+    let mut len = 0;
+    while len < buf.len() {
+        match file.read_at(&mut buf[len..], (offset + len) as u64) {
+            Ok(0) =>
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "file got smaller")),
+            Ok(n) =>
+                len += n,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted =>
+                {}
+            Err(err) =>
+                return Err(err),
+        }
+    }
+    Ok(())
+}
@@ -6,49 +6,531 @@ extern crate alloc_system;
 #[global_allocator]
 static ALLOC: alloc_system::System = alloc_system::System;
 
+extern crate capstone;
 extern crate libc;
 extern crate nix;
 extern crate term_input;
 extern crate termbox_simple;
 
+mod bell;
+mod buffer;
+mod clipboard;
 mod colors;
+mod config;
+mod debug_file;
+mod detect;
+mod doctor;
+mod dump;
+mod find;
+mod generate;
 mod gui;
+mod hash;
+mod json;
+mod json_events;
+mod load;
+mod parser;
+mod patch;
+mod persist;
+mod procmem;
+mod revert;
+mod templates;
+mod undo;
 mod utils;
 
 use std::env::args_os;
 use std::ffi::OsString;
-use std::fs::File;
-use std::io::Read;
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
 
+use config::{BellEvent, BellStyle, ColumnSum, Config, ContrastCheck, Encoding,
+             NonPrintable, OffsetFormat};
+use find::{FileType, FindOptions};
+use load::Loaded;
+use procmem::Region;
 use gui::Gui;
+use utils::{parse_bytes, parse_number, parse_range};
 
 use termbox_simple::*;
 
 fn main() {
-    let args: Vec<OsString> = args_os().collect();
-    if args.len() != 2 {
-        panic!("USAGE: rhex <file>");
+    let mut config = Config::default();
+    // Errors are reported after the arguments are read, `--doctor` shows them
+    // in its report
+    let aliases = config::load_aliases();
+    let byte_colors = config::load_byte_colors();
+    let mut doctor = false;
+    let mut tutor = false;
+    let mut json_events = false;
+    let mut dump = false;
+    let mut dump_format = dump::Format::Lines;
+    let mut revert = false;
+    let mut apply_patch = None;
+    let mut skip = 0;
+    let mut length = None;
+    let mut compare = None;
+    let mut find = None;
+    let mut recursive = false;
+    let mut min_size = None;
+    let mut max_size = None;
+    let mut file_type = None;
+    let mut open = false;
+    let mut pid = None;
+    let mut addr = None;
+    let mut paths: Vec<OsString> = Vec::new();
+
+    let mut args = args_os().skip(1);
+    while let Some(arg) = args.next() {
+        let name = arg.to_string_lossy().into_owned();
+        let args = &mut args;
+        match arg.to_str() {
+            Some("-h") | Some("--help") => {
+                print!("{}", USAGE);
+                return;
+            }
+            Some("-V") | Some("--version") => {
+                println!("rhex {}", env!("CARGO_PKG_VERSION"));
+                return;
+            }
+            // The rest are files, e.g. a file named `--zen`
+            Some("--") => {
+                paths.extend(args);
+                break;
+            }
+            Some("--doctor") =>
+                doctor = true,
+            Some("--tutor") =>
+                tutor = true,
+            Some("--json-events") =>
+                json_events = true,
+            Some("--dump") =>
+                dump = true,
+            Some("--plain") =>
+                dump_format = dump::Format::Plain,
+            Some("--revert") =>
+                revert = true,
+            Some("--apply-patch") =>
+                apply_patch = Some(path_value(args, &name)),
+            Some("--skip") | Some("--offset") =>
+                skip = option_value(args, &name, "a number", parse_number),
+            Some("--length") =>
+                length = Some(option_value(args, &name, "a number", parse_number)),
+            // Same as `--skip <start> --length <end - start>`
+            Some("--range") => {
+                let (start, end) = option_value(args, &name, "<start>:<end>", parse_range);
+                skip = start;
+                length = Some(end - start);
+            }
+            Some("--status") =>
+                config.status_format = Some(option_value(args, &name, "a format", |a| {
+                    Some(a.to_owned())
+                })),
+            Some("--compare") =>
+                compare = Some(path_value(args, &name)),
+            Some("--find") =>
+                find = Some(option_value(args, &name, "hex bytes or a string", |a| {
+                    parse_bytes(a).filter(|pattern| !pattern.is_empty())
+                })),
+            Some("--recursive") | Some("-r") =>
+                recursive = true,
+            Some("--min-size") =>
+                min_size = Some(option_value(args, &name, "a number", parse_number) as u64),
+            Some("--max-size") =>
+                max_size = Some(option_value(args, &name, "a number", parse_number) as u64),
+            Some("--type") =>
+                file_type = Some(option_value(args, &name, "elf, pe, or macho", FileType::parse)),
+            Some("--open") =>
+                open = true,
+            Some("--pid") =>
+                pid = Some(option_value(args, &name, "a process id", |a| a.parse().ok())),
+            Some("--addr") =>
+                addr = Some(option_value(args, &name, "<start>:<len>, <n>, or <name>", |a| {
+                    Some(a.to_owned())
+                })),
+            Some("--zen") =>
+                config.zen = true,
+            Some("-R") | Some("--readonly") =>
+                config.readonly = true,
+            Some("--follow") =>
+                config.follow = true,
+            Some("--squeeze") =>
+                config.squeeze = true,
+            Some("--bits") =>
+                config.bits = true,
+            Some("--search-jump") =>
+                config.search_jump = true,
+            Some("--search-center") =>
+                config.search_center = true,
+            Some("--little-endian") =>
+                config.big_endian = false,
+            Some("--big-endian") =>
+                config.big_endian = true,
+            Some("--sectors") =>
+                config.sectors = true,
+            Some("--sector-size") => {
+                config.sectors = true;
+                config.sector_size = option_value(args, &name, "a number > 0", |a| {
+                    parse_number(a).filter(|&size| size > 0)
+                });
+            }
+            Some("--footer") =>
+                config.footer = Some(option_value(args, &name, "xor or sum", ColumnSum::parse)),
+            Some("--encoding") => {
+                let encodings = "auto, ascii, utf8, utf16le, utf16be, latin1, ebcdic, or sjis";
+                match option_value(args, &name, encodings, |a| {
+                    if a == "auto" { Some(None) } else { Encoding::parse(a).map(Some) }
+                }) {
+                    Some(encoding) =>
+                        config.encoding = encoding,
+                    None =>
+                        config.auto_encoding = true,
+                }
+            }
+            Some("--nonprint") =>
+                config.non_printable =
+                    option_value(args, &name, "dot, pictures, or cp437", NonPrintable::parse),
+            Some("--offsets") =>
+                config.offset_format =
+                    option_value(args, &name, "hex, dec, or oct", OffsetFormat::parse),
+            Some("--base") =>
+                config.base = Some(option_value(args, &name, "a number", parse_number)),
+            Some("--theme") => {
+                let themes = format!("one of {}", colors::THEMES.join(", "));
+                option_value(args, &name, &themes, |a| {
+                    if colors::set_theme(a) { Some(()) } else { None }
+                });
+            }
+            Some("--fps") =>
+                config.fps = option_value(args, &name, "a number", |a| a.parse().ok()),
+            Some("--group") =>
+                config.group_size = option_value(args, &name, "1, 2, 4, or 8", |a| {
+                    a.parse().ok().filter(|size| [1, 2, 4, 8].contains(size))
+                }),
+            Some("--cols") =>
+                config.cols = Some(option_value(args, &name, "a number > 0", |a| {
+                    a.parse().ok().filter(|&cols| cols > 0)
+                })),
+            Some("--max-matches") =>
+                config.max_matches = option_value(args, &name, "a number > 0", |a| {
+                    a.parse().ok().filter(|&max| max > 0)
+                }),
+            Some("--autosave") =>
+                config.autosave_secs = option_value(args, &name, "seconds", |a| a.parse().ok()),
+            Some("--bell") =>
+                config.bell_events =
+                    option_value(args, &name, "search, edge, goto, or all", BellEvent::parse),
+            Some("--bell-style") =>
+                config.bell_style =
+                    option_value(args, &name, "audible or visual", BellStyle::parse),
+            Some("--contrast") =>
+                config.contrast =
+                    option_value(args, &name, "fix, warn, or off", ContrastCheck::parse),
+            _ if name.starts_with('-') && name.len() > 1 =>
+                arg_error(&format!("Unknown option: {}", name)),
+            _ =>
+                paths.push(arg),
+        }
     }
 
-    let path = Path::new(&args[1]);
-    let contents = match File::open(path) {
+    if doctor {
+        if tutor || dump || revert || json_events || find.is_some() || pid.is_some()
+            || !paths.is_empty()
+        {
+            usage();
+        }
+        if !doctor::run(&aliases, &byte_colors, &config) {
+            process::exit(1);
+        }
+        return;
+    }
+    config.aliases = match aliases {
+        Ok(aliases) =>
+            aliases,
         Err(err) =>
-            panic!("Can't read file {:?}: {}", path, err),
-        Ok(mut file) => {
-            let mut ret = Vec::new();
-            file.read_to_end(&mut ret).unwrap();
-            ret
+            error(&err),
+    };
+    config.byte_colors = match byte_colors {
+        Ok(byte_colors) =>
+            byte_colors,
+        Err(err) =>
+            error(&err),
+    };
+
+    if let Some(pid) = pid {
+        if tutor || dump || revert || json_events || find.is_some() || apply_patch.is_some()
+            || !paths.is_empty()
+        {
+            usage();
         }
+        let maps = match procmem::maps(pid) {
+            Ok(maps) =>
+                maps,
+            Err(err) =>
+                error(&format!("Can't read memory maps of process {}: {}", pid, err)),
+        };
+        let spec = match addr {
+            Some(spec) =>
+                spec,
+            None => {
+                // Pick one with --addr
+                procmem::print_maps(&maps);
+                return;
+            }
+        };
+        let region = match Region::parse(pid, &spec, &maps) {
+            Ok(region) =>
+                region,
+            Err(err) =>
+                error(&err),
+        };
+        let (contents, unreadable) = match region.read() {
+            Ok(ret) =>
+                ret,
+            Err(err) =>
+                error(&format!("Can't read memory of process {}: {}", pid, err)),
+        };
+        config.base = Some(region.start);
+
+        let mut gui = init_gui(vec![(region.name(), contents)], &config);
+        gui.set_process(region, unreadable);
+        gui.mainloop();
+        return;
+    } else if addr.is_some() {
+        usage();
+    }
+
+    if !tutor && paths.is_empty() {
+        usage();
+    }
+
+    // With `--open`, the file with the first match is opened below
+    let mut open_match = None;
+    if let Some(pattern) = find {
+        if tutor || dump || revert || json_events {
+            usage();
+        }
+        let opts = FindOptions {
+            pattern,
+            recursive,
+            min_size,
+            max_size,
+            file_type,
+        };
+        let paths: Vec<PathBuf> = paths.iter().map(PathBuf::from).collect();
+        match find::run(&paths, &opts, &config) {
+            None =>
+                process::exit(1),
+            Some(first) =>
+                if !open {
+                    return;
+                } else {
+                    open_match = Some((opts.pattern, first));
+                },
+        }
+    } else if recursive || min_size.is_some() || max_size.is_some() || file_type.is_some() || open {
+        usage();
+    }
+
+    if revert {
+        if tutor || dump || json_events || paths.len() != 2 {
+            usage();
+        }
+        if let Err(err) = revert::run(path_str(&paths[0]), path_str(&paths[1])) {
+            error(&err);
+        }
+        return;
+    }
+
+    if dump_format != dump::Format::Lines && !dump {
+        usage();
+    }
+    if dump {
+        if tutor || json_events || apply_patch.is_some() || paths.len() != 1 {
+            usage();
+        }
+        let path = path_str(&paths[0]);
+        if let Err(err) = dump::run(path, &config, skip, length, dump_format) {
+            error(&format!("Can't dump file {:?}: {}", path, err));
+        }
+        return;
+    }
+
+    if json_events {
+        if tutor || paths.len() != 1 {
+            usage();
+        }
+        let (path, loaded) = read_files(&paths, 0, None).pop().unwrap();
+        json_events::run(&path, &loaded.contents, &config);
+        return;
+    }
+
+    // A part of the file (e.g. of a large disk) is shown at its offsets in the
+    // file
+    let partial = skip != 0 || length.is_some();
+    if partial {
+        if tutor || open_match.is_some() || paths.len() != 1 {
+            usage();
+        }
+        config.base = Some(config.base.unwrap_or(0) + skip);
+    }
+
+    let files: Vec<(String, Loaded)> = if tutor {
+        let contents = gui::hex::tutor::sample_buffer();
+        vec![("[tutor]".to_owned(), Loaded { contents, unreadable: Vec::new() })]
+    } else if let Some((_, (ref path, _))) = open_match {
+        read_files(&[OsString::from(path)], 0, None)
+    } else {
+        read_files(&paths, skip, length)
     };
+    let mut unreadable = Vec::with_capacity(files.len());
+    let files = files
+        .into_iter()
+        .map(|(path, loaded)| {
+            unreadable.push(loaded.unreadable);
+            (path, loaded.contents)
+        })
+        .collect();
 
-    let mut tb = Termbox::init().unwrap();
+    // Read before initializing the terminal, so that errors are readable
+    let reference = compare.map(|path| read_files(&[path], 0, None).pop().unwrap());
+    let patch = apply_patch.map(|path| {
+        if tutor || partial || open_match.is_some() || paths.len() != 1 {
+            usage();
+        }
+        let bytes = match fs::read(&path) {
+            Ok(bytes) =>
+                bytes,
+            Err(err) =>
+                error(&format!("Can't read patch {:?}: {}", path, err)),
+        };
+        match patch::parse(&bytes) {
+            Ok(patch) =>
+                patch,
+            Err(err) =>
+                error(&format!("Can't read patch {:?}: {}", path, err)),
+        }
+    });
+
+    let mut gui = init_gui(files, &config);
+    if tutor {
+        gui.enable_tutor();
+    }
+    for (idx, ranges) in unreadable.into_iter().enumerate() {
+        gui.set_unreadable(idx, ranges);
+    }
+    if partial {
+        gui.set_partial();
+    }
+    if let Some((_, reference)) = reference {
+        gui.set_reference(reference.contents);
+    }
+    if !tutor {
+        gui.restore_sessions();
+    }
+    if let Some((pattern, (_, offset))) = open_match {
+        gui.open_match(pattern, offset);
+    }
+    if let Some(patch) = patch {
+        gui.apply_patch(&patch);
+    }
+    gui.mainloop();
+}
+
+const USAGE: &str = "USAGE: rhex [--offsets hex|dec|oct] [--base <addr>] [--fps <n>]\n            [--group 1|2|4|8] [--cols <n>]\n            [--max-matches <n>] [--autosave <secs>]\n            [--bell search,edge,goto|all] [--bell-style audible|visual]\n            [--contrast fix|warn|off] [--status <format>] [--zen]\n            [--sectors] [--sector-size <n>] [--compare <file>] [--follow]\n            [--squeeze] [--search-jump] [--search-center] [--bits]\n            [--nonprint dot|pictures|cp437] [-R|--readonly]\n            [--theme dark|light] [--little-endian|--big-endian]\n            [--encoding auto|ascii|utf8|utf16le|utf16be|latin1|ebcdic|sjis] [--footer xor|sum]\n            [--] <file>...\n       rhex [--skip|--offset <n>] [--length <n>] [--range <start>:<end>] .. <file>\n       rhex --apply-patch <patch> .. <file>\n       rhex --dump [--skip <n>] [--length <n>] [--range <start>:<end>] [--plain]\n            [--offsets ..] [--base ..] [--group ..] [--cols ..] <file>\n       rhex --revert <dump> <file>\n       rhex --find <pattern> [--recursive] [--min-size <n>] [--max-size <n>]\n            [--type elf|pe|macho] [--open] <path>...\n       rhex --pid <pid> [--addr <start>:<len>|<n>|<name>] [--follow] ..\n       rhex --json-events <file>\n       rhex --tutor\n       rhex --doctor [--status <format>]\n       rhex --help\n       rhex --version\n";
+
+/// Wrong arguments: show the usage and exit.
+fn usage() -> ! {
+    eprint!("{}", USAGE);
+    process::exit(2);
+}
+
+/// Print the error and exit. Errors before the terminal is initialized are
+/// reported this way, instead of with a panic.
+fn error(msg: &str) -> ! {
+    eprintln!("rhex: {}", msg);
+    process::exit(1);
+}
+
+/// An invalid argument, or an option without a value.
+fn arg_error(msg: &str) -> ! {
+    eprintln!("rhex: {}", msg);
+    eprintln!("Try 'rhex --help' for more information.");
+    process::exit(2);
+}
+
+/// The value of option `name`, parsed with `parse`. `expected` describes the
+/// valid values, for the error when the value is missing or invalid.
+fn option_value<I, T, F>(args: &mut I, name: &str, expected: &str, parse: F) -> T
+where
+    I: Iterator<Item = OsString>,
+    F: FnOnce(&str) -> Option<T>,
+{
+    let arg = match args.next() {
+        Some(arg) =>
+            arg,
+        None =>
+            arg_error(&format!("{} needs a value: {}", name, expected)),
+    };
+    match arg.to_str().and_then(parse) {
+        Some(value) =>
+            value,
+        None =>
+            arg_error(&format!("Invalid value for {}: {:?} (expected {})", name, arg, expected)),
+    }
+}
+
+/// The path given to option `name`.
+fn path_value<I: Iterator<Item = OsString>>(args: &mut I, name: &str) -> OsString {
+    match args.next() {
+        Some(path) =>
+            path,
+        None =>
+            arg_error(&format!("{} needs a file", name)),
+    }
+}
+
+fn path_str(path: &OsString) -> &str {
+    match path.to_str() {
+        Some(path) =>
+            path,
+        None =>
+            error(&format!("Path is not valid UTF-8: {:?}", path)),
+    }
+}
+
+fn init_gui(files: Vec<(String, Vec<u8>)>, config: &Config) -> Gui {
+    // For wcwidth(), which termbox uses to skip the cells taken by wide
+    // characters
+    unsafe {
+        libc::setlocale(libc::LC_CTYPE, b"\0".as_ptr() as *const libc::c_char);
+    }
+    let mut tb = match Termbox::init() {
+        Ok(tb) =>
+            tb,
+        Err(err) =>
+            error(&format!("Can't initialize the terminal: {:?}", err)),
+    };
     tb.set_output_mode(OutputMode::Output256);
     tb.set_clear_attributes(TB_DEFAULT, TB_DEFAULT);
 
     let scr_x = tb.width();
     let scr_y = tb.height();
 
-    let mut gui = Gui::new_hex_gui(tb, &contents, path.to_str().unwrap(), scr_x, scr_y);
-    gui.mainloop();
+    Gui::new(tb, files, config, scr_x, scr_y)
+}
+
+/// Read the files, or parts of them (`--skip`, `--length`).
+fn read_files(args: &[OsString], skip: usize, length: Option<usize>) -> Vec<(String, Loaded)> {
+    args
+        .iter()
+        .map(|arg| {
+            let path = Path::new(arg);
+            match load::load(path, skip, length) {
+                Ok(loaded) =>
+                    (path_str(arg).to_owned(), loaded),
+                Err(err) =>
+                    error(&format!("Can't read file {:?}: {}", path, err)),
+            }
+        })
+        .collect()
 }
@@ -0,0 +1,1717 @@
+////////////////////////////////////////////////////////////////////////////////
+// ELF parser. Supports 32 and 64-bit, little and big endian files.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cmp;
+
+use hash::to_hex;
+use parser::{flags_str, ParseError, Reader};
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+/// `e_type` of relocatable (object) files.
+pub const ET_REL: u16 = 1;
+
+/// `e_machine` values.
+pub const EM_386: u16 = 3;
+pub const EM_ARM: u16 = 40;
+pub const EM_X86_64: u16 = 62;
+pub const EM_AARCH64: u16 = 183;
+
+
+/// `STT_*` values of section and file symbols.
+pub const STT_SECTION: u8 = 3;
+pub const STT_FILE: u8 = 4;
+
+/// Special section indices of symbols.
+pub const SHN_UNDEF: u16 = 0;
+pub const SHN_LORESERVE: u16 = 0xff00;
+
+pub struct Elf {
+    pub is_64: bool,
+    pub big_endian: bool,
+    pub header: Header,
+    pub program_headers: Vec<ProgramHeader>,
+    pub section_headers: Vec<SectionHeader>,
+}
+
+pub struct Header {
+    pub e_type: u16,
+    pub e_machine: u16,
+    pub e_phoff: u64,
+    pub e_shoff: u64,
+    pub e_phnum: u16,
+    pub e_shnum: u16,
+    pub e_shstrndx: u16,
+}
+
+/// `p_type` of program headers.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SegmentType {
+    Null,
+    Load,
+    Dynamic,
+    Interp,
+    Note,
+    Shlib,
+    Phdr,
+    Tls,
+    GnuEhFrame,
+    GnuStack,
+    GnuRelro,
+    GnuProperty,
+    /// OS or processor-specific, or invalid.
+    Unknown(u32),
+}
+
+impl SegmentType {
+    fn from_u32(ty: u32) -> SegmentType {
+        match ty {
+            0 =>
+                SegmentType::Null,
+            1 =>
+                SegmentType::Load,
+            2 =>
+                SegmentType::Dynamic,
+            3 =>
+                SegmentType::Interp,
+            4 =>
+                SegmentType::Note,
+            5 =>
+                SegmentType::Shlib,
+            6 =>
+                SegmentType::Phdr,
+            7 =>
+                SegmentType::Tls,
+            0x6474_e550 =>
+                SegmentType::GnuEhFrame,
+            0x6474_e551 =>
+                SegmentType::GnuStack,
+            0x6474_e552 =>
+                SegmentType::GnuRelro,
+            0x6474_e553 =>
+                SegmentType::GnuProperty,
+            _ =>
+                SegmentType::Unknown(ty),
+        }
+    }
+
+    /// Name as in `readelf -l`, e.g. `LOAD`.
+    pub fn name(self) -> String {
+        let name = match self {
+            SegmentType::Null =>
+                "NULL",
+            SegmentType::Load =>
+                "LOAD",
+            SegmentType::Dynamic =>
+                "DYNAMIC",
+            SegmentType::Interp =>
+                "INTERP",
+            SegmentType::Note =>
+                "NOTE",
+            SegmentType::Shlib =>
+                "SHLIB",
+            SegmentType::Phdr =>
+                "PHDR",
+            SegmentType::Tls =>
+                "TLS",
+            SegmentType::GnuEhFrame =>
+                "GNU_EH_FRAME",
+            SegmentType::GnuStack =>
+                "GNU_STACK",
+            SegmentType::GnuRelro =>
+                "GNU_RELRO",
+            SegmentType::GnuProperty =>
+                "GNU_PROPERTY",
+            SegmentType::Unknown(ty) =>
+                return format!("0x{:x}", ty),
+        };
+        name.to_owned()
+    }
+}
+
+/// `sh_type` of section headers.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SectionType {
+    Null,
+    Progbits,
+    Symtab,
+    Strtab,
+    Rela,
+    Hash,
+    Dynamic,
+    Note,
+    Nobits,
+    Rel,
+    Shlib,
+    Dynsym,
+    InitArray,
+    FiniArray,
+    PreinitArray,
+    Group,
+    SymtabShndx,
+    GnuHash,
+    GnuVerdef,
+    GnuVerneed,
+    GnuVersym,
+    /// OS or processor-specific, or invalid.
+    Unknown(u32),
+}
+
+impl SectionType {
+    fn from_u32(ty: u32) -> SectionType {
+        match ty {
+            0 =>
+                SectionType::Null,
+            1 =>
+                SectionType::Progbits,
+            2 =>
+                SectionType::Symtab,
+            3 =>
+                SectionType::Strtab,
+            4 =>
+                SectionType::Rela,
+            5 =>
+                SectionType::Hash,
+            6 =>
+                SectionType::Dynamic,
+            7 =>
+                SectionType::Note,
+            8 =>
+                SectionType::Nobits,
+            9 =>
+                SectionType::Rel,
+            10 =>
+                SectionType::Shlib,
+            11 =>
+                SectionType::Dynsym,
+            14 =>
+                SectionType::InitArray,
+            15 =>
+                SectionType::FiniArray,
+            16 =>
+                SectionType::PreinitArray,
+            17 =>
+                SectionType::Group,
+            18 =>
+                SectionType::SymtabShndx,
+            0x6fff_fff6 =>
+                SectionType::GnuHash,
+            0x6fff_fffd =>
+                SectionType::GnuVerdef,
+            0x6fff_fffe =>
+                SectionType::GnuVerneed,
+            0x6fff_ffff =>
+                SectionType::GnuVersym,
+            _ =>
+                SectionType::Unknown(ty),
+        }
+    }
+
+    /// Name as in `readelf -S`, e.g. `PROGBITS`.
+    pub fn name(self) -> String {
+        let name = match self {
+            SectionType::Null =>
+                "NULL",
+            SectionType::Progbits =>
+                "PROGBITS",
+            SectionType::Symtab =>
+                "SYMTAB",
+            SectionType::Strtab =>
+                "STRTAB",
+            SectionType::Rela =>
+                "RELA",
+            SectionType::Hash =>
+                "HASH",
+            SectionType::Dynamic =>
+                "DYNAMIC",
+            SectionType::Note =>
+                "NOTE",
+            SectionType::Nobits =>
+                "NOBITS",
+            SectionType::Rel =>
+                "REL",
+            SectionType::Shlib =>
+                "SHLIB",
+            SectionType::Dynsym =>
+                "DYNSYM",
+            SectionType::InitArray =>
+                "INIT_ARRAY",
+            SectionType::FiniArray =>
+                "FINI_ARRAY",
+            SectionType::PreinitArray =>
+                "PREINIT_ARRAY",
+            SectionType::Group =>
+                "GROUP",
+            SectionType::SymtabShndx =>
+                "SYMTAB_SHNDX",
+            SectionType::GnuHash =>
+                "GNU_HASH",
+            SectionType::GnuVerdef =>
+                "VERDEF",
+            SectionType::GnuVerneed =>
+                "VERNEED",
+            SectionType::GnuVersym =>
+                "VERSYM",
+            SectionType::Unknown(ty) =>
+                return format!("0x{:x}", ty),
+        };
+        name.to_owned()
+    }
+}
+
+pub struct ProgramHeader {
+    pub p_type: SegmentType,
+    pub p_offset: u64,
+    pub p_vaddr: u64,
+    pub p_filesz: u64,
+    /// Larger than `p_filesz` when the segment has zero-filled parts, e.g.
+    /// `.bss`.
+    pub p_memsz: u64,
+}
+
+pub struct SectionHeader {
+    /// Read from the section header string table, using `sh_name`.
+    pub name: String,
+    pub sh_name: u32,
+    pub sh_type: SectionType,
+    pub sh_addr: u64,
+    pub sh_offset: u64,
+    pub sh_size: u64,
+    pub sh_link: u32,
+    pub sh_info: u32,
+    pub sh_addralign: u64,
+}
+
+pub struct Symbol {
+    pub name: String,
+    pub value: u64,
+    pub size: u64,
+    /// `STB_*`, upper 4 bits of `st_info`.
+    pub bind: u8,
+    /// `STT_*`, lower 4 bits of `st_info`.
+    pub ty: u8,
+    pub shndx: u16,
+    /// From `.dynsym` rather than `.symtab`?
+    pub dynamic: bool,
+}
+
+impl Symbol {
+    pub fn bind_str(&self) -> &'static str {
+        match self.bind {
+            0 =>
+                "LOCAL",
+            1 =>
+                "GLOBAL",
+            2 =>
+                "WEAK",
+            10 =>
+                "UNIQUE",
+            _ =>
+                "?",
+        }
+    }
+
+    pub fn type_str(&self) -> &'static str {
+        match self.ty {
+            0 =>
+                "NOTYPE",
+            1 =>
+                "OBJECT",
+            2 =>
+                "FUNC",
+            3 =>
+                "SECTION",
+            4 =>
+                "FILE",
+            5 =>
+                "COMMON",
+            6 =>
+                "TLS",
+            10 =>
+                "IFUNC",
+            _ =>
+                "?",
+        }
+    }
+}
+
+/// The file as loaded in memory: loadable segments at their addresses.
+pub struct MemoryImage {
+    /// Address of the first byte.
+    pub base: u64,
+    pub data: Vec<u8>,
+    /// Ranges (offsets in `data`) that are not in the file: parts of segments
+    /// beyond their file size (e.g. `.bss`), and gaps between segments. These
+    /// are zero-filled.
+    pub virtual_ranges: Vec<(usize, usize)>,
+}
+
+/// Memory images larger than this are not created, e.g. when segments are at
+/// addresses far apart.
+const MAX_IMAGE_SIZE: u64 = 256 * 1024 * 1024;
+
+/// An entry of a `SHT_REL` or `SHT_RELA` section.
+pub struct Relocation {
+    /// Address (or offset in the target section, in relocatable files) of
+    /// the location to modify.
+    pub offset: u64,
+    /// Index in the symbol table, 0 for no symbol.
+    pub sym: u32,
+    pub ty: u32,
+    /// `None` in `SHT_REL` sections, where the addend is at the location.
+    pub addend: Option<i64>,
+    /// `R_*` name of the type, for x86_64 and AArch64.
+    pub type_name: Option<&'static str>,
+    pub symbol_name: Option<String>,
+    /// Name of the relocation section, e.g. `.rela.dyn`.
+    pub section: String,
+    /// Index of the section the relocation applies to, in relocatable files.
+    target_section: u32,
+}
+
+/// GOT slot of a `jmp *slot` in an x86 PLT stub, and the relocation index
+/// of a `push $index`.
+fn x86_stub_slot(
+    stub: &[u8],
+    addr: u64,
+    is_64: bool,
+    got_plt: Option<u64>,
+) -> (Option<u64>, Option<u32>) {
+    let imm32 = |idx: usize| -> Option<u32> {
+        let bytes = stub.get(idx..idx + 4)?;
+        Some(bytes.iter().rev().fold(0, |n, &b| (n << 8) | u32::from(b)))
+    };
+    let mut slot = None;
+    let mut reloc_index = None;
+    // Stubs are a few instructions, decode the ones they use and stop at
+    // anything else (e.g. padding)
+    let mut idx = 0;
+    while idx < stub.len() {
+        let opcode = (stub[idx], stub.get(idx + 1).cloned().unwrap_or(0));
+        match opcode {
+            // endbr64, endbr32
+            (0xf3, 0x0f) =>
+                idx += 4,
+            // bnd prefix
+            (0xf2, _) =>
+                idx += 1,
+            // jmp *disp32(%rip) on x86_64, jmp *addr32 on i386
+            (0xff, 0x25) => {
+                slot = imm32(idx + 2).map(|imm| {
+                    if is_64 {
+                        addr.wrapping_add(idx as u64 + 6).wrapping_add(imm as i32 as u64)
+                    } else {
+                        u64::from(imm)
+                    }
+                });
+                idx += 6;
+            }
+            // jmp *disp32(%ebx)
+            (0xff, 0xa3) => {
+                slot = match (imm32(idx + 2), got_plt) {
+                    (Some(imm), Some(got_plt)) =>
+                        Some(got_plt.wrapping_add(imm as i32 as u64) & 0xffff_ffff),
+                    _ =>
+                        None,
+                };
+                idx += 6;
+            }
+            // push GOT[1] in the first stub
+            (0xff, 0x35) | (0xff, 0xb3) =>
+                idx += 6,
+            (0x68, _) => {
+                reloc_index = imm32(idx + 1);
+                idx += 5;
+            }
+            // jmp rel32, to the first stub
+            (0xe9, _) =>
+                idx += 5,
+            _ =>
+                break,
+        }
+    }
+    (slot, reloc_index)
+}
+
+/// GOT slot of an `adrp x16, page; ldr x17, [x16, #off]` in an AArch64 PLT
+/// stub.
+fn aarch64_stub_slot(stub: &[u8], addr: u64) -> Option<u64> {
+    let words: Vec<u32> = stub
+        .chunks(4)
+        .filter(|word| word.len() == 4)
+        .map(|word| word.iter().rev().fold(0, |n, &b| (n << 8) | u32::from(b)))
+        .collect();
+    for (idx, pair) in words.windows(2).enumerate() {
+        let (adrp, ldr) = (pair[0], pair[1]);
+        if adrp & 0x9f00_0000 != 0x9000_0000 || ldr & 0xffc0_0000 != 0xf940_0000 {
+            continue;
+        }
+        let imm = ((adrp >> 29) & 0x3) | (((adrp >> 5) & 0x7_ffff) << 2);
+        // Sign-extend the 21-bit immediate, in pages
+        let imm = (((imm << 11) as i32) >> 11) as i64 as u64;
+        let pc = addr.wrapping_add(idx as u64 * 4);
+        let page = (pc & !0xfff).wrapping_add(imm << 12);
+        let off = u64::from((ldr >> 10) & 0xfff) * 8;
+        return Some(page.wrapping_add(off));
+    }
+    None
+}
+
+fn x86_64_reloc_name(ty: u32) -> Option<&'static str> {
+    let name = match ty {
+        0 =>
+            "R_X86_64_NONE",
+        1 =>
+            "R_X86_64_64",
+        2 =>
+            "R_X86_64_PC32",
+        3 =>
+            "R_X86_64_GOT32",
+        4 =>
+            "R_X86_64_PLT32",
+        5 =>
+            "R_X86_64_COPY",
+        6 =>
+            "R_X86_64_GLOB_DAT",
+        7 =>
+            "R_X86_64_JUMP_SLOT",
+        8 =>
+            "R_X86_64_RELATIVE",
+        9 =>
+            "R_X86_64_GOTPCREL",
+        10 =>
+            "R_X86_64_32",
+        11 =>
+            "R_X86_64_32S",
+        12 =>
+            "R_X86_64_16",
+        13 =>
+            "R_X86_64_PC16",
+        14 =>
+            "R_X86_64_8",
+        15 =>
+            "R_X86_64_PC8",
+        16 =>
+            "R_X86_64_DTPMOD64",
+        17 =>
+            "R_X86_64_DTPOFF64",
+        18 =>
+            "R_X86_64_TPOFF64",
+        19 =>
+            "R_X86_64_TLSGD",
+        20 =>
+            "R_X86_64_TLSLD",
+        21 =>
+            "R_X86_64_DTPOFF32",
+        22 =>
+            "R_X86_64_GOTTPOFF",
+        23 =>
+            "R_X86_64_TPOFF32",
+        24 =>
+            "R_X86_64_PC64",
+        25 =>
+            "R_X86_64_GOTOFF64",
+        26 =>
+            "R_X86_64_GOTPC32",
+        37 =>
+            "R_X86_64_IRELATIVE",
+        41 =>
+            "R_X86_64_GOTPCRELX",
+        42 =>
+            "R_X86_64_REX_GOTPCRELX",
+        _ =>
+            return None,
+    };
+    Some(name)
+}
+
+fn aarch64_reloc_name(ty: u32) -> Option<&'static str> {
+    let name = match ty {
+        0 =>
+            "R_AARCH64_NONE",
+        257 =>
+            "R_AARCH64_ABS64",
+        258 =>
+            "R_AARCH64_ABS32",
+        259 =>
+            "R_AARCH64_ABS16",
+        260 =>
+            "R_AARCH64_PREL64",
+        261 =>
+            "R_AARCH64_PREL32",
+        262 =>
+            "R_AARCH64_PREL16",
+        275 =>
+            "R_AARCH64_ADR_PREL_PG_HI21",
+        277 =>
+            "R_AARCH64_ADD_ABS_LO12_NC",
+        278 =>
+            "R_AARCH64_LDST8_ABS_LO12_NC",
+        282 =>
+            "R_AARCH64_JUMP26",
+        283 =>
+            "R_AARCH64_CALL26",
+        284 =>
+            "R_AARCH64_LDST16_ABS_LO12_NC",
+        285 =>
+            "R_AARCH64_LDST32_ABS_LO12_NC",
+        286 =>
+            "R_AARCH64_LDST64_ABS_LO12_NC",
+        299 =>
+            "R_AARCH64_LDST128_ABS_LO12_NC",
+        311 =>
+            "R_AARCH64_ADR_GOT_PAGE",
+        312 =>
+            "R_AARCH64_LD64_GOT_LO12_NC",
+        1024 =>
+            "R_AARCH64_COPY",
+        1025 =>
+            "R_AARCH64_GLOB_DAT",
+        1026 =>
+            "R_AARCH64_JUMP_SLOT",
+        1027 =>
+            "R_AARCH64_RELATIVE",
+        1028 =>
+            "R_AARCH64_TLS_DTPMOD",
+        1029 =>
+            "R_AARCH64_TLS_DTPREL",
+        1030 =>
+            "R_AARCH64_TLS_TPREL",
+        1031 =>
+            "R_AARCH64_TLSDESC",
+        1032 =>
+            "R_AARCH64_IRELATIVE",
+        _ =>
+            return None,
+    };
+    Some(name)
+}
+
+/// A stub in `.plt`, `.plt.sec` or `.plt.got`, decoded to find the GOT slot
+/// it jumps through.
+pub struct PltStub {
+    pub addr: u64,
+    pub offset: usize,
+    /// Name of the section, e.g. `.plt`.
+    pub section: String,
+    /// Address of the GOT slot the stub loads its target from. `None` when
+    /// the stub doesn't jump through the GOT (e.g. lazy stubs next to
+    /// `.plt.sec`) or couldn't be decoded.
+    pub got_slot: Option<u64>,
+    /// Index of the relocation pushed before jumping to the resolver, in
+    /// lazy x86 stubs.
+    pub reloc_index: Option<u32>,
+}
+
+/// `DF_BIND_NOW` in `DT_FLAGS`.
+const DF_BIND_NOW: u64 = 0x8;
+
+/// `DF_1_NOW` in `DT_FLAGS_1`.
+const DF_1_NOW: u64 = 0x1;
+
+/// An entry of a `SHT_NOTE` section.
+pub struct Note {
+    /// Name of the note section, e.g. `.note.gnu.build-id`.
+    pub section: String,
+    /// Owner of the note, e.g. "GNU".
+    pub owner: String,
+    pub ty: u32,
+    /// File offset of the descriptor.
+    pub desc_offset: usize,
+    pub desc: Vec<u8>,
+}
+
+/// `NT_*` values of notes owned by "GNU".
+pub const NT_GNU_ABI_TAG: u32 = 1;
+pub const NT_GNU_BUILD_ID: u32 = 3;
+pub const NT_GNU_PROPERTY_TYPE_0: u32 = 5;
+
+impl Note {
+    pub fn type_str(&self) -> Option<&'static str> {
+        if self.owner != "GNU" {
+            return None;
+        }
+        match self.ty {
+            NT_GNU_ABI_TAG =>
+                Some("NT_GNU_ABI_TAG"),
+            2 =>
+                Some("NT_GNU_HWCAP"),
+            NT_GNU_BUILD_ID =>
+                Some("NT_GNU_BUILD_ID"),
+            4 =>
+                Some("NT_GNU_GOLD_VERSION"),
+            NT_GNU_PROPERTY_TYPE_0 =>
+                Some("NT_GNU_PROPERTY_TYPE_0"),
+            _ =>
+                None,
+        }
+    }
+}
+
+/// Contents of `.gnu_debuglink`: name of the file with the debug info of a
+/// stripped file, and the CRC-32 of that file.
+pub struct DebugLink {
+    pub name: String,
+    pub crc: u32,
+}
+
+/// An entry of the `.dynamic` section.
+pub struct Dyn {
+    /// `DT_*`
+    pub tag: u64,
+    pub value: DynValue,
+}
+
+pub enum DynValue {
+    /// Offset in the dynamic string table, e.g. `DT_NEEDED`, read as a
+    /// string.
+    Str(String),
+    /// Virtual address, e.g. `DT_INIT`.
+    Addr(u64),
+    /// Size, count, or flags.
+    Num(u64),
+}
+
+enum TagKind {
+    Str,
+    Addr,
+    Num,
+}
+
+/// Name of a `DT_*` tag as shown by `readelf -d`, and how its value is used.
+fn tag_info(tag: u64) -> Option<(&'static str, TagKind)> {
+    let info = match tag {
+        0 =>
+            ("NULL", TagKind::Num),
+        1 =>
+            ("NEEDED", TagKind::Str),
+        2 =>
+            ("PLTRELSZ", TagKind::Num),
+        3 =>
+            ("PLTGOT", TagKind::Addr),
+        4 =>
+            ("HASH", TagKind::Addr),
+        5 =>
+            ("STRTAB", TagKind::Addr),
+        6 =>
+            ("SYMTAB", TagKind::Addr),
+        7 =>
+            ("RELA", TagKind::Addr),
+        8 =>
+            ("RELASZ", TagKind::Num),
+        9 =>
+            ("RELAENT", TagKind::Num),
+        10 =>
+            ("STRSZ", TagKind::Num),
+        11 =>
+            ("SYMENT", TagKind::Num),
+        12 =>
+            ("INIT", TagKind::Addr),
+        13 =>
+            ("FINI", TagKind::Addr),
+        14 =>
+            ("SONAME", TagKind::Str),
+        15 =>
+            ("RPATH", TagKind::Str),
+        16 =>
+            ("SYMBOLIC", TagKind::Num),
+        17 =>
+            ("REL", TagKind::Addr),
+        18 =>
+            ("RELSZ", TagKind::Num),
+        19 =>
+            ("RELENT", TagKind::Num),
+        20 =>
+            ("PLTREL", TagKind::Num),
+        21 =>
+            ("DEBUG", TagKind::Num),
+        22 =>
+            ("TEXTREL", TagKind::Num),
+        23 =>
+            ("JMPREL", TagKind::Addr),
+        24 =>
+            ("BIND_NOW", TagKind::Num),
+        25 =>
+            ("INIT_ARRAY", TagKind::Addr),
+        26 =>
+            ("FINI_ARRAY", TagKind::Addr),
+        27 =>
+            ("INIT_ARRAYSZ", TagKind::Num),
+        28 =>
+            ("FINI_ARRAYSZ", TagKind::Num),
+        29 =>
+            ("RUNPATH", TagKind::Str),
+        30 =>
+            ("FLAGS", TagKind::Num),
+        32 =>
+            ("PREINIT_ARRAY", TagKind::Addr),
+        33 =>
+            ("PREINIT_ARRAYSZ", TagKind::Num),
+        35 =>
+            ("RELRSZ", TagKind::Num),
+        36 =>
+            ("RELR", TagKind::Addr),
+        37 =>
+            ("RELRENT", TagKind::Num),
+        0x6fff_fef5 =>
+            ("GNU_HASH", TagKind::Addr),
+        0x6fff_fff0 =>
+            ("VERSYM", TagKind::Addr),
+        0x6fff_fff9 =>
+            ("RELACOUNT", TagKind::Num),
+        0x6fff_fffa =>
+            ("RELCOUNT", TagKind::Num),
+        0x6fff_fffb =>
+            ("FLAGS_1", TagKind::Num),
+        0x6fff_fffc =>
+            ("VERDEF", TagKind::Addr),
+        0x6fff_fffd =>
+            ("VERDEFNUM", TagKind::Num),
+        0x6fff_fffe =>
+            ("VERNEED", TagKind::Addr),
+        0x6fff_ffff =>
+            ("VERNEEDNUM", TagKind::Num),
+        _ =>
+            return None,
+    };
+    Some(info)
+}
+
+impl Dyn {
+    /// `None` for unknown (e.g. processor-specific) tags.
+    pub fn tag_str(&self) -> Option<&'static str> {
+        tag_info(self.tag).map(|(name, _)| name)
+    }
+}
+
+impl Elf {
+    /// Fails when the data is not an ELF file, or the headers are invalid or
+    /// truncated. Contents of sections are not read here, methods reading
+    /// those skip entries that are out of the file.
+    pub fn parse(data: &[u8]) -> Result<Elf, ParseError> {
+        if data.get(0..4) != Some(ELF_MAGIC) {
+            return Err(ParseError::BadMagic {
+                format: "an ELF",
+                offset: 0,
+                magic: ELF_MAGIC,
+            });
+        }
+        // e_ident, class and encoding are needed to know the rest of the
+        // header
+        let ident_truncated = ParseError::Truncated {
+            what: "ELF header",
+            offset: 0,
+            size: 16,
+        };
+        let is_64 = match data.get(4) {
+            Some(&1) =>
+                false,
+            Some(&2) =>
+                true,
+            Some(&class) =>
+                return Err(ParseError::Invalid {
+                    field: "ELF class",
+                    offset: 4,
+                    value: u64::from(class),
+                    expected: "1 (32-bit) or 2 (64-bit)",
+                }),
+            None =>
+                return Err(ident_truncated),
+        };
+        let big_endian = match data.get(5) {
+            Some(&1) =>
+                false,
+            Some(&2) =>
+                true,
+            Some(&encoding) =>
+                return Err(ParseError::Invalid {
+                    field: "ELF data encoding",
+                    offset: 5,
+                    value: u64::from(encoding),
+                    expected: "1 (little endian) or 2 (big endian)",
+                }),
+            None =>
+                return Err(ident_truncated),
+        };
+        let r = Reader::new(data, big_endian);
+
+        let header = parse_header(r, is_64).ok_or(ParseError::Truncated {
+            what: "ELF header",
+            offset: 0,
+            size: if is_64 { 64 } else { 52 },
+        })?;
+
+        let mut program_headers = Vec::with_capacity(header.e_phnum as usize);
+        let entsize = if is_64 { 56 } else { 32 };
+        for i in 0..header.e_phnum as usize {
+            let offset = (header.e_phoff as usize).saturating_add(i * entsize);
+            let ph = parse_program_header(r, offset, is_64).ok_or(ParseError::Truncated {
+                what: "program header table",
+                offset: header.e_phoff as usize,
+                size: header.e_phnum as usize * entsize,
+            })?;
+            program_headers.push(ph);
+        }
+
+        let mut section_headers = Vec::with_capacity(header.e_shnum as usize);
+        let entsize = if is_64 { 64 } else { 40 };
+        for i in 0..header.e_shnum as usize {
+            let offset = (header.e_shoff as usize).saturating_add(i * entsize);
+            let sh = parse_section_header(r, offset, is_64).ok_or(ParseError::Truncated {
+                what: "section header table",
+                offset: header.e_shoff as usize,
+                size: header.e_shnum as usize * entsize,
+            })?;
+            section_headers.push(sh);
+        }
+
+        // Section names are read after all headers as the string table can
+        // be any of the sections
+        let shstrtab_offset = section_headers
+            .get(header.e_shstrndx as usize)
+            .map(|sh| sh.sh_offset);
+        if let Some(shstrtab_offset) = shstrtab_offset {
+            for sh in &mut section_headers {
+                let name_offset = shstrtab_offset.saturating_add(u64::from(sh.sh_name)) as usize;
+                sh.name = r.c_str(name_offset).unwrap_or_default();
+            }
+        }
+
+        Ok(Elf {
+            is_64,
+            big_endian,
+            header,
+            program_headers,
+            section_headers,
+        })
+    }
+
+    /// Symbols in `.symtab` and `.dynsym`, in the order they appear in the
+    /// file. Entries that can't be read (e.g. truncated tables) are skipped.
+    pub fn symbols(&self, data: &[u8]) -> Vec<Symbol> {
+        let r = Reader::new(data, self.big_endian);
+        let mut ret = Vec::new();
+        for sh in &self.section_headers {
+            if sh.sh_type != SectionType::Symtab && sh.sh_type != SectionType::Dynsym {
+                continue;
+            }
+            let strtab = match self.section_headers.get(sh.sh_link as usize) {
+                Some(strtab) =>
+                    strtab,
+                None =>
+                    continue,
+            };
+            let entsize = if self.is_64 { 24 } else { 16 };
+            for i in 0..table_entries(sh, entsize, data.len()) {
+                let offset = match entry_offset(sh, i, entsize) {
+                    Some(offset) =>
+                        offset,
+                    None =>
+                        break,
+                };
+                if let Some(mut sym) = parse_symbol(r, offset, self.is_64, strtab.sh_offset) {
+                    sym.dynamic = sh.sh_type == SectionType::Dynsym;
+                    ret.push(sym);
+                }
+            }
+        }
+        ret
+    }
+
+    /// Build the memory image from loadable (`PT_LOAD`) segments.
+    pub fn memory_image(&self, data: &[u8]) -> Result<MemoryImage, &'static str> {
+        let mut segments: Vec<&ProgramHeader> = self.program_headers
+            .iter()
+            .filter(|ph| ph.p_type == SegmentType::Load && ph.p_memsz != 0)
+            .collect();
+        if segments.is_empty() {
+            return Err("No loadable segments");
+        }
+        segments.sort_by_key(|ph| ph.p_vaddr);
+
+        let base = segments[0].p_vaddr;
+        let end = segments
+            .iter()
+            .map(|ph| ph.p_vaddr.saturating_add(ph.p_memsz))
+            .max()
+            .unwrap();
+        if end - base > MAX_IMAGE_SIZE {
+            return Err("Memory image is too large (segments are too far apart)");
+        }
+
+        let mut image = vec![0; (end - base) as usize];
+        // File-backed ranges, sorted by start as segments are sorted
+        let mut backed: Vec<(usize, usize)> = Vec::new();
+        for ph in segments {
+            let start = (ph.p_vaddr - base) as usize;
+            let file_start = cmp::min(ph.p_offset as usize, data.len());
+            let file_end = cmp::min(file_start.saturating_add(ph.p_filesz as usize), data.len());
+            // (`end` may be saturated, so this can be less than `p_memsz`)
+            let mem_len = cmp::min(ph.p_memsz as usize, image.len() - start);
+            let len = cmp::min(file_end - file_start, mem_len);
+            image[start..start + len].copy_from_slice(&data[file_start..file_start + len]);
+            if len != 0 {
+                match backed.last_mut() {
+                    Some(last) if last.1 >= start => {
+                        last.1 = cmp::max(last.1, start + len);
+                        continue;
+                    }
+                    _ =>
+                        {}
+                }
+                backed.push((start, start + len));
+            }
+        }
+
+        let mut virtual_ranges = Vec::new();
+        let mut offset = 0;
+        for (start, end) in backed {
+            if start > offset {
+                virtual_ranges.push((offset, start));
+            }
+            offset = end;
+        }
+        if offset < image.len() {
+            virtual_ranges.push((offset, image.len()));
+        }
+
+        Ok(MemoryImage {
+            base,
+            data: image,
+            virtual_ranges,
+        })
+    }
+
+    /// Entries of all `SHT_REL` and `SHT_RELA` sections, in the order they
+    /// appear in the file. Entries that can't be read are skipped.
+    pub fn relocations(&self, data: &[u8]) -> Vec<Relocation> {
+        let r = Reader::new(data, self.big_endian);
+        let mut ret = Vec::new();
+        for sh in &self.section_headers {
+            let rela = match sh.sh_type {
+                SectionType::Rela =>
+                    true,
+                SectionType::Rel =>
+                    false,
+                _ =>
+                    continue,
+            };
+            // Symbol table of the relocations, and its string table
+            let symtab = self.section_headers.get(sh.sh_link as usize);
+            let strtab_offset = symtab
+                .and_then(|symtab| self.section_headers.get(symtab.sh_link as usize))
+                .map(|strtab| strtab.sh_offset);
+
+            let word = if self.is_64 { 8 } else { 4 };
+            let entsize = if rela { word * 3 } else { word * 2 };
+            for i in 0..table_entries(sh, entsize, data.len()) {
+                let offset = match entry_offset(sh, i, entsize) {
+                    Some(offset) =>
+                        offset,
+                    None =>
+                        break,
+                };
+                // Offset of the field `n` of the entry
+                let field = |n: usize| offset.checked_add(n * word);
+                let entry = if self.is_64 {
+                    match (r.u64(offset), field(1).and_then(|offset| r.u64(offset))) {
+                        (Some(r_offset), Some(info)) =>
+                            Some((r_offset, (info >> 32) as u32, info as u32)),
+                        _ =>
+                            None,
+                    }
+                } else {
+                    match (r.u32(offset), field(1).and_then(|offset| r.u32(offset))) {
+                        (Some(r_offset), Some(info)) =>
+                            Some((u64::from(r_offset), info >> 8, info & 0xff)),
+                        _ =>
+                            None,
+                    }
+                };
+                let (r_offset, sym, ty) = match entry {
+                    Some(entry) =>
+                        entry,
+                    None =>
+                        break,
+                };
+                let addend = if !rela {
+                    None
+                } else if self.is_64 {
+                    field(2).and_then(|offset| r.u64(offset)).map(|addend| addend as i64)
+                } else {
+                    field(2)
+                        .and_then(|offset| r.u32(offset))
+                        .map(|addend| i64::from(addend as i32))
+                };
+
+                let symbol_name = match (symtab, strtab_offset) {
+                    (Some(symtab), Some(strtab_offset)) if sym != 0 => {
+                        let sym_entsize = if self.is_64 { 24 } else { 16 };
+                        let sym_offset = (symtab.sh_offset as usize)
+                            .saturating_add(sym as usize * sym_entsize);
+                        parse_symbol(r, sym_offset, self.is_64, strtab_offset)
+                            .map(|sym| self.symbol_name(sym))
+                    }
+                    _ =>
+                        None,
+                };
+
+                ret.push(Relocation {
+                    offset: r_offset,
+                    sym,
+                    ty,
+                    addend,
+                    type_name: match self.header.e_machine {
+                        EM_X86_64 =>
+                            x86_64_reloc_name(ty),
+                        EM_AARCH64 =>
+                            aarch64_reloc_name(ty),
+                        _ =>
+                            None,
+                    },
+                    symbol_name,
+                    section: sh.name.clone(),
+                    target_section: sh.sh_info,
+                });
+            }
+        }
+        ret
+    }
+
+    /// Section symbols don't have names, use the section's name for those.
+    fn symbol_name(&self, sym: Symbol) -> String {
+        if sym.name.is_empty() && sym.ty == STT_SECTION {
+            if let Some(sh) = self.section_headers.get(sym.shndx as usize) {
+                return sh.name.clone();
+            }
+        }
+        sym.name
+    }
+
+    /// Notes in all `SHT_NOTE` sections. Fails when the offsets of a note
+    /// overflow.
+    pub fn notes(&self, data: &[u8]) -> Result<Vec<Note>, ParseError> {
+        let r = Reader::new(data, self.big_endian);
+        let mut ret = Vec::new();
+        for sh in &self.section_headers {
+            if sh.sh_type != SectionType::Note {
+                continue;
+            }
+            // Descriptors and notes start at 4-byte boundaries, or 8 in
+            // 8-aligned sections (e.g. `.note.gnu.property` in 64-bit files)
+            let start = sh.sh_offset as usize;
+            let align = if sh.sh_addralign == 8 { 8 } else { 4 };
+            let pad = |offset: usize| -> Option<usize> {
+                start.checked_add((offset - start).checked_add(align - 1)? / align * align)
+            };
+            let overflow = || ParseError::Truncated {
+                what: "note",
+                offset: start,
+                size: sh.sh_size as usize,
+            };
+
+            let end = start.checked_add(sh.sh_size as usize).ok_or_else(overflow)?;
+            let mut offset = start;
+            loop {
+                let name_offset = offset.checked_add(12).ok_or_else(overflow)?;
+                if name_offset > end {
+                    break;
+                }
+                let header = (r.u32(offset), r.u32(offset + 4), r.u32(offset + 8));
+                let (namesz, descsz, ty) = match header {
+                    (Some(namesz), Some(descsz), Some(ty)) =>
+                        (namesz as usize, descsz as usize, ty),
+                    _ =>
+                        break,
+                };
+                let name_end = name_offset.checked_add(namesz).ok_or_else(overflow)?;
+                let desc_offset = pad(name_end).ok_or_else(overflow)?;
+                let desc_end = desc_offset.checked_add(descsz).ok_or_else(overflow)?;
+                let desc = match data.get(desc_offset..desc_end) {
+                    Some(desc) =>
+                        desc.to_vec(),
+                    None =>
+                        break,
+                };
+                let owner = data.get(name_offset..name_end)
+                    .map(|name| {
+                        let name = name.split(|&b| b == 0).next().unwrap_or(&[]);
+                        String::from_utf8_lossy(name).into_owned()
+                    })
+                    .unwrap_or_default();
+                ret.push(Note {
+                    section: sh.name.clone(),
+                    owner,
+                    ty,
+                    desc_offset,
+                    desc,
+                });
+                offset = pad(desc_end).ok_or_else(overflow)?;
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Build-id (`NT_GNU_BUILD_ID` note), which is also the name of the debug
+    /// info file.
+    pub fn build_id(&self, data: &[u8]) -> Option<Vec<u8>> {
+        self.notes(data)
+            .ok()?
+            .into_iter()
+            .find(|note| note.owner == "GNU" && note.ty == NT_GNU_BUILD_ID)
+            .map(|note| note.desc)
+    }
+
+    /// `.gnu_debuglink`: NUL-terminated file name, padded to 4 bytes, then
+    /// the CRC.
+    pub fn debuglink(&self, data: &[u8]) -> Option<DebugLink> {
+        let sh = self.section_headers.iter().find(|sh| sh.name == ".gnu_debuglink")?;
+        let start = sh.sh_offset as usize;
+        let section = data.get(start..start.checked_add(sh.sh_size as usize)?)?;
+        let name_len = section.iter().position(|&b| b == 0)?;
+        let name = String::from_utf8_lossy(&section[..name_len]).into_owned();
+        let crc = Reader::new(section, self.big_endian).u32((name_len + 4) / 4 * 4)?;
+        Some(DebugLink { name, crc })
+    }
+
+    /// Human-readable descriptor of a note: build-id in hex, OS and version
+    /// for ABI tags, features for GNU properties. Other notes are shown in
+    /// hex.
+    pub fn note_value(&self, note: &Note) -> String {
+        if note.owner != "GNU" {
+            return to_hex(&note.desc);
+        }
+        let r = Reader::new(&note.desc, self.big_endian);
+        match note.ty {
+            NT_GNU_BUILD_ID =>
+                to_hex(&note.desc),
+            NT_GNU_ABI_TAG =>
+            match (r.u32(0), r.u32(4), r.u32(8), r.u32(12)) {
+                (Some(os), Some(major), Some(minor), Some(patch)) => {
+                    let os = match os {
+                        0 =>
+                            "Linux".to_owned(),
+                        1 =>
+                            "Hurd".to_owned(),
+                        2 =>
+                            "Solaris".to_owned(),
+                        3 =>
+                            "FreeBSD".to_owned(),
+                        _ =>
+                            format!("OS {}", os),
+                    };
+                    format!("{}, ABI {}.{}.{}", os, major, minor, patch)
+                }
+                _ =>
+                    to_hex(&note.desc),
+            },
+            NT_GNU_PROPERTY_TYPE_0 =>
+                self.gnu_properties(&note.desc),
+            _ =>
+                to_hex(&note.desc),
+        }
+    }
+
+    fn gnu_properties(&self, desc: &[u8]) -> String {
+        let r = Reader::new(desc, self.big_endian);
+        let align = if self.is_64 { 8 } else { 4 };
+        let mut props = Vec::new();
+        let mut offset = 0;
+        while let (Some(ty), Some(size)) = (r.u32(offset), r.u32(offset + 4)) {
+            let data_offset = offset + 8;
+            let value = r.u32(data_offset).unwrap_or(0);
+            let prop = match ty {
+                0xc000_0002 =>
+                    format!("x86 feature: {}", flags_str(value, &[(1, "IBT"), (2, "SHSTK")])),
+                0xc000_8002 =>
+                    format!(
+                        "x86 ISA needed: {}",
+                        flags_str(
+                            value,
+                            &[
+                                (1, "x86-64-baseline"),
+                                (2, "x86-64-v2"),
+                                (4, "x86-64-v3"),
+                                (8, "x86-64-v4"),
+                            ]
+                        )
+                    ),
+                0xc000_0000 =>
+                    format!(
+                        "AArch64 feature: {}",
+                        flags_str(value, &[(1, "BTI"), (2, "PAC"), (4, "GCS")])
+                    ),
+                1 =>
+                    format!("stack size: {}", value),
+                2 =>
+                    "no copy on protected".to_owned(),
+                _ => {
+                    let data_end = data_offset.saturating_add(size as usize);
+                    let data = desc.get(data_offset..data_end).unwrap_or(&[]);
+                    format!("type 0x{:x}: {}", ty, to_hex(data))
+                }
+            };
+            props.push(prop);
+            offset = data_offset + (size as usize).next_multiple_of(align);
+        }
+        props.join("; ")
+    }
+
+    /// File offset of the location a relocation modifies.
+    pub fn relocation_offset(&self, rel: &Relocation) -> Option<usize> {
+        if self.header.e_type == ET_REL {
+            let sh = self.section_headers.get(rel.target_section as usize)?;
+            if sh.sh_type == SectionType::Nobits || rel.offset >= sh.sh_size {
+                return None;
+            }
+            sh.sh_offset.checked_add(rel.offset).map(|offset| offset as usize)
+        } else {
+            self.addr_offset(rel.offset)
+        }
+    }
+
+    /// Are PLT slots resolved when the file is loaded rather than on the
+    /// first call? Set with `-z now`.
+    pub fn bind_now(&self, data: &[u8]) -> bool {
+        self.dynamic(data).iter().any(|entry| match (entry.tag, &entry.value) {
+            // DT_BIND_NOW
+            (24, _) =>
+                true,
+            // DT_FLAGS
+            (30, &DynValue::Num(flags)) =>
+                flags & DF_BIND_NOW != 0,
+            // DT_FLAGS_1
+            (0x6fff_fffb, &DynValue::Num(flags)) =>
+                flags & DF_1_NOW != 0,
+            _ =>
+                false,
+        })
+    }
+
+    /// Stubs in the PLT sections, for x86, x86_64 and AArch64. Empty for
+    /// other architectures.
+    pub fn plt_stubs(&self, data: &[u8]) -> Vec<PltStub> {
+        let mut ret = Vec::new();
+        // i386 PIC stubs address the GOT relative to %ebx, which holds the
+        // address of `.got.plt`
+        let got_plt = self.section_headers
+            .iter()
+            .find(|sh| sh.name == ".got.plt")
+            .map(|sh| sh.sh_addr);
+        for sh in &self.section_headers {
+            if sh.sh_type == SectionType::Nobits
+                || (sh.name != ".plt" && sh.name != ".plt.sec" && sh.name != ".plt.got")
+            {
+                continue;
+            }
+            let contents = match data.get(sh.sh_offset as usize..) {
+                Some(contents) =>
+                    &contents[..cmp::min(contents.len(), sh.sh_size as usize)],
+                None =>
+                    continue,
+            };
+            let mut stub_offset = 0;
+            while stub_offset < contents.len() {
+                let size = match self.header.e_machine {
+                    EM_AARCH64 if sh.name == ".plt" && stub_offset == 0 =>
+                        32,
+                    EM_X86_64 | EM_386 if sh.name == ".plt.got"
+                        && !contents.starts_with(b"\xf3\x0f\x1e") =>
+                        // No `endbr`
+                        8,
+                    EM_X86_64 | EM_386 | EM_AARCH64 =>
+                        16,
+                    _ =>
+                        return ret,
+                };
+                let stub = &contents[stub_offset..cmp::min(stub_offset + size, contents.len())];
+                let addr = sh.sh_addr.wrapping_add(stub_offset as u64);
+                let (got_slot, reloc_index) = match self.header.e_machine {
+                    EM_AARCH64 =>
+                        (aarch64_stub_slot(stub, addr), None),
+                    _ =>
+                        x86_stub_slot(stub, addr, self.is_64, got_plt),
+                };
+                ret.push(PltStub {
+                    addr,
+                    offset: sh.sh_offset as usize + stub_offset,
+                    section: sh.name.clone(),
+                    got_slot,
+                    reloc_index,
+                });
+                stub_offset += size;
+            }
+        }
+        ret
+    }
+
+    /// Entries of the `.dynamic` section, up to `DT_NULL`. Empty for
+    /// statically linked files.
+    pub fn dynamic(&self, data: &[u8]) -> Vec<Dyn> {
+        let r = Reader::new(data, self.big_endian);
+        let mut ret = Vec::new();
+        let sh = match self.section_headers.iter().find(|sh| sh.sh_type == SectionType::Dynamic) {
+            Some(sh) =>
+                sh,
+            None =>
+                return ret,
+        };
+        let strtab_offset = self.section_headers
+            .get(sh.sh_link as usize)
+            .map(|strtab| strtab.sh_offset);
+
+        let entsize = if self.is_64 { 16 } else { 8 };
+        for i in 0..table_entries(sh, entsize, data.len()) {
+            let offset = match entry_offset(sh, i, entsize) {
+                Some(offset) =>
+                    offset,
+                None =>
+                    break,
+            };
+            let (tag, val) = if self.is_64 {
+                match (r.u64(offset), offset.checked_add(8).and_then(|offset| r.u64(offset))) {
+                    (Some(tag), Some(val)) =>
+                        (tag, val),
+                    _ =>
+                        break,
+                }
+            } else {
+                match (r.u32(offset), offset.checked_add(4).and_then(|offset| r.u32(offset))) {
+                    (Some(tag), Some(val)) =>
+                        (u64::from(tag), u64::from(val)),
+                    _ =>
+                        break,
+                }
+            };
+            if tag == 0 {
+                break;
+            }
+
+            let value = match tag_info(tag) {
+                Some((_, TagKind::Str)) => {
+                    let name = strtab_offset
+                        .and_then(|strtab| r.c_str(strtab.saturating_add(val) as usize));
+                    match name {
+                        Some(name) =>
+                            DynValue::Str(name),
+                        None =>
+                            DynValue::Num(val),
+                    }
+                }
+                Some((_, TagKind::Addr)) =>
+                    DynValue::Addr(val),
+                Some((_, TagKind::Num)) | None =>
+                    DynValue::Num(val),
+            };
+            ret.push(Dyn { tag, value });
+        }
+        ret
+    }
+
+    /// File offset of a virtual address, when it's in a section with contents
+    /// in the file.
+    pub fn addr_offset(&self, addr: u64) -> Option<usize> {
+        self.section_headers
+            .iter()
+            .find(|sh| {
+                sh.sh_type != SectionType::Nobits && sh.sh_addr != 0 && addr >= sh.sh_addr
+                    && addr - sh.sh_addr < sh.sh_size
+            })
+            .and_then(|sh| sh.sh_offset.checked_add(addr - sh.sh_addr))
+            .map(|offset| offset as usize)
+    }
+
+    /// Virtual address of a file offset, when it's in a loaded segment.
+    pub fn offset_addr(&self, offset: usize) -> Option<u64> {
+        let offset = offset as u64;
+        self.program_headers
+            .iter()
+            .find(|ph| {
+                ph.p_type == SegmentType::Load && offset >= ph.p_offset
+                    && offset - ph.p_offset < ph.p_filesz
+            })
+            .map(|ph| ph.p_vaddr + (offset - ph.p_offset))
+    }
+
+    /// File offset of a virtual address, when it's in the file part of a
+    /// loaded segment. Unlike `addr_offset()` this works without section
+    /// headers.
+    pub fn load_offset(&self, addr: u64) -> Option<usize> {
+        self.program_headers
+            .iter()
+            .find(|ph| {
+                ph.p_type == SegmentType::Load && addr >= ph.p_vaddr
+                    && addr - ph.p_vaddr < ph.p_filesz
+            })
+            .and_then(|ph| ph.p_offset.checked_add(addr - ph.p_vaddr))
+            .map(|offset| offset as usize)
+    }
+
+    /// Index of the loaded segment with the virtual address, including the
+    /// zero-filled part after the file contents.
+    pub fn load_segment(&self, addr: u64) -> Option<usize> {
+        self.program_headers.iter().position(|ph| {
+            ph.p_type == SegmentType::Load && addr >= ph.p_vaddr && addr - ph.p_vaddr < ph.p_memsz
+        })
+    }
+
+    /// Index of the loaded segment with the file offset.
+    pub fn offset_segment(&self, offset: usize) -> Option<usize> {
+        let offset = offset as u64;
+        self.program_headers.iter().position(|ph| {
+            ph.p_type == SegmentType::Load && offset >= ph.p_offset
+                && offset - ph.p_offset < ph.p_filesz
+        })
+    }
+
+    /// Section with the virtual address, when the file has section headers.
+    pub fn addr_section(&self, addr: u64) -> Option<&SectionHeader> {
+        self.section_headers
+            .iter()
+            .find(|sh| sh.sh_addr != 0 && addr >= sh.sh_addr && addr - sh.sh_addr < sh.sh_size)
+    }
+
+    /// File offset of the symbol's value, when it's defined in a section
+    /// with contents in the file.
+    pub fn symbol_offset(&self, sym: &Symbol) -> Option<usize> {
+        if sym.shndx == SHN_UNDEF || sym.shndx >= SHN_LORESERVE {
+            return None;
+        }
+        let sh = self.section_headers.get(sym.shndx as usize)?;
+        if sh.sh_type == SectionType::Nobits {
+            return None;
+        }
+        // Relocatable files have section-relative values
+        let rel = if self.header.e_type == ET_REL {
+            sym.value
+        } else {
+            sym.value.checked_sub(sh.sh_addr)?
+        };
+        if rel < sh.sh_size {
+            sh.sh_offset.checked_add(rel).map(|offset| offset as usize)
+        } else {
+            None
+        }
+    }
+}
+
+/// A field of the ELF header, for editing the header.
+pub struct HeaderField {
+    pub name: &'static str,
+    pub offset: usize,
+    /// In bytes.
+    pub size: usize,
+    /// Current value, `None` when the header is truncated.
+    pub value: Option<u64>,
+    /// Valid values, inclusive.
+    pub min: u64,
+    pub max: u64,
+    big_endian: bool,
+}
+
+impl HeaderField {
+    /// Bytes of the value, in the byte order of the file. Fails when the value
+    /// is out of the range of the field.
+    pub fn encode(&self, value: u64) -> Result<Vec<u8>, String> {
+        if value < self.min || value > self.max {
+            return Err(format!(
+                "{} must be in 0x{:x}-0x{:x}",
+                self.name, self.min, self.max
+            ));
+        }
+        let mut bytes: Vec<u8> = (0..self.size).map(|i| (value >> (i * 8)) as u8).collect();
+        if self.big_endian {
+            bytes.reverse();
+        }
+        Ok(bytes)
+    }
+}
+
+/// Fields of the ELF header. Only the magic needs to be valid, so that broken
+/// headers can be fixed: the class and byte order are read from `e_ident`,
+/// and 64-bit little endian is assumed when they're invalid.
+pub fn header_fields(data: &[u8]) -> Option<Vec<HeaderField>> {
+    if data.get(0..4) != Some(ELF_MAGIC) {
+        return None;
+    }
+    let is_64 = data.get(4) != Some(&1);
+    let big_endian = data.get(5) == Some(&2);
+    let r = Reader::new(data, big_endian);
+
+    let word = if is_64 { 8 } else { 4 };
+    // Offsets of the fields after e_flags, which depend on the word size
+    let rest = if is_64 { 52 } else { 40 };
+    let layout: &[(&'static str, usize, usize)] = &[
+        ("EI_CLASS", 4, 1),
+        ("EI_DATA", 5, 1),
+        ("EI_VERSION", 6, 1),
+        ("EI_OSABI", 7, 1),
+        ("EI_ABIVERSION", 8, 1),
+        ("e_type", 16, 2),
+        ("e_machine", 18, 2),
+        ("e_version", 20, 4),
+        ("e_entry", 24, word),
+        ("e_phoff", 24 + word, word),
+        ("e_shoff", 24 + 2 * word, word),
+        ("e_flags", 24 + 3 * word, 4),
+        ("e_ehsize", rest, 2),
+        ("e_phentsize", rest + 2, 2),
+        ("e_phnum", rest + 4, 2),
+        ("e_shentsize", rest + 6, 2),
+        ("e_shnum", rest + 8, 2),
+        ("e_shstrndx", rest + 10, 2),
+    ];
+
+    let fields = layout
+        .iter()
+        .map(|&(name, offset, size)| {
+            let size_max = if size == 8 { !0 } else { (1 << (size * 8)) - 1 };
+            let (min, max) = match name {
+                // 32 or 64-bit, little or big endian
+                "EI_CLASS" | "EI_DATA" =>
+                    (1, 2),
+                // Offsets of the tables are in the file
+                "e_phoff" | "e_shoff" =>
+                    (0, cmp::min(size_max, data.len() as u64)),
+                _ =>
+                    (0, size_max),
+            };
+            HeaderField {
+                name,
+                offset,
+                size,
+                value: match size {
+                    1 =>
+                        r.u8(offset).map(u64::from),
+                    2 =>
+                        r.u16(offset).map(u64::from),
+                    4 =>
+                        r.u32(offset).map(u64::from),
+                    _ =>
+                        r.u64(offset),
+                },
+                min,
+                max,
+                big_endian,
+            }
+        })
+        .collect();
+    Some(fields)
+}
+
+fn parse_header(r: Reader, is_64: bool) -> Option<Header> {
+    if is_64 {
+        Some(Header {
+            e_type: r.u16(16)?,
+            e_machine: r.u16(18)?,
+            e_phoff: r.u64(32)?,
+            e_shoff: r.u64(40)?,
+            e_phnum: r.u16(56)?,
+            e_shnum: r.u16(60)?,
+            e_shstrndx: r.u16(62)?,
+        })
+    } else {
+        Some(Header {
+            e_type: r.u16(16)?,
+            e_machine: r.u16(18)?,
+            e_phoff: u64::from(r.u32(28)?),
+            e_shoff: u64::from(r.u32(32)?),
+            e_phnum: r.u16(44)?,
+            e_shnum: r.u16(48)?,
+            e_shstrndx: r.u16(50)?,
+        })
+    }
+}
+
+fn parse_program_header(r: Reader, offset: usize, is_64: bool) -> Option<ProgramHeader> {
+    if is_64 {
+        Some(ProgramHeader {
+            p_type: SegmentType::from_u32(r.u32(offset)?),
+            p_offset: r.u64(offset + 8)?,
+            p_vaddr: r.u64(offset + 16)?,
+            p_filesz: r.u64(offset + 32)?,
+            p_memsz: r.u64(offset + 40)?,
+        })
+    } else {
+        Some(ProgramHeader {
+            p_type: SegmentType::from_u32(r.u32(offset)?),
+            p_offset: u64::from(r.u32(offset + 4)?),
+            p_vaddr: u64::from(r.u32(offset + 8)?),
+            p_filesz: u64::from(r.u32(offset + 16)?),
+            p_memsz: u64::from(r.u32(offset + 20)?),
+        })
+    }
+}
+
+fn parse_section_header(r: Reader, offset: usize, is_64: bool) -> Option<SectionHeader> {
+    if is_64 {
+        Some(SectionHeader {
+            name: String::new(),
+            sh_name: r.u32(offset)?,
+            sh_type: SectionType::from_u32(r.u32(offset + 4)?),
+            sh_addr: r.u64(offset + 16)?,
+            sh_offset: r.u64(offset + 24)?,
+            sh_size: r.u64(offset + 32)?,
+            sh_link: r.u32(offset + 40)?,
+            sh_info: r.u32(offset + 44)?,
+            sh_addralign: r.u64(offset + 48)?,
+        })
+    } else {
+        Some(SectionHeader {
+            name: String::new(),
+            sh_name: r.u32(offset)?,
+            sh_type: SectionType::from_u32(r.u32(offset + 4)?),
+            sh_addr: u64::from(r.u32(offset + 12)?),
+            sh_offset: u64::from(r.u32(offset + 16)?),
+            sh_size: u64::from(r.u32(offset + 20)?),
+            sh_link: r.u32(offset + 24)?,
+            sh_info: r.u32(offset + 28)?,
+            sh_addralign: u64::from(r.u32(offset + 32)?),
+        })
+    }
+}
+
+/// Entries in a table of `entsize`-byte entries in the section. Clamped to
+/// the entries that fit in the file, `sh_size` of a corrupted file can be
+/// huge.
+fn table_entries(sh: &SectionHeader, entsize: usize, data_len: usize) -> usize {
+    let in_file = data_len.saturating_sub(sh.sh_offset as usize) / entsize;
+    cmp::min(sh.sh_size as usize / entsize, in_file)
+}
+
+/// Offset of the entry `idx` of a table in the section.
+fn entry_offset(sh: &SectionHeader, idx: usize, entsize: usize) -> Option<usize> {
+    idx.checked_mul(entsize)?.checked_add(sh.sh_offset as usize)
+}
+
+fn parse_symbol(r: Reader, offset: usize, is_64: bool, strtab_offset: u64) -> Option<Symbol> {
+    // The fields below can't overflow
+    offset.checked_add(if is_64 { 24 } else { 16 })?;
+    let (st_name, value, size, info, shndx) = if is_64 {
+        (
+            r.u32(offset)?,
+            r.u64(offset + 8)?,
+            r.u64(offset + 16)?,
+            r.u8(offset + 4)?,
+            r.u16(offset + 6)?,
+        )
+    } else {
+        (
+            r.u32(offset)?,
+            u64::from(r.u32(offset + 4)?),
+            u64::from(r.u32(offset + 8)?),
+            r.u8(offset + 12)?,
+            r.u16(offset + 14)?,
+        )
+    };
+    Some(Symbol {
+        name: r.c_str(strtab_offset.saturating_add(u64::from(st_name)) as usize)?,
+        value,
+        size,
+        bind: info >> 4,
+        ty: info & 0xf,
+        shndx,
+        dynamic: false,
+    })
+}
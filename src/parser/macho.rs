@@ -0,0 +1,527 @@
+////////////////////////////////////////////////////////////////////////////////
+// Mach-O parser. Supports 64-bit images of both endiannesses.
+////////////////////////////////////////////////////////////////////////////////
+
+use parser::{flags_str, ParseError, Reader};
+
+/// `magic` of 64-bit Mach-O headers, read in the file's endianness.
+pub const MH_MAGIC_64: u32 = 0xfeed_facf;
+/// 32-bit images, which are not supported.
+pub const MH_MAGIC: u32 = 0xfeed_face;
+
+/// `MH_MAGIC_64` in little endian files.
+const MAGIC_64_LE: &[u8] = b"\xcf\xfa\xed\xfe";
+
+/// Size of `mach_header_64`, load commands follow the header.
+pub const HEADER_SIZE: usize = 32;
+
+/// Load command types.
+pub const LC_SEGMENT_64: u32 = 0x19;
+pub const LC_LOAD_DYLIB: u32 = 0xc;
+pub const LC_LOAD_WEAK_DYLIB: u32 = 0x8000_0018;
+pub const LC_REEXPORT_DYLIB: u32 = 0x8000_001f;
+pub const LC_MAIN: u32 = 0x8000_0028;
+
+/// Sizes of `segment_command_64` and `section_64`.
+const SEGMENT_SIZE: usize = 72;
+const SECTION_SIZE: usize = 80;
+
+/// Returns whether the data starts with a Mach-O magic in either endianness.
+/// 32-bit images are detected too, so that `parse()` can tell why they can't
+/// be parsed.
+pub fn is_macho(data: &[u8]) -> bool {
+    let magic = data.get(0..4);
+    let magics: [&[u8]; 4] = [
+        MAGIC_64_LE,
+        b"\xfe\xed\xfa\xcf",
+        b"\xce\xfa\xed\xfe",
+        b"\xfe\xed\xfa\xce",
+    ];
+    magics.iter().any(|&m| magic == Some(m))
+}
+
+pub struct MachO {
+    pub big_endian: bool,
+    pub header: Header,
+    pub load_commands: Vec<LoadCommand>,
+}
+
+pub struct Header {
+    pub cpu_type: u32,
+    pub cpu_subtype: u32,
+    pub file_type: u32,
+    pub n_cmds: u32,
+    pub size_of_cmds: u32,
+    pub flags: u32,
+}
+
+pub struct LoadCommand {
+    /// File offset of the command.
+    pub offset: usize,
+    pub cmd: u32,
+    pub size: u32,
+    pub kind: LoadCommandKind,
+}
+
+pub enum LoadCommandKind {
+    /// `LC_SEGMENT_64`
+    Segment(Segment),
+    /// `LC_MAIN`
+    Main {
+        /// File offset of the entry point.
+        entry_offset: u64,
+        stack_size: u64,
+    },
+    /// `LC_LOAD_DYLIB` and its weak and re-export variants.
+    Dylib(Dylib),
+    /// Commands without parsed contents.
+    Other,
+}
+
+pub struct Segment {
+    /// Up to 16 bytes, NUL-padded.
+    pub name: String,
+    pub vm_addr: u64,
+    pub vm_size: u64,
+    pub file_offset: u64,
+    pub file_size: u64,
+    pub max_prot: u32,
+    pub init_prot: u32,
+    pub sections: Vec<Section>,
+}
+
+pub struct Section {
+    pub name: String,
+    pub segment_name: String,
+    pub addr: u64,
+    pub size: u64,
+    /// File offset of the contents, 0 for zero-fill sections like `__bss`.
+    pub offset: u32,
+    /// Power of two.
+    pub align: u32,
+    pub flags: u32,
+}
+
+pub struct Dylib {
+    /// Install name, e.g. "/usr/lib/libSystem.B.dylib".
+    pub name: String,
+    pub current_version: u32,
+    pub compatibility_version: u32,
+}
+
+/// Segment protections.
+pub const VM_PROT_READ: u32 = 0x1;
+pub const VM_PROT_WRITE: u32 = 0x2;
+pub const VM_PROT_EXECUTE: u32 = 0x4;
+
+/// Section types, in the low byte of section flags.
+const SECTION_TYPE: u32 = 0xff;
+const S_ZEROFILL: u32 = 0x1;
+const S_GB_ZEROFILL: u32 = 0xc;
+const S_THREAD_LOCAL_ZEROFILL: u32 = 0x12;
+
+impl Header {
+    pub fn cpu_type_str(&self) -> Option<&'static str> {
+        match self.cpu_type {
+            0x0100_0007 =>
+                Some("x86-64"),
+            0x0100_000c =>
+                Some("ARM64"),
+            0x0200_000c =>
+                Some("ARM64_32"),
+            0x0100_0012 =>
+                Some("PowerPC 64"),
+            _ =>
+                None,
+        }
+    }
+
+    pub fn file_type_str(&self) -> Option<&'static str> {
+        match self.file_type {
+            0x1 =>
+                Some("object"),
+            0x2 =>
+                Some("executable"),
+            0x4 =>
+                Some("core"),
+            0x5 =>
+                Some("preloaded executable"),
+            0x6 =>
+                Some("dynamic library"),
+            0x7 =>
+                Some("dynamic linker"),
+            0x8 =>
+                Some("bundle"),
+            0xa =>
+                Some("debug symbols"),
+            0xb =>
+                Some("kernel extension"),
+            0xc =>
+                Some("fileset"),
+            _ =>
+                None,
+        }
+    }
+
+    pub fn flags_str(&self) -> String {
+        flags_str(
+            self.flags,
+            &[
+                (0x0000_0001, "NOUNDEFS"),
+                (0x0000_0004, "DYLDLINK"),
+                (0x0000_0008, "BINDATLOAD"),
+                (0x0000_0010, "PREBOUND"),
+                (0x0000_0080, "TWOLEVEL"),
+                (0x0000_0100, "FORCE_FLAT"),
+                (0x0000_2000, "SUBSECTIONS_VIA_SYMBOLS"),
+                (0x0000_8000, "WEAK_DEFINES"),
+                (0x0001_0000, "BINDS_TO_WEAK"),
+                (0x0002_0000, "ALLOW_STACK_EXECUTION"),
+                (0x0020_0000, "PIE"),
+                (0x0080_0000, "HAS_TLV_DESCRIPTORS"),
+                (0x0100_0000, "NO_HEAP_EXECUTION"),
+                (0x0200_0000, "APP_EXTENSION_SAFE"),
+            ],
+        )
+    }
+}
+
+impl LoadCommand {
+    pub fn cmd_str(&self) -> Option<&'static str> {
+        match self.cmd {
+            0x2 =>
+                Some("LC_SYMTAB"),
+            0xb =>
+                Some("LC_DYSYMTAB"),
+            LC_LOAD_DYLIB =>
+                Some("LC_LOAD_DYLIB"),
+            0xd =>
+                Some("LC_ID_DYLIB"),
+            0xe =>
+                Some("LC_LOAD_DYLINKER"),
+            LC_SEGMENT_64 =>
+                Some("LC_SEGMENT_64"),
+            0x1b =>
+                Some("LC_UUID"),
+            0x1d =>
+                Some("LC_CODE_SIGNATURE"),
+            0x1e =>
+                Some("LC_SEGMENT_SPLIT_INFO"),
+            0x22 =>
+                Some("LC_DYLD_INFO"),
+            0x24 =>
+                Some("LC_VERSION_MIN_MACOSX"),
+            0x25 =>
+                Some("LC_VERSION_MIN_IPHONEOS"),
+            0x26 =>
+                Some("LC_FUNCTION_STARTS"),
+            0x29 =>
+                Some("LC_DATA_IN_CODE"),
+            0x2a =>
+                Some("LC_SOURCE_VERSION"),
+            0x32 =>
+                Some("LC_BUILD_VERSION"),
+            LC_LOAD_WEAK_DYLIB =>
+                Some("LC_LOAD_WEAK_DYLIB"),
+            0x8000_001c =>
+                Some("LC_RPATH"),
+            LC_REEXPORT_DYLIB =>
+                Some("LC_REEXPORT_DYLIB"),
+            0x8000_0022 =>
+                Some("LC_DYLD_INFO_ONLY"),
+            LC_MAIN =>
+                Some("LC_MAIN"),
+            0x8000_0033 =>
+                Some("LC_DYLD_EXPORTS_TRIE"),
+            0x8000_0034 =>
+                Some("LC_DYLD_CHAINED_FIXUPS"),
+            _ =>
+                None,
+        }
+    }
+}
+
+/// Protections in `ls` style, e.g. "r-x".
+pub fn prot_str(prot: u32) -> String {
+    let flag = |bit: u32, ch: char| if prot & bit != 0 { ch } else { '-' };
+    let mut ret = String::new();
+    ret.push(flag(VM_PROT_READ, 'r'));
+    ret.push(flag(VM_PROT_WRITE, 'w'));
+    ret.push(flag(VM_PROT_EXECUTE, 'x'));
+    ret
+}
+
+/// Dylib versions are packed as X.Y.Z in 16, 8, and 8 bits.
+pub fn version_str(version: u32) -> String {
+    format!("{}.{}.{}", version >> 16, (version >> 8) & 0xff, version & 0xff)
+}
+
+impl Section {
+    /// Whether the section takes no space in the file.
+    pub fn is_zerofill(&self) -> bool {
+        let ty = self.flags & SECTION_TYPE;
+        ty == S_ZEROFILL || ty == S_GB_ZEROFILL || ty == S_THREAD_LOCAL_ZEROFILL
+    }
+}
+
+impl MachO {
+    /// Fails when the data is not a 64-bit Mach-O image, or the load commands
+    /// are truncated.
+    pub fn parse(data: &[u8]) -> Result<MachO, ParseError> {
+        let magic_le = Reader::new(data, false).u32(0);
+        let big_endian = match magic_le {
+            Some(MH_MAGIC_64) =>
+                false,
+            Some(magic) if magic.swap_bytes() == MH_MAGIC_64 =>
+                true,
+            Some(magic) if magic == MH_MAGIC || magic.swap_bytes() == MH_MAGIC =>
+                return Err(ParseError::BadMagic {
+                    format: "a 64-bit Mach-O",
+                    offset: 0,
+                    magic: MAGIC_64_LE,
+                }),
+            _ =>
+                return Err(ParseError::BadMagic {
+                    format: "a Mach-O",
+                    offset: 0,
+                    magic: MAGIC_64_LE,
+                }),
+        };
+        let r = Reader::new(data, big_endian);
+
+        let header = parse_header(r).ok_or(truncated("Mach-O header", 0, HEADER_SIZE))?;
+
+        let mut load_commands = Vec::new();
+        let mut offset = HEADER_SIZE;
+        for _ in 0..header.n_cmds {
+            let (cmd, size) = match (r.u32(offset), r.u32(offset + 4)) {
+                (Some(cmd), Some(size)) =>
+                    (cmd, size),
+                _ =>
+                    return Err(truncated("load commands", offset, 8)),
+            };
+            // Commands are 8-byte aligned in 64-bit images
+            if size < 8 || size % 8 != 0 {
+                return Err(ParseError::Invalid {
+                    field: "load command size",
+                    offset: offset + 4,
+                    value: u64::from(size),
+                    expected: "a multiple of 8",
+                });
+            }
+            let end = match offset.checked_add(size as usize) {
+                Some(end) if end <= data.len() =>
+                    end,
+                _ =>
+                    return Err(truncated("load commands", offset, size as usize)),
+            };
+            let kind = parse_load_command(r, offset, cmd, size)?;
+            load_commands.push(LoadCommand {
+                offset,
+                cmd,
+                size,
+                kind,
+            });
+            offset = end;
+        }
+
+        Ok(MachO {
+            big_endian,
+            header,
+            load_commands,
+        })
+    }
+
+    pub fn segments(&self) -> Vec<&Segment> {
+        self.load_commands
+            .iter()
+            .filter_map(|cmd| match cmd.kind {
+                LoadCommandKind::Segment(ref segment) =>
+                    Some(segment),
+                _ =>
+                    None,
+            })
+            .collect()
+    }
+
+    /// Libraries loaded by `LC_LOAD_DYLIB` and variants, with their load
+    /// commands.
+    pub fn dylibs(&self) -> Vec<(&LoadCommand, &Dylib)> {
+        self.load_commands
+            .iter()
+            .filter_map(|cmd| match cmd.kind {
+                LoadCommandKind::Dylib(ref dylib) =>
+                    Some((cmd, dylib)),
+                _ =>
+                    None,
+            })
+            .collect()
+    }
+
+    /// Virtual address of a file offset, when it's in a segment.
+    pub fn offset_addr(&self, offset: u64) -> Option<u64> {
+        self.segments()
+            .into_iter()
+            .find(|segment| {
+                offset >= segment.file_offset && offset - segment.file_offset < segment.file_size
+            })
+            .map(|segment| segment.vm_addr.wrapping_add(offset - segment.file_offset))
+    }
+
+    /// `LC_MAIN` command, for executables.
+    pub fn main(&self) -> Option<(&LoadCommand, u64)> {
+        self.load_commands
+            .iter()
+            .filter_map(|cmd| match cmd.kind {
+                LoadCommandKind::Main { entry_offset, .. } =>
+                    Some((cmd, entry_offset)),
+                _ =>
+                    None,
+            })
+            .next()
+    }
+}
+
+fn parse_header(r: Reader) -> Option<Header> {
+    Some(Header {
+        cpu_type: r.u32(4)?,
+        cpu_subtype: r.u32(8)?,
+        file_type: r.u32(12)?,
+        n_cmds: r.u32(16)?,
+        size_of_cmds: r.u32(20)?,
+        flags: r.u32(24)?,
+    })
+}
+
+fn truncated(what: &'static str, offset: usize, size: usize) -> ParseError {
+    ParseError::Truncated { what, offset, size }
+}
+
+/// Load commands smaller than their fixed-size part.
+fn check_cmd_size(offset: usize, size: u32, min: usize) -> Result<(), ParseError> {
+    if (size as usize) < min {
+        Err(ParseError::Invalid {
+            field: "load command size",
+            offset: offset + 4,
+            value: u64::from(size),
+            expected: "at least the size of the command's fields",
+        })
+    } else {
+        Ok(())
+    }
+}
+
+/// Parses a load command that is known to be in the file.
+fn parse_load_command(
+    r: Reader,
+    offset: usize,
+    cmd: u32,
+    size: u32,
+) -> Result<LoadCommandKind, ParseError> {
+    match cmd {
+        LC_SEGMENT_64 => {
+            let segment = parse_segment(r, offset, size)?;
+            Ok(LoadCommandKind::Segment(segment))
+        }
+        LC_MAIN => {
+            check_cmd_size(offset, size, 24)?;
+            let fields = (r.u64(offset + 8), r.u64(offset + 16));
+            match fields {
+                (Some(entry_offset), Some(stack_size)) =>
+                    Ok(LoadCommandKind::Main {
+                        entry_offset,
+                        stack_size,
+                    }),
+                _ =>
+                    Err(truncated("LC_MAIN", offset, 24)),
+            }
+        }
+        LC_LOAD_DYLIB | LC_LOAD_WEAK_DYLIB | LC_REEXPORT_DYLIB => {
+            check_cmd_size(offset, size, 24)?;
+            let fields = (r.u32(offset + 8), r.u32(offset + 16), r.u32(offset + 20));
+            let (name_offset, current_version, compatibility_version) = match fields {
+                (Some(a), Some(b), Some(c)) =>
+                    (a, b, c),
+                _ =>
+                    return Err(truncated("dylib command", offset, 24)),
+            };
+            // The name is in the command, NUL-padded
+            let name = if name_offset < size {
+                r.c_str(offset + name_offset as usize).unwrap_or_default()
+            } else {
+                String::new()
+            };
+            Ok(LoadCommandKind::Dylib(Dylib {
+                name,
+                current_version,
+                compatibility_version,
+            }))
+        }
+        _ =>
+            Ok(LoadCommandKind::Other),
+    }
+}
+
+fn parse_segment(r: Reader, offset: usize, size: u32) -> Result<Segment, ParseError> {
+    check_cmd_size(offset, size, SEGMENT_SIZE)?;
+    let n_sects = r.u32(offset + 64).ok_or(truncated("segment command", offset, SEGMENT_SIZE))?;
+    if u64::from(n_sects) * SECTION_SIZE as u64 > u64::from(size) - SEGMENT_SIZE as u64 {
+        return Err(ParseError::Invalid {
+            field: "number of sections",
+            offset: offset + 64,
+            value: u64::from(n_sects),
+            expected: "sections that fit in the segment command",
+        });
+    }
+    let sections_offset = offset + SEGMENT_SIZE;
+    let mut sections = Vec::with_capacity(n_sects as usize);
+    for i in 0..n_sects as usize {
+        let section = parse_section(r, sections_offset + i * SECTION_SIZE).ok_or(truncated(
+            "section table",
+            sections_offset,
+            n_sects as usize * SECTION_SIZE,
+        ))?;
+        sections.push(section);
+    }
+    parse_segment_fields(r, offset, sections)
+        .ok_or(truncated("segment command", offset, SEGMENT_SIZE))
+}
+
+fn parse_segment_fields(r: Reader, offset: usize, sections: Vec<Section>) -> Option<Segment> {
+    Some(Segment {
+        name: fixed_str(r, offset + 8)?,
+        vm_addr: r.u64(offset + 24)?,
+        vm_size: r.u64(offset + 32)?,
+        file_offset: r.u64(offset + 40)?,
+        file_size: r.u64(offset + 48)?,
+        max_prot: r.u32(offset + 56)?,
+        init_prot: r.u32(offset + 60)?,
+        sections,
+    })
+}
+
+fn parse_section(r: Reader, offset: usize) -> Option<Section> {
+    Some(Section {
+        name: fixed_str(r, offset)?,
+        segment_name: fixed_str(r, offset + 16)?,
+        addr: r.u64(offset + 32)?,
+        size: r.u64(offset + 40)?,
+        offset: r.u32(offset + 48)?,
+        align: r.u32(offset + 52)?,
+        flags: r.u32(offset + 64)?,
+    })
+}
+
+/// 16-byte NUL-padded name, used for segment and section names.
+fn fixed_str(r: Reader, offset: usize) -> Option<String> {
+    let mut bytes = Vec::with_capacity(16);
+    for i in 0..16 {
+        match r.u8(offset + i)? {
+            0 =>
+                break,
+            b =>
+                bytes.push(b),
+        }
+    }
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
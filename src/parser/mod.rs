@@ -0,0 +1,200 @@
+////////////////////////////////////////////////////////////////////////////////
+// Parsers for executable formats
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cmp;
+use std::fmt;
+
+use json::{self, Json};
+
+pub mod elf;
+pub mod macho;
+pub mod pe;
+
+/// Why a file couldn't be parsed. Shown to the user, and collected for bug
+/// reports with `report()`.
+#[derive(Debug)]
+pub enum ParseError {
+    /// The file doesn't have the magic number of the format at the offset.
+    BadMagic {
+        /// The format's name with an article, e.g. "an ELF".
+        format: &'static str,
+        offset: usize,
+        magic: &'static [u8],
+    },
+    /// A header field at the offset has a value the parser can't handle, e.g.
+    /// ELF class 3.
+    Invalid {
+        field: &'static str,
+        offset: usize,
+        value: u64,
+        /// Values the parser can handle, e.g. "1 or 2".
+        expected: &'static str,
+    },
+    /// A structure of `size` bytes at the offset is (partly) beyond the end of
+    /// the file.
+    Truncated {
+        what: &'static str,
+        offset: usize,
+        size: usize,
+    },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParseError::BadMagic { format, .. } =>
+                write!(f, "Not {} file", format),
+            ParseError::Invalid { field, value, .. } =>
+                write!(f, "Invalid {}: {}", field, value),
+            ParseError::Truncated { what, .. } =>
+                write!(f, "Truncated file: {} is out of the file", what),
+        }
+    }
+}
+
+/// Details of an error found while parsing a file, for users to attach to bug
+/// reports or to document corrupt files. Listed with `:report`, and exported
+/// as JSON with `:report <file>`.
+pub struct Report {
+    /// e.g. "ELF", "template".
+    pub parser: &'static str,
+    /// What was being parsed, e.g. "section header table".
+    pub stage: String,
+    /// Offset of the structure or field in the file.
+    pub offset: Option<usize>,
+    pub expected: String,
+    pub actual: String,
+    /// The error as shown in the info line.
+    pub message: String,
+}
+
+impl Report {
+    pub fn to_json(&self) -> Json {
+        let offset = match self.offset {
+            Some(offset) =>
+                Json::Num(offset as u64),
+            None =>
+                Json::Null,
+        };
+        Json::Obj(vec![
+            ("parser", json::str(self.parser)),
+            ("stage", json::str(&self.stage)),
+            ("offset", offset),
+            ("expected", json::str(&self.expected)),
+            ("actual", json::str(&self.actual)),
+            ("message", json::str(&self.message)),
+        ])
+    }
+}
+
+impl ParseError {
+    /// `data` is the parsed file, for the actual bytes and size.
+    pub fn report(&self, parser: &'static str, data: &[u8]) -> Report {
+        let (stage, offset, expected, actual) = match *self {
+            ParseError::BadMagic {
+                offset, magic, ..
+            } => {
+                let end = cmp::min(offset.saturating_add(magic.len()), data.len());
+                let found = data.get(offset..end).unwrap_or(&[]);
+                ("magic number", offset, hex_bytes(magic), hex_bytes(found))
+            }
+            ParseError::Invalid {
+                field,
+                offset,
+                value,
+                expected,
+            } =>
+                (field, offset, expected.to_owned(), value.to_string()),
+            ParseError::Truncated { what, offset, size } =>
+                (
+                    what,
+                    offset,
+                    format!("{} bytes at 0x{:x}", size, offset),
+                    format!("file size {}", data.len()),
+                ),
+        };
+        Report {
+            parser,
+            stage: stage.to_owned(),
+            offset: Some(offset),
+            expected,
+            actual,
+            message: self.to_string(),
+        }
+    }
+}
+
+/// e.g. "7f 45 4c 46", or "nothing" when there are no bytes.
+fn hex_bytes(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return "nothing".to_owned();
+    }
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    hex.join(" ")
+}
+
+/// Reads integers of the file's endianness. Out-of-bounds reads return `None`,
+/// so parsers don't panic on truncated or corrupt files.
+#[derive(Clone, Copy)]
+pub struct Reader<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8], big_endian: bool) -> Reader<'a> {
+        Reader { data, big_endian }
+    }
+
+    pub fn u8(&self, offset: usize) -> Option<u8> {
+        self.data.get(offset).cloned()
+    }
+
+    pub fn u16(&self, offset: usize) -> Option<u16> {
+        self.uint(offset, 2).map(|n| n as u16)
+    }
+
+    pub fn u32(&self, offset: usize) -> Option<u32> {
+        self.uint(offset, 4).map(|n| n as u32)
+    }
+
+    pub fn u64(&self, offset: usize) -> Option<u64> {
+        self.uint(offset, 8)
+    }
+
+    fn uint(&self, offset: usize, size: usize) -> Option<u64> {
+        let bytes = self.data.get(offset..offset.checked_add(size)?)?;
+        let mut ret = 0u64;
+        for i in 0..size {
+            let i = if self.big_endian { i } else { size - 1 - i };
+            ret = (ret << 8) | u64::from(bytes[i]);
+        }
+        Some(ret)
+    }
+
+    /// NUL-terminated string at the offset. Invalid UTF-8 is replaced.
+    pub fn c_str(&self, offset: usize) -> Option<String> {
+        let bytes = self.data.get(offset..)?;
+        let len = bytes.iter().position(|&b| b == 0)?;
+        Some(String::from_utf8_lossy(&bytes[..len]).into_owned())
+    }
+}
+
+/// Names of bits set in `flags`, or the number when no names are known.
+pub fn flags_str(flags: u32, names: &[(u32, &str)]) -> String {
+    let mut ret: Vec<String> = names
+        .iter()
+        .filter(|&&(bit, _)| flags & bit != 0)
+        .map(|&(_, name)| name.to_owned())
+        .collect();
+    let unknown = names.iter().fold(flags, |flags, &(bit, _)| flags & !bit);
+    if unknown != 0 {
+        ret.push(format!("0x{:x}", unknown));
+    }
+    if ret.is_empty() {
+        "none".to_owned()
+    } else {
+        ret.join(", ")
+    }
+}
@@ -0,0 +1,535 @@
+////////////////////////////////////////////////////////////////////////////////
+// PE/COFF parser. Supports PE32 and PE32+ (64-bit) images.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cmp;
+
+use parser::{flags_str, ParseError, Reader};
+
+/// Signatures of the DOS header and the PE header.
+const DOS_MAGIC: &[u8] = b"MZ";
+const PE_MAGIC: &[u8] = b"PE\0\0";
+
+/// `Magic` of optional headers.
+pub const PE32_MAGIC: u16 = 0x10b;
+pub const PE32_PLUS_MAGIC: u16 = 0x20b;
+
+/// Indices of data directories.
+pub const EXPORT_DIRECTORY: usize = 0;
+pub const IMPORT_DIRECTORY: usize = 1;
+
+/// How many import descriptors and functions are read at most, to avoid
+/// spending a long time on corrupt tables.
+const MAX_ENTRIES: usize = 100_000;
+
+pub struct Pe {
+    /// File offset of the "PE\0\0" signature, `e_lfanew` of the DOS header.
+    pub pe_offset: usize,
+    pub coff_header: CoffHeader,
+    /// Object files don't have optional headers.
+    pub optional_header: Option<OptionalHeader>,
+    pub sections: Vec<Section>,
+}
+
+pub struct CoffHeader {
+    pub machine: u16,
+    pub number_of_sections: u16,
+    pub time_date_stamp: u32,
+    pub size_of_optional_header: u16,
+    pub characteristics: u16,
+}
+
+pub struct OptionalHeader {
+    /// `PE32_MAGIC` or `PE32_PLUS_MAGIC`.
+    pub magic: u16,
+    pub address_of_entry_point: u32,
+    pub image_base: u64,
+    pub section_alignment: u32,
+    pub file_alignment: u32,
+    pub subsystem: u16,
+    pub dll_characteristics: u16,
+    pub data_directories: Vec<DataDirectory>,
+}
+
+pub struct DataDirectory {
+    pub virtual_address: u32,
+    pub size: u32,
+}
+
+pub struct Section {
+    /// Up to 8 bytes, NUL-padded.
+    pub name: String,
+    pub virtual_size: u32,
+    pub virtual_address: u32,
+    pub size_of_raw_data: u32,
+    pub pointer_to_raw_data: u32,
+    pub characteristics: u32,
+}
+
+/// Section characteristics.
+pub const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+pub const IMAGE_SCN_MEM_READ: u32 = 0x4000_0000;
+pub const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+impl Section {
+    /// Permissions in `ls` style, e.g. "r-x".
+    pub fn perms_str(&self) -> String {
+        let flag = |bit: u32, ch: char| if self.characteristics & bit != 0 { ch } else { '-' };
+        let mut ret = String::new();
+        ret.push(flag(IMAGE_SCN_MEM_READ, 'r'));
+        ret.push(flag(IMAGE_SCN_MEM_WRITE, 'w'));
+        ret.push(flag(IMAGE_SCN_MEM_EXECUTE, 'x'));
+        ret
+    }
+}
+
+/// A function imported from a DLL.
+pub struct Import {
+    pub dll: String,
+    /// `None` for functions imported by ordinal.
+    pub name: Option<String>,
+    /// Ordinal for functions imported by ordinal, otherwise the hint (index
+    /// in the DLL's export name table).
+    pub ordinal: u16,
+    /// File offset of the entry in the import address table, which the
+    /// loader overwrites with the function's address.
+    pub iat_offset: Option<usize>,
+}
+
+/// A function exported from the image.
+pub struct Export {
+    /// `None` for functions exported only by ordinal.
+    pub name: Option<String>,
+    pub ordinal: u32,
+    pub rva: u32,
+    /// Forwarded exports are in another DLL, e.g. "NTDLL.RtlAllocateHeap".
+    pub forwarder: Option<String>,
+}
+
+impl CoffHeader {
+    pub fn machine_str(&self) -> Option<&'static str> {
+        match self.machine {
+            0x14c =>
+                Some("i386"),
+            0x1c0 =>
+                Some("ARM"),
+            0x1c4 =>
+                Some("ARMv7 Thumb-2"),
+            0x200 =>
+                Some("IA-64"),
+            0x8664 =>
+                Some("x86-64"),
+            0xaa64 =>
+                Some("ARM64"),
+            _ =>
+                None,
+        }
+    }
+
+    pub fn characteristics_str(&self) -> String {
+        flags_str(
+            u32::from(self.characteristics),
+            &[
+                (0x0001, "RELOCS_STRIPPED"),
+                (0x0002, "EXECUTABLE_IMAGE"),
+                (0x0004, "LINE_NUMS_STRIPPED"),
+                (0x0008, "LOCAL_SYMS_STRIPPED"),
+                (0x0020, "LARGE_ADDRESS_AWARE"),
+                (0x0100, "32BIT_MACHINE"),
+                (0x0200, "DEBUG_STRIPPED"),
+                (0x1000, "SYSTEM"),
+                (0x2000, "DLL"),
+            ],
+        )
+    }
+}
+
+impl OptionalHeader {
+    pub fn is_pe32_plus(&self) -> bool {
+        self.magic == PE32_PLUS_MAGIC
+    }
+
+    pub fn subsystem_str(&self) -> Option<&'static str> {
+        match self.subsystem {
+            1 =>
+                Some("native"),
+            2 =>
+                Some("Windows GUI"),
+            3 =>
+                Some("Windows CUI"),
+            7 =>
+                Some("POSIX CUI"),
+            9 =>
+                Some("Windows CE GUI"),
+            10 =>
+                Some("EFI application"),
+            11 =>
+                Some("EFI boot service driver"),
+            12 =>
+                Some("EFI runtime driver"),
+            13 =>
+                Some("EFI ROM"),
+            14 =>
+                Some("Xbox"),
+            16 =>
+                Some("Windows boot application"),
+            _ =>
+                None,
+        }
+    }
+
+    pub fn dll_characteristics_str(&self) -> String {
+        flags_str(
+            u32::from(self.dll_characteristics),
+            &[
+                (0x0020, "HIGH_ENTROPY_VA"),
+                (0x0040, "DYNAMIC_BASE"),
+                (0x0080, "FORCE_INTEGRITY"),
+                (0x0100, "NX_COMPAT"),
+                (0x0200, "NO_ISOLATION"),
+                (0x0400, "NO_SEH"),
+                (0x0800, "NO_BIND"),
+                (0x1000, "APPCONTAINER"),
+                (0x2000, "WDM_DRIVER"),
+                (0x4000, "GUARD_CF"),
+                (0x8000, "TERMINAL_SERVER_AWARE"),
+            ],
+        )
+    }
+}
+
+impl Pe {
+    /// Fails when the data is not a PE image, or the headers are truncated.
+    /// Imports and exports are read by `imports()` and `exports()`.
+    pub fn parse(data: &[u8]) -> Result<Pe, ParseError> {
+        if data.get(0..2) != Some(DOS_MAGIC) {
+            return Err(ParseError::BadMagic {
+                format: "a PE",
+                offset: 0,
+                magic: DOS_MAGIC,
+            });
+        }
+        // PE files are little endian
+        let r = Reader::new(data, false);
+
+        let pe_offset = r.u32(0x3c).ok_or(ParseError::Truncated {
+            what: "DOS header",
+            offset: 0,
+            size: 0x40,
+        })? as usize;
+        if data.get(pe_offset..pe_offset.saturating_add(4)) != Some(PE_MAGIC) {
+            // Plain DOS executable
+            return Err(ParseError::BadMagic {
+                format: "a PE",
+                offset: pe_offset,
+                magic: PE_MAGIC,
+            });
+        }
+
+        let coff_offset = pe_offset + 4;
+        let coff_header = parse_coff_header(r, coff_offset).ok_or(ParseError::Truncated {
+            what: "COFF header",
+            offset: coff_offset,
+            size: 20,
+        })?;
+
+        let optional_offset = coff_offset + 20;
+        let optional_header = if coff_header.size_of_optional_header == 0 {
+            None
+        } else {
+            let truncated = ParseError::Truncated {
+                what: "optional header",
+                offset: optional_offset,
+                size: coff_header.size_of_optional_header as usize,
+            };
+            let magic = match r.u16(optional_offset) {
+                Some(magic) =>
+                    magic,
+                None =>
+                    return Err(truncated),
+            };
+            if magic != PE32_MAGIC && magic != PE32_PLUS_MAGIC {
+                return Err(ParseError::Invalid {
+                    field: "optional header magic",
+                    offset: optional_offset,
+                    value: u64::from(magic),
+                    expected: "0x10b (PE32) or 0x20b (PE32+)",
+                });
+            }
+            let header = parse_optional_header(r, optional_offset, magic).ok_or(truncated)?;
+            Some(header)
+        };
+
+        let sections_offset = optional_offset + coff_header.size_of_optional_header as usize;
+        let mut sections = Vec::with_capacity(coff_header.number_of_sections as usize);
+        for i in 0..coff_header.number_of_sections as usize {
+            let section =
+                parse_section(r, data, sections_offset + i * 40).ok_or(ParseError::Truncated {
+                    what: "section table",
+                    offset: sections_offset,
+                    size: coff_header.number_of_sections as usize * 40,
+                })?;
+            sections.push(section);
+        }
+
+        Ok(Pe {
+            pe_offset,
+            coff_header,
+            optional_header,
+            sections,
+        })
+    }
+
+    /// File offset of a relative virtual address, when it's in a section
+    /// with contents in the file (or in the headers, which are mapped at RVA
+    /// 0).
+    pub fn rva_offset(&self, rva: u32) -> Option<usize> {
+        for section in &self.sections {
+            if rva >= section.virtual_address {
+                let rel = rva - section.virtual_address;
+                let size = if section.virtual_size == 0 {
+                    section.size_of_raw_data
+                } else {
+                    section.virtual_size
+                };
+                if rel < size {
+                    if rel >= section.size_of_raw_data {
+                        // Zero-filled, not in the file
+                        return None;
+                    }
+                    return Some(section.pointer_to_raw_data as usize + rel as usize);
+                }
+            }
+        }
+        let headers_end = self.sections
+            .iter()
+            .map(|section| section.pointer_to_raw_data)
+            .filter(|&offset| offset != 0)
+            .min()?;
+        if rva < headers_end {
+            Some(rva as usize)
+        } else {
+            None
+        }
+    }
+
+    fn data_directory(&self, idx: usize) -> Option<&DataDirectory> {
+        let dir = self.optional_header.as_ref()?.data_directories.get(idx)?;
+        if dir.virtual_address == 0 {
+            None
+        } else {
+            Some(dir)
+        }
+    }
+
+    /// Imported functions, grouped by DLL in the order of the import
+    /// directory. Entries that can't be read are skipped.
+    pub fn imports(&self, data: &[u8]) -> Vec<Import> {
+        let mut ret = Vec::new();
+        let dir = match self.data_directory(IMPORT_DIRECTORY) {
+            Some(dir) =>
+                dir,
+            None =>
+                return ret,
+        };
+        let r = Reader::new(data, false);
+        let pe32_plus = self.optional_header.as_ref().is_some_and(|h| h.is_pe32_plus());
+        let thunk_size = if pe32_plus { 8 } else { 4 };
+
+        for i in 0..MAX_ENTRIES {
+            let descriptor_rva = dir.virtual_address.wrapping_add(i as u32 * 20);
+            let descriptor = match self.rva_offset(descriptor_rva) {
+                Some(descriptor) =>
+                    descriptor,
+                None =>
+                    break,
+            };
+            let fields = (r.u32(descriptor), r.u32(descriptor + 12), r.u32(descriptor + 16));
+            let (lookup_table, name, iat) = match fields {
+                (Some(lookup_table), Some(name), Some(iat)) =>
+                    (lookup_table, name, iat),
+                _ =>
+                    break,
+            };
+            if name == 0 && iat == 0 {
+                break;
+            }
+            let dll = self.rva_offset(name)
+                .and_then(|offset| r.c_str(offset))
+                .unwrap_or_default();
+            // The lookup table is missing in some old linkers' output, the
+            // IAT has the same contents in the file then
+            let lookup_table = if lookup_table == 0 { iat } else { lookup_table };
+
+            for j in 0..MAX_ENTRIES {
+                let thunk_rva = j as u32 * thunk_size;
+                let thunk = self.rva_offset(lookup_table.wrapping_add(thunk_rva)).and_then(
+                    |offset| if pe32_plus { r.u64(offset) } else { r.u32(offset).map(u64::from) },
+                );
+                let thunk = match thunk {
+                    Some(0) | None =>
+                        break,
+                    Some(thunk) =>
+                        thunk,
+                };
+                let by_ordinal = thunk >> (thunk_size * 8 - 1) != 0;
+                let (name, ordinal) = if by_ordinal {
+                    (None, thunk as u16)
+                } else {
+                    let hint_name = self.rva_offset(thunk as u32);
+                    (
+                        hint_name.and_then(|offset| r.c_str(offset + 2)),
+                        hint_name.and_then(|offset| r.u16(offset)).unwrap_or(0),
+                    )
+                };
+                ret.push(Import {
+                    dll: dll.clone(),
+                    name,
+                    ordinal,
+                    iat_offset: self.rva_offset(iat.wrapping_add(thunk_rva)),
+                });
+            }
+        }
+        ret
+    }
+
+    /// Exported functions, in the order of the export address table.
+    pub fn exports(&self, data: &[u8]) -> Vec<Export> {
+        let mut ret = Vec::new();
+        let dir = match self.data_directory(EXPORT_DIRECTORY) {
+            Some(dir) =>
+                dir,
+            None =>
+                return ret,
+        };
+        let r = Reader::new(data, false);
+        let offset = match self.rva_offset(dir.virtual_address) {
+            Some(offset) =>
+                offset,
+            None =>
+                return ret,
+        };
+        let fields = (
+            r.u32(offset + 16),
+            r.u32(offset + 20),
+            r.u32(offset + 24),
+            r.u32(offset + 28),
+            r.u32(offset + 32),
+            r.u32(offset + 36),
+        );
+        let (base, n_functions, n_names, functions, names, ordinals) = match fields {
+            (Some(a), Some(b), Some(c), Some(d), Some(e), Some(f)) =>
+                (a, b, c, d, e, f),
+            _ =>
+                return ret,
+        };
+
+        // Names of exports, by index in the address table
+        let mut export_names: Vec<Option<String>> = Vec::new();
+        for i in 0..n_names as usize {
+            if i >= MAX_ENTRIES {
+                break;
+            }
+            let name_rva = self.rva_offset(names.wrapping_add(i as u32 * 4))
+                .and_then(|offset| r.u32(offset));
+            let idx = self.rva_offset(ordinals.wrapping_add(i as u32 * 2))
+                .and_then(|offset| r.u16(offset));
+            if let (Some(name_rva), Some(idx)) = (name_rva, idx) {
+                let idx = idx as usize;
+                if idx >= export_names.len() {
+                    export_names.resize(idx + 1, None);
+                }
+                export_names[idx] = self.rva_offset(name_rva).and_then(|offset| r.c_str(offset));
+            }
+        }
+
+        for i in 0..n_functions as usize {
+            if i >= MAX_ENTRIES {
+                break;
+            }
+            let rva = match self.rva_offset(functions.wrapping_add(i as u32 * 4))
+                .and_then(|offset| r.u32(offset))
+            {
+                Some(rva) =>
+                    rva,
+                None =>
+                    break,
+            };
+            if rva == 0 {
+                // Unused ordinal
+                continue;
+            }
+            // Addresses in the export directory are names of forwarded
+            // functions
+            let forwarder = if rva >= dir.virtual_address
+                && rva - dir.virtual_address < dir.size
+            {
+                self.rva_offset(rva).and_then(|offset| r.c_str(offset))
+            } else {
+                None
+            };
+            ret.push(Export {
+                name: export_names.get(i).cloned().unwrap_or(None),
+                ordinal: base.wrapping_add(i as u32),
+                rva,
+                forwarder,
+            });
+        }
+        ret
+    }
+}
+
+fn parse_coff_header(r: Reader, offset: usize) -> Option<CoffHeader> {
+    Some(CoffHeader {
+        machine: r.u16(offset)?,
+        number_of_sections: r.u16(offset + 2)?,
+        time_date_stamp: r.u32(offset + 4)?,
+        size_of_optional_header: r.u16(offset + 16)?,
+        characteristics: r.u16(offset + 18)?,
+    })
+}
+
+fn parse_optional_header(r: Reader, offset: usize, magic: u16) -> Option<OptionalHeader> {
+    let pe32_plus = magic == PE32_PLUS_MAGIC;
+    let image_base = if pe32_plus {
+        r.u64(offset + 24)?
+    } else {
+        u64::from(r.u32(offset + 28)?)
+    };
+    // Data directories come after the stack and heap sizes, which are 64-bit
+    // in PE32+
+    let (n_dirs_offset, dirs_offset) = if pe32_plus { (108, 112) } else { (92, 96) };
+    let n_dirs = r.u32(offset + n_dirs_offset)?;
+    let mut data_directories = Vec::new();
+    // There are 16 directories, more are not defined
+    for i in 0..cmp::min(n_dirs as usize, 16) {
+        let dir_offset = offset + dirs_offset + i * 8;
+        data_directories.push(DataDirectory {
+            virtual_address: r.u32(dir_offset)?,
+            size: r.u32(dir_offset + 4)?,
+        });
+    }
+    Some(OptionalHeader {
+        magic,
+        address_of_entry_point: r.u32(offset + 16)?,
+        image_base,
+        section_alignment: r.u32(offset + 32)?,
+        file_alignment: r.u32(offset + 36)?,
+        subsystem: r.u16(offset + 68)?,
+        dll_characteristics: r.u16(offset + 70)?,
+        data_directories,
+    })
+}
+
+fn parse_section(r: Reader, data: &[u8], offset: usize) -> Option<Section> {
+    let name = data.get(offset..offset + 8)?;
+    let name = name.split(|&b| b == 0).next().unwrap_or(&[]);
+    Some(Section {
+        name: String::from_utf8_lossy(name).into_owned(),
+        virtual_size: r.u32(offset + 8)?,
+        virtual_address: r.u32(offset + 12)?,
+        size_of_raw_data: r.u32(offset + 16)?,
+        pointer_to_raw_data: r.u32(offset + 20)?,
+        characteristics: r.u32(offset + 36)?,
+    })
+}
@@ -0,0 +1,389 @@
+////////////////////////////////////////////////////////////////////////////////
+// Patches: the edits of a buffer, without the rest of the file
+// (`:export-patch`), applied to a file with `rhex --apply-patch`.
+//
+// Two formats: IPS, used for ROM hacks, and text with a line per change:
+//
+//     0x1f: 41 42 -> de ad
+//     0x200: - -> 00 01
+//     0x300: 01 02 -> -
+//
+// Text patches have the old bytes, they're checked when applying. IPS
+// patches only have the new bytes, offsets must be less than 16 MiB.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cmp;
+
+/// Bytes per line of text patches.
+const TEXT_BYTES: usize = 16;
+
+const IPS_HEADER: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+
+/// Offsets of IPS records are 24 bits.
+const IPS_MAX_OFFSET: usize = 0xff_ffff;
+
+/// Records can't start here, the offset reads as "EOF".
+const IPS_EOF_OFFSET: usize = 0x45_4f46;
+
+/// Records are at most this long.
+const IPS_MAX_RECORD: usize = 0xffff;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PatchFormat {
+    Ips,
+    Text,
+}
+
+impl PatchFormat {
+    /// IPS for `.ips` files, text otherwise.
+    pub fn from_path(path: &str) -> PatchFormat {
+        if path.to_lowercase().ends_with(".ips") {
+            PatchFormat::Ips
+        } else {
+            PatchFormat::Text
+        }
+    }
+}
+
+pub struct Change {
+    pub offset: usize,
+
+    /// Bytes replaced. `None` in IPS patches, where as many bytes as in `new`
+    /// are replaced (or added after the end).
+    pub old: Option<Vec<u8>>,
+
+    pub new: Vec<u8>,
+}
+
+pub struct Patch {
+    /// In the order of the patch file. Sorted by offset in patches made by
+    /// `diff`.
+    pub changes: Vec<Change>,
+
+    /// Length of the file after the changes, when it's shorter.
+    pub truncate: Option<usize>,
+}
+
+/// Changes from `old` to `new`: runs of differing bytes, and the bytes added
+/// or removed at the end.
+pub fn diff(old: &[u8], new: &[u8]) -> Patch {
+    let common = cmp::min(old.len(), new.len());
+    let mut changes = Vec::new();
+    let mut idx = 0;
+    while idx < common {
+        if old[idx] == new[idx] {
+            idx += 1;
+            continue;
+        }
+        let start = idx;
+        while idx < common && old[idx] != new[idx] {
+            idx += 1;
+        }
+        changes.push(Change {
+            offset: start,
+            old: Some(old[start..idx].to_vec()),
+            new: new[start..idx].to_vec(),
+        });
+    }
+    if old.len() != new.len() {
+        changes.push(Change {
+            offset: common,
+            old: Some(old[common..].to_vec()),
+            new: new[common..].to_vec(),
+        });
+    }
+    Patch {
+        changes,
+        truncate: None,
+    }
+}
+
+impl Patch {
+    /// Bytes changed, added, or removed.
+    pub fn size(&self) -> usize {
+        self.changes
+            .iter()
+            .map(|change| cmp::max(change.new.len(), change.old.as_ref().map_or(0, Vec::len)))
+            .sum()
+    }
+
+    /// `new`: the contents after the changes.
+    pub fn write(&self, format: PatchFormat, new: &[u8]) -> Result<Vec<u8>, String> {
+        match format {
+            PatchFormat::Ips =>
+                self.write_ips(new),
+            PatchFormat::Text =>
+                Ok(self.write_text().into_bytes()),
+        }
+    }
+
+    fn write_text(&self) -> String {
+        let mut ret = String::new();
+        for change in &self.changes {
+            let old = change.old.as_ref().map_or(&[][..], |old| &old[..]);
+            let lines = cmp::max(old.len(), change.new.len());
+            let mut start = 0;
+            // A line per `TEXT_BYTES` bytes, so that long runs stay readable.
+            // Offsets are in the old file, where lines of added bytes are at
+            // the end of the change.
+            loop {
+                let end = start + TEXT_BYTES;
+                ret.push_str(&format!(
+                    "0x{:x}: {} -> {}\n",
+                    change.offset + cmp::min(start, old.len()),
+                    hex_or_dash(old, start, end),
+                    hex_or_dash(&change.new, start, end)
+                ));
+                start = end;
+                if start >= lines {
+                    break;
+                }
+            }
+        }
+        ret
+    }
+
+    fn write_ips(&self, new: &[u8]) -> Result<Vec<u8>, String> {
+        let mut ret = IPS_HEADER.to_vec();
+        let mut truncate = self.truncate;
+        for change in &self.changes {
+            let old_len = change.old.as_ref().map_or(0, Vec::len);
+            if old_len > change.new.len() {
+                // Only possible at the end, see `diff`
+                truncate = Some(change.offset + change.new.len());
+            }
+            let mut offset = change.offset;
+            let mut bytes = &change.new[..];
+            // Offset 0x454f46 reads as "EOF", start a byte earlier
+            if offset == IPS_EOF_OFFSET && !bytes.is_empty() {
+                offset -= 1;
+                bytes = &new[offset..offset + 1 + bytes.len()];
+            }
+            while !bytes.is_empty() {
+                if offset > IPS_MAX_OFFSET {
+                    return Err(format!("Change at 0x{:x} is out of IPS range (16 MiB)", offset));
+                }
+                let mut len = cmp::min(bytes.len(), IPS_MAX_RECORD);
+                if offset + len == IPS_EOF_OFFSET && len < bytes.len() {
+                    len -= 1;
+                }
+                ret.extend_from_slice(&u24_be(offset));
+                ret.push((len >> 8) as u8);
+                ret.push(len as u8);
+                ret.extend_from_slice(&bytes[..len]);
+                offset += len;
+                bytes = &bytes[len..];
+            }
+        }
+        ret.extend_from_slice(IPS_EOF);
+        if let Some(len) = truncate {
+            if len > IPS_MAX_OFFSET {
+                return Err(format!("Length 0x{:x} is out of IPS range (16 MiB)", len));
+            }
+            ret.extend_from_slice(&u24_be(len));
+        }
+        Ok(ret)
+    }
+
+    /// Offsets of the first and after the last byte changed in `data`, and the
+    /// new bytes of that range. Offsets of the changes are in `data`, before
+    /// the changes. Fails when the old bytes of a text patch don't match.
+    ///
+    /// IPS records are applied in the order of the patch, so a record
+    /// overwrites the records before it that it overlaps.
+    pub fn apply(&self, data: &[u8]) -> Result<(usize, usize, Vec<u8>), String> {
+        let removed: Vec<usize> = self.changes
+            .iter()
+            .map(|change| match change.old {
+                Some(ref old) =>
+                    old.len(),
+                None =>
+                    cmp::min(change.new.len(), data.len().saturating_sub(change.offset)),
+            })
+            .collect();
+        // IPS patches can write after the end, the gap is filled with zeros
+        let start = self.changes
+            .iter()
+            .map(|change| change.offset)
+            .chain(self.truncate)
+            .min()
+            .map(|start| cmp::min(start, data.len()))
+            .ok_or_else(|| "Empty patch".to_owned())?;
+        let end = if self.truncate.is_some() {
+            data.len()
+        } else {
+            let end = self.changes
+                .iter()
+                .zip(&removed)
+                .map(|(change, removed)| change.offset + removed)
+                .max()
+                .unwrap_or(start);
+            cmp::min(end, data.len())
+        };
+
+        // IPS records don't move bytes, so their offsets stay the same after
+        // applying a record. Text changes can add or remove bytes, they're
+        // applied from the last one so that offsets of the rest stay valid.
+        let mut order: Vec<usize> = (0..self.changes.len()).collect();
+        if self.changes.iter().any(|change| change.old.is_some()) {
+            order.sort_by_key(|&idx| cmp::Reverse(self.changes[idx].offset));
+        }
+
+        let mut bytes = data[start..end].to_vec();
+        for idx in order {
+            let change = &self.changes[idx];
+            let offset = change.offset - start;
+            if offset > bytes.len() {
+                bytes.resize(offset, 0);
+            }
+            let removed = match change.old {
+                Some(ref old) => {
+                    if bytes.get(offset..offset + old.len()) != Some(&old[..]) {
+                        return Err(format!(
+                            "Patch doesn't match the file at 0x{:x}",
+                            change.offset
+                        ));
+                    }
+                    old.len()
+                }
+                None =>
+                    cmp::min(change.new.len(), bytes.len() - offset),
+            };
+            bytes.splice(offset..offset + removed, change.new.iter().cloned());
+        }
+        if let Some(len) = self.truncate {
+            bytes.truncate(len - start);
+        }
+        Ok((start, end, bytes))
+    }
+}
+
+/// Hex of `bytes[start..end]`, clipped to the slice, or "-" when empty.
+fn hex_or_dash(bytes: &[u8], start: usize, end: usize) -> String {
+    let end = cmp::min(end, bytes.len());
+    if start >= end {
+        return "-".to_owned();
+    }
+    let hex: Vec<String> = bytes[start..end].iter().map(|b| format!("{:02x}", b)).collect();
+    hex.join(" ")
+}
+
+fn u24_be(n: usize) -> [u8; 3] {
+    [(n >> 16) as u8, (n >> 8) as u8, n as u8]
+}
+
+/// Parse an IPS or a text patch.
+pub fn parse(bytes: &[u8]) -> Result<Patch, String> {
+    let patch = if bytes.starts_with(IPS_HEADER) {
+        parse_ips(&bytes[IPS_HEADER.len()..])?
+    } else {
+        match ::std::str::from_utf8(bytes) {
+            Ok(text) =>
+                parse_text(text)?,
+            Err(_) =>
+                return Err("Not an IPS or a text patch".to_owned()),
+        }
+    };
+    if patch.changes.is_empty() && patch.truncate.is_none() {
+        return Err("Empty patch".to_owned());
+    }
+    Ok(patch)
+}
+
+fn parse_ips(mut bytes: &[u8]) -> Result<Patch, String> {
+    let truncated = || "Truncated IPS patch".to_owned();
+    let mut changes = Vec::new();
+    loop {
+        if bytes.starts_with(IPS_EOF) {
+            bytes = &bytes[IPS_EOF.len()..];
+            break;
+        }
+        let header = bytes.get(..5).ok_or_else(truncated)?;
+        let offset = read_u24_be(header);
+        let len = (header[3] as usize) << 8 | header[4] as usize;
+        bytes = &bytes[5..];
+        let new = if len == 0 {
+            // RLE record: run length and the byte
+            let rle = bytes.get(..3).ok_or_else(truncated)?;
+            bytes = &bytes[3..];
+            vec![rle[2]; (rle[0] as usize) << 8 | rle[1] as usize]
+        } else {
+            let new = bytes.get(..len).ok_or_else(truncated)?.to_vec();
+            bytes = &bytes[len..];
+            new
+        };
+        changes.push(Change {
+            offset,
+            old: None,
+            new,
+        });
+    }
+    let truncate = if bytes.len() >= 3 { Some(read_u24_be(bytes)) } else { None };
+    Ok(Patch { changes, truncate })
+}
+
+fn read_u24_be(bytes: &[u8]) -> usize {
+    (bytes[0] as usize) << 16 | (bytes[1] as usize) << 8 | bytes[2] as usize
+}
+
+fn parse_text(text: &str) -> Result<Patch, String> {
+    let mut changes: Vec<Change> = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = || format!("Line {}: expected <offset>: <old> -> <new>", idx + 1);
+        let colon = line.find(':').ok_or_else(err)?;
+        let arrow = line.find("->").ok_or_else(err)?;
+        if arrow < colon {
+            return Err(err());
+        }
+        let offset = parse_offset(line[..colon].trim()).ok_or_else(err)?;
+        let old = parse_hex_or_dash(&line[colon + 1..arrow]).ok_or_else(err)?;
+        let new = parse_hex_or_dash(&line[arrow + 2..]).ok_or_else(err)?;
+
+        // Join the lines of a change
+        if let Some(last) = changes.last_mut() {
+            let last_old = last.old.as_mut().unwrap();
+            if last.offset + last_old.len() == offset {
+                last_old.extend_from_slice(&old);
+                last.new.extend_from_slice(&new);
+                continue;
+            }
+        }
+        changes.push(Change {
+            offset,
+            old: Some(old),
+            new,
+        });
+    }
+    Ok(Patch {
+        changes,
+        truncate: None,
+    })
+}
+
+fn parse_offset(s: &str) -> Option<usize> {
+    if s.starts_with("0x") || s.starts_with("0X") {
+        usize::from_str_radix(&s[2..], 16).ok()
+    } else {
+        usize::from_str_radix(s, 16).ok()
+    }
+}
+
+fn parse_hex_or_dash(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim();
+    if s == "-" {
+        return Some(Vec::new());
+    }
+    let mut ret = Vec::new();
+    for byte in s.split_whitespace() {
+        if byte.len() != 2 {
+            return None;
+        }
+        ret.push(u8::from_str_radix(byte, 16).ok()?);
+    }
+    Some(ret)
+}
@@ -0,0 +1,347 @@
+////////////////////////////////////////////////////////////////////////////////
+// Per-file state persisted under ~/.local/share/rhex
+////////////////////////////////////////////////////////////////////////////////
+
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use hash::{sha256, to_hex};
+
+/// `$XDG_DATA_HOME/rhex`, or `~/.local/share/rhex` when that's not set.
+pub fn data_dir() -> Option<PathBuf> {
+    match env::var_os("XDG_DATA_HOME") {
+        Some(ref dir) if !dir.is_empty() =>
+            Some(Path::new(dir).join("rhex")),
+        _ =>
+            env::var_os("HOME").map(|home| Path::new(&home).join(".local/share/rhex")),
+    }
+}
+
+/// Path of the sidecar file in `kind` directory for the given file. Like
+/// vim's undo files, the absolute path of the file is used as the name, with
+/// '/'s replaced with '%'s. Creates the directory if it doesn't exist.
+pub fn sidecar_path(kind: &str, file: &str) -> Option<PathBuf> {
+    let dir = data_dir()?.join(kind);
+    fs::create_dir_all(&dir).ok()?;
+    let abs = fs::canonicalize(file).ok()?;
+    let name = abs.to_string_lossy().replace('/', "%");
+    Some(dir.join(name))
+}
+
+/// Save `contents` as a timestamped backup of the sidecar of `kind`, in
+/// `<kind>-backup` directory, as `<sidecar name>.<unix time>`. Only the newest
+/// `keep` backups of the file are kept. Errors are ignored.
+pub fn backup(kind: &str, file: &str, contents: &[u8], keep: usize) {
+    let path = match sidecar_path(&format!("{}-backup", kind), file) {
+        Some(path) =>
+            path,
+        None =>
+            return,
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|t| t.as_secs())
+        .unwrap_or(0);
+    let mut name = path.file_name().unwrap().to_os_string();
+    name.push(format!(".{}", now));
+    if fs::write(path.with_file_name(&name), contents).is_err() {
+        return;
+    }
+
+    // Remove old backups
+    let prefix = format!("{}.", path.file_name().unwrap().to_string_lossy());
+    let dir = match fs::read_dir(path.parent().unwrap()) {
+        Ok(dir) =>
+            dir,
+        Err(_) =>
+            return,
+    };
+    let mut backups: Vec<(u64, PathBuf)> = dir
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.starts_with(&prefix) {
+                return None;
+            }
+            name[prefix.len()..].parse().ok().map(|time| (time, entry.path()))
+        })
+        .collect();
+    backups.sort();
+    let n_old = backups.len().saturating_sub(keep);
+    for (_, old) in &backups[..n_old] {
+        let _ = fs::remove_file(old);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Session files
+////////////////////////////////////////////////////////////////////////////////
+
+/// Version of the session format we write. Only bumped when the meaning of
+/// existing records changes, new kinds of records don't need a new version as
+/// readers keep the records they don't know.
+pub const SESSION_VERSION: u32 = 1;
+
+const SESSION_MAGIC: &str = "rhex-session";
+
+/// A sidecar in the session format: a header line
+/// `rhex-session <version> sha256:<checksum of the records>`, then one record
+/// per line, `<tag> <fields>`.
+pub struct Session {
+    /// Version the file was written with. 0 for files written before the
+    /// format had a header, their lines are returned as records as they are.
+    pub version: u32,
+    pub records: Vec<Record>,
+}
+
+#[derive(Clone)]
+pub struct Record {
+    pub tag: String,
+    pub fields: String,
+}
+
+pub enum SessionError {
+    Io(io::Error),
+
+    /// Bad header, or the checksum doesn't match the records (e.g. the file
+    /// was truncated).
+    Corrupt,
+
+    /// Written by a newer rhex that changed the format.
+    TooNew(u32),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SessionError::Io(ref err) =>
+                write!(f, "{}", err),
+            SessionError::Corrupt =>
+                write!(f, "checksum mismatch"),
+            SessionError::TooNew(version) =>
+                write!(f, "written by a newer rhex (format version {})", version),
+        }
+    }
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session {
+            version: SESSION_VERSION,
+            records: Vec::new(),
+        }
+    }
+
+    pub fn parse(contents: &[u8]) -> Result<Session, SessionError> {
+        let contents = String::from_utf8_lossy(contents);
+
+        if !contents.starts_with(SESSION_MAGIC) {
+            return Ok(Session {
+                version: 0,
+                records: parse_records(&contents),
+            });
+        }
+
+        let (header, body) = match contents.find('\n') {
+            Some(idx) =>
+                (&contents[..idx], &contents[idx + 1..]),
+            None =>
+                return Err(SessionError::Corrupt),
+        };
+        let mut words = header.split(' ').skip(1);
+        let version = words.next().and_then(|w| w.parse().ok());
+        let checksum = words.next().filter(|w| w.starts_with("sha256:"));
+        let (version, checksum) = match (version, checksum) {
+            (Some(version), Some(checksum)) =>
+                (version, &checksum["sha256:".len()..]),
+            _ =>
+                return Err(SessionError::Corrupt),
+        };
+        if version > SESSION_VERSION {
+            return Err(SessionError::TooNew(version));
+        }
+        if to_hex(&sha256(body.as_bytes())) != checksum {
+            return Err(SessionError::Corrupt);
+        }
+
+        Ok(Session {
+            version,
+            records: parse_records(body),
+        })
+    }
+
+    /// Always in the current version.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut body = String::new();
+        for record in &self.records {
+            body.push_str(&record.tag);
+            if !record.fields.is_empty() {
+                body.push(' ');
+                body.push_str(&record.fields);
+            }
+            body.push('\n');
+        }
+        let mut ret = format!(
+            "{} {} sha256:{}\n",
+            SESSION_MAGIC,
+            SESSION_VERSION,
+            to_hex(&sha256(body.as_bytes()))
+        );
+        ret.push_str(&body);
+        ret.into_bytes()
+    }
+}
+
+fn parse_records(body: &str) -> Vec<Record> {
+    body.lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let mut words = line.splitn(2, ' ');
+            Record {
+                tag: words.next().unwrap_or("").to_owned(),
+                fields: words.next().unwrap_or("").to_owned(),
+            }
+        })
+        .collect()
+}
+
+/// Read the sidecar of `kind` of the file. `Ok(None)` when there isn't one.
+pub fn load_session(kind: &str, file: &str) -> Result<Option<Session>, SessionError> {
+    let path = match sidecar_path(kind, file) {
+        Some(path) =>
+            path,
+        None =>
+            return Ok(None),
+    };
+    match fs::read(&path) {
+        Ok(contents) =>
+            Session::parse(&contents).map(Some),
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound =>
+            Ok(None),
+        Err(err) =>
+            Err(SessionError::Io(err)),
+    }
+}
+
+/// Write the sidecar of `kind` of the file. Written to a temporary file
+/// first, so a crash while writing doesn't leave a half-written sidecar.
+pub fn save_session(kind: &str, file: &str, session: &Session) -> io::Result<()> {
+    let path = sidecar_path(kind, file)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory"))?;
+    let mut tmp_name = path.file_name().unwrap().to_os_string();
+    tmp_name.push(".tmp");
+    let tmp = path.with_file_name(tmp_name);
+    fs::write(&tmp, session.serialize())?;
+    fs::rename(&tmp, &path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &[u8]) -> Session {
+        match Session::parse(contents) {
+            Ok(session) =>
+                session,
+            Err(err) =>
+                panic!("can't parse session: {}", err),
+        }
+    }
+
+    fn record(tag: &str, fields: &str) -> Record {
+        Record {
+            tag: tag.to_owned(),
+            fields: fields.to_owned(),
+        }
+    }
+
+    #[test]
+    fn legacy_lines_are_records() {
+        let session = parse(b"a 16 start\nb 32\n\n");
+        assert_eq!(session.version, 0);
+        assert_eq!(session.records.len(), 2);
+        assert_eq!(session.records[0].tag, "a");
+        assert_eq!(session.records[0].fields, "16 start");
+        assert_eq!(session.records[1].tag, "b");
+        assert_eq!(session.records[1].fields, "32");
+    }
+
+    #[test]
+    fn unknown_records_round_trip() {
+        let mut session = Session::new();
+        session.records.push(record("mark", "a 16 start"));
+        session.records.push(record("from-the-future", "x y  z"));
+        session.records.push(record("flag", ""));
+
+        let parsed = parse(&session.serialize());
+        assert_eq!(parsed.version, SESSION_VERSION);
+        assert_eq!(parsed.records.len(), 3);
+        for (a, b) in session.records.iter().zip(parsed.records.iter()) {
+            assert_eq!(a.tag, b.tag);
+            assert_eq!(a.fields, b.fields);
+        }
+        assert_eq!(parsed.serialize(), session.serialize());
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let mut session = Session::new();
+        session.records.push(record("mark", "a 16 start"));
+        let mut contents = session.serialize();
+        let len = contents.len();
+        contents[len - 2] = b'x';
+        match Session::parse(&contents) {
+            Err(SessionError::Corrupt) =>
+                {}
+            _ =>
+                panic!("tampered session not detected"),
+        }
+
+        // Truncated
+        let contents = session.serialize();
+        match Session::parse(&contents[..contents.len() - 3]) {
+            Err(SessionError::Corrupt) =>
+                {}
+            _ =>
+                panic!("truncated session not detected"),
+        }
+    }
+
+    #[test]
+    fn header_without_newline() {
+        let header = format!(
+            "{} {} sha256:{}",
+            SESSION_MAGIC,
+            SESSION_VERSION,
+            to_hex(&sha256(b""))
+        );
+        match Session::parse(header.as_bytes()) {
+            Err(SessionError::Corrupt) =>
+                {}
+            _ =>
+                panic!("header without a newline accepted"),
+        }
+    }
+
+    #[test]
+    fn too_new() {
+        let body = "mark a 16\n";
+        let contents = format!(
+            "{} {} sha256:{}\n{}",
+            SESSION_MAGIC,
+            SESSION_VERSION + 1,
+            to_hex(&sha256(body.as_bytes())),
+            body
+        );
+        match Session::parse(contents.as_bytes()) {
+            Err(SessionError::TooNew(version)) =>
+                assert_eq!(version, SESSION_VERSION + 1),
+            _ =>
+                panic!("session from a newer version accepted"),
+        }
+    }
+}
@@ -0,0 +1,167 @@
+////////////////////////////////////////////////////////////////////////////////
+// `rhex --pid <pid> --addr <region>`: memory of a running process, read from
+// `/proc/<pid>/mem`. Regions are picked from `/proc/<pid>/maps`, `rhex --pid
+// <pid>` lists them.
+//
+// Reading another process's memory needs ptrace permission (same user and
+// `kernel.yama.ptrace_scope` 0, or root).
+////////////////////////////////////////////////////////////////////////////////
+
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+
+use utils::parse_number;
+
+/// Unreadable parts of the memory are found a page at a time.
+const PAGE_SIZE: usize = 4096;
+
+/// Contents of a region, and the ranges of buffer offsets that couldn't be
+/// read.
+type Memory = (Vec<u8>, Vec<(usize, usize)>);
+
+/// A line of `/proc/<pid>/maps`.
+pub struct Mapping {
+    pub start: usize,
+    pub end: usize,
+    /// e.g. `r-xp`
+    pub perms: String,
+    /// Path of the mapped file, `[heap]`, `[stack]` etc. Empty for anonymous
+    /// mappings.
+    pub name: String,
+}
+
+impl Mapping {
+    fn parse(line: &str) -> Option<Mapping> {
+        let mut fields = line.split_whitespace();
+        let range = fields.next()?;
+        let perms = fields.next()?.to_owned();
+        // Offset, device, inode
+        fields.nth(2)?;
+        let name = fields.collect::<Vec<&str>>().join(" ");
+
+        let dash = range.find('-')?;
+        let start = usize::from_str_radix(&range[..dash], 16).ok()?;
+        let end = usize::from_str_radix(&range[dash + 1..], 16).ok()?;
+        Some(Mapping { start, end, perms, name })
+    }
+
+    pub fn is_readable(&self) -> bool {
+        self.perms.starts_with('r')
+    }
+}
+
+/// Part of a process's memory shown in a buffer.
+#[derive(Clone, Copy)]
+pub struct Region {
+    pub pid: u32,
+    pub start: usize,
+    pub len: usize,
+}
+
+impl Region {
+    /// `<start>:<len>`, the number of a mapping in the list printed by
+    /// `print_maps`, or a part of a mapping's name (e.g. `[stack]`, `libc`),
+    /// for the first readable mapping with that name.
+    pub fn parse(pid: u32, spec: &str, maps: &[Mapping]) -> Result<Region, String> {
+        if let Some(colon) = spec.find(':') {
+            return match (parse_number(&spec[..colon]), parse_number(&spec[colon + 1..])) {
+                (Some(start), Some(len)) if len > 0 =>
+                    Ok(Region { pid, start, len }),
+                _ =>
+                    Err(format!("Invalid region: {} (expected <start>:<len>)", spec)),
+            };
+        }
+        let mapping = match spec.parse::<usize>() {
+            Ok(idx) =>
+                maps.get(idx).ok_or_else(|| format!("No mapping {}", idx))?,
+            Err(_) =>
+                maps.iter()
+                    .find(|m| m.is_readable() && m.name.contains(spec))
+                    .ok_or_else(|| format!("No readable mapping with name {}", spec))?,
+        };
+        Ok(Region {
+            pid,
+            start: mapping.start,
+            len: mapping.end - mapping.start,
+        })
+    }
+
+    /// Shown as the path of the buffer.
+    pub fn name(&self) -> String {
+        format!("pid {} 0x{:x}-0x{:x}", self.pid, self.start, self.start + self.len)
+    }
+
+    /// Read the memory. Pages that can't be read (e.g. guard pages) are
+    /// zeros, and returned as ranges of buffer offsets.
+    pub fn read(&self) -> io::Result<Memory> {
+        let mut file = File::open(format!("/proc/{}/mem", self.pid))?;
+        let mut data = vec![0; self.len];
+        let mut unreadable: Vec<(usize, usize)> = Vec::new();
+
+        // Read up to page boundaries, so that a failed read is a single page
+        let mut offset = 0;
+        while offset < self.len {
+            let addr = self.start + offset;
+            let chunk_end = ((addr / PAGE_SIZE + 1) * PAGE_SIZE - self.start).min(self.len);
+            let ok = file.seek(SeekFrom::Start(addr as u64)).is_ok()
+                && read_full(&mut file, &mut data[offset..chunk_end]).is_ok();
+            if !ok {
+                match unreadable.last_mut() {
+                    Some(last) if last.1 == offset =>
+                        last.1 = chunk_end,
+                    _ =>
+                        unreadable.push((offset, chunk_end)),
+                }
+            }
+            offset = chunk_end;
+        }
+
+        if unreadable == [(0, self.len)] {
+            return Err(io::Error::other("memory is not readable"));
+        }
+        Ok((data, unreadable))
+    }
+}
+
+pub fn maps(pid: u32) -> io::Result<Vec<Mapping>> {
+    let file = File::open(format!("/proc/{}/maps", pid))?;
+    let mut ret = Vec::new();
+    for line in BufReader::new(file).lines() {
+        if let Some(mapping) = Mapping::parse(&line?) {
+            ret.push(mapping);
+        }
+    }
+    Ok(ret)
+}
+
+/// Numbered list of mappings, for picking one with `--addr <n>`.
+pub fn print_maps(maps: &[Mapping]) {
+    for (idx, mapping) in maps.iter().enumerate() {
+        println!(
+            "{:3} {:12x}-{:12x} {} {:>8} {}",
+            idx,
+            mapping.start,
+            mapping.end,
+            mapping.perms,
+            mapping.end - mapping.start,
+            mapping.name
+        );
+    }
+}
+
+fn read_full(file: &mut File, buf: &mut [u8]) -> io::Result<()> {
+    let mut len = 0;
+    while len < buf.len() {
+        match file.read(&mut buf[len..]) {
+            Ok(0) =>
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "end of memory")),
+            Ok(n) =>
+                len += n,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted =>
+                {}
+            Err(err) =>
+                return Err(err),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,199 @@
+////////////////////////////////////////////////////////////////////////////////
+// `rhex --revert <dump> <file>`: convert a hex dump back to binary, like
+// `xxd -r`, so that a dump edited in a text editor can be turned back into a
+// file. `:read-dump` writes a dump into the buffer.
+//
+// Reads dumps of rhex (`--dump`, `:dump`), `xxd`, `hexdump -C`, and plain hex
+// digits (`xxd -p`, `--dump --plain`). Bytes are placed at the offsets of
+// their lines, gaps are filled with zeros. Lines of plain dumps don't have
+// offsets, they follow the previous line.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cmp;
+use std::fs;
+
+pub struct Dump {
+    /// Bytes, and the offset of the first one. In the order of the lines, a
+    /// run overwrites the ones before it where they overlap.
+    pub runs: Vec<(usize, Vec<u8>)>,
+
+    /// Whether the lines had offsets. Plain dumps start at offset 0.
+    pub has_offsets: bool,
+
+    /// End of the dump given by an offset without bytes, at the end of
+    /// `hexdump -C` output.
+    end: usize,
+}
+
+impl Dump {
+    /// Offsets of the first byte and the byte after the last one.
+    pub fn span(&self) -> (usize, usize) {
+        let start = self.runs.iter().map(|&(offset, _)| offset).min().unwrap_or(0);
+        let end = self.runs
+            .iter()
+            .map(|&(offset, ref bytes)| offset + bytes.len())
+            .max()
+            .unwrap_or(0);
+        (start, cmp::max(end, self.end))
+    }
+
+    /// Bytes of `start..end`. Bytes that are not in the dump are taken from
+    /// `under`, the old contents of the range, or are zeros after it.
+    pub fn bytes(&self, start: usize, end: usize, under: &[u8]) -> Vec<u8> {
+        let mut ret = under.to_vec();
+        ret.resize(end - start, 0);
+        for &(offset, ref bytes) in &self.runs {
+            ret[offset - start..offset - start + bytes.len()].copy_from_slice(bytes);
+        }
+        ret
+    }
+}
+
+pub fn parse(text: &str) -> Result<Dump, String> {
+    let mut dump = Dump {
+        runs: Vec::new(),
+        has_offsets: false,
+        end: 0,
+    };
+    // Where the next line's bytes go, when it doesn't have an offset
+    let mut pos = 0;
+    // Bytes of the last line, repeated until the next offset after a '*'
+    let mut last_line: Vec<u8> = Vec::new();
+    let mut repeat = false;
+
+    for (idx, line) in text.lines().enumerate() {
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+        if line == "*" {
+            repeat = true;
+            continue;
+        }
+        let err = |what: &str| format!("Line {}: {}", idx + 1, what);
+
+        let (offset, hex) = split_line(line, dump.has_offsets);
+        if let Some(offset) = offset {
+            let offset = offset.ok_or_else(|| err("invalid offset"))?;
+            if repeat && !last_line.is_empty() {
+                while pos < offset {
+                    let len = cmp::min(last_line.len(), offset - pos);
+                    add_bytes(&mut dump.runs, pos, &last_line[..len]);
+                    pos += len;
+                }
+            }
+            dump.has_offsets = true;
+            dump.end = cmp::max(dump.end, offset);
+            pos = offset;
+        }
+        repeat = false;
+
+        let bytes = parse_hex(hex).ok_or_else(|| err("invalid hex digits"))?;
+        add_bytes(&mut dump.runs, pos, &bytes);
+        pos += bytes.len();
+        if !bytes.is_empty() {
+            last_line = bytes;
+        }
+    }
+
+    if dump.runs.is_empty() {
+        return Err("No bytes in the dump".to_owned());
+    }
+    Ok(dump)
+}
+
+/// Offset and hex digits of a line. The offset is `None` when the line
+/// doesn't have one, `Some(None)` when it's not a number. `in_offsets`: the
+/// lines before had offsets, a line with a single number is an offset.
+fn split_line(line: &str, in_offsets: bool) -> (Option<Option<usize>>, &str) {
+    // rhex: offset│hex│ASCII
+    if let Some(idx) = line.find('│') {
+        let rest = &line[idx + '│'.len_utf8()..];
+        let hex = match rest.find('│') {
+            Some(end) =>
+                &rest[..end],
+            None =>
+                rest,
+        };
+        return (Some(parse_offset(line[..idx].trim())), hex);
+    }
+
+    let first = line.split_whitespace().next().unwrap();
+    let rest = line.trim_start()[first.len()..].trim_start();
+    if let Some(offset) = first.strip_suffix(':') {
+        // xxd: offset: hex  ASCII
+        let hex = match rest.find("  ") {
+            Some(end) =>
+                &rest[..end],
+            None =>
+                rest,
+        };
+        return (Some(parse_hex_offset(offset)), hex);
+    }
+    if let Some(end) = rest.find('|') {
+        // hexdump -C: offset  hex  |ASCII|
+        return (Some(parse_hex_offset(first)), &rest[..end]);
+    }
+    if rest.is_empty() && in_offsets {
+        // Length, at the end of hexdump -C output
+        return (Some(parse_hex_offset(first)), "");
+    }
+    (None, line)
+}
+
+/// Offsets as shown by rhex: hex with "0x", octal with "0o", or decimal.
+fn parse_offset(s: &str) -> Option<usize> {
+    if let Some(octal) = s.strip_prefix("0o") {
+        usize::from_str_radix(octal, 8).ok()
+    } else if let Some(hex) = s.strip_prefix("0x") {
+        usize::from_str_radix(hex, 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+/// Offsets of xxd and hexdump, hex without a prefix.
+fn parse_hex_offset(s: &str) -> Option<usize> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    usize::from_str_radix(s, 16).ok()
+}
+
+/// Groups of hex digits separated with whitespace. Groups are of whole bytes.
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    let mut ret = Vec::new();
+    for group in s.split_whitespace() {
+        if group.len() % 2 != 0 {
+            return None;
+        }
+        for idx in 0..group.len() / 2 {
+            let byte = group.get(idx * 2..idx * 2 + 2)?;
+            ret.push(u8::from_str_radix(byte, 16).ok()?);
+        }
+    }
+    Some(ret)
+}
+
+/// Add the bytes at the offset, to the last run when they follow it.
+fn add_bytes(runs: &mut Vec<(usize, Vec<u8>)>, offset: usize, bytes: &[u8]) {
+    if bytes.is_empty() {
+        return;
+    }
+    if let Some(&mut (start, ref mut last)) = runs.last_mut() {
+        if start + last.len() == offset {
+            last.extend_from_slice(bytes);
+            return;
+        }
+    }
+    runs.push((offset, bytes.to_vec()));
+}
+
+/// `--revert`: write the bytes of the dump at `input` to `output`.
+pub fn run(input: &str, output: &str) -> Result<usize, String> {
+    let text =
+        fs::read_to_string(input).map_err(|err| format!("Can't read {}: {}", input, err))?;
+    let dump = parse(&text).map_err(|err| format!("{}: {}", input, err))?;
+    let (_, end) = dump.span();
+    let bytes = dump.bytes(0, end, &[]);
+    fs::write(output, &bytes).map_err(|err| format!("Can't write {}: {}", output, err))?;
+    Ok(bytes.len())
+}
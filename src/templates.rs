@@ -0,0 +1,749 @@
+////////////////////////////////////////////////////////////////////////////////
+// Binary templates: structure definitions in a small line-based language,
+// applied to the buffer to get a tree of fields. Example:
+//
+//     # PNG signature and the first chunks
+//     endian big
+//
+//     struct png
+//         signature u8[8]
+//         chunks chunk[3]
+//     end
+//
+//     struct chunk
+//         length u32
+//         type char[4]
+//         data u8[length]
+//         crc u32
+//     end
+//
+// The first struct is applied at the start offset. Field types are `u8` to
+// `u64` and `i8` to `i64` (optionally ending with `le` or `be`, e.g. `u32le`),
+// `char`, and names of structs. Array lengths are numbers or names of integer
+// fields read before. Fields between `if <field> [<op> <number>]` and `end`
+// are read when the condition holds, ops are `==`, `!=`, `<`, `>`, and `&`
+// (any of the bits set). Without an op the condition is that the field is not
+// zero. `endian big|little` sets the endianness of the integer fields after it,
+// default is little endian.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::cmp;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+
+use parser::{Reader, Report};
+use utils::parse_number;
+
+/// Applying a template stops after this many fields, so that corrupt lengths
+/// don't hang the editor.
+const MAX_FIELDS: usize = 100_000;
+
+/// Structs can't be nested deeper than this, so that recursive structs end.
+const MAX_DEPTH: usize = 64;
+
+/// How many elements of `char` and `u8` arrays are shown.
+const MAX_SHOWN_CHARS: usize = 64;
+const MAX_SHOWN_BYTES: usize = 16;
+
+pub struct Template {
+    /// The first one is the root.
+    structs: Vec<Struct>,
+}
+
+struct Struct {
+    name: String,
+    items: Vec<Item>,
+}
+
+enum Item {
+    Field {
+        line: usize,
+        name: String,
+        ty: Type,
+        /// Number of elements, for arrays.
+        len: Option<Len>,
+    },
+    If {
+        line: usize,
+        cond: Cond,
+        items: Vec<Item>,
+    },
+}
+
+#[derive(Clone, Copy)]
+enum Type {
+    Int {
+        size: usize,
+        signed: bool,
+        big_endian: bool,
+    },
+    Char,
+    /// Index in `Template::structs`.
+    Struct(usize),
+}
+
+enum Len {
+    Const(usize),
+    Field(String),
+}
+
+struct Cond {
+    field: String,
+    op: Op,
+    value: u64,
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    And,
+}
+
+impl Cond {
+    fn holds(&self, value: u64) -> bool {
+        match self.op {
+            Op::Eq =>
+                value == self.value,
+            Op::Ne =>
+                value != self.value,
+            Op::Lt =>
+                value < self.value,
+            Op::Gt =>
+                value > self.value,
+            Op::And =>
+                value & self.value != 0,
+        }
+    }
+}
+
+/// A field of an applied template. Elements of structs and arrays follow
+/// them, one level deeper.
+pub struct Field {
+    pub depth: usize,
+    pub name: String,
+    pub offset: usize,
+    pub size: usize,
+    /// `None` for structs and arrays of integers and structs.
+    pub value: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum TemplateError {
+    /// Syntax error at the line (1-based).
+    Syntax { line: usize, msg: String },
+    /// An array length or condition refers to a field that wasn't read, e.g.
+    /// because it's in an `if` that didn't hold.
+    UnknownField { line: usize, name: String },
+    /// The field of `size` bytes is (partly) beyond the end of the file.
+    Truncated {
+        field: String,
+        offset: usize,
+        size: usize,
+    },
+    TooManyFields,
+    TooDeep,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TemplateError::Syntax { line, ref msg } =>
+                write!(f, "Line {}: {}", line, msg),
+            TemplateError::UnknownField { line, ref name } =>
+                write!(f, "Line {}: unknown field {}", line, name),
+            TemplateError::Truncated {
+                ref field, offset, ..
+            } =>
+                write!(f, "Truncated file: {} at 0x{:x} is out of the file", field, offset),
+            TemplateError::TooManyFields =>
+                write!(f, "More than {} fields", MAX_FIELDS),
+            TemplateError::TooDeep =>
+                write!(f, "Structs nested more than {} levels", MAX_DEPTH),
+        }
+    }
+}
+
+impl TemplateError {
+    /// `data` is the buffer the template is applied to.
+    pub fn report(&self, data: &[u8]) -> Report {
+        let (stage, offset, expected, actual) = match *self {
+            TemplateError::Syntax { line, ref msg } =>
+                (format!("line {}", line), None, "valid syntax".to_owned(), msg.clone()),
+            TemplateError::UnknownField { line, ref name } =>
+                (
+                    format!("line {}", line),
+                    None,
+                    format!("field {} read before", name),
+                    "not read".to_owned(),
+                ),
+            TemplateError::Truncated {
+                ref field,
+                offset,
+                size,
+            } =>
+                (
+                    field.clone(),
+                    Some(offset),
+                    format!("{} bytes at 0x{:x}", size, offset),
+                    format!("file size {}", data.len()),
+                ),
+            TemplateError::TooManyFields =>
+                (
+                    "fields".to_owned(),
+                    None,
+                    format!("at most {} fields", MAX_FIELDS),
+                    "more".to_owned(),
+                ),
+            TemplateError::TooDeep =>
+                (
+                    "structs".to_owned(),
+                    None,
+                    format!("at most {} levels of structs", MAX_DEPTH),
+                    "more".to_owned(),
+                ),
+        };
+        Report {
+            parser: "template",
+            stage,
+            offset,
+            expected,
+            actual,
+            message: self.to_string(),
+        }
+    }
+}
+
+impl Template {
+    /// Errors are shown to the user.
+    pub fn load(path: &str) -> Result<Template, String> {
+        let mut src = String::new();
+        let read = File::open(path).and_then(|mut f| f.read_to_string(&mut src));
+        if let Err(err) = read {
+            return Err(format!("Can't read {}: {}", path, err));
+        }
+        Template::parse(&src).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    pub fn parse(src: &str) -> Result<Template, TemplateError> {
+        let lines: Vec<(usize, Vec<&str>)> = src.lines()
+            .enumerate()
+            .map(|(idx, line)| {
+                let line = match line.find('#') {
+                    Some(comment) =>
+                        &line[..comment],
+                    None =>
+                        line,
+                };
+                (idx + 1, line.split_whitespace().collect::<Vec<&str>>())
+            })
+            .filter(|(_, words)| !words.is_empty())
+            .collect();
+
+        // Struct names first, so that structs can be used before they're
+        // defined
+        let names: Vec<&str> = lines
+            .iter()
+            .filter(|(_, words)| words[0] == "struct" && words.len() == 2)
+            .map(|(_, words)| words[1])
+            .collect();
+
+        let mut parser = Parser {
+            lines: &lines,
+            pos: 0,
+            names: &names,
+            big_endian: false,
+        };
+        let mut structs = Vec::new();
+        while let Some(&(line, ref words)) = parser.next() {
+            match words.split_first() {
+                Some((&"struct", &[name])) => {
+                    if structs.iter().any(|s: &Struct| s.name == name) {
+                        return Err(syntax(line, &format!("struct {} is already defined", name)));
+                    }
+                    let items = parser.items(line)?;
+                    structs.push(Struct {
+                        name: name.to_owned(),
+                        items,
+                    });
+                }
+                Some((&"endian", args)) =>
+                    parser.endian(line, args)?,
+                _ =>
+                    return Err(syntax(line, "expected struct or endian")),
+            }
+        }
+
+        if structs.is_empty() {
+            return Err(syntax(1, "no structs"));
+        }
+        Ok(Template { structs })
+    }
+
+    /// Fields of the first struct at the offset. When the template can't be
+    /// applied to the end, the fields read so far are returned with the
+    /// error.
+    pub fn apply(&self, data: &[u8], offset: usize) -> (Vec<Field>, Option<TemplateError>) {
+        let mut eval = Eval {
+            template: self,
+            data,
+            fields: Vec::new(),
+            scopes: Vec::new(),
+        };
+        let name = &self.structs[0].name;
+        let ret = eval.struct_(0, name, offset, 0);
+        (eval.fields, ret.err())
+    }
+}
+
+fn syntax(line: usize, msg: &str) -> TemplateError {
+    TemplateError::Syntax {
+        line,
+        msg: msg.to_owned(),
+    }
+}
+
+struct Parser<'a> {
+    /// Line numbers and words of the non-empty lines.
+    lines: &'a [(usize, Vec<&'a str>)],
+    pos: usize,
+    /// Names of the structs, in definition order.
+    names: &'a [&'a str],
+    /// For integer types without `le` or `be`.
+    big_endian: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Option<&'a (usize, Vec<&'a str>)> {
+        let ret = self.lines.get(self.pos);
+        self.pos += 1;
+        ret
+    }
+
+    fn endian(&mut self, line: usize, args: &[&str]) -> Result<(), TemplateError> {
+        self.big_endian = match *args {
+            ["big"] =>
+                true,
+            ["little"] =>
+                false,
+            _ =>
+                return Err(syntax(line, "expected endian big or endian little")),
+        };
+        Ok(())
+    }
+
+    /// Items of a struct or an `if` starting at line `start`, up to the `end`.
+    fn items(&mut self, start: usize) -> Result<Vec<Item>, TemplateError> {
+        let mut items = Vec::new();
+        loop {
+            let &(line, ref words) = match self.next() {
+                Some(line) =>
+                    line,
+                None =>
+                    return Err(syntax(start, "missing end")),
+            };
+            match words.split_first() {
+                Some((&"end", &[])) =>
+                    return Ok(items),
+                Some((&"endian", args)) =>
+                    self.endian(line, args)?,
+                Some((&"if", args)) => {
+                    let cond = parse_cond(line, args)?;
+                    let if_items = self.items(line)?;
+                    items.push(Item::If {
+                        line,
+                        cond,
+                        items: if_items,
+                    });
+                }
+                Some((&"struct", _)) =>
+                    return Err(syntax(line, "structs can't be defined in structs")),
+                Some((&name, &[ty])) => {
+                    let (ty, len) = self.parse_field_type(line, ty)?;
+                    items.push(Item::Field {
+                        line,
+                        name: name.to_owned(),
+                        ty,
+                        len,
+                    });
+                }
+                _ =>
+                    return Err(syntax(line, "expected <name> <type>, if, or end")),
+            }
+        }
+    }
+
+    /// `<type>` or `<type>[<len>]`.
+    fn parse_field_type(
+        &self,
+        line: usize,
+        s: &str,
+    ) -> Result<(Type, Option<Len>), TemplateError> {
+        let (ty, len) = match s.find('[') {
+            None =>
+                (s, None),
+            Some(bracket) => {
+                if !s.ends_with(']') {
+                    return Err(syntax(line, "expected ] after the array length"));
+                }
+                let len = &s[bracket + 1..s.len() - 1];
+                let len = match parse_number(len) {
+                    Some(n) =>
+                        Len::Const(n),
+                    None if !len.is_empty() =>
+                        Len::Field(len.to_owned()),
+                    None =>
+                        return Err(syntax(line, "missing array length")),
+                };
+                (&s[..bracket], Some(len))
+            }
+        };
+        match self.parse_type(ty) {
+            Some(ty) =>
+                Ok((ty, len)),
+            None =>
+                Err(syntax(line, &format!("unknown type {}", ty))),
+        }
+    }
+
+    fn parse_type(&self, s: &str) -> Option<Type> {
+        if s == "char" {
+            return Some(Type::Char);
+        }
+        if let Some(idx) = self.names.iter().position(|&name| name == s) {
+            return Some(Type::Struct(idx));
+        }
+        let (s, big_endian) = if let Some(s) = s.strip_suffix("le") {
+            (s, false)
+        } else if let Some(s) = s.strip_suffix("be") {
+            (s, true)
+        } else {
+            (s, self.big_endian)
+        };
+        let signed = match s.chars().next() {
+            Some('u') =>
+                false,
+            Some('i') =>
+                true,
+            _ =>
+                return None,
+        };
+        let size = match &s[1..] {
+            "8" =>
+                1,
+            "16" =>
+                2,
+            "32" =>
+                4,
+            "64" =>
+                8,
+            _ =>
+                return None,
+        };
+        Some(Type::Int {
+            size,
+            signed,
+            big_endian,
+        })
+    }
+}
+
+/// `<field>` or `<field> <op> <number>`.
+fn parse_cond(line: usize, args: &[&str]) -> Result<Cond, TemplateError> {
+    let (field, op, value) = match *args {
+        [field] =>
+            (field, Op::Ne, 0),
+        [field, op, value] => {
+            let op = match op {
+                "==" =>
+                    Op::Eq,
+                "!=" =>
+                    Op::Ne,
+                "<" =>
+                    Op::Lt,
+                ">" =>
+                    Op::Gt,
+                "&" =>
+                    Op::And,
+                _ =>
+                    return Err(syntax(line, &format!("unknown operator {}", op))),
+            };
+            match parse_number(value) {
+                Some(value) =>
+                    (field, op, value as u64),
+                None =>
+                    return Err(syntax(line, &format!("expected a number, found {}", value))),
+            }
+        }
+        _ =>
+            return Err(syntax(line, "expected if <field> [<op> <number>]")),
+    };
+    Ok(Cond {
+        field: field.to_owned(),
+        op,
+        value,
+    })
+}
+
+struct Eval<'a> {
+    template: &'a Template,
+    data: &'a [u8],
+    fields: Vec<Field>,
+    /// Integer fields read so far in the structs being read, innermost
+    /// struct last.
+    scopes: Vec<Vec<(String, u64)>>,
+}
+
+impl<'a> Eval<'a> {
+    /// Returns the index of the field.
+    fn push(
+        &mut self,
+        depth: usize,
+        name: &str,
+        offset: usize,
+        size: usize,
+        value: Option<String>,
+    ) -> Result<usize, TemplateError> {
+        if self.fields.len() >= MAX_FIELDS {
+            return Err(TemplateError::TooManyFields);
+        }
+        self.fields.push(Field {
+            depth,
+            name: name.to_owned(),
+            offset,
+            size,
+            value,
+        });
+        Ok(self.fields.len() - 1)
+    }
+
+    /// Value of an integer field, in the innermost struct that has it.
+    fn lookup(&self, line: usize, name: &str) -> Result<u64, TemplateError> {
+        for scope in self.scopes.iter().rev() {
+            if let Some(&(_, value)) = scope.iter().rev().find(|(field, _)| field == name) {
+                return Ok(value);
+            }
+        }
+        Err(TemplateError::UnknownField {
+            line,
+            name: name.to_owned(),
+        })
+    }
+
+    fn bytes(&self, name: &str, offset: usize, size: usize) -> Result<&'a [u8], TemplateError> {
+        let data = self.data;
+        match offset.checked_add(size).and_then(|end| data.get(offset..end)) {
+            Some(bytes) =>
+                Ok(bytes),
+            None =>
+                Err(TemplateError::Truncated {
+                    field: name.to_owned(),
+                    offset,
+                    size,
+                }),
+        }
+    }
+
+    /// Sets the size of a struct or an array at `idx` from its last element,
+    /// for when reading the elements failed.
+    fn set_partial_size(&mut self, idx: usize) {
+        let offset = self.fields[idx].offset;
+        let end = self.fields.last().map_or(offset, |field| field.offset + field.size);
+        self.fields[idx].size = end - offset;
+    }
+
+    /// Returns the offset after the struct.
+    fn struct_(
+        &mut self,
+        idx: usize,
+        name: &str,
+        offset: usize,
+        depth: usize,
+    ) -> Result<usize, TemplateError> {
+        if depth >= MAX_DEPTH {
+            return Err(TemplateError::TooDeep);
+        }
+        let field_idx = self.push(depth, name, offset, 0, None)?;
+        let template = self.template;
+        self.scopes.push(Vec::new());
+        let ret = self.items(&template.structs[idx].items, offset, depth + 1);
+        self.scopes.pop();
+        match ret {
+            Ok(end) => {
+                self.fields[field_idx].size = end - offset;
+                Ok(end)
+            }
+            Err(err) => {
+                self.set_partial_size(field_idx);
+                Err(err)
+            }
+        }
+    }
+
+    fn items(
+        &mut self,
+        items: &'a [Item],
+        mut offset: usize,
+        depth: usize,
+    ) -> Result<usize, TemplateError> {
+        for item in items {
+            match *item {
+                Item::Field {
+                    line,
+                    ref name,
+                    ty,
+                    ref len,
+                } => {
+                    offset = match *len {
+                        None =>
+                            self.value(name, ty, offset, depth)?,
+                        Some(Len::Const(n)) =>
+                            self.array(name, ty, n, offset, depth)?,
+                        Some(Len::Field(ref field)) => {
+                            let n = self.lookup(line, field)?;
+                            // Lengths that don't fit are beyond the end of the
+                            // file anyway
+                            let n = if n > usize::MAX as u64 {
+                                usize::MAX
+                            } else {
+                                n as usize
+                            };
+                            self.array(name, ty, n, offset, depth)?
+                        }
+                    };
+                }
+                Item::If {
+                    line,
+                    ref cond,
+                    ref items,
+                } => {
+                    let value = self.lookup(line, &cond.field)?;
+                    if cond.holds(value) {
+                        offset = self.items(items, offset, depth)?;
+                    }
+                }
+            }
+        }
+        Ok(offset)
+    }
+
+    /// Returns the offset after the value.
+    fn value(
+        &mut self,
+        name: &str,
+        ty: Type,
+        offset: usize,
+        depth: usize,
+    ) -> Result<usize, TemplateError> {
+        match ty {
+            Type::Int {
+                size,
+                signed,
+                big_endian,
+            } => {
+                let r = Reader::new(self.bytes(name, offset, size)?, big_endian);
+                let n = match size {
+                    1 =>
+                        r.u8(0).map(u64::from),
+                    2 =>
+                        r.u16(0).map(u64::from),
+                    4 =>
+                        r.u32(0).map(u64::from),
+                    _ =>
+                        r.u64(0),
+                };
+                // The bytes are checked above
+                let n = n.unwrap();
+                let value = if signed {
+                    // Shift the sign bit to the top, then back with sign
+                    // extension
+                    let shift = 64 - size * 8;
+                    (((n << shift) as i64) >> shift).to_string()
+                } else {
+                    format!("{} (0x{:x})", n, n)
+                };
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.push((name.to_owned(), n));
+                }
+                self.push(depth, name, offset, size, Some(value))?;
+                Ok(offset + size)
+            }
+            Type::Char => {
+                let bytes = self.bytes(name, offset, 1)?;
+                self.push(depth, name, offset, 1, Some(format!("'{}'", show_chars(bytes))))?;
+                Ok(offset + 1)
+            }
+            Type::Struct(idx) =>
+                self.struct_(idx, name, offset, depth),
+        }
+    }
+
+    /// Returns the offset after the array.
+    fn array(
+        &mut self,
+        name: &str,
+        ty: Type,
+        len: usize,
+        offset: usize,
+        depth: usize,
+    ) -> Result<usize, TemplateError> {
+        let (bytes, chars) = match ty {
+            Type::Char =>
+                (true, true),
+            Type::Int { size: 1, .. } =>
+                (true, false),
+            _ =>
+                (false, false),
+        };
+        if bytes {
+            // One field for the whole array, there are usually too many
+            // elements to show one per row
+            let bytes = self.bytes(name, offset, len)?;
+            let value = if chars {
+                let shown = &bytes[..cmp::min(bytes.len(), MAX_SHOWN_CHARS)];
+                let dots = if bytes.len() > shown.len() { "..." } else { "" };
+                format!("\"{}\"{}", show_chars(shown), dots)
+            } else {
+                let shown = &bytes[..cmp::min(bytes.len(), MAX_SHOWN_BYTES)];
+                let hex: Vec<String> = shown.iter().map(|b| format!("{:02x}", b)).collect();
+                let dots = if bytes.len() > shown.len() { " ..." } else { "" };
+                format!("{}{}", hex.join(" "), dots)
+            };
+            self.push(depth, name, offset, len, Some(value))?;
+            return Ok(offset + len);
+        }
+
+        let field_idx = self.push(depth, name, offset, 0, None)?;
+        let mut end = offset;
+        for i in 0..len {
+            match self.value(&format!("{}[{}]", name, i), ty, end, depth + 1) {
+                Ok(next) =>
+                    end = next,
+                Err(err) => {
+                    self.set_partial_size(field_idx);
+                    return Err(err);
+                }
+            }
+        }
+        self.fields[field_idx].size = end - offset;
+        Ok(end)
+    }
+}
+
+/// Printable ASCII characters as they are, others as dots, as in the ASCII
+/// view.
+fn show_chars(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
@@ -0,0 +1,224 @@
+////////////////////////////////////////////////////////////////////////////////
+// Undo history of a buffer
+//
+// The newest edits are kept in memory. When they take more than
+// `MAX_MEMORY` bytes the oldest ones are moved to a temporary file (deleted
+// right after it's created, so it goes away with the process), and read back
+// when undo gets to them. The file shrinks as they're read.
+////////////////////////////////////////////////////////////////////////////////
+
+use std::collections::VecDeque;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::process;
+
+/// Bytes of edits kept in memory, older edits are moved to the file.
+const MAX_MEMORY: usize = 16 * 1024 * 1024;
+
+/// Memory taken by an edit, on top of its bytes.
+const EDIT_OVERHEAD: usize = 64;
+
+/// `offset`, length of `removed`, length of `inserted`, as little-endian u64s.
+const HEADER_SIZE: usize = 24;
+
+/// Bytes at `offset` were replaced: `removed` with `inserted`.
+pub struct Edit {
+    pub offset: usize,
+    pub removed: Vec<u8>,
+    pub inserted: Vec<u8>,
+}
+
+impl Edit {
+    fn size(&self) -> usize {
+        self.removed.len() + self.inserted.len() + EDIT_OVERHEAD
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(HEADER_SIZE + self.removed.len() + self.inserted.len());
+        for &n in &[self.offset, self.removed.len(), self.inserted.len()] {
+            ret.extend_from_slice(&(n as u64).to_le_bytes());
+        }
+        ret.extend_from_slice(&self.removed);
+        ret.extend_from_slice(&self.inserted);
+        ret
+    }
+
+    fn deserialize(bytes: &[u8]) -> Option<Edit> {
+        if bytes.len() < HEADER_SIZE {
+            return None;
+        }
+        let mut header = [0; 3];
+        for (i, n) in header.iter_mut().enumerate() {
+            let mut word = [0; 8];
+            word.copy_from_slice(&bytes[i * 8..i * 8 + 8]);
+            *n = u64::from_le_bytes(word) as usize;
+        }
+        let [offset, removed, inserted] = header;
+        if bytes.len() != HEADER_SIZE + removed + inserted {
+            return None;
+        }
+        let removed_end = HEADER_SIZE + removed;
+        Some(Edit {
+            offset,
+            removed: bytes[HEADER_SIZE..removed_end].to_vec(),
+            inserted: bytes[removed_end..].to_vec(),
+        })
+    }
+}
+
+pub struct History {
+    /// Newest edits, oldest first.
+    recent: VecDeque<Edit>,
+
+    /// `size()` of the edits in `recent`.
+    recent_size: usize,
+
+    /// Older edits in `file`: position and length of each, oldest first.
+    spilled: Vec<(u64, usize)>,
+    file: Option<File>,
+
+    /// Undone edits, the last one is redone first. Cleared on new edits.
+    redo: Vec<Edit>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            recent: VecDeque::new(),
+            recent_size: 0,
+            spilled: Vec::new(),
+            file: None,
+            redo: Vec::new(),
+        }
+    }
+
+    /// Edits that can be undone.
+    pub fn depth(&self) -> usize {
+        self.recent.len() + self.spilled.len()
+    }
+
+    /// Edits that are in the file.
+    pub fn spilled(&self) -> usize {
+        self.spilled.len()
+    }
+
+    pub fn redo_depth(&self) -> usize {
+        self.redo.len()
+    }
+
+    pub fn push(&mut self, edit: Edit) {
+        self.redo.clear();
+        self.push_recent(edit);
+    }
+
+    /// `byte` at `offset` was changed from `old`. When it's the byte inserted
+    /// by the last edit (e.g. the second digit in insert mode) the last edit is
+    /// updated instead.
+    pub fn push_set(&mut self, offset: usize, old: u8, byte: u8) {
+        self.redo.clear();
+        if let Some(last) = self.recent.back_mut() {
+            if last.offset == offset && last.removed.is_empty() && last.inserted.len() == 1 {
+                last.inserted[0] = byte;
+                return;
+            }
+        }
+        self.push_recent(Edit {
+            offset,
+            removed: vec![old],
+            inserted: vec![byte],
+        });
+    }
+
+    /// Take the last edit to undo it. Give it back with `undone`.
+    pub fn pop_undo(&mut self) -> io::Result<Option<Edit>> {
+        match self.recent.pop_back() {
+            Some(edit) => {
+                self.recent_size -= edit.size();
+                Ok(Some(edit))
+            }
+            None =>
+                self.unspill(),
+        }
+    }
+
+    pub fn undone(&mut self, edit: Edit) {
+        self.redo.push(edit);
+    }
+
+    /// Take the last undone edit to redo it. Give it back with `redone`.
+    pub fn pop_redo(&mut self) -> Option<Edit> {
+        self.redo.pop()
+    }
+
+    pub fn redone(&mut self, edit: Edit) {
+        self.push_recent(edit);
+    }
+
+    fn push_recent(&mut self, edit: Edit) {
+        self.recent_size += edit.size();
+        self.recent.push_back(edit);
+        // The newest edit stays in memory even when it's larger than the limit
+        while self.recent_size > MAX_MEMORY && self.recent.len() > 1 {
+            if self.spill().is_err() {
+                // Keep the edits in memory, the undo history is still complete
+                break;
+            }
+        }
+    }
+
+    /// Move the oldest edit in memory to the file.
+    fn spill(&mut self) -> io::Result<()> {
+        if self.file.is_none() {
+            self.file = Some(temp_file()?);
+        }
+        let pos = self.spilled.last().map_or(0, |&(pos, len)| pos + len as u64);
+        let bytes = self.recent.front().unwrap().serialize();
+        self.file.as_ref().unwrap().write_all_at(&bytes, pos)?;
+        self.spilled.push((pos, bytes.len()));
+        let edit = self.recent.pop_front().unwrap();
+        self.recent_size -= edit.size();
+        Ok(())
+    }
+
+    /// Read the newest edit in the file and remove it from the file.
+    fn unspill(&mut self) -> io::Result<Option<Edit>> {
+        let (pos, len) = match self.spilled.last() {
+            Some(&last) =>
+                last,
+            None =>
+                return Ok(None),
+        };
+        let file = self.file.as_ref().unwrap();
+        let mut bytes = vec![0; len];
+        file.read_exact_at(&mut bytes, pos)?;
+        let edit = match Edit::deserialize(&bytes) {
+            Some(edit) =>
+                edit,
+            None =>
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "corrupt undo file")),
+        };
+        file.set_len(pos)?;
+        self.spilled.pop();
+        Ok(Some(edit))
+    }
+}
+
+/// An unnamed file in the temporary directory.
+fn temp_file() -> io::Result<File> {
+    let mut n = 0;
+    loop {
+        let path = env::temp_dir().join(format!("rhex-undo-{}-{}", process::id(), n));
+        match OpenOptions::new().read(true).write(true).create_new(true).open(&path) {
+            Ok(file) => {
+                fs::remove_file(&path)?;
+                return Ok(file);
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists =>
+                n += 1,
+            Err(err) =>
+                return Err(err),
+        }
+    }
+}
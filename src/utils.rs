@@ -11,13 +11,74 @@ pub fn hex_char(nibble: u8) -> u8 {
     }
 }
 
+/// Parse a decimal or 0x-prefixed hexadecimal number.
+pub fn parse_number(s: &str) -> Option<usize> {
+    if s.starts_with("0x") || s.starts_with("0X") {
+        usize::from_str_radix(&s[2..], 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+/// Parse `<start>:<end>` (end exclusive), e.g. `0x100:0x200`.
+pub fn parse_range(s: &str) -> Option<(usize, usize)> {
+    let colon = s.find(':')?;
+    let start = parse_number(&s[..colon])?;
+    let end = parse_number(&s[colon + 1..])?;
+    if start <= end {
+        Some((start, end))
+    } else {
+        None
+    }
+}
+
+/// Parse a byte sequence: hex digits, optionally separated with spaces
+/// (`de ad be ef`), or text in double quotes (`"foo"`, encoded as UTF-8).
+pub fn parse_bytes(s: &str) -> Option<Vec<u8>> {
+    let s = s.trim();
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        return Some(s.as_bytes()[1..s.len() - 1].to_vec());
+    }
+
+    let digits: Vec<u8> = s.chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<Vec<u8>>>()?;
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    Some(digits.chunks(2).map(|d| (d[0] << 4) | d[1]).collect())
+}
+
 use colors::Style;
 use colors;
 use termbox_simple::*;
 
+/// Whether the offset is in one of the sorted, non-overlapping `(start, end)`
+/// ranges (end exclusive).
+pub fn in_ranges(ranges: &[(usize, usize)], offset: usize) -> bool {
+    range_index(ranges, offset).is_some()
+}
+
+/// Index of the range the offset is in, see `in_ranges()`.
+pub fn range_index(ranges: &[(usize, usize)], offset: usize) -> Option<usize> {
+    match ranges.binary_search_by(|&(start, _)| start.cmp(&offset)) {
+        Ok(idx) =>
+            Some(idx),
+        Err(0) =>
+            None,
+        Err(idx) =>
+            if offset < ranges[idx - 1].1 {
+                Some(idx - 1)
+            } else {
+                None
+            },
+    }
+}
+
 pub fn draw_box(tb: &mut Termbox, pos_x: i32, pos_y: i32, width: i32, height: i32) {
-    let fg = colors::DEFAULT.fg;
-    let bg = colors::DEFAULT.bg;
+    let fg = colors::palette().default.fg;
+    let bg = colors::palette().default.bg;
 
     for x in 1..width - 1 {
         tb.change_cell(pos_x + x, pos_y, '─', fg, bg);